@@ -24,7 +24,9 @@ mod reconstructor;
 
 
 use clap::{Command, CommandFactory, Parser};
-use reconstructor::ReconstructedTransmission;
+use std::path::PathBuf;
+use std::sync::Arc;
+use reconstructor::{KeyboardLayout, ModuleContext, ReconstructedTransmission, SerialEncoding};
 use sniffer::{PacketCaptureImpl, UrbXractPacket};
 use tokio::sync::mpsc;
 use sniffer::PacketCapture;
@@ -34,6 +36,60 @@ use sniffer::PacketCapture;
 struct CLIArgs {
     #[arg(short, long, help="Specify Capture Interface (Required)")]
     iface: Option<String>,
+
+    #[arg(short, long, value_name="FILE", help="Read Packets from a Capture File instead of an Interface")]
+    read: Option<String>,
+
+    #[arg(long, default_value_t=SerialEncoding::Utf8, help="Serial Stream Encoding: utf-8, latin-1, cp437 or shift-jis")]
+    serial_encoding: SerialEncoding,
+
+    #[arg(long, default_value_t=KeyboardLayout::Us, help="HID Keyboard Layout for typed Text: us, uk, de or fr")]
+    keyboard_layout: KeyboardLayout,
+
+    #[arg(long, value_name="FILE", help="Read Serial Framing Rules from a Config File")]
+    framing_config: Option<String>,
+
+    #[arg(long, value_name="BYTES", help="Serial Frame Delimiters, space separated with \\r \\n \\0 \\xNN escapes, or none")]
+    frame_delimiter: Option<String>,
+
+    #[arg(long, value_name="REGEX", help="End a Serial Frame when the decoded Row matches")]
+    frame_regex: Option<String>,
+
+    #[arg(long, value_name="DURATION", help="End a Serial Frame after Silence in Capture Time, e.g. 50ms")]
+    frame_idle_timeout: Option<String>,
+
+    #[arg(long, value_name="LENGTH", help="Maximum Serial Frame Length in bytes")]
+    frame_max_length: Option<String>,
+
+    #[arg(long, value_name="LENGTH", help="Split Serial Streams into fixed-length Frames")]
+    frame_fixed_length: Option<String>,
+
+    #[arg(long, value_name="CODEC", help="Binary Serial Framing: slip, cobs, hdlc or length:OFFSET:SIZE:le|be:ADJUST")]
+    frame_codec: Option<String>,
+
+    #[arg(long, value_name="PROTOCOL", help="Serial Application Protocol: modbus, nmea or at")]
+    serial_protocol: Option<String>,
+
+    #[arg(long, value_name="DIR", help="Write Isochronous Audio Streams as WAV Files into a Directory")]
+    audio_dir: Option<String>,
+
+    #[arg(long, value_name="DIR", help="Write UVC Video Frames as JPEG or raw YUV Files into a Directory")]
+    video_dir: Option<String>,
+
+    #[arg(long, value_name="FILE", help="Export Ethernet Frames of CDC ECM, NCM and RNDIS Interfaces to a pcap File")]
+    ethernet_pcap: Option<String>,
+
+    #[arg(long, value_name="FILE", help="Export Bluetooth HCI Packets to a pcap File, or btsnoop for .btsnoop and .log Files")]
+    bluetooth_capture: Option<String>,
+
+    #[arg(long, value_name="DIR", help="Write Objects transferred over MTP and PTP into a Directory")]
+    object_dir: Option<String>,
+
+    #[arg(long, help="Print Captured Descriptors like lsusb -v and exit")]
+    lsusb: bool,
+
+    #[arg(long, help="Check Captured Descriptors and Requests against USB Chapter 9 and exit")]
+    lint: bool,
     
     #[arg(long, help="Show License Information")]
     license_info: bool
@@ -50,9 +106,12 @@ async fn main() {
     }
     
     /* Print License and Available Capture Interface */
-    println!("\n{}\n", licenses::get_license_string_short());
+    let is_headless = cli_args.lsusb || cli_args.lint;
+    if !is_headless {
+        println!("\n{}\n", licenses::get_license_string_short());
+    }
 
-    if cli_args.iface.is_none() {
+    if cli_args.iface.is_none() && cli_args.read.is_none() {
         /* Enumerate the Capture Devices */
         println!(
             "Available Capture Interfaces:\n{}\n", 
@@ -73,20 +132,89 @@ async fn main() {
         return;
     }
 
+    /* CLI Framing Options override the Default Rule of the Config File */
+    let config_text = match &cli_args.framing_config {
+        Some(config_path) => match std::fs::read_to_string(config_path) {
+            Ok(config_text) => config_text,
+            Err(error) => {
+                eprintln!("Unable to read Framing Config {}: {}", config_path, error);
+                return;
+            }
+        },
+        None => String::new()
+    };
+
+    let framing_overrides: Vec<(&str, String)> = [
+        ("delimiter", cli_args.frame_delimiter),
+        ("regex", cli_args.frame_regex),
+        ("idle_timeout", cli_args.frame_idle_timeout),
+        ("max_length", cli_args.frame_max_length),
+        ("fixed_length", cli_args.frame_fixed_length),
+        ("codec", cli_args.frame_codec),
+        ("protocol", cli_args.serial_protocol),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| value.map(|value| (key, value)))
+    .collect();
+
+    let serial_framing = match reconstructor::parse_framing_config(&config_text, &framing_overrides) {
+        Ok(serial_framing) => serial_framing,
+        Err(error) => {
+            eprintln!("Invalid Framing Config: {}", error);
+            return;
+        }
+    };
+
     /* Create Multi-producer Single-Consumer Channel and start capture */
     let (sniffer_tx, sniffer_rx) = mpsc::channel::<UrbXractPacket>(2);
-    let capture_handle = sniffer::capture(cli_args.iface.unwrap(), sniffer_tx);
+    let capture_handle = match cli_args.read {
+        Some(file_path) => sniffer::capture_file(file_path, sniffer_tx),
+        None => sniffer::capture(cli_args.iface.unwrap(), sniffer_tx)
+    };
 
     /* Create Channel for Packet Reconstruction and Pass Sniffer Receiver */
-    let (reconstruct_tx, reconstruct_rx) = mpsc::channel::<ReconstructedTransmission>(2);
-    reconstructor::consume(reconstruct_tx, sniffer_rx);
+    let module_context = ModuleContext {
+        serial_encoding: cli_args.serial_encoding,
+        serial_framing: Arc::new(serial_framing),
+        keyboard_layout: cli_args.keyboard_layout,
+        audio_directory: cli_args.audio_dir.clone().map(PathBuf::from),
+        video_directory: cli_args.video_dir.clone().map(PathBuf::from),
+        ethernet_pcap: cli_args.ethernet_pcap.clone().map(PathBuf::from),
+        bluetooth_capture: cli_args.bluetooth_capture.clone().map(PathBuf::from),
+        object_directory: cli_args.object_dir.clone().map(PathBuf::from),
+        ..ModuleContext::default()
+    };
+    let (reconstruct_tx, mut reconstruct_rx) = mpsc::channel::<ReconstructedTransmission>(2);
+    let consume_handle = reconstructor::consume(reconstruct_tx, sniffer_rx, module_context.clone());
+
+    if is_headless {
+        /* Snoop until the Capture File ends or the User interrupts a live Capture */
+        tokio::select! {
+            _ = async { while reconstruct_rx.recv().await.is_some() {} } => {},
+            _ = tokio::signal::ctrl_c() => {}
+        }
+
+        capture_handle.abort();
+        consume_handle.abort();
+        let device_registry = module_context.device_registry.read().unwrap();
+        if cli_args.lsusb {
+            print!("{}", reconstructor::format_registry_report(&device_registry));
+        }
+
+        if cli_args.lint {
+            print!("{}", reconstructor::format_lint_report(&device_registry));
+        }
+
+        return;
+    }
 
     /* Create User Interface and start the Render loop */
     let terminal_interface = ratatui::init();
-    let mut app = textui::UserInterface::new(reconstruct_rx);
+    let mut app = textui::UserInterface::new(reconstruct_rx, module_context.device_registry.clone(), module_context.pointer_trail.clone());
     app.run(terminal_interface).await;
 
     /* Reset the Terminal */
     capture_handle.abort();
+    consume_handle.abort();
     ratatui::restore();
 }
\ No newline at end of file
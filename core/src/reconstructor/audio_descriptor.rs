@@ -0,0 +1,247 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use super::device_model::{DeviceModel, InterfaceModel};
+
+/*
+    USB Audio Class 1.0 and 2.0 Descriptors. Streaming Interfaces describe
+    their Format in AS_GENERAL and FORMAT_TYPE, the Control Interface lists
+    Terminals, Units and (UAC2) the Clock Entities they are driven by
+*/
+pub const CLASS_AUDIO: u8 = 0x01;
+pub const SUBCLASS_AUDIO_CONTROL: u8 = 0x01;
+const PROTOCOL_UAC2: u8 = 0x20;
+const DESCRIPTOR_CS_INTERFACE: u8 = 0x24;
+const SUBTYPE_AS_GENERAL: u8 = 0x01;
+const SUBTYPE_FORMAT_TYPE: u8 = 0x02;
+const FORMAT_TYPE_I: u8 = 0x01;
+
+pub const SUBTYPE_INPUT_TERMINAL: u8 = 0x02;
+pub const SUBTYPE_OUTPUT_TERMINAL: u8 = 0x03;
+pub const SUBTYPE_FEATURE_UNIT: u8 = 0x06;
+pub const SUBTYPE_CLOCK_SOURCE: u8 = 0x0A;
+pub const SUBTYPE_CLOCK_SELECTOR: u8 = 0x0B;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioVersion {
+    Uac1,
+    Uac2
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleEncoding {
+    Pcm,
+    Pcm8,
+    Float,
+    ALaw,
+    MuLaw,
+    Unsupported(u32)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamFormat {
+    pub version: AudioVersion,
+    pub encoding: SampleEncoding,
+    pub channels: u16,
+    pub subslot_size: u8,
+    pub bit_resolution: u8,
+    pub sample_rates: Vec<u32>, /* Discrete UAC1 Rates, empty for Continuous Ranges and UAC2 */
+    pub terminal_link: u8,
+}
+
+#[derive(Debug, Default)]
+pub struct AudioTopology {
+    pub entities: HashMap<u8, u8>, /* Entity ID, Descriptor Subtype */
+    pub terminal_clocks: HashMap<u8, u8>, /* Terminal ID, Clock Entity ID (UAC2) */
+}
+
+impl AudioVersion {
+    pub fn from_protocol(protocol: u8) -> Self {
+        match protocol {
+            PROTOCOL_UAC2 => AudioVersion::Uac2,
+            _ => AudioVersion::Uac1
+        }
+    }
+}
+
+impl SampleEncoding {
+    pub fn get_name(&self) -> String {
+        match self {
+            SampleEncoding::Pcm => String::from("PCM"),
+            SampleEncoding::Pcm8 => String::from("PCM8"),
+            SampleEncoding::Float => String::from("IEEE Float"),
+            SampleEncoding::ALaw => String::from("A-Law"),
+            SampleEncoding::MuLaw => String::from("µ-Law"),
+            SampleEncoding::Unsupported(format_tag) => format!("Format 0x{:X}", format_tag),
+        }
+    }
+}
+
+fn read_rate(data: &[u8]) -> u32 {
+    /* UAC1 Sampling Frequencies are 24 Bit */
+    u32::from_le_bytes([data[0], data[1], data[2], 0])
+}
+
+impl StreamFormat {
+    pub fn parse(interface: &InterfaceModel) -> Option<Self> {
+        let version = AudioVersion::from_protocol(interface.protocol);
+        let class_descriptors = interface.extra_descriptors
+            .iter()
+            .filter(|descriptor| descriptor.len() >= 3 && descriptor[1] == DESCRIPTOR_CS_INTERFACE);
+
+        let (mut general, mut format_type) = (None, None);
+        for descriptor in class_descriptors {
+            match descriptor[2] {
+                SUBTYPE_AS_GENERAL => general = Some(descriptor.as_slice()),
+                SUBTYPE_FORMAT_TYPE => format_type = Some(descriptor.as_slice()),
+                _ => {}
+            }
+        }
+
+        let (general, format_type) = (general?, format_type?);
+        match version {
+            /* AS_GENERAL: wFormatTag. FORMAT_TYPE I: Channels, Subframe, Resolution, Rates */
+            AudioVersion::Uac1 if general.len() >= 7 && format_type.len() >= 8 && format_type[3] == FORMAT_TYPE_I => {
+                let encoding = match u16::from_le_bytes([general[5], general[6]]) {
+                    0x0001 => SampleEncoding::Pcm,
+                    0x0002 => SampleEncoding::Pcm8,
+                    0x0003 => SampleEncoding::Float,
+                    0x0004 => SampleEncoding::ALaw,
+                    0x0005 => SampleEncoding::MuLaw,
+                    format_tag => SampleEncoding::Unsupported(format_tag as u32),
+                };
+
+                let sample_rates = match format_type[7] {
+                    0 => vec![],
+                    rate_count => format_type[8..]
+                        .chunks_exact(3)
+                        .take(rate_count as usize)
+                        .map(read_rate)
+                        .collect(),
+                };
+
+                Some(StreamFormat {
+                    version,
+                    encoding,
+                    channels: format_type[4] as u16,
+                    subslot_size: format_type[5],
+                    bit_resolution: format_type[6],
+                    sample_rates,
+                    terminal_link: general[3],
+                })
+            },
+
+            /* AS_GENERAL: bmFormats, Channels. FORMAT_TYPE I: Subslot, Resolution. Rates come from the Clock */
+            AudioVersion::Uac2 if general.len() >= 11 && format_type.len() >= 6 && format_type[3] == FORMAT_TYPE_I => {
+                let formats = u32::from_le_bytes([general[6], general[7], general[8], general[9]]);
+                let encoding = match formats.trailing_zeros() {
+                    0 => SampleEncoding::Pcm,
+                    1 => SampleEncoding::Pcm8,
+                    2 => SampleEncoding::Float,
+                    3 => SampleEncoding::ALaw,
+                    4 => SampleEncoding::MuLaw,
+                    _ => SampleEncoding::Unsupported(formats),
+                };
+
+                Some(StreamFormat {
+                    version,
+                    encoding,
+                    channels: general[10] as u16,
+                    subslot_size: format_type[4],
+                    bit_resolution: format_type[5],
+                    sample_rates: vec![],
+                    terminal_link: general[3],
+                })
+            },
+
+            _ => None
+        }
+    }
+
+    pub fn get_frame_size(&self) -> usize {
+        self.channels as usize * self.subslot_size as usize
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{} {} ch {} bit", self.encoding.get_name(), self.channels, self.bit_resolution)
+    }
+}
+
+impl AudioTopology {
+    pub fn parse(control_interface: &InterfaceModel) -> Self {
+        /* Every Terminal, Unit and Clock starts with its ID after the Subtype */
+        let version = AudioVersion::from_protocol(control_interface.protocol);
+        let mut audio_topology = AudioTopology::default();
+        let class_descriptors = control_interface.extra_descriptors
+            .iter()
+            .filter(|descriptor| descriptor.len() >= 4 && descriptor[1] == DESCRIPTOR_CS_INTERFACE && descriptor[2] != 0x01);
+
+        for descriptor in class_descriptors {
+            audio_topology.entities.insert(descriptor[3], descriptor[2]);
+            let clock_offset = match descriptor[2] {
+                SUBTYPE_INPUT_TERMINAL => 7,
+                SUBTYPE_OUTPUT_TERMINAL => 8,
+                _ => continue,
+            };
+
+            if version == AudioVersion::Uac2 && let Some(clock_id) = descriptor.get(clock_offset) {
+                audio_topology.terminal_clocks.insert(descriptor[3], *clock_id);
+            }
+        }
+
+        audio_topology
+    }
+
+    pub fn from_device(device: &DeviceModel) -> Self {
+        /* Entity IDs are unique across the Audio Function */
+        let mut audio_topology = AudioTopology::default();
+        let control_interfaces = device.get_active_configuration()
+            .into_iter()
+            .flat_map(|configuration| configuration.interfaces.iter())
+            .filter(|interface| interface.class == CLASS_AUDIO && interface.subclass == SUBCLASS_AUDIO_CONTROL);
+
+        for control_interface in control_interfaces {
+            let interface_topology = AudioTopology::parse(control_interface);
+            audio_topology.entities.extend(interface_topology.entities);
+            audio_topology.terminal_clocks.extend(interface_topology.terminal_clocks);
+        }
+
+        audio_topology
+    }
+}
+
+pub fn get_entity_name(version: AudioVersion, subtype: u8) -> &'static str {
+    match (version, subtype) {
+        (_, SUBTYPE_INPUT_TERMINAL) => "Input Terminal",
+        (_, SUBTYPE_OUTPUT_TERMINAL) => "Output Terminal",
+        (_, 0x04) => "Mixer Unit",
+        (_, 0x05) => "Selector Unit",
+        (_, SUBTYPE_FEATURE_UNIT) => "Feature Unit",
+        (AudioVersion::Uac1, 0x07) => "Processing Unit",
+        (AudioVersion::Uac1, 0x08) => "Extension Unit",
+        (AudioVersion::Uac2, 0x07) => "Effect Unit",
+        (AudioVersion::Uac2, 0x08) => "Processing Unit",
+        (AudioVersion::Uac2, 0x09) => "Extension Unit",
+        (AudioVersion::Uac2, SUBTYPE_CLOCK_SOURCE) => "Clock Source",
+        (AudioVersion::Uac2, SUBTYPE_CLOCK_SELECTOR) => "Clock Selector",
+        (AudioVersion::Uac2, 0x0C) => "Clock Multiplier",
+        (AudioVersion::Uac2, 0x0D) => "Sample Rate Converter",
+        _ => "Entity"
+    }
+}
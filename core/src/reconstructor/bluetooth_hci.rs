@@ -0,0 +1,562 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use super::ethernet_frame::PcapWriter;
+
+/*
+    Bluetooth Host Controller Interface. Commands carry an Opcode made of
+    OGF and OCF, Events an Event Code, ACL Data a Connection Handle. Both
+    Capture Formats prefix each Packet with its H4 Packet Indicator
+*/
+const LINKTYPE_BLUETOOTH_HCI_H4_WITH_PHDR: u32 = 201;
+const BTSNOOP_MAGIC: &[u8; 8] = b"btsnoop\0";
+const BTSNOOP_VERSION: u32 = 1;
+const BTSNOOP_DATALINK_H4: u32 = 1002;
+const BTSNOOP_EPOCH_DELTA: u64 = 0x00DCDDB30F2F8000; /* Microseconds from 0 AD to the Unix Epoch */
+pub const EVENT_COMMAND_COMPLETE: u8 = 0x0E;
+pub const EVENT_COMMAND_STATUS: u8 = 0x0F;
+const EVENT_LE_META: u8 = 0x3E;
+const CID_ATT: u16 = 0x0004;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HciPacketType {
+    Command,
+    Acl,
+    Event
+}
+
+pub enum HciCapture {
+    Pcap(PcapWriter),
+    Btsnoop(File),
+}
+
+impl HciPacketType {
+    fn get_indicator(&self) -> u8 {
+        match self {
+            HciPacketType::Command => 0x01,
+            HciPacketType::Acl => 0x02,
+            HciPacketType::Event => 0x04,
+        }
+    }
+}
+
+impl HciCapture {
+    pub fn create(path: &PathBuf) -> io::Result<Self> {
+        /* btsnoop for .btsnoop and .log Files, pcap otherwise */
+        let is_btsnoop = path.extension().is_some_and(|extension| extension == "btsnoop" || extension == "log");
+        if !is_btsnoop {
+            return Ok(HciCapture::Pcap(PcapWriter::create(path, LINKTYPE_BLUETOOTH_HCI_H4_WITH_PHDR)?));
+        }
+
+        if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            fs::create_dir_all(directory)?;
+        }
+
+        let mut file = File::create(path)?;
+        file.write_all(BTSNOOP_MAGIC)?;
+        file.write_all(&BTSNOOP_VERSION.to_be_bytes())?;
+        file.write_all(&BTSNOOP_DATALINK_H4.to_be_bytes())?;
+        Ok(HciCapture::Btsnoop(file))
+    }
+
+    pub fn write_packet(&mut self, timestamp: u64, packet_type: HciPacketType, is_received: bool, packet: &[u8]) -> io::Result<()> {
+        let mut h4_packet = Vec::with_capacity(packet.len() + 1);
+        h4_packet.push(packet_type.get_indicator());
+        h4_packet.extend_from_slice(packet);
+
+        match self {
+            /* Pseudo Header: Direction as big endian u32, 1 for received */
+            HciCapture::Pcap(pcap_writer) => {
+                let mut frame = (is_received as u32).to_be_bytes().to_vec();
+                frame.extend_from_slice(&h4_packet);
+                pcap_writer.write_frame(timestamp, &frame)
+            },
+
+            /* Record: Original and Included Length, Flags, Drops, Timestamp */
+            HciCapture::Btsnoop(file) => {
+                let flags = (is_received as u32) | (((packet_type != HciPacketType::Acl) as u32) << 1);
+                let mut record = Vec::with_capacity(24 + h4_packet.len());
+                record.extend_from_slice(&(h4_packet.len() as u32).to_be_bytes());
+                record.extend_from_slice(&(h4_packet.len() as u32).to_be_bytes());
+                record.extend_from_slice(&flags.to_be_bytes());
+                record.extend_from_slice(&0u32.to_be_bytes());
+                record.extend_from_slice(&(timestamp + BTSNOOP_EPOCH_DELTA).to_be_bytes());
+                record.extend_from_slice(&h4_packet);
+                file.write_all(&record)
+            }
+        }
+    }
+}
+
+pub fn get_packet_length(packet_type: HciPacketType, data: &[u8]) -> Option<usize> {
+    /* Header Length plus the Parameter or Data Length it announces */
+    match packet_type {
+        HciPacketType::Command => Some(3 + *data.get(2)? as usize),
+        HciPacketType::Acl => Some(4 + u16::from_le_bytes([*data.get(2)?, *data.get(3)?]) as usize),
+        HciPacketType::Event => Some(2 + *data.get(1)? as usize),
+    }
+}
+
+pub fn format_address(address: &[u8]) -> String {
+    /* BD_ADDR is transmitted least significant Byte first */
+    address.iter().rev().map(|byte| format!("{:02X}", byte)).collect::<Vec<String>>().join(":")
+}
+
+pub fn get_opcode_name(opcode: u16) -> String {
+    let opcode_name = match opcode {
+        0x0401 => "Inquiry",
+        0x0402 => "Inquiry Cancel",
+        0x0405 => "Create Connection",
+        0x0406 => "Disconnect",
+        0x0409 => "Accept Connection Request",
+        0x040A => "Reject Connection Request",
+        0x040B => "Link Key Request Reply",
+        0x040C => "Link Key Request Negative Reply",
+        0x040D => "PIN Code Request Reply",
+        0x0411 => "Authentication Requested",
+        0x0413 => "Set Connection Encryption",
+        0x0419 => "Remote Name Request",
+        0x041B => "Read Remote Supported Features",
+        0x041D => "Read Remote Version Information",
+        0x042B => "IO Capability Request Reply",
+        0x042C => "User Confirmation Request Reply",
+        0x0803 => "Sniff Mode",
+        0x080D => "Write Link Policy Settings",
+        0x080F => "Write Default Link Policy Settings",
+        0x0C01 => "Set Event Mask",
+        0x0C03 => "Reset",
+        0x0C05 => "Set Event Filter",
+        0x0C13 => "Write Local Name",
+        0x0C14 => "Read Local Name",
+        0x0C16 => "Write Connection Accept Timeout",
+        0x0C18 => "Write Page Timeout",
+        0x0C1A => "Write Scan Enable",
+        0x0C1C => "Write Page Scan Activity",
+        0x0C1E => "Write Inquiry Scan Activity",
+        0x0C23 => "Read Class of Device",
+        0x0C24 => "Write Class of Device",
+        0x0C25 => "Read Voice Setting",
+        0x0C26 => "Write Voice Setting",
+        0x0C33 => "Host Buffer Size",
+        0x0C45 => "Write Inquiry Mode",
+        0x0C52 => "Write Extended Inquiry Response",
+        0x0C56 => "Write Simple Pairing Mode",
+        0x0C63 => "Set Event Mask Page 2",
+        0x0C6D => "Write LE Host Supported",
+        0x0C7A => "Write Secure Connections Host Support",
+        0x1001 => "Read Local Version Information",
+        0x1002 => "Read Local Supported Commands",
+        0x1003 => "Read Local Supported Features",
+        0x1004 => "Read Local Extended Features",
+        0x1005 => "Read Buffer Size",
+        0x1009 => "Read BD_ADDR",
+        0x1405 => "Read RSSI",
+        0x2001 => "LE Set Event Mask",
+        0x2002 => "LE Read Buffer Size",
+        0x2003 => "LE Read Local Supported Features",
+        0x2005 => "LE Set Random Address",
+        0x2006 => "LE Set Advertising Parameters",
+        0x2008 => "LE Set Advertising Data",
+        0x2009 => "LE Set Scan Response Data",
+        0x200A => "LE Set Advertising Enable",
+        0x200B => "LE Set Scan Parameters",
+        0x200C => "LE Set Scan Enable",
+        0x200D => "LE Create Connection",
+        0x200E => "LE Create Connection Cancel",
+        0x200F => "LE Read Filter Accept List Size",
+        0x2010 => "LE Clear Filter Accept List",
+        0x2011 => "LE Add Device To Filter Accept List",
+        0x2013 => "LE Connection Update",
+        0x2016 => "LE Read Remote Features",
+        0x2018 => "LE Rand",
+        0x2019 => "LE Enable Encryption",
+        0x201C => "LE Read Supported States",
+        0x2022 => "LE Set Data Length",
+        0x2027 => "LE Add Device To Resolving List",
+        0x2029 => "LE Clear Resolving List",
+        0x202D => "LE Set Address Resolution Enable",
+        0x2031 => "LE Set Default PHY",
+        0x2036 => "LE Set Extended Advertising Parameters",
+        0x2039 => "LE Set Extended Advertising Enable",
+        0x2041 => "LE Set Extended Scan Parameters",
+        0x2042 => "LE Set Extended Scan Enable",
+        0x2043 => "LE Extended Create Connection",
+        _ if opcode >> 10 == 0x3F => return format!("Vendor Command 0x{:03X}", opcode & 0x03FF),
+        _ => return format!("Command OGF 0x{:02X} OCF 0x{:03X}", opcode >> 10, opcode & 0x03FF),
+    };
+
+    String::from(opcode_name)
+}
+
+fn get_event_name(event_code: u8) -> String {
+    let event_name = match event_code {
+        0x01 => "Inquiry Complete",
+        0x02 => "Inquiry Result",
+        0x03 => "Connection Complete",
+        0x04 => "Connection Request",
+        0x05 => "Disconnection Complete",
+        0x06 => "Authentication Complete",
+        0x07 => "Remote Name Request Complete",
+        0x08 => "Encryption Change",
+        0x0B => "Read Remote Supported Features Complete",
+        0x0C => "Read Remote Version Information Complete",
+        EVENT_COMMAND_COMPLETE => "Command Complete",
+        EVENT_COMMAND_STATUS => "Command Status",
+        0x10 => "Hardware Error",
+        0x13 => "Number Of Completed Packets",
+        0x14 => "Mode Change",
+        0x16 => "PIN Code Request",
+        0x17 => "Link Key Request",
+        0x18 => "Link Key Notification",
+        0x22 => "Inquiry Result with RSSI",
+        0x2F => "Extended Inquiry Result",
+        0x30 => "Encryption Key Refresh Complete",
+        0x31 => "IO Capability Request",
+        0x32 => "IO Capability Response",
+        0x33 => "User Confirmation Request",
+        0x36 => "Simple Pairing Complete",
+        EVENT_LE_META => "LE Meta",
+        0xFF => "Vendor",
+        _ => return format!("Event 0x{:02X}", event_code),
+    };
+
+    String::from(event_name)
+}
+
+fn get_subevent_name(subevent_code: u8) -> String {
+    let subevent_name = match subevent_code {
+        0x01 => "LE Connection Complete",
+        0x02 => "LE Advertising Report",
+        0x03 => "LE Connection Update Complete",
+        0x04 => "LE Read Remote Features Complete",
+        0x05 => "LE Long Term Key Request",
+        0x07 => "LE Data Length Change",
+        0x0A => "LE Enhanced Connection Complete",
+        0x0C => "LE PHY Update Complete",
+        0x0D => "LE Extended Advertising Report",
+        _ => return format!("LE Subevent 0x{:02X}", subevent_code),
+    };
+
+    String::from(subevent_name)
+}
+
+pub fn get_status_name(status: u8) -> String {
+    let status_name = match status {
+        0x00 => "Success",
+        0x01 => "Unknown HCI Command",
+        0x02 => "Unknown Connection Identifier",
+        0x03 => "Hardware Failure",
+        0x04 => "Page Timeout",
+        0x05 => "Authentication Failure",
+        0x06 => "PIN or Key Missing",
+        0x07 => "Memory Capacity Exceeded",
+        0x08 => "Connection Timeout",
+        0x09 => "Connection Limit Exceeded",
+        0x0B => "Connection Already Exists",
+        0x0C => "Command Disallowed",
+        0x0D => "Rejected due to Limited Resources",
+        0x0E => "Rejected due to Security Reasons",
+        0x0F => "Rejected due to Unacceptable BD_ADDR",
+        0x10 => "Connection Accept Timeout Exceeded",
+        0x11 => "Unsupported Feature or Parameter Value",
+        0x12 => "Invalid HCI Command Parameters",
+        0x13 => "Remote User Terminated Connection",
+        0x14 => "Remote Device Terminated due to Low Resources",
+        0x15 => "Remote Device Terminated due to Power Off",
+        0x16 => "Connection Terminated by Local Host",
+        0x1A => "Unsupported Remote Feature",
+        0x1F => "Unspecified Error",
+        0x22 => "LMP or LL Response Timeout",
+        0x28 => "Instant Passed",
+        0x3A => "Controller Busy",
+        0x3B => "Unacceptable Connection Parameters",
+        0x3C => "Advertising Timeout",
+        0x3D => "Connection Terminated due to MIC Failure",
+        0x3E => "Connection Failed to be Established",
+        _ => return format!("Error 0x{:02X}", status),
+    };
+
+    String::from(status_name)
+}
+
+fn get_att_opcode_name(opcode: u8) -> String {
+    let opcode_name = match opcode {
+        0x01 => "Error Response",
+        0x02 => "Exchange MTU Request",
+        0x03 => "Exchange MTU Response",
+        0x04 => "Find Information Request",
+        0x05 => "Find Information Response",
+        0x06 => "Find By Type Value Request",
+        0x07 => "Find By Type Value Response",
+        0x08 => "Read By Type Request",
+        0x09 => "Read By Type Response",
+        0x0A => "Read Request",
+        0x0B => "Read Response",
+        0x0C => "Read Blob Request",
+        0x0D => "Read Blob Response",
+        0x10 => "Read By Group Type Request",
+        0x11 => "Read By Group Type Response",
+        0x12 => "Write Request",
+        0x13 => "Write Response",
+        0x1B => "Handle Value Notification",
+        0x1D => "Handle Value Indication",
+        0x1E => "Handle Value Confirmation",
+        0x52 => "Write Command",
+        _ => return format!("Opcode 0x{:02X}", opcode),
+    };
+
+    String::from(opcode_name)
+}
+
+fn get_channel_name(channel_id: u16) -> String {
+    match channel_id {
+        0x0001 => String::from("L2CAP Signaling"),
+        CID_ATT => String::from("ATT"),
+        0x0005 => String::from("LE Signaling"),
+        0x0006 => String::from("SMP"),
+        0x0007 => String::from("BR/EDR SMP"),
+        channel_id => format!("CID 0x{:04X}", channel_id),
+    }
+}
+
+pub fn describe_command(packet: &[u8]) -> String {
+    /* Parameters of Commands worth reading in the Table */
+    let opcode = u16::from_le_bytes([packet[0], packet[1]]);
+    let parameters = &packet[3..];
+    let details = match (opcode, parameters.len()) {
+        (0x0406, 3..) => format!(": Handle 0x{:03X}, {}", u16::from_le_bytes([parameters[0], parameters[1]]) & 0x0FFF, get_status_name(parameters[2])),
+        (0x0405 | 0x0419, 6..) => format!(": {}", format_address(&parameters[0..6])),
+        (0x0C13, 1..) => format!(": \"{}\"", String::from_utf8_lossy(parameters.split(|byte| *byte == 0).next().unwrap_or_default())),
+        (0x0C1A, 1..) => format!(": {}", match parameters[0] {
+            0x00 => "No Scans",
+            0x01 => "Inquiry Scan",
+            0x02 => "Page Scan",
+            _ => "Inquiry and Page Scan",
+        }),
+
+        (0x200A | 0x200C, 1..) => format!(": {}", if parameters[0] != 0 { "Enabled" } else { "Disabled" }),
+        (0x200D, 12..) => format!(": {}", format_address(&parameters[6..12])),
+        (0x2005, 6..) => format!(": {}", format_address(&parameters[0..6])),
+        _ if parameters.is_empty() => String::new(),
+        _ => format!(", {} bytes", parameters.len()),
+    };
+
+    format!("{}{}", get_opcode_name(opcode), details)
+}
+
+pub fn describe_return_parameters(opcode: u16, parameters: &[u8]) -> String {
+    /* Return Parameters start with the Status */
+    let Some(status) = parameters.first() else {
+        return String::new();
+    };
+
+    let details = match (opcode, parameters.len()) {
+        (_, _) if *status != 0 => String::new(),
+        (0x1009, 7..) => format!(", {}", format_address(&parameters[1..7])),
+        (0x1001, 9..) => format!(
+            ", HCI Version {}, Revision 0x{:04X}, Manufacturer {}, Subversion 0x{:04X}",
+            parameters[1],
+            u16::from_le_bytes([parameters[2], parameters[3]]),
+            u16::from_le_bytes([parameters[5], parameters[6]]),
+            u16::from_le_bytes([parameters[7], parameters[8]])
+        ),
+
+        (0x1005, 8..) => format!(", ACL {} bytes x {}", u16::from_le_bytes([parameters[1], parameters[2]]), u16::from_le_bytes([parameters[4], parameters[5]])),
+        (0x2002, 4..) => format!(", LE ACL {} bytes x {}", u16::from_le_bytes([parameters[1], parameters[2]]), parameters[3]),
+        (0x0C14, 2..) => format!(", \"{}\"", String::from_utf8_lossy(parameters[1..].split(|byte| *byte == 0).next().unwrap_or_default())),
+        (0x1405, 4..) => format!(", RSSI {} dB", parameters[3] as i8),
+        _ => String::new(),
+    };
+
+    format!("{}{}", get_status_name(*status), details)
+}
+
+pub fn describe_event(packet: &[u8]) -> (String, bool) {
+    /* Returns the Description and whether the Event reports a Failure */
+    let event_code = packet[0];
+    let parameters = &packet[2..];
+    let status = parameters.first().copied().unwrap_or_default();
+    let (details, is_error) = match (event_code, parameters.len()) {
+        (0x03, 11..) => (format!(
+            ": {}, Handle 0x{:03X}, {}",
+            get_status_name(status),
+            u16::from_le_bytes([parameters[1], parameters[2]]) & 0x0FFF,
+            format_address(&parameters[3..9])
+        ), status != 0),
+
+        (0x04, 6..) => (format!(": {}", format_address(&parameters[0..6])), false),
+        (0x05, 4..) => (format!(
+            ": {}, Handle 0x{:03X}, Reason {}",
+            get_status_name(status),
+            u16::from_le_bytes([parameters[1], parameters[2]]) & 0x0FFF,
+            get_status_name(parameters[3])
+        ), status != 0),
+
+        (0x07, 7..) => (format!(
+            ": {}, {} \"{}\"",
+            get_status_name(status),
+            format_address(&parameters[1..7]),
+            String::from_utf8_lossy(parameters[7..].split(|byte| *byte == 0).next().unwrap_or_default())
+        ), status != 0),
+
+        (0x08, 4..) => (format!(
+            ": {}, Handle 0x{:03X}, Encryption {}",
+            get_status_name(status),
+            u16::from_le_bytes([parameters[1], parameters[2]]) & 0x0FFF,
+            if parameters[3] != 0 { "On" } else { "Off" }
+        ), status != 0),
+
+        (0x01 | 0x06, 1..) => (format!(": {}", get_status_name(status)), status != 0),
+        (0x10, 1..) => (format!(": Code 0x{:02X}", status), true),
+        (0x13, 1..) => {
+            let handles: Vec<String> = parameters[1..]
+                .chunks_exact(4)
+                .take(parameters[0] as usize)
+                .map(|entry| format!("0x{:03X}: {}", u16::from_le_bytes([entry[0], entry[1]]) & 0x0FFF, u16::from_le_bytes([entry[2], entry[3]])))
+                .collect();
+
+            (format!(": {}", handles.join(", ")), false)
+        },
+
+        (EVENT_LE_META, 1..) => return describe_le_event(parameters),
+        _ => (String::new(), false),
+    };
+
+    (format!("{}{}", get_event_name(event_code), details), is_error)
+}
+
+fn describe_le_event(parameters: &[u8]) -> (String, bool) {
+    let subevent_code = parameters[0];
+    let status = parameters.get(1).copied().unwrap_or_default();
+    let (details, is_error) = match (subevent_code, parameters.len()) {
+        (0x01 | 0x0A, 12..) => (format!(
+            ": {}, Handle 0x{:03X}, {}, {}",
+            get_status_name(status),
+            u16::from_le_bytes([parameters[2], parameters[3]]) & 0x0FFF,
+            if parameters[4] == 0 { "Central" } else { "Peripheral" },
+            format_address(&parameters[6..12])
+        ), status != 0),
+
+        /* First Report: Event Type, Address Type, Address, Data Length, Data, RSSI */
+        (0x02, 11..) => {
+            let data_length = parameters[10] as usize;
+            let rssi = parameters.get(11 + data_length).map_or(String::new(), |rssi| format!(", RSSI {} dBm", *rssi as i8));
+            (format!(": {}, {} bytes{}", format_address(&parameters[4..10]), data_length, rssi), false)
+        },
+
+        (0x03, 10..) => (format!(
+            ": {}, Handle 0x{:03X}, Interval {:.2} ms, Latency {}, Timeout {} ms",
+            get_status_name(status),
+            u16::from_le_bytes([parameters[2], parameters[3]]) & 0x0FFF,
+            u16::from_le_bytes([parameters[4], parameters[5]]) as f64 * 1.25,
+            u16::from_le_bytes([parameters[6], parameters[7]]),
+            u16::from_le_bytes([parameters[8], parameters[9]]) as u32 * 10
+        ), status != 0),
+
+        (0x04 | 0x0C, 2..) => (format!(": {}", get_status_name(status)), status != 0),
+        _ => (String::new(), false),
+    };
+
+    (format!("{}{}", get_subevent_name(subevent_code), details), is_error)
+}
+
+pub fn describe_acl(packet: &[u8]) -> String {
+    /* Packet Boundary 0 and 2 start an L2CAP Frame, 1 continues it */
+    let handle_field = u16::from_le_bytes([packet[0], packet[1]]);
+    let data = &packet[4..];
+    let is_start = (handle_field >> 12) & 0x03 != 1;
+    let l2cap = match data.get(0..4) {
+        Some(l2cap_header) if is_start => {
+            let channel_id = u16::from_le_bytes([l2cap_header[2], l2cap_header[3]]);
+            let att_opcode = data.get(4).filter(|_| channel_id == CID_ATT).map_or(String::new(), |opcode| format!(" {}", get_att_opcode_name(*opcode)));
+            format!(", {}{}, L2CAP {} bytes", get_channel_name(channel_id), att_opcode, u16::from_le_bytes([l2cap_header[0], l2cap_header[1]]))
+        },
+
+        _ if is_start => String::new(),
+        _ => String::from(", Continuation"),
+    };
+
+    format!("ACL Handle 0x{:03X}{}, {} bytes", handle_field & 0x0FFF, l2cap, data.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HCI_RESET: [u8; 3] = [0x03, 0x0C, 0x00];
+    const RESET_COMPLETE: [u8; 6] = [0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00];
+
+    fn write_capture(extension: &str) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("urbxtract-hci-{}.{}", std::process::id(), extension));
+        let mut hci_capture = HciCapture::create(&path).unwrap();
+        hci_capture.write_packet(1_000_000, HciPacketType::Command, false, &HCI_RESET).unwrap();
+        hci_capture.write_packet(1_000_250, HciPacketType::Event, true, &RESET_COMPLETE).unwrap();
+        hci_capture.write_packet(1_000_500, HciPacketType::Acl, true, &[0x01, 0x20, 0x00, 0x00]).unwrap();
+        drop(hci_capture);
+
+        let capture = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        capture
+    }
+
+    #[test]
+    fn writes_btsnoop_records() {
+        let capture = write_capture("btsnoop");
+        let (header, records) = capture.split_at(16);
+        assert_eq!(header, b"btsnoop\0\x00\x00\x00\x01\x00\x00\x03\xEA");
+
+        /* Lengths include the H4 Indicator, Flags: Bit 0 Received, Bit 1 Command or Event */
+        let timestamp = |unix_time: u64| (unix_time + 0x00DC_DDB3_0F2F_8000).to_be_bytes();
+        let mut expected = vec![];
+        for (length, flags, unix_time, h4_packet) in [
+            (4u32, 2u32, 1_000_000, [&[0x01], HCI_RESET.as_slice()].concat()),
+            (7, 3, 1_000_250, [&[0x04], RESET_COMPLETE.as_slice()].concat()),
+            (5, 1, 1_000_500, vec![0x02, 0x01, 0x20, 0x00, 0x00]),
+        ] {
+            expected.extend_from_slice(&length.to_be_bytes());
+            expected.extend_from_slice(&length.to_be_bytes());
+            expected.extend_from_slice(&flags.to_be_bytes());
+            expected.extend_from_slice(&[0x00; 4]);
+            expected.extend_from_slice(&timestamp(unix_time));
+            expected.extend_from_slice(&h4_packet);
+        }
+
+        assert_eq!(records, expected.as_slice());
+        assert_eq!(&records[16..24], &[0x00, 0xDC, 0xDD, 0xB3, 0x0F, 0x3E, 0xC2, 0x40]);
+    }
+
+    #[test]
+    fn writes_pcap_direction_header() {
+        let capture = write_capture("pcap");
+        let (header, records) = capture.split_at(24);
+        assert_eq!(&header[0..4], &[0xD4, 0xC3, 0xB2, 0xA1]);
+        assert_eq!(&header[20..24], &201u32.to_le_bytes());
+
+        /* Record Header, then the Direction as big endian u32 and the H4 Packet */
+        assert_eq!(&records[0..16], &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00]);
+        assert_eq!(&records[16..24], &[0x00, 0x00, 0x00, 0x00, 0x01, 0x03, 0x0C, 0x00]);
+        assert_eq!(&records[24..40], &[0x01, 0x00, 0x00, 0x00, 0xFA, 0x00, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x00]);
+        assert_eq!(&records[40..51], &[0x00, 0x00, 0x00, 0x01, 0x04, 0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00]);
+        assert_eq!(&records[67..71], &[0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(records.len(), 76);
+    }
+}
@@ -0,0 +1,463 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use super::device_model::{self, ConfigurationModel, DeviceModel, DeviceRegistry, EndpointModel, InterfaceModel};
+use super::protocol_control;
+
+/*
+    Checks follow USB 2.0 Chapter 9 and USB 3.2 Chapter 9, only what
+    can be proven from the captured Descriptors and Requests is reported
+*/
+const REQUEST_GET_STATUS: u8 = 0x00;
+const REQUEST_GET_CONFIGURATION: u8 = 0x08;
+const CLASS_AUDIO: u8 = 0x01;
+const DESCRIPTOR_SS_ENDPOINT_COMPANION: u8 = 0x30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintSeverity {
+    Error,
+    Warning,
+    Info
+}
+
+#[derive(Debug, Clone)]
+pub struct LintFinding {
+    pub severity: LintSeverity,
+    pub location: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DeviceSpeed {
+    High,
+    Super,
+    Unknown
+}
+
+impl LintSeverity {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            LintSeverity::Error => "ERROR",
+            LintSeverity::Warning => "WARNING",
+            LintSeverity::Info => "INFO",
+        }
+    }
+}
+
+struct DeviceLinter<'a> {
+    device: &'a DeviceModel,
+    usb_version: u16,
+    speed: DeviceSpeed,
+    findings: Vec<LintFinding>,
+}
+
+impl<'a> DeviceLinter<'a> {
+    fn new(device: &'a DeviceModel) -> Self {
+        let usb_version = device.descriptor.as_ref().map(|descriptor| descriptor.usb_version).unwrap_or(0);
+        Self {
+            device,
+            usb_version,
+            speed: Self::infer_speed(device, usb_version),
+            findings: vec![],
+        }
+    }
+
+    fn infer_speed(device: &DeviceModel, usb_version: u16) -> DeviceSpeed {
+        /* Captures do not carry the Bus Speed, EP0 and Bulk sizes are speed specific */
+        let max_packet_size0 = device.descriptor.as_ref().map(|descriptor| descriptor.max_packet_size0).unwrap_or(0);
+        if usb_version >= 0x0300 && max_packet_size0 == 9 {
+            return DeviceSpeed::Super;
+        }
+
+        let has_highspeed_bulk = device.configurations
+            .iter()
+            .flat_map(|configuration| configuration.interfaces.iter())
+            .flat_map(|interface| interface.endpoints.iter())
+            .any(|endpoint| endpoint.attributes & 0x03 == 2 && endpoint.max_packet_size & 0x07FF == 512);
+
+        if has_highspeed_bulk { DeviceSpeed::High } else { DeviceSpeed::Unknown }
+    }
+
+    fn report(&mut self, severity: LintSeverity, location: String, message: String) {
+        self.findings.push(LintFinding { severity, location, message });
+    }
+
+    fn lint_device_descriptor(&mut self) {
+        let Some(descriptor) = &self.device.descriptor else {
+            self.report(LintSeverity::Info, String::from("Device"), String::from("Device Descriptor was not captured, re-plug the Device while capturing"));
+            return;
+        };
+
+        if descriptor.length != 18 {
+            self.report(LintSeverity::Error, String::from("Device"), format!("bLength is {}, Device Descriptors are 18 bytes", descriptor.length));
+        }
+
+        let is_valid_packet_size0 = match descriptor.max_packet_size0 {
+            8 | 16 | 32 | 64 => true,
+            9 => descriptor.usb_version >= 0x0300,
+            _ => false
+        };
+
+        if !is_valid_packet_size0 {
+            self.report(LintSeverity::Error, String::from("Device"), format!("bMaxPacketSize0 {} is invalid for USB {:x}.{:02x}", descriptor.max_packet_size0, descriptor.usb_version >> 8, descriptor.usb_version & 0xFF));
+        }
+
+        if descriptor.num_configurations == 0 {
+            self.report(LintSeverity::Error, String::from("Device"), String::from("bNumConfigurations is 0"));
+        }
+
+        /* Composite Devices using IADs must declare the Multi-Interface Function Class */
+        let has_associations = self.device.configurations.iter().any(|configuration| !configuration.associations.is_empty());
+        if has_associations && (descriptor.class, descriptor.subclass, descriptor.protocol) != (0xEF, 0x02, 0x01) {
+            self.report(LintSeverity::Warning, String::from("Device"), format!(
+                "Interface Associations are used but bDeviceClass/SubClass/Protocol is {:02x}/{:02x}/{:02x} instead of ef/02/01",
+                descriptor.class,
+                descriptor.subclass,
+                descriptor.protocol
+            ));
+        }
+    }
+
+    fn lint_descriptor_walk(&mut self, configuration: &ConfigurationModel, location: &str) -> bool {
+        let raw_data = &configuration.raw_data;
+        let expected_length = (configuration.total_length as usize).min(configuration.requested_length as usize);
+
+        if raw_data.len() > configuration.total_length as usize {
+            self.report(LintSeverity::Error, location.to_string(), format!("Device returned {} bytes, more than wTotalLength {}", raw_data.len(), configuration.total_length));
+        } else if raw_data.len() < expected_length {
+            self.report(LintSeverity::Error, location.to_string(), format!("Device returned {} of {} requested bytes, wTotalLength does not match the Descriptors", raw_data.len(), expected_length));
+        }
+
+        /* The Hierarchy is only meaningful once the complete Configuration was read */
+        if raw_data.len() < configuration.total_length as usize {
+            if configuration.requested_length < configuration.total_length {
+                self.report(LintSeverity::Info, location.to_string(), format!("Only {} of {} bytes were requested by the Host", configuration.requested_length, configuration.total_length));
+            }
+
+            return false;
+        }
+
+        /* Validate bLength of every Descriptor against its Type */
+        let mut offset = 0;
+        let mut interface_class = 0;
+        while offset < raw_data.len() {
+            let descriptor_length = raw_data[offset] as usize;
+            let descriptor_type = *raw_data.get(offset + 1).unwrap_or(&0);
+
+            if descriptor_length < 2 {
+                self.report(LintSeverity::Error, location.to_string(), format!("Descriptor at offset {} has bLength {}, the remaining {} bytes are unreachable", offset, descriptor_length, raw_data.len() - offset));
+                return true;
+            }
+
+            if offset + descriptor_length > raw_data.len() {
+                self.report(LintSeverity::Error, location.to_string(), format!("{} Descriptor at offset {} with bLength {} overruns wTotalLength {}", protocol_control::get_descriptor_type_name(descriptor_type), offset, descriptor_length, configuration.total_length));
+                return true;
+            }
+
+            let expected_lengths: &[usize] = match descriptor_type {
+                device_model::DESCRIPTOR_CONFIGURATION => &[9],
+                device_model::DESCRIPTOR_INTERFACE => &[9],
+                device_model::DESCRIPTOR_ENDPOINT if interface_class == CLASS_AUDIO => &[7, 9],
+                device_model::DESCRIPTOR_ENDPOINT => &[7],
+                device_model::DESCRIPTOR_INTERFACE_ASSOCIATION => &[8],
+                DESCRIPTOR_SS_ENDPOINT_COMPANION => &[6],
+                _ => &[]
+            };
+
+            if !expected_lengths.is_empty() && !expected_lengths.contains(&descriptor_length) {
+                self.report(LintSeverity::Error, location.to_string(), format!("{} Descriptor at offset {} has bLength {}, expected {}", protocol_control::get_descriptor_type_name(descriptor_type), offset, descriptor_length, expected_lengths[0]));
+            }
+
+            if descriptor_type == device_model::DESCRIPTOR_INTERFACE && descriptor_length >= 6 {
+                interface_class = raw_data[offset + 5];
+            }
+
+            offset += descriptor_length;
+        }
+
+        true
+    }
+
+    fn lint_endpoint(&mut self, endpoint: &EndpointModel, location: &str) {
+        let transfer_type = endpoint.attributes & 0x03;
+        let packet_size = endpoint.max_packet_size & 0x07FF;
+        let additional_transactions = (endpoint.max_packet_size >> 11) & 0x03;
+
+        if endpoint.address & 0x0F == 0 {
+            self.report(LintSeverity::Error, location.to_string(), String::from("Endpoint 0 must not have an Endpoint Descriptor"));
+        }
+
+        if endpoint.address & 0x70 != 0 {
+            self.report(LintSeverity::Warning, location.to_string(), format!("bEndpointAddress 0x{:02x} sets reserved bits 6..4", endpoint.address));
+        }
+
+        if additional_transactions == 3 {
+            self.report(LintSeverity::Error, location.to_string(), format!("wMaxPacketSize 0x{:04x} uses the reserved value 3 in bits 12..11", endpoint.max_packet_size));
+        } else if additional_transactions != 0 && (transfer_type == 2 || self.speed == DeviceSpeed::Super) {
+            self.report(LintSeverity::Error, location.to_string(), format!("wMaxPacketSize 0x{:04x} sets additional transactions, only High-Speed Periodic Endpoints may", endpoint.max_packet_size));
+        }
+
+        /* Permitted Sizes per Transfer Type and Speed, USB 2.0 5.5 - 5.8 and USB 3.2 9.6.6 */
+        let size_error = match (transfer_type, self.speed) {
+            (2, DeviceSpeed::Super) if packet_size != 1024 => Some("SuperSpeed Bulk Endpoints must use 1024"),
+            (2, DeviceSpeed::High) if packet_size != 512 => Some("High-Speed Bulk Endpoints must use 512"),
+            (2, DeviceSpeed::Unknown) if ![8, 16, 32, 64].contains(&packet_size) => Some("Full-Speed Bulk Endpoints must use 8, 16, 32 or 64"),
+            (3, _) if packet_size == 0 => Some("Interrupt Endpoints must transfer data"),
+            (3, DeviceSpeed::Unknown) if packet_size > 64 && additional_transactions == 0 && self.usb_version < 0x0200 => Some("Full-Speed Interrupt Endpoints are limited to 64"),
+            (1 | 3, _) if packet_size > 1024 => Some("Periodic Endpoints are limited to 1024"),
+            _ => None
+        };
+
+        if let Some(size_error) = size_error {
+            self.report(LintSeverity::Error, location.to_string(), format!("wMaxPacketSize {} is invalid, {}", packet_size, size_error));
+        }
+
+        let is_interval_valid = match transfer_type {
+            1 => (1..=16).contains(&endpoint.interval),
+            3 if self.speed != DeviceSpeed::Unknown => (1..=16).contains(&endpoint.interval),
+            3 => endpoint.interval != 0,
+            _ => true
+        };
+
+        if !is_interval_valid {
+            self.report(LintSeverity::Error, location.to_string(), format!("bInterval {} is out of range for a {} Endpoint", endpoint.interval, if transfer_type == 1 { "Isochronous" } else { "Interrupt" }));
+        }
+
+        let has_companion = endpoint.extra_descriptors.iter().any(|descriptor| descriptor.get(1) == Some(&DESCRIPTOR_SS_ENDPOINT_COMPANION));
+        if self.speed == DeviceSpeed::Super && !has_companion {
+            self.report(LintSeverity::Error, location.to_string(), String::from("SuperSpeed Endpoints require a SuperSpeed Endpoint Companion Descriptor"));
+        }
+    }
+
+    fn lint_interface(&mut self, interface: &InterfaceModel, location: &str) {
+        if interface.num_endpoints as usize != interface.endpoints.len() {
+            self.report(LintSeverity::Error, location.to_string(), format!("bNumEndpoints is {} but {} Endpoint Descriptors follow", interface.num_endpoints, interface.endpoints.len()));
+        }
+
+        /* Addresses must be unique within an Alternate Setting */
+        let mut endpoint_addresses = HashSet::new();
+        for endpoint in &interface.endpoints {
+            if !endpoint_addresses.insert(endpoint.address) {
+                self.report(LintSeverity::Error, location.to_string(), format!("Endpoint Address 0x{:02x} is declared more than once", endpoint.address));
+            }
+
+            self.lint_endpoint(endpoint, &format!("{}, Endpoint 0x{:02x}", location, endpoint.address));
+        }
+    }
+
+    fn lint_configuration(&mut self, configuration: &ConfigurationModel) {
+        let location = format!("Configuration {}", configuration.value);
+        if configuration.value == 0 {
+            self.report(LintSeverity::Error, location.clone(), String::from("bConfigurationValue 0 is reserved for the Unconfigured state"));
+        }
+
+        if configuration.attributes & 0x80 == 0 {
+            self.report(LintSeverity::Warning, location.clone(), format!("bmAttributes 0x{:02x} does not set the reserved bit 7", configuration.attributes));
+        }
+
+        if !self.lint_descriptor_walk(configuration, &location) {
+            return;
+        }
+
+        /* Interfaces are numbered from zero without gaps and need Alternate Setting 0 */
+        let interface_numbers: HashSet<u8> = configuration.interfaces.iter().map(|interface| interface.number).collect();
+        if interface_numbers.len() != configuration.num_interfaces as usize {
+            self.report(LintSeverity::Error, location.clone(), format!("bNumInterfaces is {} but {} Interfaces are declared", configuration.num_interfaces, interface_numbers.len()));
+        }
+
+        for interface_number in &interface_numbers {
+            if *interface_number as usize >= interface_numbers.len() {
+                self.report(LintSeverity::Warning, location.clone(), format!("Interface {} leaves a gap, Interfaces are numbered from 0", interface_number));
+            }
+
+            let has_default_setting = configuration.interfaces.iter().any(|interface| interface.number == *interface_number && interface.alternate_setting == 0);
+            if !has_default_setting {
+                self.report(LintSeverity::Error, location.clone(), format!("Interface {} has no Alternate Setting 0", interface_number));
+            }
+        }
+
+        /* Default Settings of all Interfaces are active at once */
+        let mut endpoint_owners: HashMap<u8, u8> = HashMap::new();
+        for interface in &configuration.interfaces {
+            let interface_location = format!("{}, Interface {}.{}", location, interface.number, interface.alternate_setting);
+            self.lint_interface(interface, &interface_location);
+
+            if interface.alternate_setting != 0 {
+                continue;
+            }
+
+            for endpoint in &interface.endpoints {
+                if let Some(owner) = endpoint_owners.insert(endpoint.address, interface.number)
+                    && owner != interface.number {
+                    self.report(LintSeverity::Error, location.clone(), format!("Endpoint Address 0x{:02x} is used by Interface {} and Interface {}", endpoint.address, owner, interface.number));
+                }
+            }
+        }
+
+        /* Associations must cover existing, contiguous and unshared Interfaces */
+        let mut associated_interfaces = HashSet::new();
+        for association in &configuration.associations {
+            let association_location = format!("{}, Association at Interface {}", location, association.first_interface);
+            if association.interface_count == 0 {
+                self.report(LintSeverity::Error, association_location.clone(), String::from("bInterfaceCount is 0"));
+            }
+
+            for interface_number in association.first_interface..association.first_interface.saturating_add(association.interface_count) {
+                if !interface_numbers.contains(&interface_number) {
+                    self.report(LintSeverity::Error, association_location.clone(), format!("Interface {} is associated but not declared", interface_number));
+                }
+
+                if !associated_interfaces.insert(interface_number) {
+                    self.report(LintSeverity::Error, association_location.clone(), format!("Interface {} belongs to more than one Association", interface_number));
+                }
+            }
+
+            let first_interface = configuration.interfaces.iter().find(|interface| interface.number == association.first_interface);
+            if let Some(first_interface) = first_interface
+                && association.function_class != first_interface.class {
+                self.report(LintSeverity::Warning, association_location, format!(
+                    "bFunctionClass 0x{:02x} differs from the first Interface Class 0x{:02x}",
+                    association.function_class,
+                    first_interface.class
+                ));
+            }
+        }
+    }
+
+    fn lint_strings(&mut self) {
+        /* Collect every String Index referenced by a Descriptor */
+        let mut string_references: Vec<(u8, String)> = vec![];
+        if let Some(descriptor) = &self.device.descriptor {
+            string_references.push((descriptor.manufacturer_index, String::from("Device iManufacturer")));
+            string_references.push((descriptor.product_index, String::from("Device iProduct")));
+            string_references.push((descriptor.serial_index, String::from("Device iSerialNumber")));
+        }
+
+        for configuration in &self.device.configurations {
+            string_references.push((configuration.string_index, format!("Configuration {} iConfiguration", configuration.value)));
+            for interface in &configuration.interfaces {
+                string_references.push((interface.string_index, format!("Configuration {}, Interface {}.{} iInterface", configuration.value, interface.number, interface.alternate_setting)));
+            }
+
+            for association in &configuration.associations {
+                string_references.push((association.string_index, format!("Configuration {}, Association at Interface {} iFunction", configuration.value, association.first_interface)));
+            }
+        }
+
+        string_references.retain(|(string_index, _)| *string_index != 0);
+        for (string_index, location) in &string_references {
+            let is_failed = self.device.failed_requests.iter().any(|(setup_packet, _)| {
+                setup_packet.request == device_model::REQUEST_GET_DESCRIPTOR && setup_packet.value == ((device_model::DESCRIPTOR_STRING as u16) << 8 | *string_index as u16)
+            });
+
+            if is_failed {
+                self.report(LintSeverity::Error, location.clone(), format!("String Descriptor {} is referenced but the Device rejected the Request", string_index));
+            } else if !self.device.strings.contains_key(string_index) {
+                self.report(LintSeverity::Info, location.clone(), format!("String Descriptor {} is referenced but was not captured", string_index));
+            }
+        }
+
+        if !string_references.is_empty() && !self.device.strings.is_empty() && self.device.languages.is_empty() {
+            self.report(LintSeverity::Info, String::from("Device"), String::from("String Descriptor Zero (LANGID list) was not captured"));
+        }
+    }
+
+    fn lint_failed_requests(&mut self) {
+        for (setup_packet, urb_header) in &self.device.failed_requests {
+            let location = protocol_control::describe_setup(setup_packet, None);
+            let status = protocol_control::describe_status(urb_header);
+            let descriptor_type = (setup_packet.value >> 8) as u8;
+
+            if !urb_header.is_stalled() {
+                self.report(LintSeverity::Warning, location, format!("Request failed with {}", status));
+                continue;
+            }
+
+            /* Devices must accept these Requests in the Address and Configured states */
+            let severity = match setup_packet.request {
+                REQUEST_GET_STATUS | device_model::REQUEST_SET_ADDRESS | REQUEST_GET_CONFIGURATION | device_model::REQUEST_SET_CONFIGURATION => LintSeverity::Error,
+                device_model::REQUEST_GET_DESCRIPTOR => match descriptor_type {
+                    device_model::DESCRIPTOR_DEVICE | device_model::DESCRIPTOR_CONFIGURATION => LintSeverity::Error,
+                    device_model::DESCRIPTOR_BOS if self.usb_version >= 0x0201 => LintSeverity::Error,
+                    device_model::DESCRIPTOR_STRING => continue, /* Reported with the referencing Descriptor */
+                    _ => LintSeverity::Info
+                },
+                device_model::REQUEST_SET_INTERFACE => {
+                    /* Interfaces without Alternate Settings may STALL */
+                    let has_alternate_settings = self.device.configurations
+                        .iter()
+                        .flat_map(|configuration| configuration.interfaces.iter())
+                        .any(|interface| interface.number == setup_packet.index as u8 && interface.alternate_setting != 0);
+
+                    if has_alternate_settings { LintSeverity::Error } else { LintSeverity::Info }
+                },
+                _ => LintSeverity::Info
+            };
+
+            self.report(severity, location, format!("Request answered with {}", status));
+        }
+    }
+
+    fn lint(mut self) -> Vec<LintFinding> {
+        self.lint_device_descriptor();
+        for configuration in &self.device.configurations {
+            self.lint_configuration(configuration);
+        }
+
+        self.lint_strings();
+        self.lint_failed_requests();
+
+        self.findings.sort_by_key(|finding| finding.severity);
+        self.findings
+    }
+}
+
+pub fn lint_device(device: &DeviceModel) -> Vec<LintFinding> {
+    DeviceLinter::new(device).lint()
+}
+
+pub fn format_lint_summary(findings: &[LintFinding]) -> String {
+    let count = |severity: LintSeverity| findings.iter().filter(|finding| finding.severity == severity).count();
+    format!(
+        "{} Errors, {} Warnings, {} Info",
+        count(LintSeverity::Error),
+        count(LintSeverity::Warning),
+        count(LintSeverity::Info)
+    )
+}
+
+pub fn format_lint_report(device_registry: &DeviceRegistry) -> String {
+    let mut report = String::new();
+    for ((bus_id, device_id), device) in device_registry.get_devices() {
+        let findings = lint_device(device);
+        let (vendor_id, product_id) = device.descriptor
+            .as_ref()
+            .map(|descriptor| (descriptor.vendor_id, descriptor.product_id))
+            .unwrap_or((0, 0));
+
+        let _ = writeln!(report, "Bus {:03} Device {:03}: ID {:04x}:{:04x} ({})", bus_id, device_id, vendor_id, product_id, format_lint_summary(&findings));
+        for finding in &findings {
+            let severity_tag = format!("[{}]", finding.severity.get_name());
+            let _ = writeln!(report, "  {:<9} {}: {}", severity_tag, finding.location, finding.message);
+        }
+    }
+
+    report
+}
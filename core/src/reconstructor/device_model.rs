@@ -0,0 +1,468 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use crate::sniffer::{UrbEventType, UrbTransferType, UrbXractHeader, UrbXractPacket};
+
+/*
+    Standard Requests and Descriptors:
+    USB 2.0 Specification, Chapter 9.4 and 9.6
+*/
+pub const REQUEST_SET_ADDRESS: u8 = 0x05;
+pub const REQUEST_GET_DESCRIPTOR: u8 = 0x06;
+pub const REQUEST_SET_CONFIGURATION: u8 = 0x09;
+pub const REQUEST_SET_INTERFACE: u8 = 0x0B;
+pub const DESCRIPTOR_DEVICE: u8 = 0x01;
+pub const DESCRIPTOR_CONFIGURATION: u8 = 0x02;
+pub const DESCRIPTOR_STRING: u8 = 0x03;
+pub const DESCRIPTOR_INTERFACE: u8 = 0x04;
+pub const DESCRIPTOR_ENDPOINT: u8 = 0x05;
+pub const DESCRIPTOR_INTERFACE_ASSOCIATION: u8 = 0x0B;
+pub const DESCRIPTOR_BOS: u8 = 0x0F;
+pub const DESCRIPTOR_HID_REPORT: u8 = 0x22;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SetupPacket {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct EndpointModel {
+    pub length: u8,
+    pub address: u8,
+    pub attributes: u8,
+    pub max_packet_size: u16,
+    pub interval: u8,
+    pub refresh: Option<u8>,       /* Audio Class 1.0 Endpoints only */
+    pub synch_address: Option<u8>, /* Audio Class 1.0 Endpoints only */
+    pub extra_descriptors: Vec<Vec<u8>>, /* Companion and Class-Specific Endpoint Descriptors */
+}
+
+#[derive(Debug, Clone)]
+pub struct InterfaceModel {
+    pub length: u8,
+    pub number: u8,
+    pub alternate_setting: u8,
+    pub num_endpoints: u8,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+    pub string_index: u8,
+    pub endpoints: Vec<EndpointModel>,
+    pub extra_descriptors: Vec<Vec<u8>>, /* Class-Specific Interface Descriptors (HID, CDC...) */
+}
+
+#[derive(Debug, Clone)]
+pub struct InterfaceAssociationModel {
+    pub length: u8,
+    pub first_interface: u8,
+    pub interface_count: u8,
+    pub function_class: u8,
+    pub function_subclass: u8,
+    pub function_protocol: u8,
+    pub string_index: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigurationModel {
+    pub value: u8,
+    pub total_length: u16,
+    pub num_interfaces: u8,
+    pub string_index: u8,
+    pub attributes: u8,
+    pub max_power: u8,
+    pub interfaces: Vec<InterfaceModel>,
+    pub associations: Vec<InterfaceAssociationModel>,
+    pub extra_descriptors: Vec<Vec<u8>>, /* Descriptors before the first Interface */
+    pub raw_data: Vec<u8>,                /* Descriptors as returned by the Device */
+    pub requested_length: u16,            /* wLength of the GET_DESCRIPTOR Request */
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceDescriptor {
+    pub length: u8,
+    pub usb_version: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+    pub max_packet_size0: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub device_version: u16,
+    pub manufacturer_index: u8,
+    pub product_index: u8,
+    pub serial_index: u8,
+    pub num_configurations: u8,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeviceModel {
+    pub descriptor: Option<DeviceDescriptor>,
+    pub configurations: Vec<ConfigurationModel>,
+    pub strings: HashMap<u8, String>, /* String Index, Decoded String */
+    pub languages: Vec<u16>,          /* LANGIDs from String Descriptor Zero */
+    pub bos_descriptor: Option<Vec<u8>>,
+    pub report_descriptors: HashMap<u8, Vec<u8>>, /* Interface Number, HID Report Descriptor */
+    pub failed_requests: Vec<(SetupPacket, UrbXractHeader)>, /* Standard Requests completing with an Error */
+    pub active_configuration: Option<u8>,
+    pub alternate_settings: HashMap<u8, u8>, /* Interface Number, Alternate Setting */
+}
+
+#[derive(Default)]
+pub struct DeviceRegistry {
+    devices: HashMap<(u16, u16), DeviceModel>, /* (Bus, Device), Snooped Model */
+    pending_setups: HashMap<u64, SetupPacket>, /* URB ID, Setup awaiting Completion */
+}
+
+impl SetupPacket {
+    pub fn from_bytes(setup_data: &[u8; 8]) -> Self {
+        Self {
+            request_type: setup_data[0],
+            request: setup_data[1],
+            value: u16::from_le_bytes([setup_data[2], setup_data[3]]),
+            index: u16::from_le_bytes([setup_data[4], setup_data[5]]),
+            length: u16::from_le_bytes([setup_data[6], setup_data[7]]),
+        }
+    }
+
+    pub fn is_standard(&self) -> bool {
+        (self.request_type >> 5) & 0x03 == 0
+    }
+}
+
+pub fn iterate_descriptors(descriptor_data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    /* Each Descriptor starts with bLength and bDescriptorType */
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let descriptor_length = *descriptor_data.get(offset)? as usize;
+        if descriptor_length < 2 || offset + descriptor_length > descriptor_data.len() {
+            return None;
+        }
+
+        let descriptor = &descriptor_data[offset..(offset + descriptor_length)];
+        offset += descriptor_length;
+        Some(descriptor)
+    })
+}
+
+impl DeviceDescriptor {
+    fn from_bytes(descriptor_data: &[u8]) -> Self {
+        /* The first GET_DESCRIPTOR during Enumeration only reads 8 bytes */
+        let read_u8 = |offset: usize| *descriptor_data.get(offset).unwrap_or(&0);
+        let read_u16 = |offset: usize| u16::from_le_bytes([read_u8(offset), read_u8(offset + 1)]);
+
+        Self {
+            length: read_u8(0),
+            usb_version: read_u16(2),
+            class: read_u8(4),
+            subclass: read_u8(5),
+            protocol: read_u8(6),
+            max_packet_size0: read_u8(7),
+            vendor_id: read_u16(8),
+            product_id: read_u16(10),
+            device_version: read_u16(12),
+            manufacturer_index: read_u8(14),
+            product_index: read_u8(15),
+            serial_index: read_u8(16),
+            num_configurations: read_u8(17),
+        }
+    }
+}
+
+impl ConfigurationModel {
+    fn from_bytes(descriptor_data: &[u8], requested_length: u16) -> Option<Self> {
+        if descriptor_data.len() < 9 || descriptor_data[1] != DESCRIPTOR_CONFIGURATION {
+            return None;
+        }
+
+        let mut configuration = Self {
+            value: descriptor_data[5],
+            total_length: u16::from_le_bytes([descriptor_data[2], descriptor_data[3]]),
+            num_interfaces: descriptor_data[4],
+            string_index: descriptor_data[6],
+            attributes: descriptor_data[7],
+            max_power: descriptor_data[8],
+            interfaces: vec![],
+            associations: vec![],
+            extra_descriptors: vec![],
+            raw_data: descriptor_data.to_vec(),
+            requested_length,
+        };
+
+        /* Walk the Descriptors following the Configuration Descriptor */
+        for descriptor in iterate_descriptors(descriptor_data).skip(1) {
+            match descriptor[1] {
+                DESCRIPTOR_INTERFACE if descriptor.len() >= 9 => {
+                    configuration.interfaces.push(InterfaceModel {
+                        length: descriptor[0],
+                        number: descriptor[2],
+                        alternate_setting: descriptor[3],
+                        num_endpoints: descriptor[4],
+                        class: descriptor[5],
+                        subclass: descriptor[6],
+                        protocol: descriptor[7],
+                        string_index: descriptor[8],
+                        endpoints: vec![],
+                        extra_descriptors: vec![],
+                    });
+                },
+
+                DESCRIPTOR_ENDPOINT if descriptor.len() >= 7 => {
+                    if let Some(interface) = configuration.interfaces.last_mut() {
+                        interface.endpoints.push(EndpointModel {
+                            length: descriptor[0],
+                            address: descriptor[2],
+                            attributes: descriptor[3],
+                            max_packet_size: u16::from_le_bytes([descriptor[4], descriptor[5]]),
+                            interval: descriptor[6],
+                            refresh: descriptor.get(7).copied(),
+                            synch_address: descriptor.get(8).copied(),
+                            extra_descriptors: vec![],
+                        });
+                    }
+                },
+
+                DESCRIPTOR_INTERFACE_ASSOCIATION if descriptor.len() >= 8 => {
+                    configuration.associations.push(InterfaceAssociationModel {
+                        length: descriptor[0],
+                        first_interface: descriptor[2],
+                        interface_count: descriptor[3],
+                        function_class: descriptor[4],
+                        function_subclass: descriptor[5],
+                        function_protocol: descriptor[6],
+                        string_index: descriptor[7],
+                    });
+                },
+
+                _ => {
+                    /* Other Descriptors belong to the Interface or Endpoint they follow */
+                    let interface = configuration.interfaces.last_mut();
+                    match interface {
+                        None => configuration.extra_descriptors.push(descriptor.to_vec()),
+                        Some(interface) => match interface.endpoints.last_mut() {
+                            Some(endpoint) => endpoint.extra_descriptors.push(descriptor.to_vec()),
+                            None => interface.extra_descriptors.push(descriptor.to_vec()),
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(configuration)
+    }
+}
+
+impl DeviceModel {
+    pub fn get_device_class(&self) -> u8 {
+        self.descriptor.as_ref().map(|descriptor| descriptor.class).unwrap_or(0)
+    }
+
+    pub fn get_string(&self, string_index: u8) -> &str {
+        match string_index {
+            0 => "",
+            _ => self.strings.get(&string_index).map(|string| string.as_str()).unwrap_or("")
+        }
+    }
+
+    pub fn get_active_configuration(&self) -> Option<&ConfigurationModel> {
+        /* Without a snooped SET_CONFIGURATION, a single Configuration is assumed active */
+        match self.active_configuration {
+            Some(configuration_value) => self.configurations.iter().find(|configuration| configuration.value == configuration_value),
+            None if self.configurations.len() == 1 => self.configurations.first(),
+            None => None
+        }
+    }
+
+    pub fn get_interface_by_number(&self, interface_number: u8) -> Option<&InterfaceModel> {
+        let alternate_setting = *self.alternate_settings.get(&interface_number).unwrap_or(&0);
+        self.get_active_configuration()?
+            .interfaces
+            .iter()
+            .find(|interface| interface.number == interface_number && interface.alternate_setting == alternate_setting)
+    }
+
+    pub fn get_endpoint_interface(&self, endpoint_address: u8) -> Option<&InterfaceModel> {
+        let configuration = self.get_active_configuration()?;
+        let has_endpoint = |interface: &&InterfaceModel| interface.endpoints.iter().any(|endpoint| endpoint.address == endpoint_address);
+        configuration.interfaces
+            .iter()
+            .filter(|interface| interface.alternate_setting == *self.alternate_settings.get(&interface.number).unwrap_or(&0))
+            .find(has_endpoint)
+            /* Capture started after SET_INTERFACE, the first Alternate Setting using the Endpoint */
+            .or_else(|| configuration.interfaces.iter().find(has_endpoint))
+    }
+
+    pub fn get_endpoint(&self, endpoint_address: u8) -> Option<&EndpointModel> {
+        self.get_endpoint_interface(endpoint_address)?
+            .endpoints
+            .iter()
+            .find(|endpoint| endpoint.address == endpoint_address)
+    }
+
+    fn apply_descriptor(&mut self, setup_packet: &SetupPacket, descriptor_data: &[u8]) {
+        /* HID Report Descriptors have no Header, the Request tells them apart */
+        if (setup_packet.value >> 8) as u8 == DESCRIPTOR_HID_REPORT {
+            let interface_number = setup_packet.index as u8;
+            let is_longer = self.report_descriptors.get(&interface_number).is_none_or(|known| known.len() <= descriptor_data.len());
+            if is_longer {
+                self.report_descriptors.insert(interface_number, descriptor_data.to_vec());
+            }
+
+            return;
+        }
+
+        match descriptor_data.get(1) {
+            Some(&DESCRIPTOR_DEVICE) if descriptor_data.len() >= 8 => {
+                /* Keep the complete Descriptor over the initial 8 byte read */
+                let is_complete = self.descriptor.as_ref().is_some_and(|descriptor| descriptor.num_configurations != 0);
+                if descriptor_data.len() >= 18 || !is_complete {
+                    self.descriptor = Some(DeviceDescriptor::from_bytes(descriptor_data));
+                }
+            },
+
+            Some(&DESCRIPTOR_CONFIGURATION) => {
+                let Some(configuration) = ConfigurationModel::from_bytes(descriptor_data, setup_packet.length) else { return };
+                match self.configurations.iter_mut().find(|known| known.value == configuration.value) {
+                    /* Hosts read the 9 byte Header before the full wTotalLength */
+                    Some(known) if known.raw_data.len() <= configuration.raw_data.len() => *known = configuration,
+                    Some(_) => {},
+                    None => self.configurations.push(configuration),
+                }
+            },
+
+            Some(&DESCRIPTOR_STRING) if setup_packet.value & 0xFF == 0 => {
+                self.languages = descriptor_data[2..]
+                    .chunks_exact(2)
+                    .map(|language_id| u16::from_le_bytes([language_id[0], language_id[1]]))
+                    .collect();
+            },
+
+            Some(&DESCRIPTOR_STRING) => {
+                let utf16_data: Vec<u16> = descriptor_data[2..]
+                    .chunks_exact(2)
+                    .map(|code_unit| u16::from_le_bytes([code_unit[0], code_unit[1]]))
+                    .collect();
+
+                self.strings.insert(setup_packet.value as u8, String::from_utf16_lossy(&utf16_data));
+            },
+
+            Some(&DESCRIPTOR_BOS) => {
+                let is_longer = self.bos_descriptor.as_ref().is_none_or(|known| known.len() <= descriptor_data.len());
+                if is_longer {
+                    self.bos_descriptor = Some(descriptor_data.to_vec());
+                }
+            },
+
+            _ => {}
+        }
+    }
+}
+
+pub fn get_device_key(urb_header: &UrbXractHeader) -> String {
+    format!("{}:{}", urb_header.bus_id, urb_header.device_id)
+}
+
+impl DeviceRegistry {
+    pub fn get_devices(&self) -> Vec<((u16, u16), &DeviceModel)> {
+        let mut devices: Vec<((u16, u16), &DeviceModel)> = self.devices
+            .iter()
+            .map(|(device_address, device)| (*device_address, device))
+            .collect();
+
+        devices.sort_by_key(|(device_address, _)| *device_address);
+        devices
+    }
+
+    pub fn get_device(&self, urb_header: &UrbXractHeader) -> Option<&DeviceModel> {
+        self.devices.get(&(urb_header.bus_id, urb_header.device_id))
+    }
+
+    pub fn get_interface(&self, urb_header: &UrbXractHeader) -> Option<&InterfaceModel> {
+        /* Endpoint 0 is shared by the whole Device */
+        if urb_header.endpoint_info & 0x0F == 0 {
+            return None;
+        }
+
+        self.get_device(urb_header)?.get_endpoint_interface(urb_header.endpoint_info)
+    }
+
+    pub fn observe_packet(&mut self, urb_packet: &UrbXractPacket) {
+        let urb_header = &urb_packet.header;
+        if urb_header.transfer_type != UrbTransferType::Control {
+            return;
+        }
+
+        /* Setup Packets arrive on Submission, Data (IN) and Status on Completion */
+        match urb_header.event_type {
+            UrbEventType::Submit => {
+                if let Some(setup_data) = &urb_header.setup_packet {
+                    self.pending_setups.insert(urb_header.urb_id, SetupPacket::from_bytes(setup_data));
+                }
+            },
+
+            UrbEventType::Complete | UrbEventType::Error => {
+                let Some(setup_packet) = self.pending_setups.remove(&urb_header.urb_id) else { return };
+                if !setup_packet.is_standard() {
+                    return;
+                }
+
+                if urb_header.status == 0 {
+                    self.apply_request(urb_header, &setup_packet, urb_packet.data.as_deref());
+                } else {
+                    let device_address = (urb_header.bus_id, urb_header.device_id);
+                    self.devices.entry(device_address).or_default().failed_requests.push((setup_packet, *urb_header));
+                }
+            }
+        }
+    }
+
+    fn apply_request(&mut self, urb_header: &UrbXractHeader, setup_packet: &SetupPacket, data: Option<&[u8]>) {
+        let device_address = (urb_header.bus_id, urb_header.device_id);
+        match setup_packet.request {
+            REQUEST_GET_DESCRIPTOR if setup_packet.request_type & 0x80 != 0 => {
+                if let Some(descriptor_data) = data {
+                    self.devices.entry(device_address).or_default().apply_descriptor(setup_packet, descriptor_data);
+                }
+            },
+
+            REQUEST_SET_ADDRESS => {
+                /* The Device moves from the Default Address to its assigned one */
+                if let Some(device) = self.devices.remove(&device_address) {
+                    self.devices.insert((urb_header.bus_id, setup_packet.value), device);
+                }
+            },
+
+            REQUEST_SET_CONFIGURATION => {
+                let device = self.devices.entry(device_address).or_default();
+                device.active_configuration = Some(setup_packet.value as u8);
+                device.alternate_settings.clear();
+            },
+
+            REQUEST_SET_INTERFACE => {
+                let device = self.devices.entry(device_address).or_default();
+                device.alternate_settings.insert(setup_packet.index as u8, setup_packet.value as u8);
+            },
+
+            _ => {}
+        }
+    }
+}
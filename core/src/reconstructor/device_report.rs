@@ -0,0 +1,759 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt::Write;
+use super::device_lint;
+use super::hid_descriptor;
+use super::audio_descriptor::{self, AudioTopology, AudioVersion, StreamFormat};
+use super::video_descriptor::{self, VideoFrame};
+use super::device_model::{self, ConfigurationModel, DeviceModel, DeviceRegistry, EndpointModel, InterfaceAssociationModel, InterfaceModel};
+
+/*
+    Reports follow the layout of lsusb -v (usbutils) so they can be diffed
+    against a live system, values are right aligned at column 25
+*/
+const DESCRIPTOR_HID: u8 = 0x21;
+const DESCRIPTOR_CS_INTERFACE: u8 = 0x24;
+const DESCRIPTOR_CS_ENDPOINT: u8 = 0x25;
+const DESCRIPTOR_CCID_FUNCTIONAL: u8 = 0x21;
+const DESCRIPTOR_SS_ENDPOINT_COMPANION: u8 = 0x30;
+const DESCRIPTOR_DEVICE_CAPABILITY: u8 = 0x10;
+const CLASS_CDC: u8 = 0x02;
+const CLASS_HID: u8 = 0x03;
+const CLASS_SMART_CARD: u8 = 0x0B;
+const SUBCLASS_AUDIO_STREAMING: u8 = 0x02;
+const SUBCLASS_VIDEO_STREAMING: u8 = 0x02;
+
+/* Device Tree line: Depth, Text */
+pub type DeviceTreeLine = (usize, String);
+
+pub fn get_class_name(class: u8) -> &'static str {
+    match class {
+        0x00 => "(Defined at Interface level)",
+        0x01 => "Audio",
+        0x02 => "Communications",
+        0x03 => "Human Interface Device",
+        0x05 => "Physical Interface Device",
+        0x06 => "Imaging",
+        0x07 => "Printer",
+        0x08 => "Mass Storage",
+        0x09 => "Hub",
+        0x0A => "CDC Data",
+        0x0B => "Chip/SmartCard",
+        0x0D => "Content Security",
+        0x0E => "Video",
+        0x0F => "Personal Healthcare",
+        0x10 => "Audio/Video",
+        0x11 => "Billboard",
+        0xDC => "Diagnostic",
+        0xE0 => "Wireless",
+        0xEF => "Miscellaneous Device",
+        0xFE => "Application Specific Interface",
+        0xFF => "Vendor Specific Class",
+        _ => "[unknown]"
+    }
+}
+
+fn get_subclass_name(class: u8, subclass: u8) -> &'static str {
+    match (class, subclass) {
+        (0x01, 0x01) => "Control Device",
+        (0x01, 0x02) => "Streaming",
+        (0x01, 0x03) => "MIDI Streaming",
+        (0x02, 0x02) => "Abstract (modem)",
+        (0x02, 0x06) => "Ethernet Networking",
+        (0x02, 0x0D) => "Network Control Model",
+        (0x02, 0x0E) => "Mobile Broadband Interface Model",
+        (0x03, 0x00) => "No Subclass",
+        (0x03, 0x01) => "Boot Interface Subclass",
+        (0x08, 0x01) => "RBC (typically Flash)",
+        (0x08, 0x02) => "SFF-8020i, MMC-2 (ATAPI)",
+        (0x08, 0x06) => "SCSI",
+        (0x0E, 0x01) => "Video Control",
+        (0x0E, 0x02) => "Video Streaming",
+        (0xE0, 0x01) => "Radio Frequency",
+        (0xEF, 0x02) => "?",
+        (0xFE, 0x01) => "Device Firmware Update",
+        _ => ""
+    }
+}
+
+fn get_protocol_name(class: u8, subclass: u8, protocol: u8) -> &'static str {
+    match (class, subclass, protocol) {
+        (0x02, 0x02, 0x01) => "AT-commands (v.25ter)",
+        (0x03, 0x01, 0x01) => "Keyboard",
+        (0x03, 0x01, 0x02) => "Mouse",
+        (0x08, _, 0x50) => "Bulk-Only",
+        (0x08, _, 0x62) => "UAS",
+        (0x09, _, 0x00) => "Full speed (or root) hub",
+        (0x09, _, 0x01) => "Single TT",
+        (0x09, _, 0x02) => "TT per port",
+        (0xE0, 0x01, 0x01) => "Bluetooth",
+        (0xEF, 0x02, 0x01) => "Interface Association",
+        _ => ""
+    }
+}
+
+fn get_transfer_type_name(attributes: u8) -> &'static str {
+    match attributes & 0x03 {
+        0 => "Control",
+        1 => "Isochronous",
+        2 => "Bulk",
+        _ => "Interrupt"
+    }
+}
+
+fn format_bcd(bcd_value: u16) -> String {
+    format!("{:2x}.{:02x}", bcd_value >> 8, bcd_value & 0xFF)
+}
+
+fn format_endpoint_address(endpoint_address: u8) -> String {
+    format!(
+        "EP {} {}",
+        endpoint_address & 0x0F,
+        if endpoint_address & 0x80 != 0 { "IN" } else { "OUT" }
+    )
+}
+
+fn format_max_packet_size(max_packet_size: u16) -> String {
+    /* Bits 12..11 hold the additional Transactions per Microframe */
+    format!("{}x {} bytes", ((max_packet_size >> 11) & 0x03) + 1, max_packet_size & 0x07FF)
+}
+
+fn format_unrecognized(report: &mut String, indent: usize, descriptor: &[u8]) {
+    let hex_bytes: String = descriptor.iter().map(|byte| format!(" {:02x}", byte)).collect();
+    let _ = writeln!(report, "{:indent$}** UNRECOGNIZED: {}", "", hex_bytes);
+}
+
+fn format_hid_descriptor(report: &mut String, indent: usize, descriptor: &[u8], report_descriptor: Option<&Vec<u8>>) {
+    if descriptor.len() < 9 {
+        return format_unrecognized(report, indent, descriptor);
+    }
+
+    let _ = writeln!(report, "{:indent$}HID Device Descriptor:", "");
+    let field_indent = indent + 2;
+    let _ = writeln!(report, "{:field_indent$}bLength             {:5}", "", descriptor[0]);
+    let _ = writeln!(report, "{:field_indent$}bDescriptorType     {:5}", "", descriptor[1]);
+    let _ = writeln!(report, "{:field_indent$}bcdHID              {}", "", format_bcd(u16::from_le_bytes([descriptor[2], descriptor[3]])));
+    let _ = writeln!(report, "{:field_indent$}bCountryCode        {:5} {}", "", descriptor[4], if descriptor[4] == 0 { "Not supported" } else { "" });
+    let _ = writeln!(report, "{:field_indent$}bNumDescriptors     {:5}", "", descriptor[5]);
+
+    /* Each Class Descriptor is a Type and a Length */
+    for class_descriptor in descriptor[6..].chunks_exact(3).take(descriptor[5] as usize) {
+        let descriptor_name = if class_descriptor[0] == 0x22 { "Report" } else { "" };
+        let _ = writeln!(report, "{:field_indent$}bDescriptorType     {:5} {}", "", class_descriptor[0], descriptor_name);
+        let _ = writeln!(report, "{:field_indent$}wDescriptorLength   {:5}", "", u16::from_le_bytes([class_descriptor[1], class_descriptor[2]]));
+    }
+
+    let _ = writeln!(report, "{:field_indent$}Report Descriptors: ", "");
+    let Some(report_descriptor) = report_descriptor else {
+        let _ = writeln!(report, "{:field_indent$}  ** UNAVAILABLE **", "");
+        return;
+    };
+
+    let _ = writeln!(report, "{:field_indent$}  Report Descriptor: (length is {})", "", report_descriptor.len());
+    for (item_line, explanation) in hid_descriptor::format_report_items(report_descriptor) {
+        let _ = writeln!(report, "{:field_indent$}    {}", "", item_line);
+        if let Some(explanation) = explanation {
+            let _ = writeln!(report, "{:field_indent$}                    {}", "", explanation);
+        }
+    }
+}
+
+fn format_cdc_descriptor(report: &mut String, indent: usize, descriptor: &[u8], device: &DeviceModel) {
+    /* Functional Descriptors, CDC 1.2 Table 13 */
+    let field_indent = indent + 2;
+    match (descriptor.get(2), descriptor.len()) {
+        (Some(0x00), 5..) => {
+            let _ = writeln!(report, "{:indent$}CDC Header:", "");
+            let _ = writeln!(report, "{:field_indent$}bcdCDC              {}", "", format_bcd(u16::from_le_bytes([descriptor[3], descriptor[4]])));
+        },
+
+        (Some(0x01), 5..) => {
+            let _ = writeln!(report, "{:indent$}CDC Call Management:", "");
+            let _ = writeln!(report, "{:field_indent$}bmCapabilities       0x{:02x}", "", descriptor[3]);
+            let _ = writeln!(report, "{:field_indent$}bDataInterface      {:5}", "", descriptor[4]);
+        },
+
+        (Some(0x02), 4..) => {
+            let _ = writeln!(report, "{:indent$}CDC ACM:", "");
+            let _ = writeln!(report, "{:field_indent$}bmCapabilities       0x{:02x}", "", descriptor[3]);
+            let capability_indent = field_indent + 2;
+            for (capability_bit, capability_name) in [(0x04, "sends break"), (0x02, "line coding and serial state"), (0x01, "get/set/clear comm features")] {
+                if descriptor[3] & capability_bit != 0 {
+                    let _ = writeln!(report, "{:capability_indent$}{}", "", capability_name);
+                }
+            }
+        },
+
+        (Some(0x06), 5..) => {
+            let _ = writeln!(report, "{:indent$}CDC Union:", "");
+            let _ = writeln!(report, "{:field_indent$}bMasterInterface    {:5}", "", descriptor[3]);
+            let subordinates: Vec<String> = descriptor[4..].iter().map(|interface| interface.to_string()).collect();
+            let _ = writeln!(report, "{:field_indent$}bSlaveInterface     {:>5} ", "", subordinates.join(" "));
+        },
+
+        (Some(0x0F), 13..) => {
+            let _ = writeln!(report, "{:indent$}CDC Ethernet:", "");
+            let _ = writeln!(report, "{:field_indent$}iMacAddress                 {:5} {}", "", descriptor[3], device.get_string(descriptor[3]));
+            let _ = writeln!(report, "{:field_indent$}bmEthernetStatistics    0x{:08x}", "", u32::from_le_bytes([descriptor[4], descriptor[5], descriptor[6], descriptor[7]]));
+            let _ = writeln!(report, "{:field_indent$}wMaxSegmentSize             {:5}", "", u16::from_le_bytes([descriptor[8], descriptor[9]]));
+            let _ = writeln!(report, "{:field_indent$}wNumberMCFilters            0x{:04x}", "", u16::from_le_bytes([descriptor[10], descriptor[11]]));
+            let _ = writeln!(report, "{:field_indent$}bNumberPowerFilters         {:5}", "", descriptor[12]);
+        },
+
+        _ => format_unrecognized(report, indent, descriptor)
+    }
+}
+
+fn format_audio_descriptor(report: &mut String, indent: usize, descriptor: &[u8], interface: &InterfaceModel) {
+    /* Class Specific Descriptors, UAC 1.0 Section 4 and UAC 2.0 Section 4 */
+    let version = AudioVersion::from_protocol(interface.protocol);
+    let field_indent = indent + 2;
+    match (descriptor[1], interface.subclass, descriptor.get(2), descriptor.len()) {
+        (DESCRIPTOR_CS_INTERFACE, audio_descriptor::SUBCLASS_AUDIO_CONTROL, Some(0x01), 5..) => {
+            let _ = writeln!(report, "{:indent$}Audio Control Header:", "");
+            let _ = writeln!(report, "{:field_indent$}bcdADC              {}", "", format_bcd(u16::from_le_bytes([descriptor[3], descriptor[4]])));
+        },
+
+        (DESCRIPTOR_CS_INTERFACE, audio_descriptor::SUBCLASS_AUDIO_CONTROL, Some(&subtype), 4..) => {
+            let audio_topology = AudioTopology::parse(interface);
+            let _ = writeln!(report, "{:indent$}Audio {}:", "", audio_descriptor::get_entity_name(version, subtype));
+            let _ = writeln!(report, "{:field_indent$}bEntityID           {:5}", "", descriptor[3]);
+            if matches!(subtype, audio_descriptor::SUBTYPE_INPUT_TERMINAL | audio_descriptor::SUBTYPE_OUTPUT_TERMINAL) && descriptor.len() >= 6 {
+                let _ = writeln!(report, "{:field_indent$}wTerminalType      0x{:04x}", "", u16::from_le_bytes([descriptor[4], descriptor[5]]));
+            }
+            if let Some(clock_id) = audio_topology.terminal_clocks.get(&descriptor[3]) {
+                let _ = writeln!(report, "{:field_indent$}bCSourceID          {:5}", "", clock_id);
+            }
+        },
+
+        (DESCRIPTOR_CS_INTERFACE, SUBCLASS_AUDIO_STREAMING, Some(0x01), 4..) => {
+            let _ = writeln!(report, "{:indent$}Audio Streaming General:", "");
+            let _ = writeln!(report, "{:field_indent$}bTerminalLink       {:5}", "", descriptor[3]);
+            if let Some(stream_format) = StreamFormat::parse(interface) {
+                let _ = writeln!(report, "{:field_indent$}Format              {}", "", stream_format.encoding.get_name());
+            }
+        },
+
+        (DESCRIPTOR_CS_INTERFACE, SUBCLASS_AUDIO_STREAMING, Some(0x02), _) if let Some(stream_format) = StreamFormat::parse(interface) => {
+            let _ = writeln!(report, "{:indent$}Audio Format Type I:", "");
+            let _ = writeln!(report, "{:field_indent$}bNrChannels         {:5}", "", stream_format.channels);
+            let _ = writeln!(report, "{:field_indent$}bSubslotSize        {:5}", "", stream_format.subslot_size);
+            let _ = writeln!(report, "{:field_indent$}bBitResolution      {:5}", "", stream_format.bit_resolution);
+            let sample_rates: Vec<String> = stream_format.sample_rates.iter().map(|sample_rate| format!("{} Hz", sample_rate)).collect();
+            match (stream_format.version, sample_rates.is_empty()) {
+                (AudioVersion::Uac2, _) => {
+                    let _ = writeln!(report, "{:field_indent$}Sample Rates        From Clock Source", "");
+                },
+                (AudioVersion::Uac1, true) => {
+                    let _ = writeln!(report, "{:field_indent$}Sample Rates        Continuous", "");
+                },
+                (AudioVersion::Uac1, false) => {
+                    let _ = writeln!(report, "{:field_indent$}Sample Rates        {}", "", sample_rates.join(", "));
+                }
+            }
+        },
+
+        (DESCRIPTOR_CS_ENDPOINT, SUBCLASS_AUDIO_STREAMING, Some(0x01), 4..) => {
+            let _ = writeln!(report, "{:indent$}Audio Endpoint General:", "");
+            let _ = writeln!(report, "{:field_indent$}bmAttributes         0x{:02x}", "", descriptor[3]);
+        },
+
+        _ => format_unrecognized(report, indent, descriptor)
+    }
+}
+
+fn format_video_descriptor(report: &mut String, indent: usize, descriptor: &[u8], interface: &InterfaceModel) {
+    /* Class Specific Descriptors, UVC 1.5 Section 3.7 and 3.9 */
+    let field_indent = indent + 2;
+    match (descriptor[1], interface.subclass, descriptor.get(2), descriptor.len()) {
+        (DESCRIPTOR_CS_INTERFACE, video_descriptor::SUBCLASS_VIDEO_CONTROL, Some(0x01), 11..) => {
+            let _ = writeln!(report, "{:indent$}Video Control Header:", "");
+            let _ = writeln!(report, "{:field_indent$}bcdUVC              {}", "", format_bcd(u16::from_le_bytes([descriptor[3], descriptor[4]])));
+            let _ = writeln!(report, "{:field_indent$}dwClockFrequency    {:5}", "", u32::from_le_bytes([descriptor[7], descriptor[8], descriptor[9], descriptor[10]]));
+        },
+
+        (DESCRIPTOR_CS_INTERFACE, video_descriptor::SUBCLASS_VIDEO_CONTROL, Some(&subtype), 4..) => {
+            let _ = writeln!(report, "{:indent$}Video {}:", "", video_descriptor::get_entity_name(subtype));
+            let _ = writeln!(report, "{:field_indent$}bEntityID           {:5}", "", descriptor[3]);
+            if matches!(subtype, video_descriptor::SUBTYPE_INPUT_TERMINAL | 0x03) && descriptor.len() >= 6 {
+                let _ = writeln!(report, "{:field_indent$}wTerminalType      0x{:04x}", "", u16::from_le_bytes([descriptor[4], descriptor[5]]));
+            }
+        },
+
+        (DESCRIPTOR_CS_INTERFACE, SUBCLASS_VIDEO_STREAMING, Some(0x01), 7..) => {
+            let _ = writeln!(report, "{:indent$}Video Streaming Input Header:", "");
+            let _ = writeln!(report, "{:field_indent$}bNumFormats         {:5}", "", descriptor[3]);
+            let _ = writeln!(report, "{:field_indent$}bEndpointAddress     0x{:02x}  {}", "", descriptor[6], format_endpoint_address(descriptor[6]));
+        },
+
+        (DESCRIPTOR_CS_INTERFACE, SUBCLASS_VIDEO_STREAMING, Some(&subtype), 4..) if let Some(video_frame) = VideoFrame::parse(descriptor) => {
+            let _ = writeln!(report, "{:indent$}Video Frame (Subtype 0x{:02x}):", "", subtype);
+            let _ = writeln!(report, "{:field_indent$}bFrameIndex         {:5}", "", video_frame.index);
+            let _ = writeln!(report, "{:field_indent$}wWidth              {:5}", "", video_frame.width);
+            let _ = writeln!(report, "{:field_indent$}wHeight             {:5}", "", video_frame.height);
+            let _ = writeln!(report, "{:field_indent$}dwDefaultFrameInterval {:>9} {}", "", video_frame.default_interval, video_descriptor::format_frame_rate(video_frame.default_interval));
+        },
+
+        (DESCRIPTOR_CS_INTERFACE, SUBCLASS_VIDEO_STREAMING, Some(0x04 | 0x06 | 0x10), 5..) if let Some(video_format) = video_descriptor::parse_formats(interface).into_iter().find(|video_format| video_format.index == descriptor[3]) => {
+            let _ = writeln!(report, "{:indent$}Video Format {}:", "", video_format.fourcc);
+            let _ = writeln!(report, "{:field_indent$}bFormatIndex        {:5}", "", video_format.index);
+            let _ = writeln!(report, "{:field_indent$}bNumFrameDescriptors {:4}", "", descriptor[4]);
+            if video_format.bits_per_pixel != 0 {
+                let _ = writeln!(report, "{:field_indent$}bBitsPerPixel       {:5}", "", video_format.bits_per_pixel);
+            }
+        },
+
+        (DESCRIPTOR_CS_ENDPOINT, video_descriptor::SUBCLASS_VIDEO_CONTROL, Some(0x03), 5..) => {
+            let _ = writeln!(report, "{:indent$}Video Control Interrupt Endpoint:", "");
+            let _ = writeln!(report, "{:field_indent$}wMaxTransferSize    {:5}", "", u16::from_le_bytes([descriptor[3], descriptor[4]]));
+        },
+
+        _ => format_unrecognized(report, indent, descriptor)
+    }
+}
+
+fn format_ccid_descriptor(report: &mut String, indent: usize, descriptor: &[u8]) {
+    /* Smart Card Device Class Descriptor, CCID 1.1 Table 5.1-1 */
+    if descriptor.len() < 54 {
+        return format_unrecognized(report, indent, descriptor);
+    }
+
+    let read_u32 = |offset: usize| u32::from_le_bytes([descriptor[offset], descriptor[offset + 1], descriptor[offset + 2], descriptor[offset + 3]]);
+    let _ = writeln!(report, "{:indent$}ChipCard Interface Descriptor:", "");
+    let field_indent = indent + 2;
+    let _ = writeln!(report, "{:field_indent$}bLength             {:5}", "", descriptor[0]);
+    let _ = writeln!(report, "{:field_indent$}bDescriptorType     {:5}", "", descriptor[1]);
+    let _ = writeln!(report, "{:field_indent$}bcdCCID             {}", "", format_bcd(u16::from_le_bytes([descriptor[2], descriptor[3]])));
+    let _ = writeln!(report, "{:field_indent$}nMaxSlotIndex       {:5}", "", descriptor[4]);
+    let _ = writeln!(report, "{:field_indent$}bVoltageSupport     {:5}", "", descriptor[5]);
+    let voltage_indent = field_indent + 2;
+    for (voltage_bit, voltage_name) in [(0x01, "5.0V"), (0x02, "3.0V"), (0x04, "1.8V")] {
+        if descriptor[5] & voltage_bit != 0 {
+            let _ = writeln!(report, "{:voltage_indent$}{}", "", voltage_name);
+        }
+    }
+
+    let protocols = read_u32(6);
+    let protocol_names: Vec<&str> = [(0x01, "T=0"), (0x02, "T=1")]
+        .into_iter()
+        .filter(|(protocol_bit, _)| protocols & protocol_bit != 0)
+        .map(|(_, protocol_name)| protocol_name)
+        .collect();
+    let _ = writeln!(report, "{:field_indent$}dwProtocols         {:5} {}", "", protocols, protocol_names.join(" "));
+    let _ = writeln!(report, "{:field_indent$}dwDefaultClock      {:5}", "", read_u32(10));
+    let _ = writeln!(report, "{:field_indent$}dwMaximumClock      {:5}", "", read_u32(14));
+    let _ = writeln!(report, "{:field_indent$}bNumClockSupported  {:5}", "", descriptor[18]);
+    let _ = writeln!(report, "{:field_indent$}dwDataRate        {:7} bps", "", read_u32(19));
+    let _ = writeln!(report, "{:field_indent$}dwMaxDataRate     {:7} bps", "", read_u32(23));
+    let _ = writeln!(report, "{:field_indent$}bNumDataRatesSupp.  {:5}", "", descriptor[27]);
+    let _ = writeln!(report, "{:field_indent$}dwMaxIFSD           {:5}", "", read_u32(28));
+    let _ = writeln!(report, "{:field_indent$}dwSyncProtocols  0x{:08x}", "", read_u32(32));
+    let _ = writeln!(report, "{:field_indent$}dwMechanical     0x{:08x}", "", read_u32(36));
+    let _ = writeln!(report, "{:field_indent$}dwFeatures       0x{:08x}", "", read_u32(40));
+    let _ = writeln!(report, "{:field_indent$}dwMaxCCIDMsgLen     {:5}", "", read_u32(44));
+    let _ = writeln!(report, "{:field_indent$}bClassGetResponse    0x{:02x}", "", descriptor[48]);
+    let _ = writeln!(report, "{:field_indent$}bClassEnvelope       0x{:02x}", "", descriptor[49]);
+    let _ = writeln!(report, "{:field_indent$}wLcdLayout         0x{:04x}", "", u16::from_le_bytes([descriptor[50], descriptor[51]]));
+    let _ = writeln!(report, "{:field_indent$}bPINSupport         {:5}", "", descriptor[52]);
+    let _ = writeln!(report, "{:field_indent$}bMaxCCIDBusySlots   {:5}", "", descriptor[53]);
+}
+
+fn format_extra_descriptor(report: &mut String, indent: usize, descriptor: &[u8], interface: &InterfaceModel, endpoint: Option<&EndpointModel>, device: &DeviceModel) {
+    match descriptor[1] {
+        DESCRIPTOR_HID if interface.class == CLASS_HID => format_hid_descriptor(report, indent + 2, descriptor, device.report_descriptors.get(&interface.number)),
+        DESCRIPTOR_CS_INTERFACE if interface.class == CLASS_CDC => format_cdc_descriptor(report, indent, descriptor, device),
+        DESCRIPTOR_CS_INTERFACE | DESCRIPTOR_CS_ENDPOINT if interface.class == audio_descriptor::CLASS_AUDIO => format_audio_descriptor(report, indent, descriptor, interface),
+        DESCRIPTOR_CS_INTERFACE | DESCRIPTOR_CS_ENDPOINT if interface.class == video_descriptor::CLASS_VIDEO => format_video_descriptor(report, indent, descriptor, interface),
+        DESCRIPTOR_CCID_FUNCTIONAL if interface.class == CLASS_SMART_CARD => format_ccid_descriptor(report, indent, descriptor),
+        DESCRIPTOR_SS_ENDPOINT_COMPANION if descriptor.len() >= 4 => {
+            /* bmAttributes holds MaxStreams for Bulk and Mult for Isochronous Endpoints */
+            let _ = writeln!(report, "{:indent$}bMaxBurst           {:5}", "", descriptor[2]);
+            match endpoint.map(|endpoint| endpoint.attributes & 0x03) {
+                Some(0x02) if descriptor[3] & 0x1F != 0 => {
+                    let _ = writeln!(report, "{:indent$}MaxStreams          {:5}", "", 1u32 << (descriptor[3] & 0x1F));
+                },
+                Some(0x01) if descriptor[3] & 0x03 != 0 => {
+                    let _ = writeln!(report, "{:indent$}Mult                {:5}", "", descriptor[3] & 0x03);
+                },
+                _ => {}
+            }
+        },
+
+        _ => format_unrecognized(report, indent, descriptor)
+    }
+}
+
+fn format_endpoint(report: &mut String, endpoint: &EndpointModel, interface: &InterfaceModel, device: &DeviceModel) {
+    let _ = writeln!(report, "      Endpoint Descriptor:");
+    let _ = writeln!(report, "        bLength             {:5}", endpoint.length);
+    let _ = writeln!(report, "        bDescriptorType     {:5}", device_model::DESCRIPTOR_ENDPOINT);
+    let _ = writeln!(report, "        bEndpointAddress     0x{:02x}  {}", endpoint.address, format_endpoint_address(endpoint.address));
+    let _ = writeln!(report, "        bmAttributes        {:5}", endpoint.attributes);
+    let _ = writeln!(report, "          Transfer Type            {}", get_transfer_type_name(endpoint.attributes));
+    let _ = writeln!(report, "          Synch Type               {}", ["None", "Asynchronous", "Adaptive", "Synchronous"][((endpoint.attributes >> 2) & 0x03) as usize]);
+    let _ = writeln!(report, "          Usage Type               {}", ["Data", "Feedback", "Implicit feedback Data", "Reserved"][((endpoint.attributes >> 4) & 0x03) as usize]);
+    let _ = writeln!(report, "        wMaxPacketSize     0x{:04x}  {}", endpoint.max_packet_size, format_max_packet_size(endpoint.max_packet_size));
+    let _ = writeln!(report, "        bInterval           {:5}", endpoint.interval);
+    if let Some((refresh, synch_address)) = endpoint.refresh.zip(endpoint.synch_address) {
+        let _ = writeln!(report, "        bRefresh            {:5}", refresh);
+        let _ = writeln!(report, "        bSynchAddress       {:5}", synch_address);
+    }
+
+    for descriptor in &endpoint.extra_descriptors {
+        format_extra_descriptor(report, 8, descriptor, interface, Some(endpoint), device);
+    }
+}
+
+fn format_association(report: &mut String, association: &InterfaceAssociationModel, device: &DeviceModel) {
+    let _ = writeln!(report, "    Interface Association:");
+    let _ = writeln!(report, "      bLength             {:5}", association.length);
+    let _ = writeln!(report, "      bDescriptorType     {:5}", device_model::DESCRIPTOR_INTERFACE_ASSOCIATION);
+    let _ = writeln!(report, "      bFirstInterface     {:5}", association.first_interface);
+    let _ = writeln!(report, "      bInterfaceCount     {:5}", association.interface_count);
+    let _ = writeln!(report, "      bFunctionClass      {:5} {}", association.function_class, get_class_name(association.function_class));
+    let _ = writeln!(report, "      bFunctionSubClass   {:5} {}", association.function_subclass, get_subclass_name(association.function_class, association.function_subclass));
+    let _ = writeln!(report, "      bFunctionProtocol   {:5} {}", association.function_protocol, get_protocol_name(association.function_class, association.function_subclass, association.function_protocol));
+    let _ = writeln!(report, "      iFunction           {:5} {}", association.string_index, device.get_string(association.string_index));
+}
+
+fn format_interface(report: &mut String, interface: &InterfaceModel, device: &DeviceModel) {
+    let _ = writeln!(report, "    Interface Descriptor:");
+    let _ = writeln!(report, "      bLength             {:5}", interface.length);
+    let _ = writeln!(report, "      bDescriptorType     {:5}", device_model::DESCRIPTOR_INTERFACE);
+    let _ = writeln!(report, "      bInterfaceNumber    {:5}", interface.number);
+    let _ = writeln!(report, "      bAlternateSetting   {:5}", interface.alternate_setting);
+    let _ = writeln!(report, "      bNumEndpoints       {:5}", interface.num_endpoints);
+    let _ = writeln!(report, "      bInterfaceClass     {:5} {}", interface.class, get_class_name(interface.class));
+    let _ = writeln!(report, "      bInterfaceSubClass  {:5} {}", interface.subclass, get_subclass_name(interface.class, interface.subclass));
+    let _ = writeln!(report, "      bInterfaceProtocol  {:5} {}", interface.protocol, get_protocol_name(interface.class, interface.subclass, interface.protocol));
+    let _ = writeln!(report, "      iInterface          {:5} {}", interface.string_index, device.get_string(interface.string_index));
+
+    for descriptor in &interface.extra_descriptors {
+        format_extra_descriptor(report, 6, descriptor, interface, None, device);
+    }
+
+    for endpoint in &interface.endpoints {
+        format_endpoint(report, endpoint, interface, device);
+    }
+}
+
+fn format_configuration(report: &mut String, configuration: &ConfigurationModel, device: &DeviceModel) {
+    /* SuperSpeed Devices report bMaxPower in 8mA units */
+    let is_superspeed = device.descriptor.as_ref().is_some_and(|descriptor| descriptor.usb_version >= 0x0300);
+    let max_power = configuration.max_power as u32 * if is_superspeed { 8 } else { 2 };
+
+    let _ = writeln!(report, "  Configuration Descriptor:");
+    let _ = writeln!(report, "    bLength             {:5}", configuration.raw_data[0]);
+    let _ = writeln!(report, "    bDescriptorType     {:5}", device_model::DESCRIPTOR_CONFIGURATION);
+    let _ = writeln!(report, "    wTotalLength       0x{:04x}", configuration.total_length);
+    let _ = writeln!(report, "    bNumInterfaces      {:5}", configuration.num_interfaces);
+    let _ = writeln!(report, "    bConfigurationValue {:5}", configuration.value);
+    let _ = writeln!(report, "    iConfiguration      {:5} {}", configuration.string_index, device.get_string(configuration.string_index));
+    let _ = writeln!(report, "    bmAttributes         0x{:02x}", configuration.attributes);
+    let _ = writeln!(report, "      {}", if configuration.attributes & 0x40 != 0 { "Self Powered" } else { "(Bus Powered)" });
+    if configuration.attributes & 0x20 != 0 {
+        let _ = writeln!(report, "      Remote Wakeup");
+    }
+    let _ = writeln!(report, "    MaxPower            {:5}mA", max_power);
+
+    for descriptor in &configuration.extra_descriptors {
+        format_unrecognized(report, 4, descriptor);
+    }
+
+    /* Associations precede the first Interface they group */
+    for interface in &configuration.interfaces {
+        if interface.alternate_setting == 0 {
+            configuration.associations
+                .iter()
+                .filter(|association| association.first_interface == interface.number)
+                .for_each(|association| format_association(report, association, device));
+        }
+
+        format_interface(report, interface, device);
+    }
+
+    if configuration.interfaces.is_empty() {
+        let _ = writeln!(report, "    ** UNAVAILABLE: Only {} of {} bytes captured **", configuration.raw_data.len(), configuration.total_length);
+    }
+}
+
+fn format_bos(report: &mut String, bos_descriptor: &[u8]) {
+    if bos_descriptor.len() < 5 {
+        return format_unrecognized(report, 0, bos_descriptor);
+    }
+
+    let _ = writeln!(report, "Binary Object Store Descriptor:");
+    let _ = writeln!(report, "  bLength             {:5}", bos_descriptor[0]);
+    let _ = writeln!(report, "  bDescriptorType     {:5}", bos_descriptor[1]);
+    let _ = writeln!(report, "  wTotalLength       0x{:04x}", u16::from_le_bytes([bos_descriptor[2], bos_descriptor[3]]));
+    let _ = writeln!(report, "  bNumDeviceCaps      {:5}", bos_descriptor[4]);
+
+    for capability in device_model::iterate_descriptors(bos_descriptor).skip(1) {
+        if capability[1] != DESCRIPTOR_DEVICE_CAPABILITY || capability.len() < 3 {
+            format_unrecognized(report, 2, capability);
+            continue;
+        }
+
+        match (capability[2], capability.len()) {
+            (0x02, 7..) => {
+                let attributes = u32::from_le_bytes([capability[3], capability[4], capability[5], capability[6]]);
+                let _ = writeln!(report, "  USB 2.0 Extension Device Capability:");
+                let _ = writeln!(report, "    bLength             {:5}", capability[0]);
+                let _ = writeln!(report, "    bDescriptorType     {:5}", capability[1]);
+                let _ = writeln!(report, "    bDevCapabilityType  {:5}", capability[2]);
+                let _ = writeln!(report, "    bmAttributes   0x{:08x}", attributes);
+                if attributes & 0x02 != 0 {
+                    let _ = writeln!(report, "      BESL Link Power Management (LPM) Supported");
+                }
+            },
+
+            (0x03, 10..) => {
+                let speeds_supported = u16::from_le_bytes([capability[4], capability[5]]);
+                let _ = writeln!(report, "  SuperSpeed USB Device Capability:");
+                let _ = writeln!(report, "    bLength             {:5}", capability[0]);
+                let _ = writeln!(report, "    bDescriptorType     {:5}", capability[1]);
+                let _ = writeln!(report, "    bDevCapabilityType  {:5}", capability[2]);
+                let _ = writeln!(report, "    bmAttributes         0x{:02x}", capability[3]);
+                if capability[3] & 0x02 != 0 {
+                    let _ = writeln!(report, "      Latency Tolerance Messages (LTM) Supported");
+                }
+                let _ = writeln!(report, "    wSpeedsSupported   0x{:04x}", speeds_supported);
+                for (speed_bit, speed_name) in [(0x01, "Low Speed (1Mbps)"), (0x02, "Full Speed (12Mbps)"), (0x04, "High Speed (480Mbps)"), (0x08, "SuperSpeed (5Gbps)")] {
+                    if speeds_supported & speed_bit != 0 {
+                        let _ = writeln!(report, "      Device can operate at {}", speed_name);
+                    }
+                }
+                let _ = writeln!(report, "    bFunctionalitySupport {:3}", capability[6]);
+                let _ = writeln!(report, "    bU1DevExitLat       {:5} micro seconds", capability[7]);
+                let _ = writeln!(report, "    bU2DevExitLat       {:5} micro seconds", u16::from_le_bytes([capability[8], capability[9]]));
+            },
+
+            (0x04, 20..) => {
+                let container_id = &capability[4..20];
+                let _ = writeln!(report, "  Container ID Device Capability:");
+                let _ = writeln!(report, "    bLength             {:5}", capability[0]);
+                let _ = writeln!(report, "    bDescriptorType     {:5}", capability[1]);
+                let _ = writeln!(report, "    bDevCapabilityType  {:5}", capability[2]);
+                let _ = writeln!(report, "    bReserved           {:5}", capability[3]);
+                let _ = writeln!(report, "    ContainerID             {{{}}}", format_uuid(container_id));
+            },
+
+            (0x05, 20..) => {
+                let _ = writeln!(report, "  Platform Device Capability:");
+                let _ = writeln!(report, "    bLength             {:5}", capability[0]);
+                let _ = writeln!(report, "    bDescriptorType     {:5}", capability[1]);
+                let _ = writeln!(report, "    bDevCapabilityType  {:5}", capability[2]);
+                let _ = writeln!(report, "    bReserved           {:5}", capability[3]);
+                let _ = writeln!(report, "    PlatformCapabilityUUID    {{{}}}", format_uuid(&capability[4..20]));
+            },
+
+            (0x0A, 12..) => {
+                let _ = writeln!(report, "  SuperSpeedPlus USB Device Capability:");
+                let _ = writeln!(report, "    bLength             {:5}", capability[0]);
+                let _ = writeln!(report, "    bDescriptorType     {:5}", capability[1]);
+                let _ = writeln!(report, "    bDevCapabilityType  {:5}", capability[2]);
+                let _ = writeln!(report, "    bmAttributes         0x{:08x}", u32::from_le_bytes([capability[4], capability[5], capability[6], capability[7]]));
+                let _ = writeln!(report, "      Sublink Speed Attribute count {}", (capability[4] & 0x1F) + 1);
+            },
+
+            _ => format_unrecognized(report, 2, capability)
+        }
+    }
+}
+
+fn format_uuid(uuid_data: &[u8]) -> String {
+    /* The first three Fields are Little Endian */
+    let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    let reversed = |bytes: &[u8]| hex(&bytes.iter().rev().copied().collect::<Vec<u8>>());
+    format!(
+        "{}-{}-{}-{}-{}",
+        reversed(&uuid_data[0..4]),
+        reversed(&uuid_data[4..6]),
+        reversed(&uuid_data[6..8]),
+        hex(&uuid_data[8..10]),
+        hex(&uuid_data[10..16])
+    )
+}
+
+fn format_device_title(device_address: (u16, u16), device: &DeviceModel) -> String {
+    let (vendor_id, product_id, product_name) = match &device.descriptor {
+        Some(descriptor) => (
+            descriptor.vendor_id,
+            descriptor.product_id,
+            format!("{} {}", device.get_string(descriptor.manufacturer_index), device.get_string(descriptor.product_index))
+        ),
+        None => (0, 0, String::new())
+    };
+
+    format!("Bus {:03} Device {:03}: ID {:04x}:{:04x} {}", device_address.0, device_address.1, vendor_id, product_id, product_name.trim())
+}
+
+pub fn format_device_report(device_address: (u16, u16), device: &DeviceModel) -> String {
+    let mut report = format_device_title(device_address, device) + "\n";
+    let Some(descriptor) = &device.descriptor else {
+        report += "Device Descriptor:\n  ** UNAVAILABLE: Enumeration was not captured **\n";
+        return report;
+    };
+
+    let _ = writeln!(report, "Device Descriptor:");
+    let _ = writeln!(report, "  bLength             {:5}", descriptor.length);
+    let _ = writeln!(report, "  bDescriptorType     {:5}", device_model::DESCRIPTOR_DEVICE);
+    let _ = writeln!(report, "  bcdUSB              {}", format_bcd(descriptor.usb_version));
+    let _ = writeln!(report, "  bDeviceClass        {:5} {}", descriptor.class, get_class_name(descriptor.class));
+    let _ = writeln!(report, "  bDeviceSubClass     {:5} {}", descriptor.subclass, get_subclass_name(descriptor.class, descriptor.subclass));
+    let _ = writeln!(report, "  bDeviceProtocol     {:5} {}", descriptor.protocol, get_protocol_name(descriptor.class, descriptor.subclass, descriptor.protocol));
+    let _ = writeln!(report, "  bMaxPacketSize0     {:5}", descriptor.max_packet_size0);
+    let _ = writeln!(report, "  idVendor           0x{:04x}", descriptor.vendor_id);
+    let _ = writeln!(report, "  idProduct          0x{:04x}", descriptor.product_id);
+    let _ = writeln!(report, "  bcdDevice           {}", format_bcd(descriptor.device_version));
+    let _ = writeln!(report, "  iManufacturer       {:5} {}", descriptor.manufacturer_index, device.get_string(descriptor.manufacturer_index));
+    let _ = writeln!(report, "  iProduct            {:5} {}", descriptor.product_index, device.get_string(descriptor.product_index));
+    let _ = writeln!(report, "  iSerial             {:5} {}", descriptor.serial_index, device.get_string(descriptor.serial_index));
+    let _ = writeln!(report, "  bNumConfigurations  {:5}", descriptor.num_configurations);
+
+    for configuration in &device.configurations {
+        format_configuration(&mut report, configuration, device);
+    }
+
+    if let Some(bos_descriptor) = &device.bos_descriptor {
+        format_bos(&mut report, bos_descriptor);
+    }
+
+    report
+}
+
+pub fn format_registry_report(device_registry: &DeviceRegistry) -> String {
+    device_registry
+        .get_devices()
+        .iter()
+        .map(|(device_address, device)| format_device_report(*device_address, device))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn get_interface_tree(device_tree: &mut Vec<DeviceTreeLine>, interface: &InterfaceModel, device: &DeviceModel, depth: usize) {
+    let is_active = *device.alternate_settings.get(&interface.number).unwrap_or(&0) == interface.alternate_setting;
+    let class_names: Vec<&str> = [
+        get_class_name(interface.class),
+        get_subclass_name(interface.class, interface.subclass),
+        get_protocol_name(interface.class, interface.subclass, interface.protocol),
+    ]
+    .into_iter()
+    .filter(|name| !name.is_empty())
+    .collect();
+
+    device_tree.push((depth, format!(
+        "Interface {}.{}: {}{} {}",
+        interface.number,
+        interface.alternate_setting,
+        class_names.join(" / "),
+        if is_active { "" } else { " (Inactive)" },
+        device.get_string(interface.string_index)
+    ).trim_end().to_string()));
+
+    for endpoint in &interface.endpoints {
+        device_tree.push((depth + 1, format!(
+            "Endpoint 0x{:02x} ({}): {}, {}, Interval {}",
+            endpoint.address,
+            format_endpoint_address(endpoint.address),
+            get_transfer_type_name(endpoint.attributes),
+            format_max_packet_size(endpoint.max_packet_size),
+            endpoint.interval
+        )));
+    }
+}
+
+pub fn get_device_tree(device_registry: &DeviceRegistry) -> Vec<DeviceTreeLine> {
+    let mut device_tree = vec![];
+    for (device_address, device) in device_registry.get_devices() {
+        device_tree.push((0, format_device_title(device_address, device)));
+        if let Some(descriptor) = &device.descriptor {
+            device_tree.push((1, format!(
+                "USB {}, Class: {}, EP0: {} bytes, Serial: {}",
+                format_bcd(descriptor.usb_version).trim(),
+                get_class_name(descriptor.class),
+                descriptor.max_packet_size0,
+                device.get_string(descriptor.serial_index)
+            )));
+        }
+
+        for configuration in &device.configurations {
+            let is_active = device.get_active_configuration().is_some_and(|active| active.value == configuration.value);
+            device_tree.push((1, format!(
+                "Configuration {}: {} Interfaces, {}{}",
+                configuration.value,
+                configuration.num_interfaces,
+                if configuration.attributes & 0x40 != 0 { "Self Powered" } else { "Bus Powered" },
+                if is_active { " (Active)" } else { "" }
+            )));
+
+            for interface in &configuration.interfaces {
+                /* Interfaces grouped by an Association are nested below their Function */
+                let association = configuration.associations.iter().find(|association| {
+                    (association.first_interface..association.first_interface.saturating_add(association.interface_count)).contains(&interface.number)
+                });
+
+                match association {
+                    Some(association) => {
+                        if association.first_interface == interface.number && interface.alternate_setting == 0 {
+                            device_tree.push((2, format!(
+                                "Function: Interfaces {}-{}, {} {}",
+                                association.first_interface,
+                                association.first_interface.saturating_add(association.interface_count).saturating_sub(1),
+                                get_class_name(association.function_class),
+                                device.get_string(association.string_index)
+                            ).trim_end().to_string()));
+                        }
+
+                        get_interface_tree(&mut device_tree, interface, device, 3);
+                    },
+
+                    None => get_interface_tree(&mut device_tree, interface, device, 2)
+                }
+            }
+        }
+
+        if let Some(bos_descriptor) = &device.bos_descriptor {
+            let capability_names: Vec<String> = device_model::iterate_descriptors(bos_descriptor)
+                .skip(1)
+                .filter_map(|capability| capability.get(2))
+                .map(|capability_type| match capability_type {
+                    0x02 => String::from("USB 2.0 Extension"),
+                    0x03 => String::from("SuperSpeed"),
+                    0x04 => String::from("Container ID"),
+                    0x05 => String::from("Platform"),
+                    0x0A => String::from("SuperSpeedPlus"),
+                    _ => format!("Capability 0x{:02x}", capability_type)
+                })
+                .collect();
+
+            device_tree.push((1, format!("BOS: {}", capability_names.join(", "))));
+        }
+
+        /* Chapter 9 Findings below every Device */
+        let findings = device_lint::lint_device(device);
+        device_tree.push((1, format!("Compliance: {}", device_lint::format_lint_summary(&findings))));
+        for finding in findings {
+            device_tree.push((2, format!("[{}] {}: {}", finding.severity.get_name(), finding.location, finding.message)));
+        }
+    }
+
+    device_tree
+}
+
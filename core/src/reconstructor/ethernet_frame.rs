@@ -0,0 +1,228 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+
+/* Define Constants */
+const PCAP_MAGIC: u32 = 0xA1B2C3D4;
+const PCAP_SNAPLEN: u32 = 65535;
+pub const LINKTYPE_ETHERNET: u32 = 1;
+const ETHERNET_HEADER_LENGTH: usize = 14;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const PROTOCOL_ICMP: u8 = 1;
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+const PROTOCOL_ICMPV6: u8 = 58;
+
+pub struct PcapWriter {
+    file: File,
+}
+
+impl PcapWriter {
+    pub fn create(path: &PathBuf, link_type: u32) -> io::Result<Self> {
+        if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            fs::create_dir_all(directory)?;
+        }
+
+        /* Classic pcap, Microsecond Timestamps */
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_MAGIC.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&0i32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&PCAP_SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&link_type.to_le_bytes());
+
+        let mut file = File::create(path)?;
+        file.write_all(&header)?;
+        Ok(PcapWriter { file })
+    }
+
+    pub fn write_frame(&mut self, timestamp: u64, frame: &[u8]) -> io::Result<()> {
+        let captured = &frame[..frame.len().min(PCAP_SNAPLEN as usize)];
+        let mut record = Vec::with_capacity(16 + captured.len());
+        record.extend_from_slice(&((timestamp / 1_000_000) as u32).to_le_bytes());
+        record.extend_from_slice(&((timestamp % 1_000_000) as u32).to_le_bytes());
+        record.extend_from_slice(&(captured.len() as u32).to_le_bytes());
+        record.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        record.extend_from_slice(captured);
+        self.file.write_all(&record)
+    }
+}
+
+fn format_mac(address: &[u8]) -> String {
+    address.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(":")
+}
+
+fn get_ethertype_name(ethertype: u16) -> String {
+    match ethertype {
+        ETHERTYPE_IPV4 => String::from("IPv4"),
+        ETHERTYPE_ARP => String::from("ARP"),
+        ETHERTYPE_IPV6 => String::from("IPv6"),
+        0x8863 => String::from("PPPoE Discovery"),
+        0x8864 => String::from("PPPoE Session"),
+        0x888E => String::from("EAPOL"),
+        0x88CC => String::from("LLDP"),
+        0x88F7 => String::from("PTP"),
+        0x8809 => String::from("Slow Protocols"),
+        ethertype if ethertype <= 1500 => format!("802.3 Length {}", ethertype),
+        ethertype => format!("EtherType 0x{:04X}", ethertype),
+    }
+}
+
+fn get_tcp_flags(flags: u8) -> String {
+    let flag_names: Vec<&str> = [(0x02, "SYN"), (0x10, "ACK"), (0x01, "FIN"), (0x04, "RST"), (0x08, "PSH"), (0x20, "URG")]
+        .iter()
+        .filter(|(flag_bit, _)| flags & flag_bit != 0)
+        .map(|(_, flag_name)| *flag_name)
+        .collect();
+
+    flag_names.join(",")
+}
+
+fn describe_transport(protocol: u8, source: &str, destination: &str, segment: Option<&[u8]>) -> String {
+    /* The 5-Tuple, Ports are only in the first Fragment */
+    match (protocol, segment) {
+        (PROTOCOL_TCP, Some(segment)) if segment.len() >= 14 => format!(
+            "TCP {}:{} -> {}:{} [{}]",
+            source,
+            u16::from_be_bytes([segment[0], segment[1]]),
+            destination,
+            u16::from_be_bytes([segment[2], segment[3]]),
+            get_tcp_flags(segment[13])
+        ),
+
+        (PROTOCOL_UDP, Some(segment)) if segment.len() >= 8 => format!(
+            "UDP {}:{} -> {}:{}",
+            source,
+            u16::from_be_bytes([segment[0], segment[1]]),
+            destination,
+            u16::from_be_bytes([segment[2], segment[3]])
+        ),
+
+        (PROTOCOL_ICMP, Some([icmp_type, icmp_code, ..])) => {
+            let type_name = match icmp_type {
+                0 => String::from("Echo Reply"),
+                3 => format!("Destination Unreachable (Code {})", icmp_code),
+                8 => String::from("Echo Request"),
+                11 => String::from("Time Exceeded"),
+                icmp_type => format!("Type {}", icmp_type),
+            };
+
+            format!("ICMP {} -> {} {}", source, destination, type_name)
+        },
+
+        (PROTOCOL_ICMPV6, Some([icmp_type, ..])) => {
+            let type_name = match icmp_type {
+                1 => String::from("Destination Unreachable"),
+                128 => String::from("Echo Request"),
+                129 => String::from("Echo Reply"),
+                133 => String::from("Router Solicitation"),
+                134 => String::from("Router Advertisement"),
+                135 => String::from("Neighbor Solicitation"),
+                136 => String::from("Neighbor Advertisement"),
+                143 => String::from("MLDv2 Report"),
+                icmp_type => format!("Type {}", icmp_type),
+            };
+
+            format!("ICMPv6 {} -> {} {}", source, destination, type_name)
+        },
+
+        (protocol, _) => format!("Protocol {} {} -> {}", protocol, source, destination),
+    }
+}
+
+fn describe_ipv4(packet: &[u8]) -> String {
+    let header_length = (packet[0] & 0x0F) as usize * 4;
+    if packet.len() < 20 || header_length < 20 || packet.len() < header_length {
+        return String::from("IPv4 (truncated)");
+    }
+
+    let source = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]).to_string();
+    let destination = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]).to_string();
+    let fragment_offset = u16::from_be_bytes([packet[6], packet[7]]) & 0x1FFF;
+    let segment = (fragment_offset == 0).then(|| &packet[header_length..]);
+    format!("IPv4 {}", describe_transport(packet[9], &source, &destination, segment))
+}
+
+fn describe_ipv6(packet: &[u8]) -> String {
+    if packet.len() < 40 {
+        return String::from("IPv6 (truncated)");
+    }
+
+    let source = Ipv6Addr::from(<[u8; 16]>::try_from(&packet[8..24]).unwrap()).to_string();
+    let destination = Ipv6Addr::from(<[u8; 16]>::try_from(&packet[24..40]).unwrap()).to_string();
+    let source = format!("[{}]", source);
+    let destination = format!("[{}]", destination);
+
+    /* Skip Hop-by-Hop, Routing and Destination Options, Fragments end the Walk */
+    let (mut next_header, mut offset) = (packet[6], 40);
+    while matches!(next_header, 0 | 43 | 60) && packet.len() >= offset + 2 {
+        next_header = packet[offset];
+        offset += (packet[offset + 1] as usize + 1) * 8;
+    }
+
+    let segment = (next_header != 44).then(|| packet.get(offset..)).flatten();
+    format!("IPv6 {}", describe_transport(next_header, &source, &destination, segment))
+}
+
+fn describe_arp(packet: &[u8]) -> String {
+    /* Ethernet and IPv4 ARP only */
+    if packet.len() < 28 || packet[4] != 6 || packet[5] != 4 {
+        return String::from("ARP");
+    }
+
+    let sender_ip = Ipv4Addr::new(packet[14], packet[15], packet[16], packet[17]);
+    let target_ip = Ipv4Addr::new(packet[24], packet[25], packet[26], packet[27]);
+    match u16::from_be_bytes([packet[6], packet[7]]) {
+        1 => format!("ARP Who has {}? Tell {}", target_ip, sender_ip),
+        2 => format!("ARP {} is at {}", sender_ip, format_mac(&packet[8..14])),
+        operation => format!("ARP Operation {}", operation),
+    }
+}
+
+pub fn describe_frame(frame: &[u8]) -> String {
+    /* Source and Destination MAC, EtherType behind VLAN Tags, then the IP 5-Tuple */
+    if frame.len() < ETHERNET_HEADER_LENGTH {
+        return format!("Runt Frame, {} bytes", frame.len());
+    }
+
+    let mut summary = format!("{} -> {}", format_mac(&frame[6..12]), format_mac(&frame[0..6]));
+    let (mut ethertype, mut offset) = (u16::from_be_bytes([frame[12], frame[13]]), ETHERNET_HEADER_LENGTH);
+    while matches!(ethertype, 0x8100 | 0x88A8) && frame.len() >= offset + 4 {
+        summary += &format!(" VLAN {}", u16::from_be_bytes([frame[offset], frame[offset + 1]]) & 0x0FFF);
+        ethertype = u16::from_be_bytes([frame[offset + 2], frame[offset + 3]]);
+        offset += 4;
+    }
+
+    let payload = &frame[offset..];
+    let description = match ethertype {
+        ETHERTYPE_IPV4 if !payload.is_empty() => describe_ipv4(payload),
+        ETHERTYPE_IPV6 if !payload.is_empty() => describe_ipv6(payload),
+        ETHERTYPE_ARP => describe_arp(payload),
+        ethertype => get_ethertype_name(ethertype),
+    };
+
+    format!("{} {}, {} bytes", summary, description, frame.len())
+}
@@ -0,0 +1,526 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/*
+    CBOR (RFC 8949) as used by CTAP2: Maps with Integer Keys whose Names
+    depend on the Command. Values are printed in Diagnostic Notation
+    https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-20210615.html
+*/
+
+/* Define Constants */
+const MAX_DEPTH: usize = 16;
+const BYTES_PREVIEW_LENGTH: usize = 16;
+const AUTH_DATA_FLAGS: [(u8, &str); 6] = [(0x01, "UP"), (0x04, "UV"), (0x08, "BE"), (0x10, "BS"), (0x40, "AT"), (0x80, "ED")];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CborValue {
+    Unsigned(u64),
+    Negative(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<CborValue>),
+    Map(Vec<(CborValue, CborValue)>),
+    Tag(u64, Box<CborValue>),
+    Bool(bool),
+    Null,
+    Undefined,
+    Float(f64),
+    Simple(u8),
+}
+
+struct CborReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> CborReader<'a> {
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end = self.offset.checked_add(length).ok_or("Truncated CBOR")?;
+        let bytes = self.data.get(self.offset..end).ok_or("Truncated CBOR")?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn read_argument(&mut self, additional_info: u8) -> Result<Option<u64>, String> {
+        /* None for Indefinite Lengths */
+        let argument = match additional_info {
+            0..=23 => additional_info as u64,
+            24 => self.read_bytes(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.read_bytes(2)?.try_into().unwrap()) as u64,
+            26 => u32::from_be_bytes(self.read_bytes(4)?.try_into().unwrap()) as u64,
+            27 => u64::from_be_bytes(self.read_bytes(8)?.try_into().unwrap()),
+            31 => return Ok(None),
+            _ => return Err(format!("Reserved Additional Information {}", additional_info)),
+        };
+
+        Ok(Some(argument))
+    }
+
+    fn is_break(&self) -> bool {
+        self.data.get(self.offset) == Some(&0xFF)
+    }
+
+    fn read_value(&mut self, depth: usize) -> Result<CborValue, String> {
+        if depth > MAX_DEPTH {
+            return Err(String::from("CBOR nested too deeply"));
+        }
+
+        let initial_byte = self.read_bytes(1)?[0];
+        let (major_type, additional_info) = (initial_byte >> 5, initial_byte & 0x1F);
+        let argument = self.read_argument(additional_info)?;
+        let value = match (major_type, argument) {
+            (0, Some(argument)) => CborValue::Unsigned(argument),
+            (1, Some(argument)) => CborValue::Negative(-1 - argument as i128),
+            (2 | 3, Some(length)) => {
+                let bytes = self.read_bytes(length as usize)?.to_vec();
+                if major_type == 2 { CborValue::Bytes(bytes) } else { CborValue::Text(String::from_utf8_lossy(&bytes).into_owned()) }
+            },
+
+            /* Indefinite Strings are Chunks up to a Break */
+            (2 | 3, None) => {
+                let mut bytes = vec![];
+                while !self.is_break() {
+                    match self.read_value(depth + 1)? {
+                        CborValue::Bytes(chunk) => bytes.extend(chunk),
+                        CborValue::Text(chunk) => bytes.extend(chunk.into_bytes()),
+                        _ => return Err(String::from("Invalid Chunk in Indefinite String")),
+                    }
+                }
+
+                self.offset += 1;
+                if major_type == 2 { CborValue::Bytes(bytes) } else { CborValue::Text(String::from_utf8_lossy(&bytes).into_owned()) }
+            },
+
+            (4, length) => {
+                let mut items = vec![];
+                while length.is_none_or(|length| (items.len() as u64) < length) && !(length.is_none() && self.is_break()) {
+                    items.push(self.read_value(depth + 1)?);
+                }
+
+                self.offset += length.is_none() as usize;
+                CborValue::Array(items)
+            },
+
+            (5, length) => {
+                let mut entries = vec![];
+                while length.is_none_or(|length| (entries.len() as u64) < length) && !(length.is_none() && self.is_break()) {
+                    entries.push((self.read_value(depth + 1)?, self.read_value(depth + 1)?));
+                }
+
+                self.offset += length.is_none() as usize;
+                CborValue::Map(entries)
+            },
+
+            (6, Some(tag)) => CborValue::Tag(tag, Box::new(self.read_value(depth + 1)?)),
+            (7, Some(simple_value)) => match additional_info {
+                20 => CborValue::Bool(false),
+                21 => CborValue::Bool(true),
+                22 => CborValue::Null,
+                23 => CborValue::Undefined,
+                25 => CborValue::Float(decode_half(simple_value as u16)),
+                26 => CborValue::Float(f32::from_bits(simple_value as u32) as f64),
+                27 => CborValue::Float(f64::from_bits(simple_value)),
+                _ => CborValue::Simple(simple_value as u8),
+            },
+
+            _ => return Err(format!("Unexpected CBOR Initial Byte 0x{:02X}", initial_byte)),
+        };
+
+        Ok(value)
+    }
+}
+
+fn decode_half(half: u16) -> f64 {
+    let (exponent, mantissa) = ((half >> 10) & 0x1F, (half & 0x03FF) as f64);
+    let magnitude = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (mantissa + 1024.0) * 2f64.powi(exponent as i32 - 25),
+    };
+
+    if half & 0x8000 != 0 { -magnitude } else { magnitude }
+}
+
+pub fn decode(data: &[u8]) -> Result<(CborValue, usize), String> {
+    /* The Value and the Number of Bytes it used */
+    let mut cbor_reader = CborReader { data, offset: 0 };
+    let value = cbor_reader.read_value(0)?;
+    Ok((value, cbor_reader.offset))
+}
+
+pub fn format_bytes(bytes: &[u8]) -> String {
+    let hex_bytes: String = bytes.iter().take(BYTES_PREVIEW_LENGTH).map(|byte| format!("{:02x}", byte)).collect();
+    match bytes.len() > BYTES_PREVIEW_LENGTH {
+        true => format!("h'{}…' ({} bytes)", hex_bytes, bytes.len()),
+        false => format!("h'{}'", hex_bytes),
+    }
+}
+
+pub fn format_value(value: &CborValue) -> String {
+    match value {
+        CborValue::Unsigned(number) => number.to_string(),
+        CborValue::Negative(number) => number.to_string(),
+        CborValue::Bytes(bytes) => format_bytes(bytes),
+        CborValue::Text(text) => format!("{:?}", text),
+        CborValue::Array(items) => format!("[{}]", items.iter().map(format_value).collect::<Vec<String>>().join(", ")),
+        CborValue::Map(entries) => format!(
+            "{{{}}}",
+            entries.iter().map(|(key, value)| format!("{}: {}", format_value(key), format_value(value))).collect::<Vec<String>>().join(", ")
+        ),
+        CborValue::Tag(tag, value) => format!("{}({})", tag, format_value(value)),
+        CborValue::Bool(boolean) => boolean.to_string(),
+        CborValue::Null => String::from("null"),
+        CborValue::Undefined => String::from("undefined"),
+        CborValue::Float(number) => number.to_string(),
+        CborValue::Simple(simple_value) => format!("simple({})", simple_value),
+    }
+}
+
+pub fn format_auth_data(auth_data: &[u8]) -> String {
+    /* rpIdHash, Flags, Sign Count, then Attested Credential Data when AT is set */
+    if auth_data.len() < 37 {
+        return format_bytes(auth_data);
+    }
+
+    let flags = auth_data[32];
+    let flag_names: Vec<&str> = AUTH_DATA_FLAGS.iter().filter(|(bit, _)| flags & bit != 0).map(|(_, name)| *name).collect();
+    let mut parts = vec![
+        format!("rpIdHash: {}", format_bytes(&auth_data[..32])),
+        format!("flags: {}", if flag_names.is_empty() { String::from("none") } else { flag_names.join("|") }),
+        format!("signCount: {}", u32::from_be_bytes([auth_data[33], auth_data[34], auth_data[35], auth_data[36]])),
+    ];
+
+    if flags & 0x40 != 0 && auth_data.len() >= 55 {
+        let aaguid: String = auth_data[37..53].iter().map(|byte| format!("{:02x}", byte)).collect();
+        let credential_id_length = u16::from_be_bytes([auth_data[53], auth_data[54]]) as usize;
+        parts.push(format!("aaguid: {}-{}-{}-{}-{}", &aaguid[..8], &aaguid[8..12], &aaguid[12..16], &aaguid[16..20], &aaguid[20..]));
+        if let Some(credential_id) = auth_data.get(55..(55 + credential_id_length)) {
+            parts.push(format!("credentialId: {}", format_bytes(credential_id)));
+            if let Ok((public_key, _)) = decode(&auth_data[(55 + credential_id_length)..]) {
+                parts.push(format!("credentialPublicKey: {}", format_cose_key(&public_key)));
+            }
+        }
+    }
+
+    format!("{{{}}}", parts.join(", "))
+}
+
+fn format_cose_key(cose_key: &CborValue) -> String {
+    /* Key Type and Algorithm are enough to tell Keys apart */
+    let CborValue::Map(entries) = cose_key else { return format_value(cose_key) };
+    let get_integer = |label: i128| entries.iter().find_map(|(key, value)| match (key, value) {
+        (CborValue::Unsigned(key), CborValue::Unsigned(value)) if *key as i128 == label => Some(*value as i128),
+        (CborValue::Unsigned(key), CborValue::Negative(value)) if *key as i128 == label => Some(*value),
+        _ => None
+    });
+
+    let key_type = match get_integer(1) {
+        Some(1) => "OKP",
+        Some(2) => "EC2",
+        Some(3) => "RSA",
+        _ => "Unknown",
+    };
+
+    format!("{} {}", key_type, get_integer(3).map(get_algorithm_name).unwrap_or_default())
+}
+
+pub fn get_algorithm_name(algorithm: i128) -> String {
+    match algorithm {
+        -7 => String::from("ES256"),
+        -8 => String::from("EdDSA"),
+        -35 => String::from("ES384"),
+        -36 => String::from("ES512"),
+        -37 => String::from("PS256"),
+        -257 => String::from("RS256"),
+        -25 => String::from("ECDH-ES+HKDF-256"),
+        algorithm => format!("alg({})", algorithm),
+    }
+}
+
+pub fn get_command_name(ctap_command: u8) -> &'static str {
+    match ctap_command {
+        0x01 => "authenticatorMakeCredential",
+        0x02 => "authenticatorGetAssertion",
+        0x04 => "authenticatorGetInfo",
+        0x06 => "authenticatorClientPIN",
+        0x07 => "authenticatorReset",
+        0x08 => "authenticatorGetNextAssertion",
+        0x09 => "authenticatorBioEnrollment",
+        0x0A => "authenticatorCredentialManagement",
+        0x0B => "authenticatorSelection",
+        0x0C => "authenticatorLargeBlobs",
+        0x0D => "authenticatorConfig",
+        0x40 => "authenticatorBioEnrollment (Preview)",
+        0x41 => "authenticatorCredentialManagement (Preview)",
+        0xC0..=0xFF => "Vendor Command",
+        _ => "Unknown Command"
+    }
+}
+
+pub fn get_status_name(status: u8) -> &'static str {
+    match status {
+        0x00 => "CTAP2_OK",
+        0x01 => "CTAP1_ERR_INVALID_COMMAND",
+        0x02 => "CTAP1_ERR_INVALID_PARAMETER",
+        0x03 => "CTAP1_ERR_INVALID_LENGTH",
+        0x04 => "CTAP1_ERR_INVALID_SEQ",
+        0x05 => "CTAP1_ERR_TIMEOUT",
+        0x06 => "CTAP1_ERR_CHANNEL_BUSY",
+        0x0A => "CTAP1_ERR_LOCK_REQUIRED",
+        0x0B => "CTAP1_ERR_INVALID_CHANNEL",
+        0x11 => "CTAP2_ERR_CBOR_UNEXPECTED_TYPE",
+        0x12 => "CTAP2_ERR_INVALID_CBOR",
+        0x14 => "CTAP2_ERR_MISSING_PARAMETER",
+        0x15 => "CTAP2_ERR_LIMIT_EXCEEDED",
+        0x17 => "CTAP2_ERR_FP_DATABASE_FULL",
+        0x18 => "CTAP2_ERR_LARGE_BLOB_STORAGE_FULL",
+        0x19 => "CTAP2_ERR_CREDENTIAL_EXCLUDED",
+        0x21 => "CTAP2_ERR_PROCESSING",
+        0x22 => "CTAP2_ERR_INVALID_CREDENTIAL",
+        0x23 => "CTAP2_ERR_USER_ACTION_PENDING",
+        0x24 => "CTAP2_ERR_OPERATION_PENDING",
+        0x25 => "CTAP2_ERR_NO_OPERATIONS",
+        0x26 => "CTAP2_ERR_UNSUPPORTED_ALGORITHM",
+        0x27 => "CTAP2_ERR_OPERATION_DENIED",
+        0x28 => "CTAP2_ERR_KEY_STORE_FULL",
+        0x2B => "CTAP2_ERR_UNSUPPORTED_OPTION",
+        0x2C => "CTAP2_ERR_INVALID_OPTION",
+        0x2D => "CTAP2_ERR_KEEPALIVE_CANCEL",
+        0x2E => "CTAP2_ERR_NO_CREDENTIALS",
+        0x2F => "CTAP2_ERR_USER_ACTION_TIMEOUT",
+        0x30 => "CTAP2_ERR_NOT_ALLOWED",
+        0x31 => "CTAP2_ERR_PIN_INVALID",
+        0x32 => "CTAP2_ERR_PIN_BLOCKED",
+        0x33 => "CTAP2_ERR_PIN_AUTH_INVALID",
+        0x34 => "CTAP2_ERR_PIN_AUTH_BLOCKED",
+        0x35 => "CTAP2_ERR_PIN_NOT_SET",
+        0x36 => "CTAP2_ERR_PUAT_REQUIRED",
+        0x37 => "CTAP2_ERR_PIN_POLICY_VIOLATION",
+        0x39 => "CTAP2_ERR_REQUEST_TOO_LARGE",
+        0x3A => "CTAP2_ERR_ACTION_TIMEOUT",
+        0x3B => "CTAP2_ERR_UP_REQUIRED",
+        0x3C => "CTAP2_ERR_UV_BLOCKED",
+        0x3D => "CTAP2_ERR_INTEGRITY_FAILURE",
+        0x3E => "CTAP2_ERR_INVALID_SUBCOMMAND",
+        0x3F => "CTAP2_ERR_UV_INVALID",
+        0x40 => "CTAP2_ERR_UNAUTHORIZED_PERMISSION",
+        0x7F => "CTAP1_ERR_OTHER",
+        _ => "CTAP2_ERR_UNKNOWN"
+    }
+}
+
+fn get_parameter_name(ctap_command: u8, is_response: bool, key: u64) -> Option<&'static str> {
+    let parameter_name = match (ctap_command, is_response, key) {
+        (0x01, false, 1) => "clientDataHash",
+        (0x01, false, 2) => "rp",
+        (0x01, false, 3) => "user",
+        (0x01, false, 4) => "pubKeyCredParams",
+        (0x01, false, 5) => "excludeList",
+        (0x01, false, 6) => "extensions",
+        (0x01, false, 7) => "options",
+        (0x01, false, 8) => "pinUvAuthParam",
+        (0x01, false, 9) => "pinUvAuthProtocol",
+        (0x01, false, 10) => "enterpriseAttestation",
+        (0x01, true, 1) => "fmt",
+        (0x01, true, 2) => "authData",
+        (0x01, true, 3) => "attStmt",
+        (0x01, true, 4) => "epAtt",
+        (0x01, true, 5) => "largeBlobKey",
+
+        (0x02, false, 1) => "rpId",
+        (0x02, false, 2) => "clientDataHash",
+        (0x02, false, 3) => "allowList",
+        (0x02, false, 4) => "extensions",
+        (0x02, false, 5) => "options",
+        (0x02, false, 6) => "pinUvAuthParam",
+        (0x02, false, 7) => "pinUvAuthProtocol",
+        (0x02 | 0x08, true, 1) => "credential",
+        (0x02 | 0x08, true, 2) => "authData",
+        (0x02 | 0x08, true, 3) => "signature",
+        (0x02 | 0x08, true, 4) => "user",
+        (0x02 | 0x08, true, 5) => "numberOfCredentials",
+        (0x02 | 0x08, true, 6) => "userSelected",
+        (0x02 | 0x08, true, 7) => "largeBlobKey",
+
+        (0x04, true, 1) => "versions",
+        (0x04, true, 2) => "extensions",
+        (0x04, true, 3) => "aaguid",
+        (0x04, true, 4) => "options",
+        (0x04, true, 5) => "maxMsgSize",
+        (0x04, true, 6) => "pinUvAuthProtocols",
+        (0x04, true, 7) => "maxCredentialCountInList",
+        (0x04, true, 8) => "maxCredentialIdLength",
+        (0x04, true, 9) => "transports",
+        (0x04, true, 10) => "algorithms",
+        (0x04, true, 11) => "maxSerializedLargeBlobArray",
+        (0x04, true, 12) => "forcePINChange",
+        (0x04, true, 13) => "minPINLength",
+        (0x04, true, 14) => "firmwareVersion",
+        (0x04, true, 15) => "maxCredBlobLength",
+        (0x04, true, 16) => "maxRPIDsForSetMinPINLength",
+        (0x04, true, 17) => "preferredPlatformUvAttempts",
+        (0x04, true, 18) => "uvModality",
+        (0x04, true, 19) => "certifications",
+        (0x04, true, 20) => "remainingDiscoverableCredentials",
+
+        (0x06, false, 1) => "pinUvAuthProtocol",
+        (0x06, false, 2) => "subCommand",
+        (0x06, false, 3) => "keyAgreement",
+        (0x06, false, 4) => "pinUvAuthParam",
+        (0x06, false, 5) => "newPinEnc",
+        (0x06, false, 6) => "pinHashEnc",
+        (0x06, false, 9) => "permissions",
+        (0x06, false, 10) => "rpId",
+        (0x06, true, 1) => "keyAgreement",
+        (0x06, true, 2) => "pinUvAuthToken",
+        (0x06, true, 3) => "pinRetries",
+        (0x06, true, 4) => "powerCycleState",
+        (0x06, true, 5) => "uvRetries",
+
+        (0x0A | 0x41, false, 1) => "subCommand",
+        (0x0A | 0x41, false, 2) => "subCommandParams",
+        (0x0A | 0x41, false, 3) => "pinUvAuthProtocol",
+        (0x0A | 0x41, false, 4) => "pinUvAuthParam",
+        (0x0A | 0x41, true, 1) => "existingResidentCredentialsCount",
+        (0x0A | 0x41, true, 2) => "maxPossibleRemainingResidentCredentialsCount",
+        (0x0A | 0x41, true, 3) => "rp",
+        (0x0A | 0x41, true, 4) => "rpIDHash",
+        (0x0A | 0x41, true, 5) => "totalRPs",
+        (0x0A | 0x41, true, 6) => "user",
+        (0x0A | 0x41, true, 7) => "credentialID",
+        (0x0A | 0x41, true, 8) => "publicKey",
+        (0x0A | 0x41, true, 9) => "totalCredentials",
+        (0x0A | 0x41, true, 10) => "credProtect",
+        _ => return None
+    };
+
+    Some(parameter_name)
+}
+
+fn get_pin_subcommand_name(subcommand: u64) -> &'static str {
+    match subcommand {
+        0x01 => "getPINRetries",
+        0x02 => "getKeyAgreement",
+        0x03 => "setPIN",
+        0x04 => "changePIN",
+        0x05 => "getPinToken",
+        0x06 => "getPinUvAuthTokenUsingUvWithPermissions",
+        0x07 => "getUVRetries",
+        0x09 => "getPinUvAuthTokenUsingPinWithPermissions",
+        _ => "Unknown Subcommand"
+    }
+}
+
+fn format_parameter(ctap_command: u8, parameter_name: &str, value: &CborValue) -> String {
+    match (parameter_name, value) {
+        ("authData", CborValue::Bytes(auth_data)) => format_auth_data(auth_data),
+        ("subCommand", CborValue::Unsigned(subcommand)) if ctap_command == 0x06 => get_pin_subcommand_name(*subcommand).to_string(),
+        ("pubKeyCredParams" | "algorithms", CborValue::Array(credential_parameters)) => {
+            /* [{alg: -7, type: "public-key"}] */
+            let algorithms: Vec<String> = credential_parameters
+                .iter()
+                .map(|parameter| match parameter {
+                    CborValue::Map(entries) => entries
+                        .iter()
+                        .find_map(|(key, value)| match (key, value) {
+                            (CborValue::Text(key), CborValue::Negative(algorithm)) if key == "alg" => Some(get_algorithm_name(*algorithm)),
+                            _ => None
+                        })
+                        .unwrap_or_else(|| format_value(parameter)),
+                    _ => format_value(parameter),
+                })
+                .collect();
+
+            format!("[{}]", algorithms.join(", "))
+        },
+
+        _ => format_value(value),
+    }
+}
+
+pub fn describe_parameters(ctap_command: u8, is_response: bool, parameters: &[u8]) -> String {
+    /* Top-level Map Keys are named, anything else is plain Diagnostic Notation */
+    if parameters.is_empty() {
+        return String::new();
+    }
+
+    let value = match decode(parameters) {
+        Ok((value, _)) => value,
+        Err(error) => return format!("<{}> {}", error, format_bytes(parameters)),
+    };
+
+    let CborValue::Map(entries) = &value else { return format_value(&value) };
+    let parameters: Vec<String> = entries
+        .iter()
+        .map(|(key, value)| {
+            let parameter_name = match key {
+                CborValue::Unsigned(key) => get_parameter_name(ctap_command, is_response, *key),
+                _ => None
+            };
+
+            match parameter_name {
+                Some(parameter_name) => format!("{}: {}", parameter_name, format_parameter(ctap_command, parameter_name, value)),
+                None => format!("{}: {}", format_value(key), format_value(value)),
+            }
+        })
+        .collect();
+
+    format!("{{{}}}", parameters.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_rfc_8949_examples() {
+        let (value, length) = decode(&[0xA2, 0x01, 0x63, b'a', b'b', b'c', 0x20, 0x82, 0x42, 0x01, 0x02, 0xF5]).unwrap();
+        assert_eq!(length, 12);
+        assert_eq!(format_value(&value), "{1: \"abc\", -1: [h'0102', true]}");
+
+        /* Indefinite Array of a Half Float, a Tag and Null */
+        let (value, _) = decode(&[0x9F, 0xF9, 0x3C, 0x00, 0xC1, 0x1A, 0x51, 0x4B, 0x67, 0xB0, 0xF6, 0xFF]).unwrap();
+        assert_eq!(value, CborValue::Array(vec![
+            CborValue::Float(1.0),
+            CborValue::Tag(1, Box::new(CborValue::Unsigned(1363896240))),
+            CborValue::Null,
+        ]));
+
+        let (value, _) = decode(&[0x3B, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]).unwrap();
+        assert_eq!(value, CborValue::Negative(-18446744073709551616));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        for data in [
+            &[][..],
+            &[0x63, b'a', b'b'],
+            &[0x19, 0x01],
+            &[0xA1, 0x01],
+            &[0x9F, 0x01, 0x02],
+            &[0x5B, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+            &[0x9B, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+        ] {
+            assert!(decode(data).is_err(), "{:02X?}", data);
+        }
+
+        assert_eq!(decode(&[0x81; 32]), Err(String::from("CBOR nested too deeply")));
+        assert!(decode(&[0x1C]).is_err());
+    }
+}
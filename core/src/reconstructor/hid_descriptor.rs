@@ -0,0 +1,764 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+
+use super::protocol_control::format_hex;
+
+/*
+    HID Report Descriptor Parser, Device Class Definition for HID 1.11
+    Section 6.2.2. Usages are extended: Usage Page << 16 | Usage ID
+    https://www.usb.org/sites/default/files/hid1_11.pdf
+    https://usb.org/sites/default/files/hut1_5.pdf
+*/
+
+/* Define Constants */
+pub const USAGE_PAGE_GENERIC_DESKTOP: u16 = 0x01;
+pub const USAGE_PAGE_KEYBOARD: u16 = 0x07;
+pub const USAGE_PAGE_LED: u16 = 0x08;
+pub const USAGE_PAGE_BUTTON: u16 = 0x09;
+pub const USAGE_PAGE_CONSUMER: u16 = 0x0C;
+pub const USAGE_PAGE_DIGITIZER: u16 = 0x0D;
+const USAGE_PAGE_VENDOR: u16 = 0xFF00;
+const ITEM_LONG: u8 = 0xFE;
+const MAX_REPORT_SIZE: usize = 32; /* Bits, Values are read into 32 bits */
+const FLAG_CONSTANT: u32 = 0x01;
+const FLAG_VARIABLE: u32 = 0x02;
+const FLAG_RELATIVE: u32 = 0x04;
+const COLLECTION_APPLICATION: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReportKind {
+    Input,
+    Output,
+    Feature
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportField {
+    pub kind: ReportKind,
+    pub report_id: u8,
+    pub flags: u32,
+    pub bit_offset: usize, /* From the first byte after the Report ID */
+    pub bit_size: usize,
+    pub count: usize,
+    pub usages: Vec<u32>,
+    pub usage_range: Option<(u32, u32)>,
+    pub logical_min: i64,
+    pub logical_max: i64,
+    pub physical_min: i64,
+    pub physical_max: i64,
+    pub unit: u32,
+    pub unit_exponent: i32,
+    pub application: u32,     /* Usage of the enclosing Application Collection */
+    pub collection_id: usize, /* Innermost Collection, unique within the Descriptor */
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReportDescriptor {
+    pub fields: Vec<ReportField>,
+    pub has_report_ids: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct GlobalState {
+    usage_page: u16,
+    logical_min: (i64, i64), /* Signed, Unsigned */
+    logical_max: (i64, i64),
+    physical_min: (i64, i64),
+    physical_max: (i64, i64),
+    unit_exponent: i32,
+    unit: u32,
+    report_size: usize,
+    report_id: u8,
+    report_count: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct LocalState {
+    usages: Vec<(u32, bool)>, /* Usage, is Extended */
+    usage_min: Option<(u32, bool)>,
+    usage_max: Option<(u32, bool)>,
+}
+
+impl ReportKind {
+    pub fn from_report_type(report_type: u8) -> Option<Self> {
+        /* wValue high byte of GET_REPORT and SET_REPORT */
+        match report_type {
+            1 => Some(ReportKind::Input),
+            2 => Some(ReportKind::Output),
+            3 => Some(ReportKind::Feature),
+            _ => None
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ReportKind::Input => "Input",
+            ReportKind::Output => "Output",
+            ReportKind::Feature => "Feature",
+        }
+    }
+}
+
+fn read_item_data(item_data: &[u8]) -> (u32, i64) {
+    /* Unsigned and sign-extended Value of a Short Item */
+    let unsigned = item_data.iter().rev().fold(0u32, |value, byte| (value << 8) | *byte as u32);
+    let signed = match item_data.len() {
+        1 => unsigned as u8 as i8 as i64,
+        2 => unsigned as u16 as i16 as i64,
+        4 => unsigned as i32 as i64,
+        _ => 0,
+    };
+
+    (unsigned, signed)
+}
+
+fn resolve_usage(usage_page: u16, (usage, is_extended): (u32, bool)) -> u32 {
+    if is_extended { usage } else { (usage_page as u32) << 16 | (usage & 0xFFFF) }
+}
+
+fn iterate_items(descriptor_data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    /* Yields the Prefix (Type and Tag) and Data of each Short Item, Long Items are skipped */
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        loop {
+            let prefix = *descriptor_data.get(offset)?;
+            if prefix == ITEM_LONG {
+                offset += 3 + *descriptor_data.get(offset + 1)? as usize;
+                continue;
+            }
+
+            let data_size = [0, 1, 2, 4][(prefix & 0x03) as usize];
+            let item_data = descriptor_data.get((offset + 1)..(offset + 1 + data_size))?;
+            offset += 1 + data_size;
+            return Some((prefix & 0xFC, item_data));
+        }
+    })
+}
+
+fn read_bits(data: &[u8], bit_offset: usize, bit_size: usize) -> Option<u64> {
+    /* Fields are packed Little-Endian, LSB first */
+    if bit_size == 0 || bit_size > MAX_REPORT_SIZE || bit_offset.checked_add(bit_size).is_none_or(|bit_end| bit_end > data.len() * 8) {
+        return None;
+    }
+
+    let value = (0..bit_size).fold(0u64, |value, bit| {
+        let position = bit_offset + bit;
+        value | ((((data[position / 8] >> (position % 8)) & 0x01) as u64) << bit)
+    });
+
+    Some(value)
+}
+
+impl ReportField {
+    pub fn is_constant(&self) -> bool {
+        self.flags & FLAG_CONSTANT != 0
+    }
+
+    pub fn is_variable(&self) -> bool {
+        self.flags & FLAG_VARIABLE != 0
+    }
+
+    pub fn is_relative(&self) -> bool {
+        self.flags & FLAG_RELATIVE != 0
+    }
+
+    pub fn get_usage_page(&self) -> u16 {
+        self.usages.first().or(self.usage_range.as_ref().map(|(usage_min, _)| usage_min)).map(|usage| (usage >> 16) as u16).unwrap_or(0)
+    }
+
+    pub fn get_usage(&self, index: usize) -> Option<u32> {
+        /* Variable Fields: Usages are assigned in Order, the last one repeats */
+        match self.usage_range {
+            Some((usage_min, usage_max)) => Some(u32::try_from(index).ok().and_then(|index| usage_min.checked_add(index)).map_or(usage_max, |usage| usage.min(usage_max))),
+            None => self.usages.get(index).or(self.usages.last()).copied(),
+        }
+    }
+
+    pub fn get_array_usage(&self, value: i64) -> Option<u32> {
+        /* Array Fields: the Value selects a Usage, out of range means none */
+        if value < self.logical_min || value > self.logical_max {
+            return None;
+        }
+
+        let index = u32::try_from(value - self.logical_min).ok()?;
+        let usage = match self.usage_range {
+            Some((usage_min, usage_max)) => usage_min.checked_add(index).filter(|usage| *usage <= usage_max),
+            None => self.usages.get(index as usize).copied(),
+        }?;
+
+        (usage & 0xFFFF != 0).then_some(usage)
+    }
+
+    pub fn get_payload_count(&self, payload: &[u8]) -> usize {
+        /* Values present in the Payload, the declared Report Count is not trusted */
+        match self.bit_size {
+            0 => 0,
+            bit_size => self.count.min((payload.len() * 8).saturating_sub(self.bit_offset) / bit_size),
+        }
+    }
+
+    pub fn read_value(&self, payload: &[u8], index: usize) -> Option<i64> {
+        let bit_offset = index.checked_mul(self.bit_size).and_then(|bit_offset| bit_offset.checked_add(self.bit_offset))?;
+        let raw_value = read_bits(payload, bit_offset, self.bit_size)?;
+        match self.logical_min < 0 && raw_value >> (self.bit_size - 1) & 0x01 != 0 {
+            true => Some(raw_value as i64 - (1i64 << self.bit_size)),
+            false => Some(raw_value as i64),
+        }
+    }
+
+    pub fn has_physical_value(&self) -> bool {
+        self.unit != 0 || self.unit_exponent != 0 || self.physical_min != 0 || self.physical_max != 0
+    }
+
+    pub fn get_physical_value(&self, logical_value: i64) -> f64 {
+        /* Physical Extents default to the Logical ones */
+        let (physical_min, physical_max) = match self.physical_min == 0 && self.physical_max == 0 {
+            true => (self.logical_min, self.logical_max),
+            false => (self.physical_min, self.physical_max),
+        };
+
+        let logical_range = (self.logical_max - self.logical_min) as f64;
+        let scaled = match logical_range == 0.0 {
+            true => logical_value as f64,
+            false => physical_min as f64 + (logical_value - self.logical_min) as f64 * (physical_max - physical_min) as f64 / logical_range,
+        };
+
+        scaled * 10f64.powi(self.unit_exponent)
+    }
+
+    pub fn format_value(&self, logical_value: i64) -> String {
+        /* Relative Values are Deltas, e.g. Mouse Movement */
+        if !self.has_physical_value() {
+            return if self.is_relative() { format!("{:+}", logical_value) } else { logical_value.to_string() };
+        }
+
+        let physical_value = (self.get_physical_value(logical_value) * 1000.0).round() / 1000.0;
+        let unit = format_unit(self.unit);
+        if unit.is_empty() { physical_value.to_string() } else { format!("{} {}", physical_value, unit) }
+    }
+}
+
+impl ReportDescriptor {
+    pub fn parse(descriptor_data: &[u8]) -> Self {
+        let mut report_descriptor = ReportDescriptor::default();
+        let mut global_state = GlobalState::default();
+        let mut global_stack: Vec<GlobalState> = vec![];
+        let mut local_state = LocalState::default();
+        let mut collection_stack: Vec<(u8, u32, usize)> = vec![]; /* Type, Usage, Collection ID */
+        let mut collection_count = 0;
+        let mut bit_offsets: HashMap<(ReportKind, u8), usize> = HashMap::new();
+
+        for (prefix, item_data) in iterate_items(descriptor_data) {
+            let (unsigned, signed) = read_item_data(item_data);
+            match prefix {
+                /* Main Items */
+                0x80 | 0x90 | 0xB0 => {
+                    let kind = match prefix { 0x80 => ReportKind::Input, 0x90 => ReportKind::Output, _ => ReportKind::Feature };
+                    let usage_page = global_state.usage_page;
+                    let bit_offset = bit_offsets.entry((kind, global_state.report_id)).or_default();
+                    let field_bits = global_state.report_size.saturating_mul(global_state.report_count);
+
+                    /* Values wider than 32 bits cannot be read, the Field only takes its Space */
+                    if global_state.report_size > MAX_REPORT_SIZE {
+                        *bit_offset = bit_offset.saturating_add(field_bits);
+                        local_state = LocalState::default();
+                        continue;
+                    }
+
+                    /* Unsigned Maxima are common with non-negative Minima, e.g. 0x00..0xFF in one byte */
+                    let pick = |minimum: (i64, i64), maximum: (i64, i64)| match minimum.0 >= 0 {
+                        true => (minimum.0, maximum.1),
+                        false => (minimum.0, maximum.0),
+                    };
+
+                    let (logical_min, logical_max) = pick(global_state.logical_min, global_state.logical_max);
+                    let (physical_min, physical_max) = pick(global_state.physical_min, global_state.physical_max);
+                    let application = collection_stack.iter().rev().find(|(collection_type, _, _)| *collection_type == COLLECTION_APPLICATION);
+
+                    report_descriptor.fields.push(ReportField {
+                        kind,
+                        report_id: global_state.report_id,
+                        flags: unsigned,
+                        bit_offset: *bit_offset,
+                        bit_size: global_state.report_size,
+                        count: global_state.report_count,
+                        usages: local_state.usages.iter().map(|usage| resolve_usage(usage_page, *usage)).collect(),
+                        usage_range: local_state.usage_min.zip(local_state.usage_max).map(|(usage_min, usage_max)| {
+                            (resolve_usage(usage_page, usage_min), resolve_usage(usage_page, usage_max))
+                        }),
+                        logical_min,
+                        logical_max,
+                        physical_min,
+                        physical_max,
+                        unit: global_state.unit,
+                        unit_exponent: global_state.unit_exponent,
+                        application: application.map(|(_, usage, _)| *usage).unwrap_or(0),
+                        collection_id: collection_stack.last().map(|(_, _, collection_id)| *collection_id).unwrap_or(0),
+                    });
+
+                    *bit_offset = bit_offset.saturating_add(field_bits);
+                    local_state = LocalState::default();
+                },
+
+                0xA0 => {
+                    collection_count += 1;
+                    let usage = local_state.usages.first().map(|usage| resolve_usage(global_state.usage_page, *usage)).unwrap_or(0);
+                    collection_stack.push((unsigned as u8, usage, collection_count));
+                    local_state = LocalState::default();
+                },
+
+                0xC0 => {
+                    collection_stack.pop();
+                    local_state = LocalState::default();
+                },
+
+                /* Global Items */
+                0x04 => global_state.usage_page = unsigned as u16,
+                0x14 => global_state.logical_min = (signed, unsigned as i64),
+                0x24 => global_state.logical_max = (signed, unsigned as i64),
+                0x34 => global_state.physical_min = (signed, unsigned as i64),
+                0x44 => global_state.physical_max = (signed, unsigned as i64),
+                0x54 => {
+                    /* Specified as a signed Nibble, some Devices send a whole signed Byte */
+                    global_state.unit_exponent = match unsigned {
+                        0x00..=0x07 => unsigned as i32,
+                        0x08..=0x0F => unsigned as i32 - 16,
+                        _ => signed as i32,
+                    };
+                },
+                0x64 => global_state.unit = unsigned,
+                0x74 => global_state.report_size = unsigned as usize,
+                0x84 => {
+                    global_state.report_id = unsigned as u8;
+                    report_descriptor.has_report_ids = true;
+                },
+                0x94 => global_state.report_count = unsigned as usize,
+                0xA4 => global_stack.push(global_state.clone()),
+                0xB4 => global_state = global_stack.pop().unwrap_or_default(),
+
+                /* Local Items */
+                0x08 => local_state.usages.push((unsigned, item_data.len() == 4)),
+                0x18 => local_state.usage_min = Some((unsigned, item_data.len() == 4)),
+                0x28 => local_state.usage_max = Some((unsigned, item_data.len() == 4)),
+                _ => {}
+            }
+        }
+
+        report_descriptor
+    }
+
+    pub fn split_report<'a>(&self, report_data: &'a [u8]) -> (u8, &'a [u8]) {
+        /* The Report ID prefixes every Report once the Descriptor declares any */
+        match (self.has_report_ids, report_data.split_first()) {
+            (true, Some((report_id, payload))) => (*report_id, payload),
+            _ => (0, report_data),
+        }
+    }
+
+    pub fn get_fields(&self, kind: ReportKind, report_id: u8) -> impl Iterator<Item = &ReportField> {
+        self.fields.iter().filter(move |field| field.kind == kind && field.report_id == report_id)
+    }
+}
+
+pub fn get_usage_page_name(usage_page: u16) -> String {
+    match usage_page {
+        0x01 => String::from("Generic Desktop Controls"),
+        0x02 => String::from("Simulation Controls"),
+        0x05 => String::from("Game Controls"),
+        0x06 => String::from("Generic Device Controls"),
+        0x07 => String::from("Keyboard"),
+        0x08 => String::from("LEDs"),
+        0x09 => String::from("Buttons"),
+        0x0B => String::from("Telephony"),
+        0x0C => String::from("Consumer"),
+        0x0D => String::from("Digitizer"),
+        0x0F => String::from("PID Page"),
+        0x14 => String::from("Auxiliary Display"),
+        0x20 => String::from("Sensor"),
+        0x40 => String::from("Medical Instruments"),
+        0x59 => String::from("Lighting and Illumination"),
+        0x84 => String::from("Power Device"),
+        0x85 => String::from("Battery System"),
+        0xF1D0 => String::from("FIDO Alliance"),
+        USAGE_PAGE_VENDOR..=0xFFFF => format!("Vendor Specific 0x{:04X}", usage_page),
+        _ => format!("Usage Page 0x{:02X}", usage_page),
+    }
+}
+
+fn get_keyboard_usage_name(usage_id: u16) -> Option<String> {
+    let name = match usage_id {
+        0x01 => "ErrorRollOver",
+        0x04..=0x1D => return Some(((b'A' + (usage_id - 0x04) as u8) as char).to_string()),
+        0x1E..=0x26 => return Some(((b'1' + (usage_id - 0x1E) as u8) as char).to_string()),
+        0x27 => "0",
+        0x28 => "Enter",
+        0x29 => "Escape",
+        0x2A => "Backspace",
+        0x2B => "Tab",
+        0x2C => "Space",
+        0x39 => "Caps Lock",
+        0x3A..=0x45 => return Some(format!("F{}", usage_id - 0x39)),
+        0x46 => "Print Screen",
+        0x47 => "Scroll Lock",
+        0x48 => "Pause",
+        0x49 => "Insert",
+        0x4A => "Home",
+        0x4B => "Page Up",
+        0x4C => "Delete",
+        0x4D => "End",
+        0x4E => "Page Down",
+        0x4F => "Right Arrow",
+        0x50 => "Left Arrow",
+        0x51 => "Down Arrow",
+        0x52 => "Up Arrow",
+        0x53 => "Num Lock",
+        0x65 => "Application",
+        0xE0 => "Left Control",
+        0xE1 => "Left Shift",
+        0xE2 => "Left Alt",
+        0xE3 => "Left GUI",
+        0xE4 => "Right Control",
+        0xE5 => "Right Shift",
+        0xE6 => "Right Alt",
+        0xE7 => "Right GUI",
+        _ => return None
+    };
+
+    Some(String::from(name))
+}
+
+pub fn get_usage_name(usage: u32) -> String {
+    let (usage_page, usage_id) = ((usage >> 16) as u16, (usage & 0xFFFF) as u16);
+    let name = match (usage_page, usage_id) {
+        (USAGE_PAGE_GENERIC_DESKTOP, usage_id) => match usage_id {
+            0x01 => "Pointer",
+            0x02 => "Mouse",
+            0x04 => "Joystick",
+            0x05 => "Gamepad",
+            0x06 => "Keyboard",
+            0x07 => "Keypad",
+            0x08 => "Multi-axis Controller",
+            0x30 => "X",
+            0x31 => "Y",
+            0x32 => "Z",
+            0x33 => "Rx",
+            0x34 => "Ry",
+            0x35 => "Rz",
+            0x36 => "Slider",
+            0x37 => "Dial",
+            0x38 => "Wheel",
+            0x39 => "Hat Switch",
+            0x3D => "Start",
+            0x3E => "Select",
+            0x80 => "System Control",
+            0x81 => "System Power Down",
+            0x82 => "System Sleep",
+            0x83 => "System Wake Up",
+            0x90 => "D-pad Up",
+            0x91 => "D-pad Down",
+            0x92 => "D-pad Right",
+            0x93 => "D-pad Left",
+            _ => ""
+        },
+
+        (0x02, usage_id) => match usage_id {
+            0xBA => "Rudder",
+            0xBB => "Throttle",
+            0xC4 => "Accelerator",
+            0xC5 => "Brake",
+            0xC8 => "Steering",
+            _ => ""
+        },
+
+        (USAGE_PAGE_KEYBOARD, usage_id) => return get_keyboard_usage_name(usage_id).unwrap_or(format!("Key 0x{:02X}", usage_id)),
+        (USAGE_PAGE_LED, usage_id) => match usage_id {
+            0x01 => "Num Lock",
+            0x02 => "Caps Lock",
+            0x03 => "Scroll Lock",
+            0x04 => "Compose",
+            0x05 => "Kana",
+            0x4B => "Generic Indicator",
+            _ => ""
+        },
+
+        (USAGE_PAGE_BUTTON, 0) => "No Button",
+        (USAGE_PAGE_BUTTON, usage_id) => return format!("Button {}", usage_id),
+        (USAGE_PAGE_CONSUMER, usage_id) => match usage_id {
+            0x01 => "Consumer Control",
+            0xB5 => "Scan Next Track",
+            0xB6 => "Scan Previous Track",
+            0xB7 => "Stop",
+            0xCD => "Play/Pause",
+            0xE2 => "Mute",
+            0xE9 => "Volume Increment",
+            0xEA => "Volume Decrement",
+            0x183 => "AL Consumer Control Configuration",
+            0x18A => "AL Email Reader",
+            0x192 => "AL Calculator",
+            0x194 => "AL Local Machine Browser",
+            0x221 => "AC Search",
+            0x223 => "AC Home",
+            0x224 => "AC Back",
+            0x225 => "AC Forward",
+            0x238 => "AC Pan",
+            _ => ""
+        },
+
+        (USAGE_PAGE_DIGITIZER, usage_id) => match usage_id {
+            0x01 => "Digitizer",
+            0x02 => "Pen",
+            0x04 => "Touch Screen",
+            0x05 => "Touch Pad",
+            0x0E => "Device Configuration",
+            0x20 => "Stylus",
+            0x22 => "Finger",
+            0x30 => "Tip Pressure",
+            0x32 => "In Range",
+            0x3D => "X Tilt",
+            0x3E => "Y Tilt",
+            0x42 => "Tip Switch",
+            0x44 => "Barrel Switch",
+            0x45 => "Eraser",
+            0x47 => "Confidence",
+            0x48 => "Width",
+            0x49 => "Height",
+            0x51 => "Contact Identifier",
+            0x54 => "Contact Count",
+            0x55 => "Contact Count Maximum",
+            0x56 => "Scan Time",
+            0x5B => "Transducer Serial Number",
+            _ => ""
+        },
+
+        (0xF1D0, usage_id) => match usage_id {
+            0x01 => "U2F Authenticator Device",
+            0x20 => "Input Report Data",
+            0x21 => "Output Report Data",
+            _ => ""
+        },
+
+        _ => ""
+    };
+
+    match name.is_empty() {
+        true => format!("{} 0x{:02X}", get_usage_page_name(usage_page), usage_id),
+        false => String::from(name),
+    }
+}
+
+pub fn format_unit(unit: u32) -> String {
+    /* Nibble 0 is the System, Nibbles 1..6 are signed Exponents of Length, Mass, Time, Temperature, Current and Luminous Intensity */
+    let unit_names: [&str; 6] = match unit & 0x0F {
+        0x01 => ["cm", "g", "s", "K", "A", "cd"],
+        0x02 => ["rad", "g", "s", "K", "A", "cd"],
+        0x03 => ["in", "slug", "s", "°F", "A", "cd"],
+        0x04 => ["deg", "slug", "s", "°F", "A", "cd"],
+        _ => return String::new()
+    };
+
+    unit_names
+        .iter()
+        .enumerate()
+        .filter_map(|(index, unit_name)| {
+            let nibble = ((unit >> (4 * (index + 1))) & 0x0F) as i32;
+            match if nibble > 7 { nibble - 16 } else { nibble } {
+                0 => None,
+                1 => Some(unit_name.to_string()),
+                exponent => Some(format!("{}^{}", unit_name, exponent)),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("*")
+}
+
+fn describe_field(field: &ReportField, payload: &[u8]) -> Option<String> {
+    /* Padding and Constant Fields carry no Information */
+    if field.is_constant() || field.bit_size == 0 {
+        return None;
+    }
+
+    /* Vendor Blobs are shown as Hex */
+    if field.get_usage_page() >= USAGE_PAGE_VENDOR && field.bit_size == 8 && field.count > 4 {
+        let start = field.bit_offset / 8;
+        let blob = payload.get(start..start.saturating_add(field.count).min(payload.len()))?;
+        return Some(format!("{}=[{}]", get_usage_name(field.get_usage(0)?), format_hex(blob)));
+    }
+
+    if !field.is_variable() {
+        let pressed: Vec<String> = (0..field.get_payload_count(payload))
+            .filter_map(|index| field.read_value(payload, index))
+            .filter_map(|value| field.get_array_usage(value))
+            .map(get_usage_name)
+            .collect();
+
+        return (!pressed.is_empty()).then(|| format!("[{}]", pressed.join(", ")));
+    }
+
+    /* One-bit Switches are listed when set */
+    if field.bit_size == 1 && field.logical_min == 0 && field.logical_max == 1 {
+        let pressed: Vec<String> = (0..field.get_payload_count(payload))
+            .filter(|index| field.read_value(payload, *index) == Some(1))
+            .filter_map(|index| field.get_usage(index))
+            .map(get_usage_name)
+            .collect();
+
+        return (!pressed.is_empty()).then(|| format!("[{}]", pressed.join(", ")));
+    }
+
+    let values: Vec<String> = (0..field.get_payload_count(payload))
+        .filter_map(|index| Some(format!("{}={}", get_usage_name(field.get_usage(index)?), field.format_value(field.read_value(payload, index)?))))
+        .collect();
+
+    (!values.is_empty()).then(|| values.join(" "))
+}
+
+pub fn describe_report(report_descriptor: &ReportDescriptor, kind: ReportKind, report_data: &[u8]) -> String {
+    let (report_id, payload) = report_descriptor.split_report(report_data);
+    let fields: Vec<&ReportField> = report_descriptor.get_fields(kind, report_id).collect();
+    let report_name = match report_descriptor.has_report_ids {
+        true => format!("{} Report {}", kind.get_name(), report_id),
+        false => format!("{} Report", kind.get_name()),
+    };
+
+    let Some(first_field) = fields.first() else {
+        return format!("{} (Undeclared): {}", report_name, format_hex(payload));
+    };
+
+    let application = match first_field.application {
+        0 => String::new(),
+        application => format!(" ({})", get_usage_name(application)),
+    };
+
+    let values: Vec<String> = fields.iter().filter_map(|field| describe_field(field, payload)).collect();
+    format!("{}{}: {}", report_name, application, if values.is_empty() { String::from("Idle") } else { values.join(" ") })
+}
+
+pub fn format_report_items(descriptor_data: &[u8]) -> Vec<(String, Option<String>)> {
+    /* Item Lines and their Explanation, as printed by lsusb -v */
+    let mut usage_page = 0u16;
+    iterate_items(descriptor_data)
+        .map(|(prefix, item_data)| {
+            let (unsigned, _) = read_item_data(item_data);
+            let (item_type, item_name) = match prefix {
+                0x80 => ("Main  ", "Input"),
+                0x90 => ("Main  ", "Output"),
+                0xB0 => ("Main  ", "Feature"),
+                0xA0 => ("Main  ", "Collection"),
+                0xC0 => ("Main  ", "End Collection"),
+                0x04 => ("Global", "Usage Page"),
+                0x14 => ("Global", "Logical Minimum"),
+                0x24 => ("Global", "Logical Maximum"),
+                0x34 => ("Global", "Physical Minimum"),
+                0x44 => ("Global", "Physical Maximum"),
+                0x54 => ("Global", "Unit Exponent"),
+                0x64 => ("Global", "Unit"),
+                0x74 => ("Global", "Report Size"),
+                0x84 => ("Global", "Report ID"),
+                0x94 => ("Global", "Report Count"),
+                0xA4 => ("Global", "Push"),
+                0xB4 => ("Global", "Pop"),
+                0x08 => ("Local ", "Usage"),
+                0x18 => ("Local ", "Usage Minimum"),
+                0x28 => ("Local ", "Usage Maximum"),
+                0x38 => ("Local ", "Designator Index"),
+                0x78 => ("Local ", "String Index"),
+                0xA8 => ("Local ", "Delimiter"),
+                _ => ("Unknown", "Unknown"),
+            };
+
+            let explanation = match prefix {
+                0x04 => {
+                    usage_page = unsigned as u16;
+                    Some(get_usage_page_name(usage_page))
+                },
+                0x08 | 0x18 | 0x28 => Some(get_usage_name(resolve_usage(usage_page, (unsigned, item_data.len() == 4)))),
+                0xA0 => Some(String::from(match unsigned {
+                    0x00 => "Physical",
+                    0x01 => "Application",
+                    0x02 => "Logical",
+                    0x03 => "Report",
+                    0x04 => "Named Array",
+                    0x05 => "Usage Switch",
+                    0x06 => "Usage Modifier",
+                    _ => "Vendor Defined"
+                })),
+                0x80 | 0x90 | 0xB0 => {
+                    let flag_names = [
+                        ("Data", "Constant"), ("Array", "Variable"), ("Absolute", "Relative"), ("No_Wrap", "Wrap"),
+                        ("Linear", "Non_Linear"), ("Preferred_State", "No_Preferred_State"), ("No_Null_Position", "Null_State"),
+                        ("Non_Volatile", "Volatile"), ("Bitfield", "Buffered Bytes"),
+                    ];
+
+                    Some(flag_names
+                        .iter()
+                        .enumerate()
+                        .map(|(bit, (clear, set))| if unsigned & (1 << bit) != 0 { *set } else { *clear })
+                        .collect::<Vec<&str>>()
+                        .join(" "))
+                },
+                0x64 if unsigned != 0 => Some(format_unit(unsigned)),
+                _ => None
+            };
+
+            let hex_bytes: String = item_data.iter().map(|byte| format!("0x{:02x} ", byte)).collect();
+            (format!("Item({}): {}, data= [ {}] {}", item_type, item_name, hex_bytes, unsigned), explanation)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_report_count_at_payload() {
+        /* Keyboard Modifiers declaring a Report Count of 602 Million */
+        let descriptor = [
+            0x05, 0x01, 0x09, 0x06, 0xA1, 0x01, 0x05, 0x07, 0x19, 0xE0, 0x29, 0xE7, 0x15, 0x00, 0x25, 0x01,
+            0x75, 0x01, 0x97, 0x00, 0x00, 0xE0, 0x23, 0x81, 0x02, 0xC0,
+        ];
+
+        let report_descriptor = ReportDescriptor::parse(&descriptor);
+        assert_eq!(report_descriptor.fields[0].count, 0x23E00000);
+        assert_eq!(report_descriptor.fields[0].get_payload_count(&[0x02; 8]), 64);
+        assert_eq!(
+            describe_report(&report_descriptor, ReportKind::Input, &[0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            "Input Report (Keyboard): [Left Shift]"
+        );
+    }
+
+    #[test]
+    fn rejects_wide_fields_and_usage_overflow() {
+        /* 64 bit Field, then an Array whose Usage Range ends at the top of the Usage Space */
+        let descriptor = [
+            0x06, 0x00, 0xFF, 0x09, 0x01, 0xA1, 0x01, 0x75, 0x40, 0x95, 0x01, 0x81, 0x02,
+            0x1B, 0xFE, 0xFF, 0xFF, 0xFF, 0x2B, 0xFF, 0xFF, 0xFF, 0xFF, 0x15, 0x00, 0x26, 0xFF, 0x00, 0x75, 0x08, 0x95, 0x01, 0x81, 0x00, 0xC0,
+        ];
+
+        let report_descriptor = ReportDescriptor::parse(&descriptor);
+        assert_eq!(report_descriptor.fields.len(), 1);
+        assert_eq!(report_descriptor.fields[0].bit_offset, 64);
+
+        let field = &report_descriptor.fields[0];
+        assert_eq!(field.get_array_usage(1), Some(0xFFFFFFFF));
+        assert_eq!(field.get_array_usage(2), None);
+        assert_eq!(field.get_usage(usize::MAX), Some(0xFFFFFFFF));
+    }
+}
@@ -16,12 +16,63 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>. 
 */
 
+mod audio_descriptor;
+mod bluetooth_hci;
+mod device_lint;
+mod device_model;
+mod device_report;
+mod ethernet_frame;
+mod fido_cbor;
+mod hid_descriptor;
+mod protocol_ccid;
+mod protocol_cdc;
+mod protocol_at;
+mod protocol_audio;
+mod protocol_bluetooth;
+mod protocol_control;
+mod protocol_hid;
+mod protocol_hid_fido;
+mod protocol_hid_gamepad;
+mod protocol_hid_keyboard;
+mod protocol_hid_pointer;
+mod protocol_mbim;
+mod protocol_modbus;
+mod protocol_modem;
+mod protocol_ncm;
+mod protocol_net;
+mod protocol_nmea;
+mod protocol_ptp;
+mod protocol_qmi;
+mod protocol_rndis;
 mod protocol_serial;
+mod protocol_serial_vendor;
+mod protocol_ata;
 mod protocol_scsi;
+mod protocol_uas;
+mod protocol_uvc;
+mod ptp_container;
+mod serial_codec;
+mod smartcard_apdu;
+mod video_descriptor;
+mod serial_encoding;
+mod serial_framing;
 
+use std::collections::HashMap;
 use std::ptr;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use device_model::{DeviceModel, InterfaceModel, SetupPacket};
 use tokio::sync::mpsc::{Receiver, Sender};
-use crate::sniffer::{UrbXractHeader, UrbXractPacket};
+use tokio::time::MissedTickBehavior;
+use crate::sniffer::{UrbEventType, UrbTransferType, UrbXractHeader, UrbXractPacket};
+pub use device_lint::format_lint_report;
+pub use device_model::DeviceRegistry;
+pub use protocol_hid_keyboard::KeyboardLayout;
+pub use protocol_hid_pointer::{PointerKind, PointerSample, PointerTrail};
+pub use serial_encoding::SerialEncoding;
+pub use serial_framing::{parse_framing_config, FramingConfig};
+pub use device_report::{format_registry_report, get_device_tree, DeviceTreeLine};
 
 #[repr(C, packed)]
 #[derive(Debug)]
@@ -40,13 +91,12 @@ pub struct CommandBlockWrapper {
     command_data: [u8; 16],
 }
 
-#[allow(dead_code)]
 #[repr(C, packed)]
 pub struct CommandStatusWrapper {
     signature: u32,
     tag: u32,
     residue: u32,
-    status: bool,
+    status: u8,
 }
 
 #[derive(Debug)]
@@ -54,43 +104,313 @@ pub struct ReconstructedTransmission {
     pub urbx_header: UrbXractHeader,
     pub combined_payload: String,
     pub sources: Vec<UrbXractPacket>,
+    pub is_error: bool, /* Highlighted in the Packet Table */
+}
+
+#[derive(Clone, Default)]
+pub struct ModuleContext {
+    pub device_registry: Arc<RwLock<DeviceRegistry>>,
+    pub serial_encoding: SerialEncoding,
+    pub serial_framing: Arc<FramingConfig>,
+    pub keyboard_layout: KeyboardLayout,
+    pub pointer_trail: Arc<RwLock<PointerTrail>>,
+    pub audio_directory: Option<PathBuf>, /* WAV Export of Audio Streams */
+    pub video_directory: Option<PathBuf>, /* Frame Export of Video Streams */
+    pub ethernet_pcap: Option<PathBuf>, /* Export of CDC Networking Frames */
+    pub bluetooth_capture: Option<PathBuf>, /* Export of Bluetooth HCI Packets */
+    pub object_directory: Option<PathBuf>, /* Export of MTP and PTP Objects */
 }
 
 pub trait ReconstructionModule {
-    fn new(module_tx: Sender<ReconstructedTransmission>) -> Self;
+    fn new(module_tx: Sender<ReconstructedTransmission>, module_context: &ModuleContext) -> Self;
     async fn consume_packet(&mut self, urb_packet: UrbXractPacket);
+
+    /* Called with the Capture Time of every Packet and periodically, u64::MAX once the Capture ends */
+    async fn flush_idle(&mut self, _timestamp: u64) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ModuleKind {
+    Control,
+    Serial,
+    Hid,
+    Ccid,
+    Audio,
+    Video,
+    Network,
+    Modem,
+    Bluetooth,
+    Ptp,
+    Scsi,
+    Uas
+}
+
+struct ReconstructionModules {
+    control: protocol_control::Reconstructor,
+    serial: protocol_serial::Reconstructor,
+    hid: protocol_hid::Reconstructor,
+    ccid: protocol_ccid::Reconstructor,
+    audio: protocol_audio::Reconstructor,
+    video: protocol_uvc::Reconstructor,
+    network: protocol_net::Reconstructor,
+    modem: protocol_modem::Reconstructor,
+    bluetooth: protocol_bluetooth::Reconstructor,
+    ptp: protocol_ptp::Reconstructor,
+    scsi: protocol_scsi::Reconstructor,
+    uas: protocol_uas::Reconstructor,
 }
 
 /* Define Constants  */
 const COMMAND_BLK_WRAP_SIGNATURE: u32 = 0x43425355;
+const COMMAND_STS_WRAP_SIGNATURE: u32 = 0x53425355;
+const IDLE_FLUSH_INTERVAL: Duration = Duration::from_millis(50); /* Live Captures without new Packets */
+
+/* Interface Class, Subclass, Protocol to Module (None matches any value) */
+const CLASS_MODULES: &[(u8, Option<u8>, Option<u8>, ModuleKind)] = &[
+    (0x08, None, Some(0x62), ModuleKind::Uas),    /* Mass Storage, UAS */
+    (0x08, None, Some(0x50), ModuleKind::Scsi),   /* Mass Storage, Bulk-Only Transport */
+    (0x02, Some(0x0E), None, ModuleKind::Modem),  /* CDC Mobile Broadband, MBIM */
+    (0x02, None, None, ModuleKind::Serial),       /* CDC Communications */
+    (0x0A, None, None, ModuleKind::Serial),       /* CDC Data */
+    (0x03, None, None, ModuleKind::Hid),          /* Human Interface Device */
+    (0xFF, Some(0x5D), Some(0x01), ModuleKind::Hid), /* Xbox 360 Controller, XInput */
+    (0x0B, None, None, ModuleKind::Ccid),         /* Smart Card, CCID */
+    (0x01, Some(0x01), None, ModuleKind::Audio),  /* Audio Control */
+    (0x01, Some(0x02), None, ModuleKind::Audio),  /* Audio Streaming */
+    (0x0E, None, None, ModuleKind::Video),        /* Video */
+    (0xE0, Some(0x01), Some(0x01), ModuleKind::Bluetooth), /* Bluetooth Programming Interface */
+    (0x06, Some(0x01), Some(0x01), ModuleKind::Ptp),       /* Still Image, PTP and MTP */
+];
+
+/* Interface Class to Module receiving its Class Requests from the Default Pipe */
+const CONTROL_MODULES: &[(u8, ModuleKind)] = &[
+    (0x02, ModuleKind::Serial),                   /* CDC Communications */
+    (0x03, ModuleKind::Hid),                      /* Human Interface Device */
+    (0x0B, ModuleKind::Ccid),                     /* Smart Card, CCID */
+    (0x01, ModuleKind::Audio),                    /* Audio */
+    (0x0E, ModuleKind::Video),                    /* Video */
+    (0x06, ModuleKind::Ptp),                      /* Still Image */
+];
+
+impl ReconstructionModules {
+    fn new(consume_tx: Sender<ReconstructedTransmission>, module_context: &ModuleContext) -> Self {
+        Self {
+            control: protocol_control::Reconstructor::new(consume_tx.clone(), module_context),
+            serial: protocol_serial::Reconstructor::new(consume_tx.clone(), module_context),
+            hid: protocol_hid::Reconstructor::new(consume_tx.clone(), module_context),
+            ccid: protocol_ccid::Reconstructor::new(consume_tx.clone(), module_context),
+            audio: protocol_audio::Reconstructor::new(consume_tx.clone(), module_context),
+            video: protocol_uvc::Reconstructor::new(consume_tx.clone(), module_context),
+            network: protocol_net::Reconstructor::new(consume_tx.clone(), module_context),
+            modem: protocol_modem::Reconstructor::new(consume_tx.clone(), module_context),
+            bluetooth: protocol_bluetooth::Reconstructor::new(consume_tx.clone(), module_context),
+            ptp: protocol_ptp::Reconstructor::new(consume_tx.clone(), module_context),
+            scsi: protocol_scsi::Reconstructor::new(consume_tx.clone(), module_context),
+            uas: protocol_uas::Reconstructor::new(consume_tx.clone(), module_context),
+        }
+    }
+
+    async fn dispatch(&mut self, module_kind: ModuleKind, urb_packet: UrbXractPacket) {
+        match module_kind {
+            ModuleKind::Control => self.control.consume_packet(urb_packet).await,
+            ModuleKind::Serial => self.serial.consume_packet(urb_packet).await,
+            ModuleKind::Hid => self.hid.consume_packet(urb_packet).await,
+            ModuleKind::Ccid => self.ccid.consume_packet(urb_packet).await,
+            ModuleKind::Audio => self.audio.consume_packet(urb_packet).await,
+            ModuleKind::Video => self.video.consume_packet(urb_packet).await,
+            ModuleKind::Network => self.network.consume_packet(urb_packet).await,
+            ModuleKind::Modem => self.modem.consume_packet(urb_packet).await,
+            ModuleKind::Bluetooth => self.bluetooth.consume_packet(urb_packet).await,
+            ModuleKind::Ptp => self.ptp.consume_packet(urb_packet).await,
+            ModuleKind::Scsi => self.scsi.consume_packet(urb_packet).await,
+            ModuleKind::Uas => self.uas.consume_packet(urb_packet).await,
+        }
+    }
+
+    async fn flush_idle(&mut self, timestamp: u64) {
+        self.control.flush_idle(timestamp).await;
+        self.serial.flush_idle(timestamp).await;
+        self.hid.flush_idle(timestamp).await;
+        self.ccid.flush_idle(timestamp).await;
+        self.audio.flush_idle(timestamp).await;
+        self.video.flush_idle(timestamp).await;
+        self.network.flush_idle(timestamp).await;
+        self.modem.flush_idle(timestamp).await;
+        self.bluetooth.flush_idle(timestamp).await;
+        self.ptp.flush_idle(timestamp).await;
+        self.scsi.flush_idle(timestamp).await;
+        self.uas.flush_idle(timestamp).await;
+    }
+}
+
+fn get_class_module(interface: &InterfaceModel) -> Option<ModuleKind> {
+    CLASS_MODULES
+        .iter()
+        .find(|(class, subclass, protocol, _)| {
+            *class == interface.class
+                && subclass.is_none_or(|subclass| subclass == interface.subclass)
+                && protocol.is_none_or(|protocol| protocol == interface.protocol)
+        })
+        .map(|(_, _, _, module_kind)| *module_kind)
+}
+
+fn get_interface_module(device: &DeviceModel, interface: &InterfaceModel) -> Option<ModuleKind> {
+    /* CDC Data Interfaces carry Ethernet Frames when their Control Interface is a Networking Model */
+    if protocol_net::get_network_kind(device, interface).is_some() {
+        return Some(ModuleKind::Network);
+    }
+
+    /* Broadcom based Bluetooth Controllers use a Vendor Interface */
+    if protocol_bluetooth::is_hci_interface(device, interface) {
+        return Some(ModuleKind::Bluetooth);
+    }
+
+    /* MTP Responders may use a Vendor Interface named MTP */
+    if protocol_ptp::is_mtp_interface(device, interface) {
+        return Some(ModuleKind::Ptp);
+    }
+
+    get_class_module(interface)
+}
+
+fn get_control_module(device_registry: &DeviceRegistry, urb_header: &UrbXractHeader) -> Option<ModuleKind> {
+    /* Class Requests addressed to an Interface or its Endpoint belong to the Class Module */
+    let setup_packet = SetupPacket::from_bytes(urb_header.setup_packet.as_ref()?);
+    let device = device_registry.get_device(urb_header);
+    let serial_vendor = device
+        .and_then(|device| device.descriptor.as_ref())
+        .and_then(|descriptor| protocol_serial_vendor::SerialVendor::from_ids(descriptor.vendor_id, descriptor.product_id));
 
-async fn consume_core(consume_tx: Sender<ReconstructedTransmission>, mut sniffer_rx: Receiver<UrbXractPacket>) {
+    /* Vendor Serial Adapters configure the UART with Class and Vendor Requests */
+    if serial_vendor.is_some() && (setup_packet.request_type >> 5) & 0x03 != 0 {
+        return Some(ModuleKind::Serial);
+    }
+
+    if (setup_packet.request_type >> 5) & 0x03 != 1 {
+        return None;
+    }
+
+    /* Bluetooth Controllers take HCI Commands addressed to the Device */
+    let interface = match setup_packet.request_type & 0x1F {
+        0x00 => device.and_then(protocol_bluetooth::get_hci_interface),
+        0x01 => device.and_then(|device| device.get_interface_by_number(setup_packet.index as u8)),
+        0x02 => device.and_then(|device| device.get_endpoint_interface(setup_packet.index as u8)),
+        _ => return None,
+    };
+
+    match device.zip(interface) {
+        Some((device, interface)) if protocol_bluetooth::is_hci_interface(device, interface) => Some(ModuleKind::Bluetooth),
+        Some((_, interface)) if protocol_modem::is_modem_request(interface, &setup_packet) => Some(ModuleKind::Modem),
+        Some((device, interface)) if protocol_net::get_network_kind(device, interface).is_some() => Some(ModuleKind::Network),
+        Some((_, interface)) => CONTROL_MODULES
+            .iter()
+            .find(|(class, _)| *class == interface.class)
+            .map(|(_, module_kind)| *module_kind),
+
+        /* Without Enumeration, ACM Requests are distinct enough */
+        None if protocol_cdc::is_acm_request(&setup_packet) => Some(ModuleKind::Serial),
+        None => None
+    }
+}
+
+fn get_heuristic_module(heuristic_routes: &mut HashMap<String, ModuleKind>, urb_packet: &UrbXractPacket) -> ModuleKind {
+    /*
+        Used when Enumeration was not captured, the Payload decides the Module
+        and the Device keeps that Module for the following Data and Status phases
+    */
+    let urb_header = &urb_packet.header;
+    let urb_data = urb_packet.data.as_ref().unwrap();
+    if urb_header.transfer_type != UrbTransferType::Bulk {
+        return ModuleKind::Serial;
+    }
+
+    let device_key = device_model::get_device_key(urb_header);
+    if (urb_header.endpoint_info & 0b10000000) == 0 && protocol_uas::is_command_iu(urb_data) {
+        /* Devices sending UAS Command IUs use separate Pipes, route all their traffic */
+        heuristic_routes.insert(device_key.clone(), ModuleKind::Uas);
+    } else if urb_data.len() >= size_of::<CommandBlockWrapper>() {
+        /* Check for CommandBlockWrapper */
+        let cbw_packet = unsafe { ptr::read_unaligned(urb_data.as_ptr() as *const CommandBlockWrapper) };
+        if cbw_packet.signature == COMMAND_BLK_WRAP_SIGNATURE {
+            heuristic_routes.entry(device_key.clone()).or_insert(ModuleKind::Scsi);
+        }
+    }
+
+    *heuristic_routes.get(&device_key).unwrap_or(&ModuleKind::Serial)
+}
+
+async fn consume_core(consume_tx: Sender<ReconstructedTransmission>, mut sniffer_rx: Receiver<UrbXractPacket>, module_context: ModuleContext) {
     /* Enumerate and Define Plugin Modules */
-    let mut serial_reconstructor = protocol_serial::Reconstructor::new(consume_tx.clone());
-    let mut scsi_reconstructor = protocol_scsi::Reconstructor::new(consume_tx.clone());
-    
+    let mut reconstruction_modules = ReconstructionModules::new(consume_tx, &module_context);
+    let mut heuristic_routes: HashMap<String, ModuleKind> = HashMap::new(); /* Bus:Device, Module */
+    let mut control_routes: HashMap<u64, ModuleKind> = HashMap::new(); /* URB ID, Module */
+    let mut last_received: Option<(u64, Instant)> = None; /* Capture Time and Arrival of the last Packet */
+    let mut idle_interval = tokio::time::interval(IDLE_FLUSH_INTERVAL);
+    idle_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
     /* Consume Packets as Sniffer captures them */
-    while let Some(urb_packet) = sniffer_rx.recv().await {        
-        if urb_packet.data.is_some() {
-            /* Get URB Data */
-            let urb_data = urb_packet.data.as_ref().unwrap();
-            
-            /* Check for CommandBlockWrapper */
-            let cbw_packet = unsafe { ptr::read_unaligned(urb_data.as_ptr() as *const CommandBlockWrapper) };
-            if cbw_packet.signature == COMMAND_BLK_WRAP_SIGNATURE {
-                /* Usually a SCSI Command? */
-                scsi_reconstructor.consume_packet(urb_packet).await;
+    loop {
+        let urb_packet = tokio::select! {
+            urb_packet = sniffer_rx.recv() => match urb_packet {
+                Some(urb_packet) => urb_packet,
+                None => break,
+            },
+
+            /* A quiet Bus still ends idle Frames, the Capture Clock is advanced by the Time waited */
+            _ = idle_interval.tick() => {
+                if let Some((timestamp, received_at)) = last_received {
+                    reconstruction_modules.flush_idle(timestamp.saturating_add(received_at.elapsed().as_micros() as u64)).await;
+                }
+
+                continue;
+            }
+        };
+
+        /* Idle Timeouts follow Capture Time, so Capture Files frame like a live Capture */
+        reconstruction_modules.flush_idle(urb_packet.header.timestamp).await;
+        last_received = Some((urb_packet.header.timestamp, Instant::now()));
+
+        let module_kind = {
+            /* Snoop Enumeration to learn Interfaces and Endpoints */
+            let mut device_registry = module_context.device_registry.write().unwrap();
+            device_registry.observe_packet(&urb_packet);
+
+            /* Control Transfers complete without Data, their Status is still relevant */
+            if urb_packet.header.transfer_type == UrbTransferType::Control {
+                /* Only the Submission carries the Setup Packet, the Completion follows its Route */
+                let urb_header = &urb_packet.header;
+                match urb_header.event_type {
+                    UrbEventType::Submit => {
+                        let module_kind = get_control_module(&device_registry, urb_header).unwrap_or(ModuleKind::Control);
+                        control_routes.insert(urb_header.urb_id, module_kind);
+                        module_kind
+                    },
+                    _ => control_routes.remove(&urb_header.urb_id).unwrap_or(ModuleKind::Control)
+                }
+            } else if urb_packet.data.is_none() && urb_packet.iso_descriptors.is_empty() {
+                /* Isochronous Completions report Packet Errors without Data */
+                continue;
             } else {
-                /* Use the Serial Module */
-                serial_reconstructor.consume_packet(urb_packet).await;
+                /* Route by the Class of the Interface owning the Endpoint */
+                let device = device_registry.get_device(&urb_packet.header);
+                match device.zip(device_registry.get_interface(&urb_packet.header)) {
+                    Some((device, interface)) => get_interface_module(device, interface).unwrap_or(ModuleKind::Serial),
+                    None if urb_packet.data.is_none() => continue,
+                    None => get_heuristic_module(&mut heuristic_routes, &urb_packet),
+                }
             }
-        }
+        };
+
+        reconstruction_modules.dispatch(module_kind, urb_packet).await;
     }
+
+    /* Nothing follows the last Packet, close whatever is still open */
+    reconstruction_modules.flush_idle(u64::MAX).await;
 }
 
-pub fn consume(consume_tx: Sender<ReconstructedTransmission>, sniffer_rx: Receiver<UrbXractPacket>) {
+pub fn consume(consume_tx: Sender<ReconstructedTransmission>, sniffer_rx: Receiver<UrbXractPacket>, module_context: ModuleContext) -> tokio::task::JoinHandle<()> {
     tokio::spawn(async move {
         /* Call the core-consumer */
-        consume_core(consume_tx, sniffer_rx).await;
-    });
+        consume_core(consume_tx, sniffer_rx, module_context).await;
+    })
 }
@@ -0,0 +1,173 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::ReconstructedTransmission;
+
+/*
+    AT Command Transcripts (V.250, 3GPP TS 27.007). Rows from the Host
+    start a Transaction, Rows from the Modem are collected until a Final
+    Result Code. Modem Rows outside a Transaction, or carrying another
+    Command's Prefix, are Unsolicited Result Codes.
+*/
+const FINAL_RESULT_CODES: [&str; 6] = ["OK", "CONNECT", "NO CARRIER", "BUSY", "NO ANSWER", "NO DIALTONE"];
+const ERROR_RESULT_CODES: [&str; 4] = ["ERROR", "+CME ERROR", "+CMS ERROR", "COMMAND NOT SUPPORT"];
+const RESPONSE_TIMEOUT: u64 = 180_000_000; /* Microseconds, Network Scans (AT+COPS=?) take Minutes */
+
+struct AtTransaction {
+    command: String,
+    responses: Vec<String>,
+    transmission: ReconstructedTransmission, /* Header and Sources of the Command Row */
+    last_activity: u64,
+}
+
+#[derive(Default)]
+pub struct AtSession {
+    pending_transaction: Option<AtTransaction>,
+}
+
+fn is_command(row: &str) -> bool {
+    let row = row.as_bytes();
+    row.len() >= 2 && (row[..2].eq_ignore_ascii_case(b"AT") || row[..2].eq_ignore_ascii_case(b"A/"))
+}
+
+fn get_result_code(row: &str) -> Option<bool> {
+    /* Some(is_error) for Final Result Codes */
+    let is_match = |code: &&str| row == *code || row.strip_prefix(*code).is_some_and(|rest| rest.starts_with([':', ' ']));
+    if ERROR_RESULT_CODES.iter().any(is_match) {
+        return Some(true);
+    }
+
+    match FINAL_RESULT_CODES.iter().position(is_match) {
+        Some(0) | Some(1) => Some(false),
+        Some(_) => Some(true),
+        None => None
+    }
+}
+
+fn get_command_prefix(command: &str) -> Option<String> {
+    /* AT+CSQ? answers with +CSQ:, AT^SYSINFO with ^SYSINFO: */
+    let name: String = command.get(2..)?
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || ['+', '^', '$', '#', '%', '&'].contains(c))
+        .collect();
+
+    name.starts_with(['+', '^', '$', '#', '%']).then(|| name.to_ascii_uppercase())
+}
+
+fn is_unsolicited(row: &str, command: &str) -> bool {
+    /* Responses carrying a Prefix other than the Command's own */
+    if row == "RING" || row.starts_with("+CRING:") {
+        return true;
+    }
+
+    let Some((row_prefix, _)) = row.split_once(':') else { return false };
+    if !row_prefix.starts_with(['+', '^', '$', '#', '%']) || row_prefix.contains(' ') {
+        return false;
+    }
+
+    get_command_prefix(command).is_some_and(|command_prefix| !command_prefix.eq_ignore_ascii_case(row_prefix))
+}
+
+impl AtSession {
+    fn finish(&mut self, final_code: Option<(&str, bool)>, row: Option<ReconstructedTransmission>) -> Option<ReconstructedTransmission> {
+        let mut transaction = self.pending_transaction.take()?;
+        let end_timestamp = row.as_ref().map(|row| row.urbx_header.timestamp);
+        if let Some(row) = row {
+            transaction.transmission.sources.extend(row.sources);
+        }
+
+        let (final_code, is_error) = final_code.unwrap_or(("No Final Result Code", true));
+        let response_time = match end_timestamp {
+            Some(end_timestamp) => format!(" ({:.3} ms)", end_timestamp.saturating_sub(transaction.transmission.urbx_header.timestamp) as f64 / 1000.0),
+            None => String::new(),
+        };
+
+        transaction.transmission.combined_payload = format!(
+            "[AT] {} -> {}{}{}",
+            transaction.command,
+            transaction.responses.iter().map(|response| format!("{}; ", response)).collect::<String>(),
+            final_code,
+            response_time
+        );
+
+        transaction.transmission.is_error = is_error;
+        Some(transaction.transmission)
+    }
+
+    pub fn flush_idle(&mut self, timestamp: u64) -> Option<ReconstructedTransmission> {
+        /* A Modem that never answers still shows its Command */
+        let last_activity = self.pending_transaction.as_ref()?.last_activity;
+        match timestamp.saturating_sub(last_activity) > RESPONSE_TIMEOUT {
+            true => self.finish(None, None),
+            false => None,
+        }
+    }
+
+    pub fn consume_row(&mut self, mut row: ReconstructedTransmission, is_out: bool) -> Vec<ReconstructedTransmission> {
+        /* Returns the Rows to dispatch: completed Transactions and URCs */
+        let text = row.combined_payload.trim().to_string();
+        let mut dispatch_rows = vec![];
+
+        if is_out {
+            /* A new Command abandons the previous one */
+            if is_command(&text) {
+                dispatch_rows.extend(self.finish(None, None));
+                let last_activity = row.urbx_header.timestamp;
+                self.pending_transaction = Some(AtTransaction { command: text, responses: vec![], transmission: row, last_activity });
+            } else if let Some(transaction) = self.pending_transaction.as_mut() {
+                /* SMS Bodies and other Data after a > Prompt */
+                if !text.is_empty() {
+                    transaction.responses.push(format!("<{}>", text));
+                }
+
+                transaction.last_activity = row.urbx_header.timestamp;
+                transaction.transmission.sources.extend(row.sources);
+            } else if !text.is_empty() {
+                row.combined_payload = format!("[AT] {}", text);
+                dispatch_rows.push(row);
+            }
+
+            return dispatch_rows;
+        }
+
+        match &mut self.pending_transaction {
+            /* Empty Lines and Command Echo belong to the Transaction */
+            Some(transaction) if text.is_empty() || text.eq_ignore_ascii_case(&transaction.command) => {
+                transaction.last_activity = row.urbx_header.timestamp;
+                transaction.transmission.sources.extend(row.sources);
+            },
+
+            Some(transaction) if !is_unsolicited(&text, &transaction.command) => match get_result_code(&text) {
+                Some(is_error) => dispatch_rows.extend(self.finish(Some((&text, is_error)), Some(row))),
+                None => {
+                    transaction.responses.push(text);
+                    transaction.last_activity = row.urbx_header.timestamp;
+                    transaction.transmission.sources.extend(row.sources);
+                }
+            },
+
+            _ if text.is_empty() => {},
+            _ => {
+                row.combined_payload = format!("[AT URC] {}", text);
+                dispatch_rows.push(row);
+            }
+        }
+
+        dispatch_rows
+    }
+}
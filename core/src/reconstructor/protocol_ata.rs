@@ -0,0 +1,604 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::protocol_scsi::ScsiDataDirection;
+
+/*
+    Decoders for ATA and NVMe Commands tunnelled through SCSI by USB Bridges
+    SAT: https://www.t10.org/drafts.htm#SCSI3_SAT (ATA PASS-THROUGH)
+    Vendor Bridges: https://github.com/smartmontools/smartmontools (scsiata.cpp, scsinvme.cpp)
+*/
+const JMICRON_NVME_SIGNATURE: &[u8; 4] = b"NVME";
+const JMICRON_NVME_CMD_OFFSET: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AtaTaskfile {
+    pub protocol: Option<u8>,
+    pub features: u16,
+    pub count: u16,
+    pub lba: u64,
+    pub command: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NvmeCommand {
+    pub opcode: u8,
+    pub nsid: u32,
+    pub cdw10: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JMicronNvmePhase {
+    Command,
+    NonData,
+    DataIn,
+    DataOut,
+    Response
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BridgeCommand {
+    SatPassThrough(AtaTaskfile, ScsiDataDirection),
+    CypressAtacb(AtaTaskfile),
+    JMicronAta(AtaTaskfile, ScsiDataDirection),
+    JMicronNvme(JMicronNvmePhase),
+    ASMediaNvme(NvmeCommand),
+    RealtekNvme(NvmeCommand),
+}
+
+fn get_sat_protocol_name(protocol: u8) -> &'static str {
+    match protocol {
+        0x0 => "Hard Reset",
+        0x1 => "SRST",
+        0x3 => "Non-Data",
+        0x4 => "PIO Data-In",
+        0x5 => "PIO Data-Out",
+        0x6 => "DMA",
+        0x7 => "DMA Queued",
+        0x8 => "Device Diagnostic",
+        0x9 => "Device Reset",
+        0xA => "UDMA Data-In",
+        0xB => "UDMA Data-Out",
+        0xC => "FPDMA",
+        0xF => "Return Response",
+        _ => "Reserved"
+    }
+}
+
+pub fn get_ata_command_name(command: u8) -> &'static str {
+    /*
+        Command Info:
+        ACS-4, Table 206 (Command Codes)
+    */
+    match command {
+        0x00 => "NOP",
+        0x06 => "DATA SET MANAGEMENT",
+        0x20 => "READ SECTORS",
+        0x24 => "READ SECTORS EXT",
+        0x25 => "READ DMA EXT",
+        0x27 => "READ NATIVE MAX ADDRESS EXT",
+        0x2F => "READ LOG EXT",
+        0x30 => "WRITE SECTORS",
+        0x34 => "WRITE SECTORS EXT",
+        0x35 => "WRITE DMA EXT",
+        0x3F => "WRITE LOG EXT",
+        0x40 => "READ VERIFY SECTORS",
+        0x42 => "READ VERIFY SECTORS EXT",
+        0x47 => "READ LOG DMA EXT",
+        0x60 => "READ FPDMA QUEUED",
+        0x61 => "WRITE FPDMA QUEUED",
+        0x90 => "EXECUTE DEVICE DIAGNOSTIC",
+        0x92 => "DOWNLOAD MICROCODE",
+        0x93 => "DOWNLOAD MICROCODE DMA",
+        0xA1 => "IDENTIFY PACKET DEVICE",
+        0xB0 => "SMART",
+        0xB1 => "DEVICE CONFIGURATION",
+        0xC8 => "READ DMA",
+        0xCA => "WRITE DMA",
+        0xE0 => "STANDBY IMMEDIATE",
+        0xE1 => "IDLE IMMEDIATE",
+        0xE2 => "STANDBY",
+        0xE3 => "IDLE",
+        0xE5 => "CHECK POWER MODE",
+        0xE6 => "SLEEP",
+        0xE7 => "FLUSH CACHE",
+        0xEA => "FLUSH CACHE EXT",
+        0xEC => "IDENTIFY DEVICE",
+        0xEF => "SET FEATURES",
+        0xF1 => "SECURITY SET PASSWORD",
+        0xF2 => "SECURITY UNLOCK",
+        0xF3 => "SECURITY ERASE PREPARE",
+        0xF4 => "SECURITY ERASE UNIT",
+        0xF5 => "SECURITY FREEZE LOCK",
+        0xF6 => "SECURITY DISABLE PASSWORD",
+        0xF8 => "READ NATIVE MAX ADDRESS",
+        _ => "UNKNOWN"
+    }
+}
+
+fn get_smart_feature_name(feature: u8) -> &'static str {
+    match feature {
+        0xD0 => "READ DATA",
+        0xD1 => "READ ATTRIBUTE THRESHOLDS",
+        0xD2 => "ENABLE/DISABLE ATTRIBUTE AUTOSAVE",
+        0xD4 => "EXECUTE OFF-LINE IMMEDIATE",
+        0xD5 => "READ LOG",
+        0xD6 => "WRITE LOG",
+        0xD8 => "ENABLE OPERATIONS",
+        0xD9 => "DISABLE OPERATIONS",
+        0xDA => "RETURN STATUS",
+        _ => "UNKNOWN FEATURE"
+    }
+}
+
+fn get_smart_attribute_name(attribute_id: u8) -> Option<&'static str> {
+    match attribute_id {
+        0x01 => Some("Raw Read Error Rate"),
+        0x05 => Some("Reallocated Sectors"),
+        0x09 => Some("Power-On Hours"),
+        0x0C => Some("Power Cycles"),
+        0xBE | 0xC2 => Some("Temperature"),
+        0xC5 => Some("Pending Sectors"),
+        0xC6 => Some("Offline Uncorrectable"),
+        0xC7 => Some("UDMA CRC Errors"),
+        _ => None
+    }
+}
+
+pub fn get_nvme_admin_name(opcode: u8) -> &'static str {
+    /*
+        Opcode Info:
+        NVM Express Base Specification 2.0, Figure 138
+    */
+    match opcode {
+        0x00 => "DELETE I/O SUBMISSION QUEUE",
+        0x01 => "CREATE I/O SUBMISSION QUEUE",
+        0x02 => "GET LOG PAGE",
+        0x04 => "DELETE I/O COMPLETION QUEUE",
+        0x05 => "CREATE I/O COMPLETION QUEUE",
+        0x06 => "IDENTIFY",
+        0x08 => "ABORT",
+        0x09 => "SET FEATURES",
+        0x0A => "GET FEATURES",
+        0x0C => "ASYNCHRONOUS EVENT REQUEST",
+        0x0D => "NAMESPACE MANAGEMENT",
+        0x10 => "FIRMWARE COMMIT",
+        0x11 => "FIRMWARE IMAGE DOWNLOAD",
+        0x14 => "DEVICE SELF-TEST",
+        0x15 => "NAMESPACE ATTACHMENT",
+        0x80 => "FORMAT NVM",
+        0x81 => "SECURITY SEND",
+        0x82 => "SECURITY RECEIVE",
+        0x84 => "SANITIZE",
+        _ => "UNKNOWN"
+    }
+}
+
+fn read_ata_string(data: &[u8], word_start: usize, word_end: usize) -> String {
+    /* ATA Strings are stored with the bytes of each word swapped */
+    let mut ata_string = String::new();
+    for word_index in word_start..word_end {
+        ata_string.push(data[word_index * 2 + 1] as char);
+        ata_string.push(data[word_index * 2] as char);
+    }
+
+    ata_string.trim().to_string()
+}
+
+fn read_nvme_string(data: &[u8], start: usize, end: usize) -> String {
+    String::from_utf8_lossy(&data[start..end]).trim().to_string()
+}
+
+impl AtaTaskfile {
+    pub fn describe(&self) -> String {
+        let command_name = get_ata_command_name(self.command);
+        let parameters = match self.command {
+            0xB0 => return format!("SMART {}", get_smart_feature_name(self.features as u8)),
+            0x92 | 0x93 => format!("Subcommand=0x{:02X} Blocks={} Offset={}", self.features as u8, self.count, (self.lba >> 8) & 0xFFFF),
+            0x2F | 0x3F | 0x47 => format!("Log=0x{:02X} Page={} Count={}", self.lba as u8, (self.lba >> 8) & 0xFFFF, self.count),
+            0xEF => format!("Subcommand=0x{:02X} Count=0x{:02X}", self.features as u8, self.count),
+            0x20 | 0x24 | 0x25 | 0x30 | 0x34 | 0x35 | 0x40 | 0x42 | 0xC8 | 0xCA => format!("LBA=0x{:X} Count={}", self.lba, self.count),
+            0x60 | 0x61 => format!("LBA=0x{:X} Count={}", self.lba, self.features),
+            _ => String::new()
+        };
+
+        match (command_name, parameters.is_empty()) {
+            ("UNKNOWN", _) => format!("ATA Command 0x{:02X}", self.command),
+            (_, true) => String::from(command_name),
+            (_, false) => format!("{} {}", command_name, parameters)
+        }
+    }
+
+    pub fn describe_data(&self, data: &[u8]) -> Option<String> {
+        match (self.command, self.features as u8) {
+            (0xEC | 0xA1, _) if data.len() >= 512 => {
+                /* IDENTIFY (Packet) DEVICE: Serial (10-19), Firmware (23-26), Model (27-46) */
+                let sector_count = u64::from_le_bytes(data[200..208].try_into().unwrap()) & 0xFFFF_FFFF_FFFF;
+                Some(format!(
+                    "Model=\"{}\" Serial=\"{}\" Firmware=\"{}\" Sectors={}",
+                    read_ata_string(data, 27, 47),
+                    read_ata_string(data, 10, 20),
+                    read_ata_string(data, 23, 27),
+                    sector_count
+                ))
+            },
+
+            (0xB0, 0xD0) if data.len() >= 362 => {
+                /* SMART READ DATA: 30 Attributes of 12 bytes from Offset 2 */
+                let attributes: Vec<String> = data[2..362]
+                    .chunks(12)
+                    .filter(|attribute| attribute[0] != 0)
+                    .map(|attribute| {
+                        let raw_value = u64::from_le_bytes([attribute[5], attribute[6], attribute[7], attribute[8], attribute[9], attribute[10], 0, 0]);
+                        match get_smart_attribute_name(attribute[0]) {
+                            Some(attribute_name) => format!("{}={}", attribute_name, raw_value),
+                            None => format!("ID{}={}", attribute[0], raw_value),
+                        }
+                    })
+                    .collect();
+
+                Some(format!("Attributes: {}", attributes.join(", ")))
+            },
+
+            _ => None
+        }
+    }
+}
+
+impl NvmeCommand {
+    fn from_submission_entry(entry: &[u8]) -> Option<Self> {
+        if entry.len() < 44 {
+            return None;
+        }
+
+        Some(Self {
+            opcode: entry[0],
+            nsid: u32::from_le_bytes(entry[4..8].try_into().unwrap()),
+            cdw10: u32::from_le_bytes(entry[40..44].try_into().unwrap()),
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let opcode_name = get_nvme_admin_name(self.opcode);
+        let parameters = match self.opcode {
+            0x02 => format!(
+                "Log=0x{:02X}{} NSID=0x{:X}",
+                self.cdw10 as u8,
+                match self.cdw10 as u8 {
+                    0x01 => " (Error Information)",
+                    0x02 => " (SMART / Health Information)",
+                    0x03 => " (Firmware Slot Information)",
+                    0x06 => " (Device Self-test)",
+                    _ => ""
+                },
+                self.nsid
+            ),
+            0x06 => format!(
+                "CNS=0x{:02X}{}",
+                self.cdw10 as u8,
+                match self.cdw10 as u8 {
+                    0x00 => " (Namespace)",
+                    0x01 => " (Controller)",
+                    0x02 => " (Active Namespace List)",
+                    _ => ""
+                }
+            ),
+            0x09 | 0x0A => format!("FID=0x{:02X}", self.cdw10 as u8),
+            0x10 => format!("Slot={} Action={}", self.cdw10 & 0x07, (self.cdw10 >> 3) & 0x07),
+            0x11 => format!("DWords={}", self.cdw10 as u64 + 1),
+            _ => String::new()
+        };
+
+        match (opcode_name, parameters.is_empty()) {
+            ("UNKNOWN", _) => format!("NVMe Admin 0x{:02X}", self.opcode),
+            (_, true) => format!("NVMe {}", opcode_name),
+            (_, false) => format!("NVMe {} {}", opcode_name, parameters)
+        }
+    }
+
+    pub fn describe_data(&self, data: &[u8]) -> Option<String> {
+        match (self.opcode, self.cdw10 as u8) {
+            (0x06, 0x01) if data.len() >= 72 => Some(format!(
+                "Model=\"{}\" Serial=\"{}\" Firmware=\"{}\"",
+                read_nvme_string(data, 24, 64),
+                read_nvme_string(data, 4, 24),
+                read_nvme_string(data, 64, 72)
+            )),
+
+            (0x02, 0x02) if data.len() >= 136 => Some(format!(
+                "Critical Warning=0x{:02X} Temperature={}C Spare={}% Used={}% Power-On Hours={}",
+                data[0],
+                u16::from_le_bytes([data[1], data[2]]) as i32 - 273,
+                data[3],
+                data[5],
+                u64::from_le_bytes(data[128..136].try_into().unwrap())
+            )),
+
+            _ => None
+        }
+    }
+}
+
+impl BridgeCommand {
+    pub fn get_data_direction(&self) -> Option<ScsiDataDirection> {
+        match self {
+            BridgeCommand::SatPassThrough(_, direction) => Some(*direction),
+            BridgeCommand::JMicronAta(_, direction) => Some(*direction),
+            BridgeCommand::CypressAtacb(_) => None,
+            BridgeCommand::JMicronNvme(phase) => match phase {
+                JMicronNvmePhase::Command | JMicronNvmePhase::DataOut => Some(ScsiDataDirection::Out),
+                JMicronNvmePhase::DataIn | JMicronNvmePhase::Response => Some(ScsiDataDirection::In),
+                JMicronNvmePhase::NonData => Some(ScsiDataDirection::None),
+            },
+            BridgeCommand::ASMediaNvme(_) | BridgeCommand::RealtekNvme(_) => Some(ScsiDataDirection::In),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            BridgeCommand::SatPassThrough(taskfile, _) => format!(
+                "ATA PASS-THROUGH ({}): {}",
+                get_sat_protocol_name(taskfile.protocol.unwrap_or(0)),
+                taskfile.describe()
+            ),
+            BridgeCommand::CypressAtacb(taskfile) => format!("Cypress ATACB: {}", taskfile.describe()),
+            BridgeCommand::JMicronAta(taskfile, _) => format!("JMicron ATA: {}", taskfile.describe()),
+            BridgeCommand::JMicronNvme(phase) => format!("JMicron NVMe Pass-Through: {:?} Phase", phase),
+            BridgeCommand::ASMediaNvme(nvme_command) => format!("ASMedia {}", nvme_command.describe()),
+            BridgeCommand::RealtekNvme(nvme_command) => format!("Realtek {}", nvme_command.describe()),
+        }
+    }
+}
+
+pub fn decode_jmicron_nvme_command(data: &[u8]) -> Option<NvmeCommand> {
+    /* The JMicron Command Phase sends "NVME" followed by a Submission Queue Entry */
+    if data.len() < JMICRON_NVME_CMD_OFFSET || &data[0..4] != JMICRON_NVME_SIGNATURE {
+        return None;
+    }
+
+    NvmeCommand::from_submission_entry(&data[JMICRON_NVME_CMD_OFFSET..])
+}
+
+fn decode_sat_flags(flags: u8) -> ScsiDataDirection {
+    /* T_LENGTH of zero means there is no Data Phase, T_DIR is set for Data-In */
+    if flags & 0x03 == 0 {
+        ScsiDataDirection::None
+    } else if flags & 0x08 != 0 {
+        ScsiDataDirection::In
+    } else {
+        ScsiDataDirection::Out
+    }
+}
+
+pub fn decode_bridge_cdb(cdb: &[u8]) -> Option<BridgeCommand> {
+    match cdb.first()? {
+        0x85 if cdb.len() >= 16 => {
+            /* ATA PASS-THROUGH(16), EXTEND puts the High Order Bytes next to the Low Order Bytes */
+            let is_extended = cdb[1] & 0x01 != 0;
+            let high_byte = |index: usize| if is_extended { cdb[index] as u64 } else { 0 };
+            let taskfile = AtaTaskfile {
+                protocol: Some((cdb[1] >> 1) & 0x0F),
+                features: ((high_byte(3) as u16) << 8) | cdb[4] as u16,
+                count: ((high_byte(5) as u16) << 8) | cdb[6] as u16,
+                lba: (high_byte(11) << 40) | (high_byte(9) << 32) | (high_byte(7) << 24)
+                    | ((cdb[12] as u64) << 16) | ((cdb[10] as u64) << 8) | cdb[8] as u64,
+                command: cdb[14],
+            };
+
+            Some(BridgeCommand::SatPassThrough(taskfile, decode_sat_flags(cdb[2])))
+        },
+
+        0xA1 if cdb.len() >= 12 => {
+            /* JMicron NVMe Bridges reuse the ATA PASS-THROUGH(12) Opcode without an ATA Command */
+            if cdb[9] == 0 && matches!(cdb[1], 0x00..=0x03 | 0x0F) {
+                let phase = match cdb[1] {
+                    0x00 => JMicronNvmePhase::Command,
+                    0x01 => JMicronNvmePhase::NonData,
+                    0x02 => JMicronNvmePhase::DataIn,
+                    0x03 => JMicronNvmePhase::DataOut,
+                    _ => JMicronNvmePhase::Response,
+                };
+
+                return Some(BridgeCommand::JMicronNvme(phase));
+            }
+
+            let taskfile = AtaTaskfile {
+                protocol: Some((cdb[1] >> 1) & 0x0F),
+                features: cdb[3] as u16,
+                count: cdb[4] as u16,
+                lba: ((cdb[7] as u64) << 16) | ((cdb[6] as u64) << 8) | cdb[5] as u64,
+                command: cdb[9],
+            };
+
+            Some(BridgeCommand::SatPassThrough(taskfile, decode_sat_flags(cdb[2])))
+        },
+
+        0x24 if cdb.len() >= 13 && cdb[1] == 0x24 => {
+            /* Cypress ATACB, Register Values follow the Action and Register Select bytes */
+            Some(BridgeCommand::CypressAtacb(AtaTaskfile {
+                protocol: None,
+                features: cdb[6] as u16,
+                count: cdb[7] as u16,
+                lba: ((cdb[10] as u64) << 16) | ((cdb[9] as u64) << 8) | cdb[8] as u64,
+                command: cdb[12],
+            }))
+        },
+
+        0xDF if cdb.len() >= 12 => {
+            /* JMicron SATA Bridges, Bit 4 of Byte 1 selects Data-In */
+            let direction = if u16::from_be_bytes([cdb[3], cdb[4]]) == 0 {
+                ScsiDataDirection::None
+            } else if cdb[1] & 0x10 != 0 {
+                ScsiDataDirection::In
+            } else {
+                ScsiDataDirection::Out
+            };
+
+            Some(BridgeCommand::JMicronAta(AtaTaskfile {
+                protocol: None,
+                features: cdb[5] as u16,
+                count: cdb[6] as u16,
+                lba: ((cdb[9] as u64) << 16) | ((cdb[8] as u64) << 8) | cdb[7] as u64,
+                command: cdb[11],
+            }, direction))
+        },
+
+        0xE6 if cdb.len() >= 8 => {
+            /* ASMedia ASM236x: Admin Opcode in Byte 1, Low byte of CDW10 in Byte 3 */
+            Some(BridgeCommand::ASMediaNvme(NvmeCommand {
+                opcode: cdb[1],
+                nsid: 0xFFFF_FFFF,
+                cdw10: cdb[3] as u32 | ((cdb[7] as u32) << 16),
+            }))
+        },
+
+        0xE4 if cdb.len() >= 8 => {
+            /* Realtek RTL9210: Transfer Length in Bytes 1-2, Admin Opcode in Byte 3 */
+            Some(BridgeCommand::RealtekNvme(NvmeCommand {
+                opcode: cdb[3],
+                nsid: 0xFFFF_FFFF,
+                cdw10: cdb[4] as u32 | ((cdb[7] as u32) << 16),
+            }))
+        },
+
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_taskfile(cdb: &[u8]) -> (AtaTaskfile, Option<ScsiDataDirection>) {
+        match decode_bridge_cdb(cdb) {
+            Some(bridge_command @ (BridgeCommand::SatPassThrough(taskfile, _) | BridgeCommand::JMicronAta(taskfile, _))) => (taskfile, bridge_command.get_data_direction()),
+            bridge_command => panic!("Not an ATA Bridge Command: {:?}", bridge_command),
+        }
+    }
+
+    fn put_ata_string(data: &mut [u8], word_start: usize, text: &str) {
+        for (word_index, word) in text.as_bytes().chunks(2).enumerate() {
+            data[(word_start + word_index) * 2] = word[1];
+            data[(word_start + word_index) * 2 + 1] = word[0];
+        }
+    }
+
+    #[test]
+    fn decodes_sat16_taskfile() {
+        /* READ DMA EXT, DMA Protocol with EXTEND, T_DIR In, T_LENGTH in Sector Count */
+        let cdb = [0x85, 0x0D, 0x0E, 0x00, 0x00, 0x01, 0x02, 0x44, 0x11, 0x55, 0x22, 0x66, 0x33, 0x40, 0x25, 0x00];
+        let (taskfile, direction) = get_taskfile(&cdb);
+        assert_eq!((taskfile.protocol, taskfile.features, taskfile.count, taskfile.lba, taskfile.command), (Some(0x6), 0x0000, 0x0102, 0x6655_4433_2211, 0x25));
+        assert_eq!(direction, Some(ScsiDataDirection::In));
+        assert_eq!(decode_bridge_cdb(&cdb).unwrap().describe(), "ATA PASS-THROUGH (DMA): READ DMA EXT LBA=0x665544332211 Count=258");
+
+        /* Without EXTEND the High Order Bytes are ignored */
+        let mut cdb = cdb;
+        cdb[1] = 0x0C;
+        let (taskfile, _) = get_taskfile(&cdb);
+        assert_eq!((taskfile.count, taskfile.lba), (0x02, 0x33_2211));
+    }
+
+    #[test]
+    fn decodes_sat12_taskfile() {
+        let cdb = [0xA1, 0x08, 0x0E, 0x00, 0x01, 0x00, 0x00, 0x00, 0xA0, 0xEC, 0x00, 0x00];
+        let (taskfile, direction) = get_taskfile(&cdb);
+        assert_eq!((taskfile.protocol, taskfile.count, taskfile.command), (Some(0x4), 0x01, 0xEC));
+        assert_eq!(direction, Some(ScsiDataDirection::In));
+        assert_eq!(decode_bridge_cdb(&cdb).unwrap().describe(), "ATA PASS-THROUGH (PIO Data-In): IDENTIFY DEVICE");
+
+        /* SMART RETURN STATUS: Non-Data with CK_COND, LBA Mid/High hold the SMART Signature */
+        let cdb = [0xA1, 0x06, 0x20, 0xDA, 0x00, 0x00, 0x4F, 0xC2, 0xA0, 0xB0, 0x00, 0x00];
+        let (taskfile, direction) = get_taskfile(&cdb);
+        assert_eq!((taskfile.features, taskfile.lba), (0xDA, 0xC2_4F00));
+        assert_eq!(direction, Some(ScsiDataDirection::None));
+        assert_eq!(decode_bridge_cdb(&cdb).unwrap().describe(), "ATA PASS-THROUGH (Non-Data): SMART RETURN STATUS");
+    }
+
+    #[test]
+    fn swaps_identify_strings() {
+        let mut identify_data = [0x20; 512];
+        put_ata_string(&mut identify_data, 10, "S1234567");
+        put_ata_string(&mut identify_data, 23, "FW01");
+        put_ata_string(&mut identify_data, 27, "Test Disk 2000GB");
+        identify_data[200..208].copy_from_slice(&0x0000_E8E0_88B0u64.to_le_bytes());
+
+        let (taskfile, _) = get_taskfile(&[0xA1, 0x08, 0x0E, 0x00, 0x01, 0x00, 0x00, 0x00, 0xA0, 0xEC, 0x00, 0x00]);
+        assert_eq!(
+            taskfile.describe_data(&identify_data).unwrap(),
+            "Model=\"Test Disk 2000GB\" Serial=\"S1234567\" Firmware=\"FW01\" Sectors=3907029168"
+        );
+        assert_eq!(taskfile.describe_data(&identify_data[..511]), None);
+    }
+
+    #[test]
+    fn decodes_jmicron_bridge_cdbs() {
+        /* JMicron SATA: Length in Bytes 3-4, Data-In Bit in Byte 1, Registers from Byte 5 */
+        let cdb = [0xDF, 0x10, 0x00, 0x02, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xA0, 0xEC];
+        let (taskfile, direction) = get_taskfile(&cdb);
+        assert_eq!((taskfile.count, taskfile.command), (0x01, 0xEC));
+        assert_eq!(direction, Some(ScsiDataDirection::In));
+        assert_eq!(decode_bridge_cdb(&cdb).unwrap().describe(), "JMicron ATA: IDENTIFY DEVICE");
+
+        /* JMicron NVMe: ATA PASS-THROUGH(12) with the Phase in Byte 1 and no ATA Command */
+        for (phase_byte, phase, direction) in [
+            (0x00, JMicronNvmePhase::Command, ScsiDataDirection::Out),
+            (0x01, JMicronNvmePhase::NonData, ScsiDataDirection::None),
+            (0x02, JMicronNvmePhase::DataIn, ScsiDataDirection::In),
+            (0x03, JMicronNvmePhase::DataOut, ScsiDataDirection::Out),
+            (0x0F, JMicronNvmePhase::Response, ScsiDataDirection::In),
+        ] {
+            let bridge_command = decode_bridge_cdb(&[0xA1, phase_byte, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]).unwrap();
+            assert!(matches!(bridge_command, BridgeCommand::JMicronNvme(decoded_phase) if decoded_phase == phase));
+            assert_eq!(bridge_command.get_data_direction(), Some(direction));
+        }
+
+        /* Command Phase Data: "NVME", Reserved, then the Submission Queue Entry */
+        let mut command_data = [0x00; 512];
+        command_data[0..4].copy_from_slice(b"NVME");
+        command_data[8] = 0x02;
+        command_data[12..16].copy_from_slice(&0xFFFF_FFFFu32.to_le_bytes());
+        command_data[48..52].copy_from_slice(&0x007F_0002u32.to_le_bytes());
+        let nvme_command = decode_jmicron_nvme_command(&command_data).unwrap();
+        assert_eq!((nvme_command.opcode, nvme_command.nsid, nvme_command.cdw10), (0x02, 0xFFFF_FFFF, 0x007F_0002));
+        assert_eq!(nvme_command.describe(), "NVMe GET LOG PAGE Log=0x02 (SMART / Health Information) NSID=0xFFFFFFFF");
+
+        command_data[0] = b'X';
+        assert!(decode_jmicron_nvme_command(&command_data).is_none());
+        assert!(decode_jmicron_nvme_command(&command_data[..51]).is_none());
+    }
+
+    #[test]
+    fn decodes_asmedia_and_realtek_nvme_cdbs() {
+        /* ASMedia: Opcode in Byte 1, CDW10 Bits 7..0 in Byte 3 and 23..16 in Byte 7 */
+        let Some(BridgeCommand::ASMediaNvme(nvme_command)) = decode_bridge_cdb(&[0xE6, 0x06, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]) else {
+            panic!("Not an ASMedia Command");
+        };
+        assert_eq!((nvme_command.opcode, nvme_command.cdw10), (0x06, 0x01));
+        assert_eq!(BridgeCommand::ASMediaNvme(nvme_command).describe(), "ASMedia NVMe IDENTIFY CNS=0x01 (Controller)");
+
+        /* Realtek: Transfer Length in Bytes 1-2, Opcode in Byte 3, CDW10 in Bytes 4 and 7 */
+        let Some(BridgeCommand::RealtekNvme(nvme_command)) = decode_bridge_cdb(&[0xE4, 0x00, 0x02, 0x02, 0x02, 0x00, 0x00, 0x7F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]) else {
+            panic!("Not a Realtek Command");
+        };
+        assert_eq!((nvme_command.opcode, nvme_command.cdw10), (0x02, 0x007F_0002));
+        assert_eq!(BridgeCommand::RealtekNvme(nvme_command).get_data_direction(), Some(ScsiDataDirection::In));
+
+        assert!(decode_bridge_cdb(&[0xE4, 0x00, 0x02]).is_none());
+    }
+}
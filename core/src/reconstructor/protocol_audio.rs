@@ -0,0 +1,835 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use tokio::sync::mpsc::Sender;
+use crate::sniffer::{UrbEventType, UrbTransferType, UrbXractHeader, UrbXractPacket};
+
+use super::audio_descriptor::{self, AudioTopology, AudioVersion, SampleEncoding, StreamFormat};
+use super::device_model::SetupPacket;
+use super::protocol_control::{self, PendingRequests};
+use super::{ModuleContext, ReconstructedTransmission, ReconstructionModule};
+
+/*
+    USB Audio Streaming. Isochronous Packets carry interleaved Frames in the
+    Format of the active Alternate Setting, the Sample Rate is set on the
+    Endpoint (UAC1) or on the Clock Source driving the Terminal (UAC2)
+*/
+const COMMON_SAMPLE_RATES: [u32; 14] = [8000, 11025, 16000, 22050, 24000, 32000, 44100, 48000, 88200, 96000, 176400, 192000, 352800, 384000];
+const PLACEHOLDER_SAMPLE_RATE: u32 = 48000; /* Written until the Rate is captured or estimated */
+const ESTIMATE_DURATION: u64 = 1_000_000; /* Microseconds of Stream before estimating the Rate */
+const DROPOUT_REPORT_INTERVAL: u64 = 1_000_000; /* Microseconds between Dropout Rows of a Stream */
+
+const CONTROL_SAMPLING_FREQUENCY: u8 = 0x01;
+const CONTROL_MUTE: u8 = 0x01;
+const CONTROL_VOLUME: u8 = 0x02;
+const CONTROL_CLOCK_VALID: u8 = 0x02;
+const UAC1_SET_CUR: u8 = 0x01;
+const UAC1_GET_CUR: u8 = 0x81;
+const UAC2_CUR: u8 = 0x01;
+const UAC2_RANGE: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ControlTarget {
+    Endpoint(u8),
+    Interface(u8),
+    Entity(u8, u8), /* Entity ID, Descriptor Subtype */
+}
+
+struct WavWriter {
+    file: File,
+    format_tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    bits_per_sample: u16,
+    data_length: u32,
+    is_signed_pcm8: bool, /* USB Audio PCM is signed, WAV stores 8 Bit PCM unsigned */
+}
+
+#[derive(Default)]
+struct DropoutCounter {
+    failed_packets: u64,
+    empty_packets: u64,
+    misaligned_packets: u64,
+    silence_frames: u64,
+    statuses: Vec<i32>,
+}
+
+struct AudioStream {
+    interface_number: u8,
+    alternate_setting: u8,
+    format: StreamFormat,
+    clock_id: Option<u8>,
+    configured_rate: Option<u32>,
+    estimated_rate: Option<u32>,
+    wav_writer: Option<WavWriter>,
+    first_timestamp: u64,
+    first_frames: u64,
+    frames: u64,
+    good_packets: u64,
+    good_bytes: u64,
+    dropouts: DropoutCounter, /* Since the last Dropout Row */
+    dropout_total: u64,
+    last_dropout_row: Option<u64>,
+}
+
+pub struct Reconstructor {
+    module_tx: Sender<ReconstructedTransmission>,
+    module_context: ModuleContext,
+    pending_requests: PendingRequests, /* Control Requests awaiting Completion */
+    endpoint_rates: HashMap<(u16, u16, u8), u32>, /* (Bus, Device, Endpoint), UAC1 Sampling Frequency */
+    clock_rates: HashMap<(u16, u16, u8), u32>, /* (Bus, Device, Clock Source), UAC2 Sampling Frequency */
+    device_rates: HashMap<(u16, u16), u32>, /* (Bus, Device), Latest UAC2 Sampling Frequency of any Clock */
+    streams: HashMap<(u16, u16, u8), AudioStream>, /* (Bus, Device, Endpoint) */
+    stream_counts: HashMap<(u16, u16, u8), usize>, /* (Bus, Device, Endpoint), Files written */
+}
+
+fn get_format_tag(encoding: SampleEncoding) -> Option<u16> {
+    /* WAVE_FORMAT_PCM, _IEEE_FLOAT, _ALAW and _MULAW */
+    match encoding {
+        SampleEncoding::Pcm | SampleEncoding::Pcm8 => Some(0x0001),
+        SampleEncoding::Float => Some(0x0003),
+        SampleEncoding::ALaw => Some(0x0006),
+        SampleEncoding::MuLaw => Some(0x0007),
+        SampleEncoding::Unsupported(_) => None,
+    }
+}
+
+fn get_silence_byte(format: &StreamFormat) -> u8 {
+    /* Silence as sent on the Bus, the WavWriter converts signed 8 Bit PCM */
+    match format.encoding {
+        SampleEncoding::Pcm8 => 0x80,
+        SampleEncoding::ALaw => 0xD5,
+        SampleEncoding::MuLaw => 0xFF,
+        _ => 0x00,
+    }
+}
+
+fn get_packet_status_name(status: i32) -> String {
+    /* Linux errno of the Isochronous Packet, USBD_STATUS otherwise */
+    match status {
+        -18 => String::from("Missed Interval (EXDEV)"),
+        -71 => String::from("Protocol Error (EPROTO)"),
+        -75 => String::from("Babble (EOVERFLOW)"),
+        -84 => String::from("CRC Error (EILSEQ)"),
+        -63 => String::from("Buffer Overrun (ENOSR)"),
+        -70 => String::from("Buffer Underrun (ECOMM)"),
+        -121 => String::from("Short Packet (EREMOTEIO)"),
+        -2 | -104 => String::from("Unlinked"),
+        -108 => String::from("Device Removed (ESHUTDOWN)"),
+        status if status < 0 => format!("Error {}", status),
+        status => format!("USBD_STATUS 0x{:08X}", status as u32),
+    }
+}
+
+fn round_sample_rate(measured_rate: f64) -> u32 {
+    /* Nearest common Rate within 5%, the Measurement otherwise */
+    COMMON_SAMPLE_RATES
+        .iter()
+        .copied()
+        .min_by(|left, right| (*left as f64 - measured_rate).abs().total_cmp(&(*right as f64 - measured_rate).abs()))
+        .filter(|rate| (*rate as f64 - measured_rate).abs() <= *rate as f64 * 0.05)
+        .unwrap_or(measured_rate.round() as u32)
+}
+
+fn format_decibels(data: &[u8]) -> String {
+    /* 1/256 dB Steps, 0x8000 is Silence */
+    match i16::from_le_bytes([data[0], data[1]]) {
+        i16::MIN => String::from("-inf dB"),
+        volume => format!("{:.2} dB", volume as f64 / 256.0),
+    }
+}
+
+impl WavWriter {
+    fn create(path: &PathBuf, format: &StreamFormat, sample_rate: u32) -> io::Result<Self> {
+        let Some(format_tag) = get_format_tag(format.encoding) else { return Err(io::Error::other("Format not exportable")) };
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let mut wav_writer = WavWriter {
+            file: File::create(path)?,
+            format_tag,
+            channels: format.channels,
+            sample_rate,
+            bits_per_sample: format.subslot_size as u16 * 8,
+            data_length: 0,
+            is_signed_pcm8: format.encoding == SampleEncoding::Pcm && format.subslot_size == 1,
+        };
+
+        wav_writer.write_header()?;
+        Ok(wav_writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        /* RIFF Sizes are rewritten after every Append, the File stays playable if the Capture stops */
+        let block_align = self.channels * (self.bits_per_sample / 8);
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&self.data_length.saturating_add(36).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&self.format_tag.to_le_bytes());
+        header.extend_from_slice(&self.channels.to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.saturating_mul(block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&self.bits_per_sample.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&self.data_length.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    fn append(&mut self, samples: &[u8]) -> io::Result<()> {
+        match self.is_signed_pcm8 {
+            true => self.file.write_all(&samples.iter().map(|sample| sample ^ 0x80).collect::<Vec<u8>>())?,
+            false => self.file.write_all(samples)?,
+        }
+
+        self.data_length = self.data_length.saturating_add(samples.len() as u32);
+        self.write_header()
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) -> io::Result<()> {
+        self.sample_rate = sample_rate;
+        self.write_header()
+    }
+}
+
+impl AudioStream {
+    fn get_sample_rate(&self) -> Option<u32> {
+        self.configured_rate.or(self.estimated_rate)
+    }
+
+    fn get_duration(&self) -> Option<f64> {
+        self.get_sample_rate().map(|sample_rate| self.frames as f64 / sample_rate as f64)
+    }
+
+    fn get_nominal_packet_length(&self) -> usize {
+        /* Average good Packet, whole Frames only */
+        let frame_size = self.format.get_frame_size().max(1);
+        match self.good_packets {
+            0 => 0,
+            good_packets => (self.good_bytes / good_packets) as usize / frame_size * frame_size,
+        }
+    }
+
+    fn describe_dropouts(&self, endpoint_name: &str) -> String {
+        let dropouts = &self.dropouts;
+        let mut status_names: Vec<String> = dropouts.statuses.iter().map(|status| get_packet_status_name(*status)).collect();
+        status_names.dedup();
+
+        let mut parts = vec![];
+        if dropouts.failed_packets > 0 {
+            parts.push(format!("{} failed Packets ({})", dropouts.failed_packets, status_names.join(", ")));
+        }
+
+        if dropouts.empty_packets > 0 {
+            parts.push(format!("{} empty Packets", dropouts.empty_packets));
+        }
+
+        if dropouts.misaligned_packets > 0 {
+            parts.push(format!("{} Packets with partial Frames", dropouts.misaligned_packets));
+        }
+
+        let position = match self.get_duration() {
+            Some(duration) => format!(" at {:.3} s", duration),
+            None => String::new(),
+        };
+
+        let silence = match (dropouts.silence_frames, self.get_sample_rate()) {
+            (0, _) => String::new(),
+            (silence_frames, Some(sample_rate)) => format!(", {:.1} ms of Silence inserted", silence_frames as f64 * 1000.0 / sample_rate as f64),
+            (silence_frames, None) => format!(", {} Frames of Silence inserted", silence_frames),
+        };
+
+        format!("[Audio] {} Dropout{}: {}{}", endpoint_name, position, parts.join(", "), silence)
+    }
+
+    fn describe_end(&self, endpoint_name: &str) -> String {
+        let duration = match self.get_duration() {
+            Some(duration) => format!("{:.3} s, ", duration),
+            None => String::new(),
+        };
+
+        format!("[Audio] {} Stream ended: {}{} Frames, {} Dropouts", endpoint_name, duration, self.frames, self.dropout_total)
+    }
+}
+
+impl Reconstructor {
+    async fn dispatch_row(&mut self, urb_header: UrbXractHeader, combined_payload: String, is_error: bool) {
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_header,
+            combined_payload,
+            sources: vec![UrbXractPacket { header: urb_header, data: None, iso_descriptors: vec![] }],
+            is_error,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    fn get_control_target(&self, urb_header: &UrbXractHeader, setup_packet: &SetupPacket) -> Option<(AudioVersion, ControlTarget)> {
+        /* Endpoint Requests address the Endpoint, Interface Requests an Entity in the high Byte of wIndex */
+        let device_registry = self.module_context.device_registry.read().unwrap();
+        let device = device_registry.get_device(urb_header)?;
+        match setup_packet.request_type & 0x1F {
+            0x02 => {
+                let interface = device.get_endpoint_interface(setup_packet.index as u8)?;
+                Some((AudioVersion::from_protocol(interface.protocol), ControlTarget::Endpoint(setup_packet.index as u8)))
+            },
+
+            _ => {
+                let interface = device.get_interface_by_number(setup_packet.index as u8)?;
+                let version = AudioVersion::from_protocol(interface.protocol);
+                match (setup_packet.index >> 8) as u8 {
+                    0 => Some((version, ControlTarget::Interface(interface.number))),
+                    entity_id => {
+                        let audio_topology = AudioTopology::from_device(device);
+                        let subtype = audio_topology.entities.get(&entity_id).copied().unwrap_or_default();
+                        Some((version, ControlTarget::Entity(entity_id, subtype)))
+                    }
+                }
+            }
+        }
+    }
+
+    fn describe_request(&mut self, urb_header: &UrbXractHeader, setup_packet: &SetupPacket, data: Option<&[u8]>) -> String {
+        let Some((version, control_target)) = self.get_control_target(urb_header, setup_packet) else {
+            return format!("[Audio] {}", protocol_control::describe_setup(setup_packet, Some(audio_descriptor::CLASS_AUDIO)));
+        };
+
+        let is_device_to_host = setup_packet.request_type & 0x80 != 0;
+        let request_name = match (version, setup_packet.request) {
+            (AudioVersion::Uac2, UAC2_CUR) => format!("{} CUR", if is_device_to_host { "GET" } else { "SET" }),
+            (AudioVersion::Uac2, UAC2_RANGE) => format!("{} RANGE", if is_device_to_host { "GET" } else { "SET" }),
+            (AudioVersion::Uac2, 0x03) => format!("{} MEM", if is_device_to_host { "GET" } else { "SET" }),
+            (AudioVersion::Uac1, request) => match request & 0x7F {
+                0x01 => format!("{}_CUR", if is_device_to_host { "GET" } else { "SET" }),
+                0x02 => format!("{}_MIN", if is_device_to_host { "GET" } else { "SET" }),
+                0x03 => format!("{}_MAX", if is_device_to_host { "GET" } else { "SET" }),
+                0x04 => format!("{}_RES", if is_device_to_host { "GET" } else { "SET" }),
+                0x05 => format!("{}_MEM", if is_device_to_host { "GET" } else { "SET" }),
+                0x7F => String::from("GET_STAT"),
+                _ => format!("Request 0x{:02X}", request),
+            },
+            (_, request) => format!("Request 0x{:02X}", request),
+        };
+
+        let (control_selector, channel) = ((setup_packet.value >> 8) as u8, setup_packet.value as u8);
+        let (target_name, control_name) = match control_target {
+            ControlTarget::Endpoint(endpoint) => (format!("EP 0x{:02X}", endpoint), match control_selector {
+                CONTROL_SAMPLING_FREQUENCY => "Sampling Frequency",
+                0x02 => "Pitch",
+                _ => "Endpoint Control",
+            }),
+
+            ControlTarget::Interface(interface_number) => (format!("Interface {}", interface_number), match control_selector {
+                0x01 if version == AudioVersion::Uac2 => "Active Alternate Setting",
+                0x02 if version == AudioVersion::Uac2 => "Valid Alternate Settings",
+                0x03 if version == AudioVersion::Uac2 => "Audio Data Format",
+                _ => "Interface Control",
+            }),
+
+            ControlTarget::Entity(entity_id, subtype) => {
+                let target_name = format!("{} {}", audio_descriptor::get_entity_name(version, subtype), entity_id);
+                let control_name = match (subtype, control_selector) {
+                    (audio_descriptor::SUBTYPE_FEATURE_UNIT, CONTROL_MUTE) => "Mute",
+                    (audio_descriptor::SUBTYPE_FEATURE_UNIT, CONTROL_VOLUME) => "Volume",
+                    (audio_descriptor::SUBTYPE_FEATURE_UNIT, 0x03) => "Bass",
+                    (audio_descriptor::SUBTYPE_FEATURE_UNIT, 0x04) => "Mid",
+                    (audio_descriptor::SUBTYPE_FEATURE_UNIT, 0x05) => "Treble",
+                    (audio_descriptor::SUBTYPE_FEATURE_UNIT, 0x06) => "Graphic Equalizer",
+                    (audio_descriptor::SUBTYPE_FEATURE_UNIT, 0x07) => "Automatic Gain",
+                    (audio_descriptor::SUBTYPE_FEATURE_UNIT, 0x08) => "Delay",
+                    (audio_descriptor::SUBTYPE_FEATURE_UNIT, 0x09) => "Bass Boost",
+                    (audio_descriptor::SUBTYPE_FEATURE_UNIT, 0x0A) => "Loudness",
+                    (audio_descriptor::SUBTYPE_FEATURE_UNIT, 0x0B) => "Input Gain",
+                    (audio_descriptor::SUBTYPE_CLOCK_SOURCE, CONTROL_SAMPLING_FREQUENCY) => "Sampling Frequency",
+                    (audio_descriptor::SUBTYPE_CLOCK_SOURCE, CONTROL_CLOCK_VALID) => "Clock Valid",
+                    (audio_descriptor::SUBTYPE_CLOCK_SELECTOR, 0x01) => "Clock Selector",
+                    _ => "Control",
+                };
+
+                (target_name, control_name)
+            },
+        };
+
+        let control_name = match control_target {
+            ControlTarget::Entity(_, audio_descriptor::SUBTYPE_FEATURE_UNIT) if channel == 0 => format!("{} (Master)", control_name),
+            ControlTarget::Entity(_, audio_descriptor::SUBTYPE_FEATURE_UNIT) => format!("{} (Ch {})", control_name, channel),
+            _ if control_name.ends_with("Control") => format!("{} 0x{:02X}", control_name, control_selector),
+            _ => String::from(control_name),
+        };
+
+        let value = data.filter(|data| !data.is_empty()).map(|data| match (control_name.split(" (").next(), version, setup_packet.request) {
+            /* RANGE: wNumSubRanges, then MIN, MAX, RES Triplets of the Control's Size */
+            (Some("Sampling Frequency"), AudioVersion::Uac2, UAC2_RANGE) if data.len() >= 2 => data[2..]
+                .chunks_exact(12)
+                .map(|range| {
+                    let (minimum, maximum) = (u32::from_le_bytes(range[0..4].try_into().unwrap()), u32::from_le_bytes(range[4..8].try_into().unwrap()));
+                    if minimum == maximum { format!("{} Hz", minimum) } else { format!("{}-{} Hz", minimum, maximum) }
+                })
+                .collect::<Vec<String>>()
+                .join(", "),
+            (Some("Sampling Frequency"), AudioVersion::Uac2, _) if data.len() >= 4 => format!("{} Hz", u32::from_le_bytes(data[0..4].try_into().unwrap())),
+            (Some("Sampling Frequency"), AudioVersion::Uac1, _) if data.len() >= 3 => format!("{} Hz", u32::from_le_bytes([data[0], data[1], data[2], 0])),
+            (Some("Volume"), AudioVersion::Uac2, UAC2_RANGE) if data.len() >= 2 => data[2..]
+                .chunks_exact(6)
+                .map(|range| format!("{} to {} step {}", format_decibels(&range[0..2]), format_decibels(&range[2..4]), format_decibels(&range[4..6])))
+                .collect::<Vec<String>>()
+                .join(", "),
+            (Some("Volume"), _, _) if data.len() >= 2 => format_decibels(data),
+            (Some("Mute"), _, _) => String::from(if data[0] != 0 { "Muted" } else { "Unmuted" }),
+            (Some("Clock Valid" | "Automatic Gain" | "Bass Boost" | "Loudness"), _, _) => String::from(if data[0] != 0 { "On" } else { "Off" }),
+            _ => protocol_control::format_hex(data),
+        });
+
+        match value {
+            Some(value) => format!("[Audio] {} {} {}: {}", request_name, target_name, control_name, value),
+            None => format!("[Audio] {} {} {}", request_name, target_name, control_name),
+        }
+    }
+
+    fn update_sample_rate(&mut self, urb_header: &UrbXractHeader, setup_packet: &SetupPacket, data: &[u8]) {
+        /* Sampling Frequency CUR, set by the Host or read back from the Device */
+        let Some((version, control_target)) = self.get_control_target(urb_header, setup_packet) else { return };
+        if (setup_packet.value >> 8) as u8 != CONTROL_SAMPLING_FREQUENCY {
+            return;
+        }
+
+        let device_address = (urb_header.bus_id, urb_header.device_id);
+        match (version, control_target) {
+            (AudioVersion::Uac1, ControlTarget::Endpoint(endpoint)) if matches!(setup_packet.request, UAC1_SET_CUR | UAC1_GET_CUR) && data.len() >= 3 => {
+                let sample_rate = u32::from_le_bytes([data[0], data[1], data[2], 0]);
+                self.endpoint_rates.insert((device_address.0, device_address.1, endpoint), sample_rate);
+            },
+
+            (AudioVersion::Uac2, ControlTarget::Entity(clock_id, audio_descriptor::SUBTYPE_CLOCK_SOURCE)) if setup_packet.request == UAC2_CUR && data.len() >= 4 => {
+                let sample_rate = u32::from_le_bytes(data[0..4].try_into().unwrap());
+                self.clock_rates.insert((device_address.0, device_address.1, clock_id), sample_rate);
+                self.device_rates.insert(device_address, sample_rate);
+            },
+
+            _ => {}
+        }
+    }
+
+    async fn consume_control(&mut self, mut urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let Some((setup_packet, data)) = self.pending_requests.pair(&mut urb_packet) else { return };
+        let mut description = self.describe_request(&urb_header, &setup_packet, data);
+        if urb_header.status == 0 && let Some(data) = data {
+            self.update_sample_rate(&urb_header, &setup_packet, data);
+        }
+
+        if urb_header.status != 0 {
+            description += &format!(" -> {}", protocol_control::describe_status(&urb_header));
+        }
+
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_header,
+            combined_payload: description,
+            sources: vec![urb_packet],
+            is_error: urb_header.status != 0,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    async fn consume_interrupt(&mut self, urb_packet: UrbXractPacket) {
+        /* Audio Control Status: UAC1 Status Word or UAC2 Interrupt Data Message */
+        let urb_header = urb_packet.header;
+        let Some(data) = urb_packet.data.as_deref() else { return };
+        let description = match data {
+            [info, attribute, control_channel, control_selector, interface_or_endpoint, entity_id, ..] => {
+                let attribute_name = match *attribute {
+                    UAC2_CUR => "CUR",
+                    UAC2_RANGE => "RANGE",
+                    0x03 => "MEM",
+                    _ => "Attribute",
+                };
+
+                let originator = match (*info & 0x02 != 0, *entity_id) {
+                    (true, _) => format!("EP 0x{:02X}", interface_or_endpoint),
+                    (false, 0) => format!("Interface {}", interface_or_endpoint),
+                    (false, entity_id) => format!("Entity {}", entity_id),
+                };
+
+                format!("[Audio] Interrupt: {} of {} Control 0x{:02X} Ch {} changed", attribute_name, originator, control_selector, control_channel)
+            },
+
+            [status_type, originator, ..] => {
+                let originator_kind = match status_type & 0x0F {
+                    0x00 => "Audio Control Entity",
+                    0x01 => "Audio Streaming Interface",
+                    0x02 => "Audio Streaming Endpoint",
+                    _ => "Originator",
+                };
+
+                let mut events = vec![];
+                if status_type & 0x80 != 0 {
+                    events.push("Interrupt Pending");
+                }
+
+                if status_type & 0x40 != 0 {
+                    events.push("Memory Changed");
+                }
+
+                format!("[Audio] Status: {} {} {}", originator_kind, originator, events.join(", ")).trim_end().to_string()
+            },
+
+            _ => format!("[Audio] Status: {}", protocol_control::format_hex(data)),
+        };
+
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_header,
+            combined_payload: description,
+            sources: vec![urb_packet],
+            is_error: false,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    async fn start_stream(&mut self, urb_header: &UrbXractHeader, stream_key: (u16, u16, u8), mut audio_stream: AudioStream) {
+        let endpoint_name = get_endpoint_name(urb_header.endpoint_info);
+        let sample_rate = match audio_stream.configured_rate {
+            Some(sample_rate) => format!("{} Hz", sample_rate),
+            None => String::from("Sample Rate not captured"),
+        };
+
+        let mut description = format!(
+            "[Audio] {} Interface {} Alt {}: {}, {}",
+            endpoint_name,
+            audio_stream.interface_number,
+            audio_stream.alternate_setting,
+            audio_stream.format.describe(),
+            sample_rate
+        );
+
+        let mut is_error = false;
+        if let Some(audio_directory) = self.module_context.audio_directory.clone() {
+            let stream_count = self.stream_counts.entry(stream_key).or_default();
+            let file_path = audio_directory.join(format!(
+                "audio_{:03}_{:03}_if{}_ep{:02x}_{}.wav",
+                urb_header.bus_id,
+                urb_header.device_id,
+                audio_stream.interface_number,
+                urb_header.endpoint_info,
+                stream_count
+            ));
+
+            *stream_count += 1;
+            match WavWriter::create(&file_path, &audio_stream.format, audio_stream.configured_rate.unwrap_or(PLACEHOLDER_SAMPLE_RATE)) {
+                Ok(wav_writer) => {
+                    audio_stream.wav_writer = Some(wav_writer);
+                    description += &format!(" -> {}", file_path.display());
+                },
+
+                Err(error) => {
+                    description += &format!(" (Unable to write {}: {})", file_path.display(), error);
+                    is_error = true;
+                }
+            }
+        }
+
+        self.streams.insert(stream_key, audio_stream);
+        self.dispatch_row(*urb_header, description, is_error).await;
+    }
+
+    async fn consume_stream(&mut self, urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let stream_key = (urb_header.bus_id, urb_header.device_id, urb_header.endpoint_info);
+        let endpoint_name = get_endpoint_name(urb_header.endpoint_info);
+        let is_device_to_host = urb_header.endpoint_info & 0x80 != 0;
+
+        /* The active Alternate Setting decides the Format, Feedback Endpoints carry no Audio */
+        let stream_setting = {
+            let device_registry = self.module_context.device_registry.read().unwrap();
+            let device = device_registry.get_device(&urb_header);
+            let interface = device_registry.get_interface(&urb_header);
+            let is_feedback = device
+                .and_then(|device| device.get_endpoint(urb_header.endpoint_info))
+                .is_some_and(|endpoint| (endpoint.attributes >> 4) & 0x03 == 0x01 || endpoint.max_packet_size <= 4);
+
+            match (device, interface) {
+                (Some(device), Some(interface)) if !is_feedback => {
+                    let is_known = self.streams
+                        .get(&stream_key)
+                        .is_some_and(|audio_stream| (audio_stream.interface_number, audio_stream.alternate_setting) == (interface.number, interface.alternate_setting));
+
+                    let stream_format = if is_known { None } else { StreamFormat::parse(interface) };
+                    let clock_id = stream_format
+                        .as_ref()
+                        .filter(|stream_format| stream_format.version == AudioVersion::Uac2)
+                        .and_then(|stream_format| AudioTopology::from_device(device).terminal_clocks.get(&stream_format.terminal_link).copied());
+
+                    Some((interface.number, interface.alternate_setting, is_known, stream_format, clock_id))
+                },
+                _ => None,
+            }
+        };
+
+        let Some((interface_number, alternate_setting, is_known, stream_format, clock_id)) = stream_setting else { return };
+        let device_address = (urb_header.bus_id, urb_header.device_id);
+
+        /* Formats other than Type I carry no PCM to export */
+        if !is_known && stream_format.is_none() {
+            if let Some(audio_stream) = self.streams.remove(&stream_key) && audio_stream.frames > 0 {
+                self.dispatch_row(urb_header, audio_stream.describe_end(&endpoint_name), false).await;
+            }
+
+            return;
+        }
+
+        /* A new Alternate Setting ends the previous Stream and starts a new File */
+        if let Some(stream_format) = stream_format {
+            if let Some(audio_stream) = self.streams.remove(&stream_key) && audio_stream.frames > 0 {
+                self.dispatch_row(urb_header, audio_stream.describe_end(&endpoint_name), false).await;
+            }
+
+            let configured_rate = match stream_format.version {
+                AudioVersion::Uac1 => self.endpoint_rates.get(&stream_key).copied().or((stream_format.sample_rates.len() == 1).then(|| stream_format.sample_rates[0])),
+                AudioVersion::Uac2 => clock_id.and_then(|clock_id| self.clock_rates.get(&(device_address.0, device_address.1, clock_id)).copied())
+                    .or(self.device_rates.get(&device_address).copied()),
+            };
+
+            let audio_stream = AudioStream {
+                interface_number,
+                alternate_setting,
+                format: stream_format,
+                clock_id,
+                configured_rate,
+                estimated_rate: None,
+                wav_writer: None,
+                first_timestamp: urb_header.timestamp,
+                first_frames: 0,
+                frames: 0,
+                good_packets: 0,
+                good_bytes: 0,
+                dropouts: DropoutCounter::default(),
+                dropout_total: 0,
+                last_dropout_row: None,
+            };
+
+            self.start_stream(&urb_header, stream_key, audio_stream).await;
+        }
+
+        let latest_rate = {
+            let Some(audio_stream) = self.streams.get(&stream_key) else { return };
+            match audio_stream.format.version {
+                AudioVersion::Uac1 => self.endpoint_rates.get(&stream_key).copied(),
+                AudioVersion::Uac2 => audio_stream.clock_id
+                    .and_then(|clock_id| self.clock_rates.get(&(device_address.0, device_address.1, clock_id)).copied())
+                    .or(self.device_rates.get(&device_address).copied()),
+            }
+        };
+
+        let Some(audio_stream) = self.streams.get_mut(&stream_key) else { return };
+        let mut rows: Vec<(String, bool)> = vec![];
+
+        /* The Host changed the Rate: the Samples so far belong to another File */
+        if let Some(latest_rate) = latest_rate && audio_stream.configured_rate.is_some_and(|configured_rate| configured_rate != latest_rate) {
+            let mut audio_stream = self.streams.remove(&stream_key).unwrap();
+            if audio_stream.frames > 0 {
+                self.dispatch_row(urb_header, audio_stream.describe_end(&endpoint_name), false).await;
+            }
+
+            audio_stream.configured_rate = Some(latest_rate);
+            audio_stream.estimated_rate = None;
+            audio_stream.wav_writer = None;
+            audio_stream.first_timestamp = urb_header.timestamp;
+            (audio_stream.first_frames, audio_stream.frames, audio_stream.dropout_total) = (0, 0, 0);
+            self.start_stream(&urb_header, stream_key, audio_stream).await;
+            return Box::pin(self.consume_stream(urb_packet)).await;
+        }
+
+        if let Some(latest_rate) = latest_rate && audio_stream.configured_rate.is_none() {
+            audio_stream.configured_rate = Some(latest_rate);
+            if let Some(wav_writer) = audio_stream.wav_writer.as_mut() {
+                let _ = wav_writer.set_sample_rate(latest_rate);
+            }
+
+            rows.push((format!("[Audio] {} Sample Rate {} Hz", endpoint_name, latest_rate), false));
+        }
+
+        /* IN Data arrives on Completion, OUT Data on Submission. Submissions carry no Packet Status */
+        let is_data_event = (is_device_to_host && urb_header.event_type != UrbEventType::Submit) || (!is_device_to_host && urb_header.event_type == UrbEventType::Submit);
+        let is_status_event = urb_header.event_type != UrbEventType::Submit;
+        let urb_data = urb_packet.data.as_deref().unwrap_or_default();
+        let packets: Vec<(i32, &[u8])> = match urb_packet.iso_descriptors.is_empty() {
+            true => vec![(urb_header.status, urb_data)],
+            false => urb_packet.iso_descriptors
+                .iter()
+                .map(|iso_descriptor| {
+                    let start = (iso_descriptor.offset as usize).min(urb_data.len());
+                    let end = (start + iso_descriptor.length as usize).min(urb_data.len());
+                    (iso_descriptor.status, &urb_data[start..end])
+                })
+                .collect(),
+        };
+
+        let frame_size = audio_stream.format.get_frame_size().max(1);
+        let silence_byte = get_silence_byte(&audio_stream.format);
+        let mut samples: Vec<u8> = Vec::with_capacity(urb_data.len());
+        let mut has_dropout = false;
+        for (status, packet) in packets {
+            let status = if is_status_event { status } else { 0 };
+            if status != 0 {
+                /* Lost Packets become Silence so the File keeps its Timing */
+                audio_stream.dropouts.failed_packets += 1;
+                audio_stream.dropouts.statuses.push(status);
+                has_dropout = true;
+                if is_data_event {
+                    let nominal_length = audio_stream.get_nominal_packet_length();
+                    samples.extend(std::iter::repeat_n(silence_byte, nominal_length));
+                    audio_stream.dropouts.silence_frames += (nominal_length / frame_size) as u64;
+                }
+
+                continue;
+            }
+
+            if !is_data_event {
+                continue;
+            }
+
+            /* Empty Packets count once the Stream is running */
+            if packet.is_empty() {
+                if audio_stream.good_packets > 0 {
+                    audio_stream.dropouts.empty_packets += 1;
+                    has_dropout = true;
+                }
+
+                continue;
+            }
+
+            if packet.len() % frame_size != 0 {
+                audio_stream.dropouts.misaligned_packets += 1;
+                has_dropout = true;
+            }
+
+            let whole_frames = &packet[..(packet.len() / frame_size * frame_size)];
+            audio_stream.good_packets += 1;
+            audio_stream.good_bytes += whole_frames.len() as u64;
+            samples.extend_from_slice(whole_frames);
+        }
+
+        let urb_frames = (samples.len() / frame_size) as u64;
+        if audio_stream.frames == 0 {
+            audio_stream.first_frames = urb_frames;
+            audio_stream.first_timestamp = urb_header.timestamp;
+        }
+
+        audio_stream.frames += urb_frames;
+        if !samples.is_empty() && let Some(wav_writer) = audio_stream.wav_writer.as_mut() && let Err(error) = wav_writer.append(&samples) {
+            audio_stream.wav_writer = None;
+            rows.push((format!("[Audio] {} Export stopped: {}", endpoint_name, error), true));
+        }
+
+        /* Without a captured Rate, Frames per Second of Capture Time tell it */
+        let elapsed = urb_header.timestamp.saturating_sub(audio_stream.first_timestamp);
+        if audio_stream.get_sample_rate().is_none() && elapsed >= ESTIMATE_DURATION {
+            let estimated_rate = round_sample_rate((audio_stream.frames - audio_stream.first_frames) as f64 * 1_000_000.0 / elapsed as f64);
+            audio_stream.estimated_rate = Some(estimated_rate);
+            if let Some(wav_writer) = audio_stream.wav_writer.as_mut() {
+                let _ = wav_writer.set_sample_rate(estimated_rate);
+            }
+
+            rows.push((format!("[Audio] {} Sample Rate estimated as {} Hz", endpoint_name, estimated_rate), false));
+        }
+
+        /* Dropouts are reported at most once per Interval and Stream */
+        if has_dropout {
+            audio_stream.dropout_total += 1;
+            if audio_stream.last_dropout_row.is_none_or(|last_row| urb_header.timestamp.saturating_sub(last_row) >= DROPOUT_REPORT_INTERVAL) {
+                rows.push((audio_stream.describe_dropouts(&endpoint_name), true));
+                audio_stream.dropouts = DropoutCounter::default();
+                audio_stream.last_dropout_row = Some(urb_header.timestamp);
+            }
+        }
+
+        for (description, is_error) in rows {
+            self.dispatch_row(urb_header, description, is_error).await;
+        }
+    }
+}
+
+fn get_endpoint_name(endpoint_address: u8) -> String {
+    format!("EP 0x{:02X} {}", endpoint_address, if endpoint_address & 0x80 != 0 { "IN" } else { "OUT" })
+}
+
+impl ReconstructionModule for Reconstructor {
+    fn new(module_tx: Sender<ReconstructedTransmission>, module_context: &ModuleContext) -> Self {
+        Self {
+            module_tx,
+            module_context: module_context.clone(),
+            pending_requests: PendingRequests::default(),
+            endpoint_rates: HashMap::new(),
+            clock_rates: HashMap::new(),
+            device_rates: HashMap::new(),
+            streams: HashMap::new(),
+            stream_counts: HashMap::new(),
+        }
+    }
+
+    async fn consume_packet(&mut self, urb_packet: UrbXractPacket) {
+        match urb_packet.header.transfer_type {
+            UrbTransferType::Control => self.consume_control(urb_packet).await,
+            UrbTransferType::Interrupt => self.consume_interrupt(urb_packet).await,
+            UrbTransferType::Isochronous => self.consume_stream(urb_packet).await,
+            UrbTransferType::Bulk => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_signed_pcm8_samples() {
+        let format = StreamFormat {
+            version: AudioVersion::Uac1,
+            encoding: SampleEncoding::Pcm,
+            channels: 1,
+            subslot_size: 1,
+            bit_resolution: 8,
+            sample_rates: vec![8_000],
+            terminal_link: 1,
+        };
+
+        /* Silence and Samples leave signed and are stored unsigned */
+        let path = std::env::temp_dir().join(format!("urbxtract-audio-{}.wav", std::process::id()));
+        let mut wav_writer = WavWriter::create(&path, &format, 8_000).unwrap();
+        wav_writer.append(&[get_silence_byte(&format), 0x7F, 0x80, 0xFF]).unwrap();
+        drop(wav_writer);
+
+        let wav_file = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(&wav_file[28..32], &8_000u32.to_le_bytes());
+        assert_eq!(&wav_file[40..44], &4u32.to_le_bytes());
+        assert_eq!(&wav_file[44..], &[0x80, 0xFF, 0x00, 0x7F]);
+    }
+}
@@ -0,0 +1,233 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use tokio::sync::mpsc::Sender;
+use crate::sniffer::{UrbEventType, UrbTransferType, UrbXractHeader, UrbXractPacket};
+
+use super::bluetooth_hci::{self, HciCapture, HciPacketType};
+use super::device_model::{self, DeviceModel, InterfaceModel};
+use super::protocol_control::{self, PendingRequests};
+use super::{ModuleContext, ReconstructedTransmission, ReconstructionModule};
+
+/*
+    Bluetooth Controllers following the USB Transport: HCI Commands as
+    Class Requests on the Default Pipe, Events on Interrupt IN and ACL Data
+    on Bulk. Events and ACL Packets may span several URBs
+*/
+const CLASS_WIRELESS: u8 = 0xE0;
+const CLASS_VENDOR: u8 = 0xFF;
+
+/* Vendors whose Broadcom based Controllers use a Vendor Interface, as matched by btusb */
+const BROADCOM_VENDORS: [u16; 9] = [
+    0x0A5C, /* Broadcom */
+    0x05AC, /* Apple */
+    0x0B05, /* ASUSTek */
+    0x050D, /* Belkin */
+    0x13D3, /* IMC Networks */
+    0x413C, /* Dell */
+    0x0930, /* Toshiba */
+    0x0489, /* Foxconn */
+    0x04CA, /* Lite-On */
+];
+
+pub struct Reconstructor {
+    module_tx: Sender<ReconstructedTransmission>,
+    module_context: ModuleContext,
+    pending_requests: PendingRequests, /* Control Requests awaiting Completion */
+    pending_commands: HashMap<(String, u16), (String, u64)>, /* (Bus:Device, Opcode), Description and Timestamp */
+    packet_buffers: HashMap<(String, u8), Vec<u8>>, /* (Bus:Device, Endpoint), partial Packet */
+    hci_capture: Option<HciCapture>,
+    is_capture_failed: bool,
+}
+
+pub fn is_hci_interface(device: &DeviceModel, interface: &InterfaceModel) -> bool {
+    let vendor_id = device.descriptor.as_ref().map(|descriptor| descriptor.vendor_id);
+    match (interface.class, interface.subclass, interface.protocol) {
+        (CLASS_WIRELESS, 0x01, 0x01) => true,
+        (CLASS_VENDOR, 0x01, 0x01) => vendor_id.is_some_and(|vendor_id| BROADCOM_VENDORS.contains(&vendor_id)),
+        _ => false,
+    }
+}
+
+pub fn get_hci_interface(device: &DeviceModel) -> Option<&InterfaceModel> {
+    device.get_active_configuration()?.interfaces.iter().find(|interface| is_hci_interface(device, interface))
+}
+
+impl Reconstructor {
+    async fn dispatch_row(&mut self, urb_packet: UrbXractPacket, combined_payload: String, is_error: bool) {
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_packet.header,
+            combined_payload,
+            sources: vec![urb_packet],
+            is_error,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    fn write_capture(&mut self, timestamp: u64, packet_type: HciPacketType, is_received: bool, packet: &[u8]) -> Option<String> {
+        /* Opened with the first Packet, a failed Write stops the Export */
+        let capture_path = self.module_context.bluetooth_capture.clone().filter(|_| !self.is_capture_failed)?;
+        let result = match self.hci_capture.as_mut() {
+            Some(hci_capture) => hci_capture.write_packet(timestamp, packet_type, is_received, packet),
+            None => HciCapture::create(&capture_path).and_then(|mut hci_capture| {
+                hci_capture.write_packet(timestamp, packet_type, is_received, packet)?;
+                self.hci_capture = Some(hci_capture);
+                Ok(())
+            }),
+        };
+
+        match result {
+            Ok(_) => None,
+            Err(error) => {
+                self.is_capture_failed = true;
+                self.hci_capture = None;
+                Some(format!("[HCI] Export to {} stopped: {}", capture_path.display(), error))
+            }
+        }
+    }
+
+    fn describe_event(&mut self, urb_header: &UrbXractHeader, packet: &[u8]) -> (String, bool) {
+        /* Command Complete and Command Status answer the pending Command with their Opcode */
+        let parameters = &packet[2..];
+        let (opcode, result) = match (packet[0], parameters.len()) {
+            (bluetooth_hci::EVENT_COMMAND_COMPLETE, 3..) => {
+                let opcode = u16::from_le_bytes([parameters[1], parameters[2]]);
+                (opcode, Some((bluetooth_hci::describe_return_parameters(opcode, &parameters[3..]), parameters.get(3).is_some_and(|status| *status != 0))))
+            },
+
+            (bluetooth_hci::EVENT_COMMAND_STATUS, 4..) => {
+                let opcode = u16::from_le_bytes([parameters[2], parameters[3]]);
+                (opcode, Some((format!("Status {}", bluetooth_hci::get_status_name(parameters[0])), parameters[0] != 0)))
+            },
+
+            _ => (0, None),
+        };
+
+        let Some((result, is_error)) = result else {
+            let (description, is_error) = bluetooth_hci::describe_event(packet);
+            return (format!("[HCI] {}", description), is_error);
+        };
+
+        /* Opcode 0 only returns Command Credits */
+        match self.pending_commands.remove(&(device_model::get_device_key(urb_header), opcode)) {
+            Some((command, command_timestamp)) => (format!(
+                "[HCI] {} -> {} ({:.3} ms)",
+                command,
+                result,
+                urb_header.timestamp.saturating_sub(command_timestamp) as f64 / 1000.0
+            ), is_error),
+
+            None if opcode == 0 => (String::from("[HCI] Command Credits"), false),
+            None => (format!("[HCI] {} -> {}", bluetooth_hci::get_opcode_name(opcode), result), is_error),
+        }
+    }
+
+    async fn consume_command(&mut self, mut urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let Some((setup_packet, data)) = self.pending_requests.pair(&mut urb_packet) else { return };
+        let command = data.filter(|command| {
+            setup_packet.request_type & 0x80 == 0 && bluetooth_hci::get_packet_length(HciPacketType::Command, command) == Some(command.len())
+        });
+
+        let Some(command) = command else {
+            let description = match urb_header.status {
+                0 => protocol_control::describe_setup(&setup_packet, Some(CLASS_WIRELESS)),
+                _ => format!("{} -> {}", protocol_control::describe_setup(&setup_packet, Some(CLASS_WIRELESS)), protocol_control::describe_status(&urb_header)),
+            };
+
+            return self.dispatch_row(urb_packet, description, urb_header.status != 0).await;
+        };
+
+        /* Rejected Transfers never reach the Controller */
+        let description = bluetooth_hci::describe_command(command);
+        if urb_header.status != 0 {
+            let description = format!("[HCI] {} -> {}", description, protocol_control::describe_status(&urb_header));
+            return self.dispatch_row(urb_packet, description, true).await;
+        }
+
+        let opcode = u16::from_le_bytes([command[0], command[1]]);
+        self.pending_commands.insert((device_model::get_device_key(&urb_header), opcode), (description, urb_header.timestamp));
+        if let Some(export_error) = self.write_capture(urb_header.timestamp, HciPacketType::Command, false, command) {
+            self.dispatch_row(urb_packet, export_error, true).await;
+        }
+    }
+
+    async fn consume_stream(&mut self, urb_packet: UrbXractPacket, packet_type: HciPacketType) {
+        let urb_header = urb_packet.header;
+        let is_received = urb_header.endpoint_info & 0x80 != 0;
+        let buffer_key = (device_model::get_device_key(&urb_header), urb_header.endpoint_info);
+
+        /* A failed Transfer loses the Packet Boundaries */
+        if urb_header.event_type != UrbEventType::Submit && urb_header.status != 0 {
+            self.packet_buffers.remove(&buffer_key);
+            return;
+        }
+
+        let packet_buffer = self.packet_buffers.entry(buffer_key).or_default();
+        packet_buffer.extend_from_slice(urb_packet.data.as_deref().unwrap_or_default());
+
+        let mut packets = vec![];
+        while let Some(packet_length) = bluetooth_hci::get_packet_length(packet_type, packet_buffer) && packet_buffer.len() >= packet_length {
+            packets.push(packet_buffer.drain(..packet_length).collect::<Vec<u8>>());
+        }
+
+        for packet in packets {
+            let (description, is_error) = match packet_type {
+                HciPacketType::Event => self.describe_event(&urb_header, &packet),
+                _ => (format!("[HCI] {} ({})", bluetooth_hci::describe_acl(&packet), if is_received { "Received" } else { "Sent" }), false),
+            };
+
+            let export_error = self.write_capture(urb_header.timestamp, packet_type, is_received, &packet);
+
+            /* Rows show the HCI Packet, the URB may hold more or less */
+            let packet_row = UrbXractPacket { header: urb_header, data: Some(packet), iso_descriptors: vec![] };
+            self.dispatch_row(packet_row, description, is_error).await;
+            if let Some(export_error) = export_error {
+                self.dispatch_row(UrbXractPacket { header: urb_header, data: None, iso_descriptors: vec![] }, export_error, true).await;
+            }
+        }
+    }
+}
+
+impl ReconstructionModule for Reconstructor {
+    fn new(module_tx: Sender<ReconstructedTransmission>, module_context: &ModuleContext) -> Self {
+        Self {
+            module_tx,
+            module_context: module_context.clone(),
+            pending_requests: PendingRequests::default(),
+            pending_commands: HashMap::new(),
+            packet_buffers: HashMap::new(),
+            hci_capture: None,
+            is_capture_failed: false,
+        }
+    }
+
+    async fn consume_packet(&mut self, urb_packet: UrbXractPacket) {
+        /* OUT Data travels with the Submission, IN Data with the Completion */
+        let urb_header = urb_packet.header;
+        let is_transfer_data = (urb_header.endpoint_info & 0x80 != 0) == (urb_header.event_type != UrbEventType::Submit);
+        match urb_header.transfer_type {
+            UrbTransferType::Control => self.consume_command(urb_packet).await,
+            UrbTransferType::Interrupt if is_transfer_data => self.consume_stream(urb_packet, HciPacketType::Event).await,
+            UrbTransferType::Bulk if is_transfer_data => self.consume_stream(urb_packet, HciPacketType::Acl).await,
+            _ => {}
+        }
+    }
+}
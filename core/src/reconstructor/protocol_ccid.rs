@@ -0,0 +1,490 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use tokio::sync::mpsc::Sender;
+use crate::sniffer::{UrbTransferType, UrbXractHeader, UrbXractPacket};
+
+use super::device_model::SetupPacket;
+use super::smartcard_apdu;
+use super::protocol_control::{self, PendingRequests};
+use super::{ModuleContext, ReconstructedTransmission, ReconstructionModule};
+
+/*
+    CCID (Smart Card CCID Rev 1.1). Bulk OUT carries PC_to_RDR Messages, Bulk IN
+    the RDR_to_PC Responses echoing Slot and Sequence, and Interrupt IN the Slot
+    Change Notifications. Every Message starts with a 10 Byte Header
+*/
+const HEADER_LENGTH: usize = 10;
+const MAX_MESSAGE_LENGTH: usize = 65544; /* Header and the largest Extended APDU */
+
+const PC_TO_RDR_SET_PARAMETERS: u8 = 0x61;
+const PC_TO_RDR_ICC_POWER_ON: u8 = 0x62;
+const PC_TO_RDR_ICC_POWER_OFF: u8 = 0x63;
+const PC_TO_RDR_GET_SLOT_STATUS: u8 = 0x65;
+const PC_TO_RDR_SECURE: u8 = 0x69;
+const PC_TO_RDR_T0_APDU: u8 = 0x6A;
+const PC_TO_RDR_ESCAPE: u8 = 0x6B;
+const PC_TO_RDR_GET_PARAMETERS: u8 = 0x6C;
+const PC_TO_RDR_RESET_PARAMETERS: u8 = 0x6D;
+const PC_TO_RDR_ICC_CLOCK: u8 = 0x6E;
+const PC_TO_RDR_XFR_BLOCK: u8 = 0x6F;
+const PC_TO_RDR_MECHANICAL: u8 = 0x71;
+const PC_TO_RDR_ABORT: u8 = 0x72;
+const PC_TO_RDR_SET_DATA_RATE_AND_CLOCK_FREQUENCY: u8 = 0x73;
+
+const RDR_TO_PC_DATA_BLOCK: u8 = 0x80;
+const RDR_TO_PC_SLOT_STATUS: u8 = 0x81;
+const RDR_TO_PC_PARAMETERS: u8 = 0x82;
+const RDR_TO_PC_ESCAPE: u8 = 0x83;
+const RDR_TO_PC_DATA_RATE_AND_CLOCK_FREQUENCY: u8 = 0x84;
+const RDR_TO_PC_NOTIFY_SLOT_CHANGE: u8 = 0x50;
+const RDR_TO_PC_HARDWARE_ERROR: u8 = 0x51;
+
+const COMMAND_STATUS_FAILED: u8 = 0x01;
+const COMMAND_STATUS_TIME_EXTENSION: u8 = 0x02;
+
+const REQUEST_ABORT: u8 = 0x01;
+const REQUEST_GET_CLOCK_FREQUENCIES: u8 = 0x02;
+const REQUEST_GET_DATA_RATES: u8 = 0x03;
+
+struct PendingMessage {
+    urbx_header: UrbXractHeader,
+    message_type: u8,
+    description: String,
+    time_extensions: usize,
+    sources: Vec<UrbXractPacket>,
+}
+
+struct PartialMessage {
+    data: Vec<u8>,
+    sources: Vec<UrbXractPacket>,
+}
+
+pub struct Reconstructor {
+    module_tx: Sender<ReconstructedTransmission>,
+    pending_requests: PendingRequests, /* Control Requests awaiting Completion */
+    pending_messages: HashMap<(String, u8, u8), PendingMessage>, /* (Bus:Device, Slot, Sequence), Command awaiting its Response */
+    partial_messages: HashMap<(String, u8), PartialMessage>, /* (Bus:Device, Endpoint), Message spanning several Transfers */
+}
+
+fn read_le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..(offset + 4)].try_into().unwrap())
+}
+
+fn get_message_name(message_type: u8) -> &'static str {
+    match message_type {
+        PC_TO_RDR_SET_PARAMETERS => "SetParameters",
+        PC_TO_RDR_ICC_POWER_ON => "IccPowerOn",
+        PC_TO_RDR_ICC_POWER_OFF => "IccPowerOff",
+        PC_TO_RDR_GET_SLOT_STATUS => "GetSlotStatus",
+        PC_TO_RDR_SECURE => "Secure",
+        PC_TO_RDR_T0_APDU => "T0APDU",
+        PC_TO_RDR_ESCAPE => "Escape",
+        PC_TO_RDR_GET_PARAMETERS => "GetParameters",
+        PC_TO_RDR_RESET_PARAMETERS => "ResetParameters",
+        PC_TO_RDR_ICC_CLOCK => "IccClock",
+        PC_TO_RDR_XFR_BLOCK => "XfrBlock",
+        PC_TO_RDR_MECHANICAL => "Mechanical",
+        PC_TO_RDR_ABORT => "Abort",
+        PC_TO_RDR_SET_DATA_RATE_AND_CLOCK_FREQUENCY => "SetDataRateAndClockFrequency",
+        RDR_TO_PC_DATA_BLOCK => "DataBlock",
+        RDR_TO_PC_SLOT_STATUS => "SlotStatus",
+        RDR_TO_PC_PARAMETERS => "Parameters",
+        RDR_TO_PC_ESCAPE => "Escape",
+        RDR_TO_PC_DATA_RATE_AND_CLOCK_FREQUENCY => "DataRateAndClockFrequency",
+        RDR_TO_PC_NOTIFY_SLOT_CHANGE => "NotifySlotChange",
+        RDR_TO_PC_HARDWARE_ERROR => "HardwareError",
+        _ => "Unknown Message"
+    }
+}
+
+fn get_slot_error_name(slot_error: u8) -> String {
+    match slot_error {
+        0xFF => String::from("CMD_ABORTED"),
+        0xFE => String::from("ICC_MUTE"),
+        0xFD => String::from("XFR_PARITY_ERROR"),
+        0xFC => String::from("XFR_OVERRUN"),
+        0xFB => String::from("HW_ERROR"),
+        0xF8 => String::from("BAD_ATR_TS"),
+        0xF7 => String::from("BAD_ATR_TCK"),
+        0xF6 => String::from("ICC_PROTOCOL_NOT_SUPPORTED"),
+        0xF5 => String::from("ICC_CLASS_NOT_SUPPORTED"),
+        0xF4 => String::from("PROCEDURE_BYTE_CONFLICT"),
+        0xF3 => String::from("DEACTIVATED_PROTOCOL"),
+        0xF2 => String::from("BUSY_WITH_AUTO_SEQUENCE"),
+        0xF0 => String::from("PIN_TIMEOUT"),
+        0xEF => String::from("PIN_CANCELLED"),
+        0xE0 => String::from("CMD_SLOT_BUSY"),
+        0x00 => String::from("Command not supported"),
+        0x01..=0x7F => format!("Bad Parameter at Offset {}", slot_error),
+        _ => format!("Vendor Error 0x{:02X}", slot_error),
+    }
+}
+
+fn get_icc_status_name(slot_status: u8) -> &'static str {
+    match slot_status & 0x03 {
+        0x00 => "Card Present, Active",
+        0x01 => "Card Present, Inactive",
+        0x02 => "No Card",
+        _ => "Reserved ICC Status"
+    }
+}
+
+fn describe_power_select(power_select: u8) -> &'static str {
+    match power_select {
+        0x00 => "Automatic Voltage",
+        0x01 => "5.0 V",
+        0x02 => "3.0 V",
+        0x03 => "1.8 V",
+        _ => "Reserved Voltage"
+    }
+}
+
+fn describe_level_parameter(level_parameter: u16) -> Option<&'static str> {
+    /* Extended APDU Level: Command split over several XfrBlocks */
+    match level_parameter {
+        0x0001 => Some("Chain Begin"),
+        0x0002 => Some("Chain End"),
+        0x0003 => Some("Chain Middle"),
+        0x0010 => Some("Chain Continue Response"),
+        _ => None
+    }
+}
+
+fn describe_protocol_data(protocol: u8, protocol_data: &[u8]) -> String {
+    /* abProtocolData for T=0 (5 Bytes) or T=1 (7 Bytes) */
+    match (protocol, protocol_data) {
+        (0x00, [findex_dindex, _, guard_time, waiting_integer, _]) => {
+            format!("T=0 Fi/Di 0x{:02X} Guard Time {} WI {}", findex_dindex, guard_time, waiting_integer)
+        },
+        (0x01, [findex_dindex, checksum, guard_time, waiting_integers, _, information_field_size, _]) => format!(
+            "T=1 Fi/Di 0x{:02X} {} Guard Time {} BWI {} CWI {} IFSC {}",
+            findex_dindex,
+            if checksum & 0x01 != 0 { "CRC" } else { "LRC" },
+            guard_time,
+            waiting_integers >> 4,
+            waiting_integers & 0x0F,
+            information_field_size
+        ),
+        (protocol, protocol_data) => format!("T={} {}", protocol, smartcard_apdu::format_data(protocol_data)),
+    }
+}
+
+fn describe_command(message: &[u8]) -> String {
+    let (message_type, data) = (message[0], &message[HEADER_LENGTH..]);
+    let message_name = get_message_name(message_type);
+    match message_type {
+        PC_TO_RDR_ICC_POWER_ON => format!("{} ({})", message_name, describe_power_select(message[7])),
+        PC_TO_RDR_XFR_BLOCK => {
+            let level_parameter = u16::from_le_bytes([message[8], message[9]]);
+            match describe_level_parameter(level_parameter) {
+                Some(chain_position) => format!("{} ({}) {}", message_name, chain_position, smartcard_apdu::format_data(data)),
+                None => format!("{} APDU {}", message_name, smartcard_apdu::describe_command(data)),
+            }
+        },
+
+        PC_TO_RDR_SET_PARAMETERS => format!("{} {}", message_name, describe_protocol_data(message[7], data)),
+        PC_TO_RDR_ICC_CLOCK => format!("{} ({})", message_name, if message[7] == 0 { "Restart" } else { "Stop" }),
+        PC_TO_RDR_MECHANICAL => {
+            let function = match message[7] {
+                0x01 => "Accept Card",
+                0x02 => "Eject Card",
+                0x03 => "Capture Card",
+                0x04 => "Lock Card",
+                0x05 => "Unlock Card",
+                _ => "Reserved Function",
+            };
+
+            format!("{} ({})", message_name, function)
+        },
+
+        /* PIN Verification or Modification: the APDU Template follows the PIN Structure */
+        PC_TO_RDR_SECURE => {
+            let operation = match data.first() {
+                Some(0x00) => "PIN Verification",
+                Some(0x01) => "PIN Modification",
+                Some(0x02) => "Transfer PIN",
+                Some(0x03) => "Wait ICC Response",
+                Some(0x04) => "Cancel",
+                _ => "Unknown Operation",
+            };
+
+            format!("{} ({}) {}", message_name, operation, smartcard_apdu::format_data(data.get(1..).unwrap_or_default()))
+        },
+
+        PC_TO_RDR_SET_DATA_RATE_AND_CLOCK_FREQUENCY if data.len() >= 8 => {
+            format!("{} Clock {} kHz, Data Rate {} bps", message_name, read_le32(data, 0), read_le32(data, 4))
+        },
+
+        PC_TO_RDR_ICC_POWER_OFF | PC_TO_RDR_GET_SLOT_STATUS | PC_TO_RDR_GET_PARAMETERS | PC_TO_RDR_RESET_PARAMETERS | PC_TO_RDR_ABORT => String::from(message_name),
+        _ if data.is_empty() => String::from(message_name),
+        _ => format!("{} {}", message_name, smartcard_apdu::format_data(data)),
+    }
+}
+
+fn describe_response(command_type: u8, message: &[u8]) -> (String, bool) {
+    /* bStatus: ICC Status in Bits 0-1, Command Status in Bits 6-7 */
+    let (message_type, data) = (message[0], &message[HEADER_LENGTH..]);
+    let (slot_status, slot_error) = (message[7], message[8]);
+    let message_name = get_message_name(message_type);
+    if slot_status >> 6 == COMMAND_STATUS_FAILED {
+        return (format!("{} Failed: {} ({})", message_name, get_slot_error_name(slot_error), get_icc_status_name(slot_status)), true);
+    }
+
+    match message_type {
+        RDR_TO_PC_DATA_BLOCK if command_type == PC_TO_RDR_ICC_POWER_ON => (format!("{} {}", message_name, smartcard_apdu::describe_atr(data)), false),
+        RDR_TO_PC_DATA_BLOCK if command_type == PC_TO_RDR_XFR_BLOCK && message[9] == 0x00 => {
+            let (description, is_error) = smartcard_apdu::describe_response(data);
+            (format!("{} {}", message_name, description), is_error)
+        },
+
+        /* bChainParameter: Response split over several DataBlocks */
+        RDR_TO_PC_DATA_BLOCK if command_type == PC_TO_RDR_XFR_BLOCK => {
+            let chain_position = match message[9] {
+                0x01 => "Chain Begin",
+                0x02 => "Chain End",
+                0x03 => "Chain Middle",
+                0x10 => "Chain Continue Command",
+                _ => "Reserved Chain Parameter",
+            };
+
+            (format!("{} ({}) {}", message_name, chain_position, smartcard_apdu::format_data(data)), false)
+        },
+
+        RDR_TO_PC_SLOT_STATUS => {
+            let clock_status = match message[9] {
+                0x00 => "Clock Running",
+                0x01 => "Clock Stopped (L)",
+                0x02 => "Clock Stopped (H)",
+                0x03 => "Clock Stopped",
+                _ => "Reserved Clock Status",
+            };
+
+            (format!("{} ({}, {})", message_name, get_icc_status_name(slot_status), clock_status), false)
+        },
+
+        RDR_TO_PC_PARAMETERS => (format!("{} {}", message_name, describe_protocol_data(message[9], data)), false),
+        RDR_TO_PC_DATA_RATE_AND_CLOCK_FREQUENCY if data.len() >= 8 => {
+            (format!("{} Clock {} kHz, Data Rate {} bps", message_name, read_le32(data, 0), read_le32(data, 4)), false)
+        },
+
+        _ if data.is_empty() => (String::from(message_name), false),
+        _ => (format!("{} {}", message_name, smartcard_apdu::format_data(data)), false),
+    }
+}
+
+fn describe_notification(message: &[u8]) -> (String, bool) {
+    match message[0] {
+        RDR_TO_PC_NOTIFY_SLOT_CHANGE => {
+            /* Two Bits per Slot: Card Present, Changed */
+            let slot_states: Vec<String> = message[1..]
+                .iter()
+                .flat_map(|slot_bits| (0..4).map(move |slot_offset| slot_bits >> (slot_offset * 2) & 0x03))
+                .enumerate()
+                .filter(|(slot, slot_state)| *slot == 0 || *slot_state != 0)
+                .map(|(slot, slot_state)| format!(
+                    "Slot {} {}{}",
+                    slot,
+                    if slot_state & 0x01 != 0 { "Card Present" } else { "No Card" },
+                    if slot_state & 0x02 != 0 { " (Changed)" } else { "" }
+                ))
+                .collect();
+
+            (format!("{}: {}", get_message_name(message[0]), slot_states.join(", ")), false)
+        },
+
+        RDR_TO_PC_HARDWARE_ERROR if message.len() >= 4 => {
+            let error_name = if message[3] == 0x01 { "Overcurrent" } else { "Unknown Hardware Error" };
+            (format!("{} Slot {} Seq {}: {}", get_message_name(message[0]), message[1], message[2], error_name), true)
+        },
+
+        _ => (format!("{}: {}", get_message_name(message[0]), smartcard_apdu::format_data(message)), true),
+    }
+}
+
+fn describe_request(setup_packet: &SetupPacket, data: Option<&[u8]>) -> String {
+    /* Class Requests on the Default Pipe, Frequencies and Rates are 32 Bit Arrays */
+    let format_values = |unit: &str| data
+        .unwrap_or_default()
+        .chunks_exact(4)
+        .map(|value| format!("{} {}", read_le32(value, 0), unit))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    match setup_packet.request {
+        REQUEST_ABORT => format!("[CCID] ABORT Slot {} Seq {}", setup_packet.value as u8, setup_packet.value >> 8),
+        REQUEST_GET_CLOCK_FREQUENCIES => format!("[CCID] GET_CLOCK_FREQUENCIES [{}]", format_values("kHz")),
+        REQUEST_GET_DATA_RATES => format!("[CCID] GET_DATA_RATES [{}]", format_values("bps")),
+        request => format!("[CCID] Class Request 0x{:02X} wValue=0x{:04X}", request, setup_packet.value),
+    }
+}
+
+impl Reconstructor {
+    async fn dispatch_message(&mut self, pending_message: PendingMessage, slot: u8, sequence: u8, response: Option<(String, bool, u64)>) {
+        let mut notes = vec![];
+        let (response_description, is_error) = match response {
+            Some((description, is_error, end_timestamp)) => {
+                notes.push(format!("{:.3} ms", end_timestamp.saturating_sub(pending_message.urbx_header.timestamp) as f64 / 1000.0));
+                (description, is_error)
+            },
+            None => (String::from("(Response Not Captured)"), true),
+        };
+
+        if pending_message.time_extensions > 0 {
+            notes.push(format!("{} Time Extensions", pending_message.time_extensions));
+        }
+
+        let transmission = ReconstructedTransmission {
+            urbx_header: pending_message.urbx_header,
+            combined_payload: format!(
+                "[CCID Slot {} Seq {}] {} -> {}{}",
+                slot,
+                sequence,
+                pending_message.description,
+                response_description,
+                if notes.is_empty() { String::new() } else { format!(" ({})", notes.join(", ")) }
+            ),
+            sources: pending_message.sources,
+            is_error,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    async fn dispatch_notice(&mut self, urb_header: UrbXractHeader, sources: Vec<UrbXractPacket>, notice: String, is_error: bool) {
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_header,
+            combined_payload: notice,
+            sources,
+            is_error,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    async fn consume_control(&mut self, mut urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let Some((setup_packet, data)) = self.pending_requests.pair(&mut urb_packet) else { return };
+        let mut description = describe_request(&setup_packet, data);
+        if urb_header.status != 0 {
+            description += &format!(" -> {}", protocol_control::describe_status(&urb_header));
+        }
+
+        self.dispatch_notice(urb_header, vec![urb_packet], description, urb_header.status != 0).await;
+    }
+
+    async fn consume_message(&mut self, device_key: String, message: Vec<u8>, sources: Vec<UrbXractPacket>) {
+        let urb_header = sources[0].header;
+        let (message_type, slot, sequence) = (message[0], message[5], message[6]);
+        let message_key = (device_key, slot, sequence);
+
+        /* Commands wait for the Response carrying the same Slot and Sequence */
+        if urb_header.endpoint_info & 0x80 == 0 {
+            if let Some(stale_message) = self.pending_messages.remove(&message_key) {
+                self.dispatch_message(stale_message, slot, sequence, None).await;
+            }
+
+            self.pending_messages.insert(message_key, PendingMessage {
+                urbx_header: urb_header,
+                message_type,
+                description: describe_command(&message),
+                time_extensions: 0,
+                sources,
+            });
+
+            return;
+        }
+
+        let end_timestamp = sources.last().map(|source| source.header.timestamp).unwrap_or_default();
+        match self.pending_messages.remove(&message_key) {
+            /* Time Extension: the Card needs longer, the real Response follows */
+            Some(mut pending_message) if message[7] >> 6 == COMMAND_STATUS_TIME_EXTENSION => {
+                pending_message.time_extensions += 1;
+                pending_message.sources.extend(sources);
+                self.pending_messages.insert(message_key, pending_message);
+            },
+
+            Some(mut pending_message) => {
+                let (description, is_error) = describe_response(pending_message.message_type, &message);
+                pending_message.sources.extend(sources);
+                self.dispatch_message(pending_message, slot, sequence, Some((description, is_error, end_timestamp))).await;
+            },
+
+            None => {
+                let (description, is_error) = describe_response(0x00, &message);
+                let notice = format!("[CCID Slot {} Seq {}] (Command Not Captured) -> {}", slot, sequence, description);
+                self.dispatch_notice(urb_header, sources, notice, is_error).await;
+            }
+        }
+    }
+}
+
+impl ReconstructionModule for Reconstructor {
+    fn new(module_tx: Sender<ReconstructedTransmission>, _module_context: &ModuleContext) -> Self {
+        Self {
+            module_tx,
+            pending_requests: PendingRequests::default(),
+            pending_messages: HashMap::new(),
+            partial_messages: HashMap::new(),
+        }
+    }
+
+    async fn consume_packet(&mut self, urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        if urb_header.transfer_type == UrbTransferType::Control {
+            return self.consume_control(urb_packet).await;
+        }
+
+        let urb_data = urb_packet.data.clone().unwrap_or_default();
+        if urb_header.transfer_type == UrbTransferType::Interrupt {
+            let (notice, is_error) = match urb_data.is_empty() {
+                true => return,
+                false => describe_notification(&urb_data),
+            };
+
+            return self.dispatch_notice(urb_header, vec![urb_packet], format!("[CCID] {}", notice), is_error).await;
+        }
+
+        /* Messages longer than the Transfer continue in the next one on the same Endpoint */
+        let device_key = format!("{}:{}", urb_header.bus_id, urb_header.device_id);
+        let partial_key = (device_key.clone(), urb_header.endpoint_info);
+        let mut partial_message = self.partial_messages.remove(&partial_key).unwrap_or(PartialMessage { data: vec![], sources: vec![] });
+        partial_message.data.extend_from_slice(&urb_data);
+        partial_message.sources.push(urb_packet);
+
+        if partial_message.data.len() < HEADER_LENGTH {
+            let notice = format!("[CCID] Short Message: {}", smartcard_apdu::format_data(&partial_message.data));
+            return self.dispatch_notice(urb_header, partial_message.sources, notice, true).await;
+        }
+
+        let message_length = HEADER_LENGTH + read_le32(&partial_message.data, 1) as usize;
+        if message_length > MAX_MESSAGE_LENGTH {
+            let notice = format!("[CCID] Invalid Message Length {}: {}", message_length, smartcard_apdu::format_data(&partial_message.data));
+            return self.dispatch_notice(urb_header, partial_message.sources, notice, true).await;
+        }
+
+        if partial_message.data.len() < message_length {
+            self.partial_messages.insert(partial_key, partial_message);
+            return;
+        }
+
+        partial_message.data.truncate(message_length);
+        self.consume_message(device_key, partial_message.data, partial_message.sources).await;
+    }
+}
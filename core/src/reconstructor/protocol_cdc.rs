@@ -0,0 +1,227 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>. 
+*/
+
+use super::device_model::SetupPacket;
+
+/*
+    CDC PSTN Subclass (Abstract Control Model):
+    https://www.usb.org/document-library/class-definitions-communication-devices-12
+*/
+pub const REQUEST_SET_LINE_CODING: u8 = 0x20;
+pub const REQUEST_GET_LINE_CODING: u8 = 0x21;
+pub const REQUEST_SET_CONTROL_LINE_STATE: u8 = 0x22;
+pub const REQUEST_SEND_BREAK: u8 = 0x23;
+const NOTIFICATION_NETWORK_CONNECTION: u8 = 0x00;
+const NOTIFICATION_RESPONSE_AVAILABLE: u8 = 0x01;
+const NOTIFICATION_SERIAL_STATE: u8 = 0x20;
+const NOTIFICATION_CONNECTION_SPEED_CHANGE: u8 = 0x2A;
+const NOTIFICATION_HEADER_LENGTH: usize = 8;
+
+/* Modem and Line Status normalized across Vendor Adapters */
+pub const MODEM_STATUS_CTS: u8 = 0x01;
+pub const MODEM_STATUS_DSR: u8 = 0x02;
+pub const MODEM_STATUS_RI: u8 = 0x04;
+pub const MODEM_STATUS_DCD: u8 = 0x08;
+pub const LINE_STATUS_OVERRUN: u8 = 0x10;
+pub const LINE_STATUS_PARITY: u8 = 0x20;
+pub const LINE_STATUS_FRAMING: u8 = 0x40;
+pub const LINE_STATUS_BREAK: u8 = 0x80;
+const MODEM_STATUS_NAMES: [(u8, &str); 8] = [
+    (MODEM_STATUS_CTS, "CTS"), (MODEM_STATUS_DSR, "DSR"), (MODEM_STATUS_RI, "RING"), (MODEM_STATUS_DCD, "DCD"),
+    (LINE_STATUS_OVERRUN, "OVERRUN"), (LINE_STATUS_PARITY, "PARITY_ERROR"), (LINE_STATUS_FRAMING, "FRAMING_ERROR"), (LINE_STATUS_BREAK, "BREAK"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineCoding {
+    pub baud_rate: u32,
+    pub stop_bits: u8,  /* 0: 1, 1: 1.5, 2: 2 */
+    pub parity: u8,     /* 0: None, 1: Odd, 2: Even, 3: Mark, 4: Space */
+    pub data_bits: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LineState {
+    pub line_coding: Option<LineCoding>,
+    pub dtr: Option<bool>,
+    pub rts: Option<bool>,
+    pub serial_state: Option<u16>,
+    pub modem_status: Option<u8>, /* Vendor Adapters, see MODEM_STATUS_NAMES */
+}
+
+impl LineCoding {
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < 7 {
+            return None;
+        }
+
+        Some(Self {
+            baud_rate: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+            stop_bits: data[4],
+            parity: data[5],
+            data_bits: data[6],
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        /* Conventional Notation, e.g. 115200 8N1, Vendor Adapters may not have set the Baud Rate yet */
+        format!(
+            "{}{}{}{}",
+            if self.baud_rate != 0 { format!("{} ", self.baud_rate) } else { String::new() },
+            self.data_bits,
+            ["N", "O", "E", "M", "S"].get(self.parity as usize).unwrap_or(&"?"),
+            ["1", "1.5", "2"].get(self.stop_bits as usize).unwrap_or(&"?")
+        )
+    }
+}
+
+fn format_signal(signal_name: &str, previous: Option<bool>, current: bool) -> String {
+    let level = |state: bool| if state { "On" } else { "Off" };
+    match previous {
+        Some(previous) if previous != current => format!("{}: {} -> {}", signal_name, level(previous), level(current)),
+        _ => format!("{}: {}", signal_name, level(current))
+    }
+}
+
+impl LineState {
+    pub fn update_modem_status(&mut self, modem_status: u8) -> Option<String> {
+        /* Adapters repeat their Status constantly, only Changes are reported */
+        let previous = self.modem_status.replace(modem_status);
+        if previous == Some(modem_status) {
+            return None;
+        }
+
+        let changed_bits = previous.map(|previous| previous ^ modem_status).unwrap_or(0);
+        let status_names: Vec<String> = MODEM_STATUS_NAMES
+            .iter()
+            .filter(|(status_bit, _)| modem_status & status_bit != 0 || changed_bits & status_bit != 0)
+            .map(|(status_bit, status_name)| match (modem_status & status_bit != 0, changed_bits & status_bit != 0) {
+                (true, true) => format!("+{}", status_name),
+                (false, true) => format!("-{}", status_name),
+                _ => status_name.to_string()
+            })
+            .collect();
+
+        Some(format!(
+            "Modem Status: {}",
+            if status_names.is_empty() { String::from("Idle") } else { status_names.join(" ") }
+        ))
+    }
+
+    pub fn update_control_lines(&mut self, dtr: Option<bool>, rts: Option<bool>) -> String {
+        let mut signal_changes = vec![];
+        if let Some(dtr) = dtr {
+            signal_changes.push(format_signal("DTR", self.dtr.replace(dtr), dtr));
+        }
+
+        if let Some(rts) = rts {
+            signal_changes.push(format_signal("RTS", self.rts.replace(rts), rts));
+        }
+
+        signal_changes.join(", ")
+    }
+
+    pub fn update_line_coding(&mut self, line_coding: LineCoding) -> String {
+        match self.line_coding.replace(line_coding) {
+            Some(previous) if previous != line_coding => format!("{} (was {})", line_coding.describe(), previous.describe()),
+            _ => line_coding.describe()
+        }
+    }
+}
+
+pub fn is_acm_request(setup_packet: &SetupPacket) -> bool {
+    /* Class Requests addressed to an Interface */
+    setup_packet.request_type & 0x7F == 0x21
+        && (REQUEST_SET_LINE_CODING..=REQUEST_SEND_BREAK).contains(&setup_packet.request)
+}
+
+pub fn describe_acm_request(line_state: &mut LineState, setup_packet: &SetupPacket, data: Option<&[u8]>) -> Option<String> {
+    match setup_packet.request {
+        REQUEST_SET_LINE_CODING | REQUEST_GET_LINE_CODING => {
+            let line_coding = LineCoding::from_bytes(data?)?;
+            Some(format!(
+                "{}: {}",
+                if setup_packet.request == REQUEST_SET_LINE_CODING { "SET_LINE_CODING" } else { "GET_LINE_CODING" },
+                line_state.update_line_coding(line_coding)
+            ))
+        },
+
+        REQUEST_SET_CONTROL_LINE_STATE => {
+            /* wValue Bit 0 is DTR, Bit 1 is RTS */
+            let dtr = setup_packet.value & 0x01 != 0;
+            let rts = setup_packet.value & 0x02 != 0;
+            Some(format!("SET_CONTROL_LINE_STATE: {}", line_state.update_control_lines(Some(dtr), Some(rts))))
+        },
+
+        REQUEST_SEND_BREAK => Some(match setup_packet.value {
+            0x0000 => String::from("SEND_BREAK: Off"),
+            0xFFFF => String::from("SEND_BREAK: On (until cleared)"),
+            duration => format!("SEND_BREAK: {} ms", duration)
+        }),
+
+        _ => None
+    }
+}
+
+pub fn is_notification(data: &[u8]) -> bool {
+    /* Notifications reuse the Setup Packet layout, followed by wLength bytes */
+    data.len() >= NOTIFICATION_HEADER_LENGTH && data[0] == 0xA1
+}
+
+pub fn describe_notification(line_state: &mut LineState, data: &[u8]) -> Option<String> {
+    if !is_notification(data) {
+        return None;
+    }
+
+    let value = u16::from_le_bytes([data[2], data[3]]);
+    let payload = &data[NOTIFICATION_HEADER_LENGTH..];
+    match data[1] {
+        NOTIFICATION_NETWORK_CONNECTION => Some(format!("NETWORK_CONNECTION: {}", if value != 0 { "Connected" } else { "Disconnected" })),
+        NOTIFICATION_RESPONSE_AVAILABLE => Some(String::from("RESPONSE_AVAILABLE")),
+
+        NOTIFICATION_SERIAL_STATE if payload.len() >= 2 => {
+            let serial_state = u16::from_le_bytes([payload[0], payload[1]]);
+            let changed_bits = line_state.serial_state.map(|previous| previous ^ serial_state).unwrap_or(0);
+            let state_names: Vec<String> = [
+                (0x01, "DCD"), (0x02, "DSR"), (0x04, "BREAK"), (0x08, "RING"),
+                (0x10, "FRAMING_ERROR"), (0x20, "PARITY_ERROR"), (0x40, "OVERRUN"),
+            ]
+            .iter()
+            .filter(|(state_bit, _)| serial_state & state_bit != 0 || changed_bits & state_bit != 0)
+            .map(|(state_bit, state_name)| match (serial_state & state_bit != 0, changed_bits & state_bit != 0) {
+                (true, true) => format!("+{}", state_name),
+                (false, true) => format!("-{}", state_name),
+                _ => state_name.to_string()
+            })
+            .collect();
+
+            line_state.serial_state = Some(serial_state);
+            Some(format!(
+                "SERIAL_STATE: {}",
+                if state_names.is_empty() { String::from("Idle") } else { state_names.join(" ") }
+            ))
+        },
+
+        NOTIFICATION_CONNECTION_SPEED_CHANGE if payload.len() >= 8 => Some(format!(
+            "CONNECTION_SPEED_CHANGE: Down {} bps, Up {} bps",
+            u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]),
+            u32::from_le_bytes([payload[4], payload[5], payload[6], payload[7]])
+        )),
+
+        notification => Some(format!("Notification 0x{:02X} (wValue=0x{:04X})", notification, value))
+    }
+}
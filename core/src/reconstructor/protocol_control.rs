@@ -0,0 +1,432 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use tokio::sync::mpsc::Sender;
+use crate::sniffer::{UrbEventType, UrbXractHeader, UrbXractPacket};
+
+use super::device_model::{self, SetupPacket};
+use super::{ModuleContext, ReconstructedTransmission, ReconstructionModule};
+
+/* Define Constants */
+const DATA_PREVIEW_LENGTH: usize = 32;
+const RECIPIENT_DEVICE: u8 = 0;
+const RECIPIENT_INTERFACE: u8 = 1;
+const RECIPIENT_ENDPOINT: u8 = 2;
+const REQUEST_TYPE_STANDARD: u8 = 0;
+const REQUEST_TYPE_CLASS: u8 = 1;
+const REQUEST_TYPE_VENDOR: u8 = 2;
+
+struct PendingControl {
+    urbx_header: UrbXractHeader,
+    setup_packet: SetupPacket,
+    description: String,
+    data: Option<Vec<u8>>,
+}
+
+/* Class Modules pair Control Submissions with their Completions */
+#[derive(Default)]
+pub struct PendingRequests {
+    requests: HashMap<u64, (SetupPacket, Option<Vec<u8>>)>, /* URB ID, Setup and OUT Data */
+}
+
+impl PendingRequests {
+    pub fn pair<'a>(&mut self, urb_packet: &'a mut UrbXractPacket) -> Option<(SetupPacket, Option<&'a [u8]>)> {
+        /* OUT Data travels with the Submission and is moved to the Completion, IN Data arrives with it */
+        let urb_header = urb_packet.header;
+        if urb_header.event_type == UrbEventType::Submit {
+            if let Some(setup_data) = &urb_header.setup_packet {
+                self.requests.insert(urb_header.urb_id, (SetupPacket::from_bytes(setup_data), urb_packet.data.take()));
+            }
+
+            return None;
+        }
+
+        let (setup_packet, out_data) = self.requests.remove(&urb_header.urb_id)?;
+        if setup_packet.request_type & 0x80 == 0 {
+            urb_packet.data = out_data;
+        }
+
+        Some((setup_packet, urb_packet.data.as_deref()))
+    }
+}
+
+pub struct Reconstructor {
+    module_tx: Sender<ReconstructedTransmission>,
+    module_context: ModuleContext,
+    pending: HashMap<u64, PendingControl>, /* URB ID, Control awaiting Completion */
+}
+
+fn get_standard_request_name(request: u8) -> &'static str {
+    match request {
+        0x00 => "GET_STATUS",
+        0x01 => "CLEAR_FEATURE",
+        0x03 => "SET_FEATURE",
+        0x05 => "SET_ADDRESS",
+        0x06 => "GET_DESCRIPTOR",
+        0x07 => "SET_DESCRIPTOR",
+        0x08 => "GET_CONFIGURATION",
+        0x09 => "SET_CONFIGURATION",
+        0x0A => "GET_INTERFACE",
+        0x0B => "SET_INTERFACE",
+        0x0C => "SYNCH_FRAME",
+        0x30 => "SET_SEL",
+        0x31 => "SET_ISOCH_DELAY",
+        _ => "UNKNOWN_REQUEST"
+    }
+}
+
+pub fn get_descriptor_type_name(descriptor_type: u8) -> &'static str {
+    match descriptor_type {
+        0x01 => "DEVICE",
+        0x02 => "CONFIGURATION",
+        0x03 => "STRING",
+        0x04 => "INTERFACE",
+        0x05 => "ENDPOINT",
+        0x06 => "DEVICE_QUALIFIER",
+        0x07 => "OTHER_SPEED_CONFIGURATION",
+        0x08 => "INTERFACE_POWER",
+        0x0B => "INTERFACE_ASSOCIATION",
+        0x0F => "BOS",
+        0x10 => "DEVICE_CAPABILITY",
+        0x21 => "HID",
+        0x22 => "HID_REPORT",
+        0x24 => "CS_INTERFACE",
+        0x25 => "CS_ENDPOINT",
+        0x29 => "HUB",
+        0x2A => "SUPERSPEED_HUB",
+        0x30 => "SS_ENDPOINT_COMPANION",
+        _ => "UNKNOWN_DESCRIPTOR"
+    }
+}
+
+fn get_feature_name(recipient: u8, feature: u16) -> &'static str {
+    match (recipient, feature) {
+        (RECIPIENT_ENDPOINT, 0x00) => "ENDPOINT_HALT",
+        (RECIPIENT_INTERFACE, 0x00) => "FUNCTION_SUSPEND",
+        (RECIPIENT_DEVICE, 0x01) => "DEVICE_REMOTE_WAKEUP",
+        (RECIPIENT_DEVICE, 0x02) => "TEST_MODE",
+        (RECIPIENT_DEVICE, 0x30) => "U1_ENABLE",
+        (RECIPIENT_DEVICE, 0x31) => "U2_ENABLE",
+        (RECIPIENT_DEVICE, 0x32) => "LTM_ENABLE",
+        _ => "UNKNOWN_FEATURE"
+    }
+}
+
+fn get_class_request_name(class: u8, request: u8) -> Option<&'static str> {
+    /* Class Requests are only meaningful alongside the Interface Class */
+    let request_name = match (class, request) {
+        /* Audio (UAC1 and UAC2) */
+        (0x01, 0x01) => "SET_CUR",
+        (0x01, 0x02) => "RANGE",
+        (0x01, 0x81) => "GET_CUR",
+        (0x01, 0x82) => "GET_MIN",
+        (0x01, 0x83) => "GET_MAX",
+        (0x01, 0x84) => "GET_RES",
+
+        /* CDC Communications */
+        (0x02, 0x00) => "SEND_ENCAPSULATED_COMMAND",
+        (0x02, 0x01) => "GET_ENCAPSULATED_RESPONSE",
+        (0x02, 0x20) => "SET_LINE_CODING",
+        (0x02, 0x21) => "GET_LINE_CODING",
+        (0x02, 0x22) => "SET_CONTROL_LINE_STATE",
+        (0x02, 0x23) => "SEND_BREAK",
+        (0x02, 0x40) => "SET_ETHERNET_MULTICAST_FILTERS",
+        (0x02, 0x43) => "SET_ETHERNET_PACKET_FILTER",
+        (0x02, 0x80) => "GET_NTB_PARAMETERS",
+        (0x02, 0x85) => "GET_NTB_FORMAT",
+        (0x02, 0x86) => "SET_NTB_FORMAT",
+        (0x02, 0x87) => "GET_NTB_INPUT_SIZE",
+        (0x02, 0x88) => "SET_NTB_INPUT_SIZE",
+        (0x02, 0x89) => "GET_MAX_DATAGRAM_SIZE",
+        (0x02, 0x8A) => "SET_MAX_DATAGRAM_SIZE",
+
+        /* HID */
+        (0x03, 0x01) => "GET_REPORT",
+        (0x03, 0x02) => "GET_IDLE",
+        (0x03, 0x03) => "GET_PROTOCOL",
+        (0x03, 0x09) => "SET_REPORT",
+        (0x03, 0x0A) => "SET_IDLE",
+        (0x03, 0x0B) => "SET_PROTOCOL",
+
+        /* Printer */
+        (0x07, 0x00) => "GET_DEVICE_ID",
+        (0x07, 0x01) => "GET_PORT_STATUS",
+        (0x07, 0x02) => "SOFT_RESET",
+
+        /* Mass Storage */
+        (0x08, 0xFE) => "GET_MAX_LUN",
+        (0x08, 0xFF) => "BULK_ONLY_MASS_STORAGE_RESET",
+
+        /* Hub */
+        (0x09, 0x00) => "GET_STATUS",
+        (0x09, 0x01) => "CLEAR_FEATURE",
+        (0x09, 0x03) => "SET_FEATURE",
+        (0x09, 0x06) => "GET_DESCRIPTOR",
+        (0x09, 0x08) => "CLEAR_TT_BUFFER",
+        (0x09, 0x09) => "RESET_TT",
+        (0x09, 0x0C) => "SET_HUB_DEPTH",
+
+        /* Smart Card (CCID) */
+        (0x0B, 0x01) => "ABORT",
+        (0x0B, 0x02) => "GET_CLOCK_FREQUENCIES",
+        (0x0B, 0x03) => "GET_DATA_RATES",
+
+        /* Video */
+        (0x0E, 0x01) => "SET_CUR",
+        (0x0E, 0x81) => "GET_CUR",
+        (0x0E, 0x82) => "GET_MIN",
+        (0x0E, 0x83) => "GET_MAX",
+        (0x0E, 0x84) => "GET_RES",
+        (0x0E, 0x85) => "GET_LEN",
+        (0x0E, 0x86) => "GET_INFO",
+        (0x0E, 0x87) => "GET_DEF",
+
+        /* Application Specific (DFU) */
+        (0xFE, 0x00) => "DFU_DETACH",
+        (0xFE, 0x01) => "DFU_DNLOAD",
+        (0xFE, 0x02) => "DFU_UPLOAD",
+        (0xFE, 0x03) => "DFU_GETSTATUS",
+        (0xFE, 0x04) => "DFU_CLRSTATUS",
+        (0xFE, 0x05) => "DFU_GETSTATE",
+        (0xFE, 0x06) => "DFU_ABORT",
+
+        _ => return None
+    };
+
+    Some(request_name)
+}
+
+fn get_class_name(class: u8) -> &'static str {
+    match class {
+        0x01 => "Audio",
+        0x02 => "CDC",
+        0x03 => "HID",
+        0x07 => "Printer",
+        0x08 => "Mass Storage",
+        0x09 => "Hub",
+        0x0A => "CDC Data",
+        0x0B => "Smart Card",
+        0x0E => "Video",
+        0xE0 => "Wireless",
+        0xFE => "Application",
+        0xFF => "Vendor",
+        _ => "Class"
+    }
+}
+
+pub fn describe_setup(setup_packet: &SetupPacket, interface_class: Option<u8>) -> String {
+    let recipient = setup_packet.request_type & 0x1F;
+    let request_type = (setup_packet.request_type >> 5) & 0x03;
+    let parameters = format!(
+        "(bmRequestType=0x{:02X} wValue=0x{:04X} wIndex=0x{:04X} wLength={})",
+        setup_packet.request_type,
+        setup_packet.value,
+        setup_packet.index,
+        setup_packet.length
+    );
+
+    match request_type {
+        REQUEST_TYPE_STANDARD => {
+            let request_name = get_standard_request_name(setup_packet.request);
+            let details = match setup_packet.request {
+                0x06 | 0x07 => format!(
+                    " {} Index={}{}",
+                    get_descriptor_type_name((setup_packet.value >> 8) as u8),
+                    setup_packet.value & 0xFF,
+                    if (setup_packet.value >> 8) == 0x03 && setup_packet.index != 0 { format!(" LangID=0x{:04X}", setup_packet.index) } else { String::new() }
+                ),
+                0x01 | 0x03 => format!(" {}", get_feature_name(recipient, setup_packet.value)),
+                0x05 => format!(" Address={}", setup_packet.value),
+                0x09 => format!(" Configuration={}", setup_packet.value),
+                0x0B => format!(" Interface={} Alternate={}", setup_packet.index, setup_packet.value),
+                _ => String::new()
+            };
+
+            format!("{}{} {}", request_name, details, parameters)
+        },
+
+        REQUEST_TYPE_CLASS => {
+            let class = interface_class.unwrap_or(0);
+            match get_class_request_name(class, setup_packet.request) {
+                Some(request_name) => format!("{} {} {}", get_class_name(class), request_name, parameters),
+                None => format!("Class Request 0x{:02X} {}", setup_packet.request, parameters)
+            }
+        },
+
+        REQUEST_TYPE_VENDOR => format!("Vendor Request 0x{:02X} {}", setup_packet.request, parameters),
+        _ => format!("Reserved Request 0x{:02X} {}", setup_packet.request, parameters)
+    }
+}
+
+pub fn describe_status(urb_header: &UrbXractHeader) -> String {
+    if urb_header.status == 0 {
+        String::from("OK")
+    } else if urb_header.is_stalled() {
+        String::from("STALL")
+    } else {
+        format!("Error {}", urb_header.status)
+    }
+}
+
+pub fn format_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<String>>().join(" ")
+}
+
+fn describe_descriptor_data(setup_packet: &SetupPacket, data: &[u8]) -> Option<String> {
+    if setup_packet.request != device_model::REQUEST_GET_DESCRIPTOR || !setup_packet.is_standard() {
+        return None;
+    }
+
+    match (setup_packet.value >> 8) as u8 {
+        device_model::DESCRIPTOR_DEVICE if data.len() >= 12 => Some(format!(
+            "USB {:X}.{:02X} ID {:04x}:{:04x}",
+            data[3],
+            data[2],
+            u16::from_le_bytes([data[8], data[9]]),
+            u16::from_le_bytes([data[10], data[11]])
+        )),
+
+        0x03 if setup_packet.value & 0xFF == 0 => {
+            /* String Index 0 lists the supported Language IDs */
+            let language_ids: Vec<String> = data.get(2..).unwrap_or(&[])
+                .chunks_exact(2)
+                .map(|language_id| format!("0x{:04X}", u16::from_le_bytes([language_id[0], language_id[1]])))
+                .collect();
+
+            Some(format!("LangIDs: {}", language_ids.join(", ")))
+        },
+
+        0x03 => {
+            let utf16_data: Vec<u16> = data.get(2..).unwrap_or(&[])
+                .chunks_exact(2)
+                .map(|code_unit| u16::from_le_bytes([code_unit[0], code_unit[1]]))
+                .collect();
+
+            Some(format!("\"{}\"", String::from_utf16_lossy(&utf16_data)))
+        },
+
+        _ => None
+    }
+}
+
+fn describe_data(setup_packet: &SetupPacket, data: &[u8]) -> String {
+    if let Some(decoded_data) = describe_descriptor_data(setup_packet, data) {
+        return decoded_data;
+    }
+
+    format!(
+        "{}{}",
+        format_hex(&data[..data.len().min(DATA_PREVIEW_LENGTH)]),
+        if data.len() > DATA_PREVIEW_LENGTH { " ..." } else { "" }
+    )
+}
+
+impl Reconstructor {
+    fn get_interface_class(&self, urb_header: &UrbXractHeader, setup_packet: &SetupPacket) -> Option<u8> {
+        let device_registry = self.module_context.device_registry.read().unwrap();
+        let device = device_registry.get_device(urb_header)?;
+
+        match setup_packet.request_type & 0x1F {
+            RECIPIENT_INTERFACE => device.get_interface_by_number(setup_packet.index as u8).map(|interface| interface.class),
+            RECIPIENT_ENDPOINT => device.get_endpoint_interface(setup_packet.index as u8).map(|interface| interface.class),
+            _ => Some(device.get_device_class())
+        }
+    }
+
+    async fn dispatch_control(&mut self, pending_control: PendingControl, completion: &UrbXractPacket) {
+        let is_device_to_host = pending_control.setup_packet.request_type & 0x80 != 0;
+        let data = if is_device_to_host { completion.data.as_ref() } else { pending_control.data.as_ref() };
+        let mut combined_payload = format!("{} -> {}", pending_control.description, describe_status(&completion.header));
+
+        /* Attach the Data Stage */
+        if let Some(data) = data {
+            combined_payload += &format!(
+                " (Data: {} bytes {}) | {}",
+                data.len(),
+                if is_device_to_host { "IN" } else { "OUT" },
+                describe_data(&pending_control.setup_packet, data)
+            );
+        }
+
+        let transmission = ReconstructedTransmission {
+            urbx_header: pending_control.urbx_header,
+            combined_payload,
+            sources: vec![
+                UrbXractPacket { header: pending_control.urbx_header, data: None, iso_descriptors: vec![] },
+                UrbXractPacket { header: completion.header, data: None, iso_descriptors: vec![] }
+            ],
+            is_error: completion.header.status != 0,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+}
+
+impl ReconstructionModule for Reconstructor {
+    fn new(module_tx: Sender<ReconstructedTransmission>, module_context: &ModuleContext) -> Self {
+        Self {
+            module_tx,
+            module_context: module_context.clone(),
+            pending: HashMap::new(),
+        }
+    }
+
+    async fn consume_packet(&mut self, urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        match urb_header.event_type {
+            UrbEventType::Submit => {
+                /* Setup Stage, with the Data Stage for Host to Device Requests */
+                let Some(setup_data) = &urb_header.setup_packet else { return };
+                let setup_packet = SetupPacket::from_bytes(setup_data);
+                let interface_class = self.get_interface_class(&urb_header, &setup_packet);
+
+                self.pending.insert(urb_header.urb_id, PendingControl {
+                    urbx_header: urb_header,
+                    setup_packet,
+                    description: describe_setup(&setup_packet, interface_class),
+                    data: urb_packet.data,
+                });
+            },
+
+            UrbEventType::Complete | UrbEventType::Error => {
+                /* Status Stage, with the Data Stage for Device to Host Requests */
+                match self.pending.remove(&urb_header.urb_id) {
+                    Some(pending_control) => self.dispatch_control(pending_control, &urb_packet).await,
+                    None => {
+                        let transmission = ReconstructedTransmission {
+                            urbx_header: urb_header,
+                            combined_payload: format!(
+                                "Control Transfer (Setup Not Captured) -> {}{}",
+                                describe_status(&urb_header),
+                                match &urb_packet.data {
+                                    Some(data) => format!(" (Data: {} bytes)", data.len()),
+                                    None => String::new()
+                                }
+                            ),
+                            sources: vec![urb_packet],
+                            is_error: urb_header.status != 0,
+                        };
+
+                        self.module_tx.send(transmission).await.unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,346 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::{HashMap, HashSet};
+use tokio::sync::mpsc::Sender;
+use crate::sniffer::{UrbTransferType, UrbXractHeader, UrbXractPacket};
+
+use super::device_model::SetupPacket;
+use super::hid_descriptor::{self, ReportDescriptor, ReportKind};
+use super::protocol_hid_fido::{self, FidoSession};
+use super::protocol_hid_gamepad::{self, GamepadLayout};
+use super::protocol_hid_keyboard::{self, KeyboardSession};
+use super::protocol_hid_pointer::PointerSession;
+use super::protocol_control::{self, format_hex, PendingRequests};
+use super::{ModuleContext, ReconstructedTransmission, ReconstructionModule};
+
+/*
+    HID Reports (Device Class Definition for HID 1.11, Section 7.2). Interrupt
+    IN carries Input Reports, Interrupt OUT Output Reports, and the Default Pipe
+    transfers any Report Type with GET_REPORT and SET_REPORT
+*/
+const REQUEST_GET_REPORT: u8 = 0x01;
+const REQUEST_GET_IDLE: u8 = 0x02;
+const REQUEST_GET_PROTOCOL: u8 = 0x03;
+const REQUEST_SET_REPORT: u8 = 0x09;
+const REQUEST_SET_IDLE: u8 = 0x0A;
+const REQUEST_SET_PROTOCOL: u8 = 0x0B;
+const CLASS_HID: u8 = 0x03;
+const SUBCLASS_BOOT: u8 = 0x01;
+const PROTOCOL_KEYBOARD: u8 = 0x01;
+const PROTOCOL_MOUSE: u8 = 0x02;
+
+pub struct Reconstructor {
+    module_tx: Sender<ReconstructedTransmission>,
+    module_context: ModuleContext,
+    report_descriptors: HashMap<(u16, u16, u8), (Vec<u8>, ReportDescriptor)>, /* (Bus, Device, Interface), Raw and Parsed */
+    pending_requests: PendingRequests, /* Control Requests awaiting Completion */
+    boot_interfaces: HashSet<(u16, u16, u8)>, /* (Bus, Device, Interface) switched to the Boot Protocol */
+    keyboard_sessions: HashMap<(u16, u16, u8), KeyboardSession>, /* (Bus, Device, Interface), Typed Text */
+    pointer_sessions: HashMap<(u16, u16, u8), PointerSession>, /* (Bus, Device, Interface), Mouse Cursor */
+    fido_sessions: HashMap<(u16, u16, u8), FidoSession>, /* (Bus, Device, Interface), CTAPHID Channels */
+}
+
+fn describe_idle_rate(idle_rate: u8) -> String {
+    /* 4 ms Units, Zero reports only on Change */
+    match idle_rate {
+        0 => String::from("Indefinite"),
+        idle_rate => format!("{} ms", idle_rate as u32 * 4),
+    }
+}
+
+impl Reconstructor {
+    fn get_endpoint_interface(&self, urb_header: &UrbXractHeader) -> Option<(u8, u8, u8)> {
+        /* Interface Number, Subclass and Protocol */
+        let device_registry = self.module_context.device_registry.read().unwrap();
+        let interface = device_registry.get_interface(urb_header)?;
+        Some((interface.number, interface.subclass, interface.protocol))
+    }
+
+    fn get_report_descriptor(&mut self, urb_header: &UrbXractHeader, interface_number: u8) -> Option<&ReportDescriptor> {
+        /* Parsed once per Interface, again when the Host reads a different Descriptor */
+        let raw_descriptor = {
+            let device_registry = self.module_context.device_registry.read().unwrap();
+            device_registry.get_device(urb_header)?.report_descriptors.get(&interface_number)?.clone()
+        };
+
+        let descriptor_key = (urb_header.bus_id, urb_header.device_id, interface_number);
+        let is_stale = self.report_descriptors.get(&descriptor_key).is_none_or(|(known, _)| *known != raw_descriptor);
+        if is_stale {
+            let report_descriptor = ReportDescriptor::parse(&raw_descriptor);
+            self.report_descriptors.insert(descriptor_key, (raw_descriptor, report_descriptor));
+        }
+
+        self.report_descriptors.get(&descriptor_key).map(|(_, report_descriptor)| report_descriptor)
+    }
+
+    fn get_gamepad_layout(&self, urb_header: &UrbXractHeader, interface_number: Option<u8>) -> Option<GamepadLayout> {
+        /* Vendor Layouts are known by their IDs, XInput by its Interface */
+        let device_registry = self.module_context.device_registry.read().unwrap();
+        let device = device_registry.get_device(urb_header)?;
+        let gamepad_layout = device.descriptor
+            .as_ref()
+            .and_then(|descriptor| GamepadLayout::from_ids(descriptor.vendor_id, descriptor.product_id));
+
+        let interface = match interface_number {
+            Some(interface_number) => device.get_interface_by_number(interface_number),
+            None => device.get_endpoint_interface(urb_header.endpoint_info),
+        };
+
+        gamepad_layout.or_else(|| interface.and_then(|interface| GamepadLayout::from_interface(interface.class, interface.subclass, interface.protocol)))
+    }
+
+    fn describe_report(&mut self, urb_header: &UrbXractHeader, interface_number: Option<u8>, kind: ReportKind, report_data: &[u8]) -> String {
+        let gamepad_description = self
+            .get_gamepad_layout(urb_header, interface_number)
+            .and_then(|gamepad_layout| protocol_hid_gamepad::describe_vendor_report(gamepad_layout, kind, report_data));
+
+        if let Some(gamepad_description) = gamepad_description {
+            return gamepad_description;
+        }
+
+        let report_descriptor = interface_number.and_then(|interface_number| self.get_report_descriptor(urb_header, interface_number));
+        match report_descriptor {
+            Some(report_descriptor) if kind == ReportKind::Input => protocol_hid_gamepad::describe_generic_report(report_descriptor, report_data)
+                .unwrap_or_else(|| hid_descriptor::describe_report(report_descriptor, kind, report_data)),
+            Some(report_descriptor) => hid_descriptor::describe_report(report_descriptor, kind, report_data),
+            None => format!("{} Report: {} (Report Descriptor not captured)", kind.get_name(), format_hex(report_data)),
+        }
+    }
+
+    async fn dispatch_description(&mut self, urb_header: UrbXractHeader, sources: Vec<UrbXractPacket>, combined_payload: String, is_error: bool) {
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_header,
+            combined_payload,
+            sources,
+            is_error,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    fn describe_request(&mut self, urb_header: &UrbXractHeader, setup_packet: &SetupPacket, data: Option<&[u8]>) -> String {
+        let interface_number = setup_packet.index as u8;
+        let (report_type, report_id) = ((setup_packet.value >> 8) as u8, setup_packet.value as u8);
+        match setup_packet.request {
+            REQUEST_GET_REPORT | REQUEST_SET_REPORT => {
+                let request_name = if setup_packet.request == REQUEST_GET_REPORT { "GET_REPORT" } else { "SET_REPORT" };
+                let Some(kind) = ReportKind::from_report_type(report_type) else {
+                    return format!("[HID] {} Reserved Type {} ID {}", request_name, report_type, report_id);
+                };
+
+                match data {
+                    Some(report_data) if !report_data.is_empty() => {
+                        if kind == ReportKind::Output {
+                            self.update_caps_lock(urb_header, interface_number, report_data);
+                        }
+
+                        format!("[HID] {} {}", request_name, self.describe_report(urb_header, Some(interface_number), kind, report_data))
+                    },
+                    _ => format!("[HID] {} {} Report ID {} (No Data)", request_name, kind.get_name(), report_id),
+                }
+            },
+
+            REQUEST_GET_IDLE => match data.and_then(|data| data.first()) {
+                Some(idle_rate) => format!("[HID] GET_IDLE Report ID {}: {}", report_id, describe_idle_rate(*idle_rate)),
+                None => format!("[HID] GET_IDLE Report ID {}", report_id),
+            },
+
+            REQUEST_SET_IDLE => format!("[HID] SET_IDLE Report ID {}: {}", report_id, describe_idle_rate(report_type)),
+            REQUEST_GET_PROTOCOL => match data.and_then(|data| data.first()) {
+                Some(0) => String::from("[HID] GET_PROTOCOL: Boot Protocol"),
+                Some(_) => String::from("[HID] GET_PROTOCOL: Report Protocol"),
+                None => String::from("[HID] GET_PROTOCOL"),
+            },
+
+            REQUEST_SET_PROTOCOL => format!("[HID] SET_PROTOCOL: {} Protocol", if setup_packet.value == 0 { "Boot" } else { "Report" }),
+            _ => format!("[HID] {}", protocol_control::describe_setup(setup_packet, Some(CLASS_HID))),
+        }
+    }
+
+    fn is_boot_report(&self, urb_header: &UrbXractHeader, interface_number: u8) -> bool {
+        /* Boot Reports follow SET_PROTOCOL(Boot), or stand in for a missing Report Descriptor */
+        let interface_key = (urb_header.bus_id, urb_header.device_id, interface_number);
+        let device_registry = self.module_context.device_registry.read().unwrap();
+        let device = device_registry.get_device(urb_header);
+        self.boot_interfaces.contains(&interface_key) || device.is_none_or(|device| !device.report_descriptors.contains_key(&interface_number))
+    }
+
+    fn update_caps_lock(&mut self, urb_header: &UrbXractHeader, interface_number: u8, report_data: &[u8]) {
+        /* The Host mirrors Caps Lock to the Keyboard LEDs */
+        let interface_key = (urb_header.bus_id, urb_header.device_id, interface_number);
+        if !self.keyboard_sessions.contains_key(&interface_key) {
+            return;
+        }
+
+        let caps_lock = match self.is_boot_report(urb_header, interface_number) {
+            true => protocol_hid_keyboard::get_caps_lock_led(None, report_data),
+            false => {
+                let report_descriptor = self.get_report_descriptor(urb_header, interface_number);
+                protocol_hid_keyboard::get_caps_lock_led(report_descriptor, report_data)
+            }
+        };
+
+        if let Some(caps_lock) = caps_lock && let Some(keyboard_session) = self.keyboard_sessions.get_mut(&interface_key) {
+            keyboard_session.set_caps_lock(caps_lock);
+        }
+    }
+
+    fn get_keyboard_usages(&mut self, urb_header: &UrbXractHeader, (interface_number, subclass, protocol): (u8, u8, u8), report_data: &[u8]) -> Option<Vec<u16>> {
+        match self.is_boot_report(urb_header, interface_number) {
+            true if subclass == SUBCLASS_BOOT && protocol == PROTOCOL_KEYBOARD && report_data.len() == 8 => {
+                protocol_hid_keyboard::get_keyboard_usages(None, report_data)
+            },
+            true => None,
+            false => {
+                let report_descriptor = self.get_report_descriptor(urb_header, interface_number);
+                protocol_hid_keyboard::get_keyboard_usages(report_descriptor, report_data)
+            }
+        }
+    }
+
+    fn is_fido_interface(&mut self, urb_header: &UrbXractHeader, interface_number: u8, kind: ReportKind, report_data: &[u8]) -> bool {
+        /* Security Keys declare the FIDO Usage Page, a Broadcast INIT gives away the rest */
+        let interface_key = (urb_header.bus_id, urb_header.device_id, interface_number);
+        self.fido_sessions.contains_key(&interface_key)
+            || (kind == ReportKind::Output && protocol_hid_fido::is_init_request(report_data))
+            || self.get_report_descriptor(urb_header, interface_number).is_some_and(protocol_hid_fido::is_fido_descriptor)
+    }
+
+    fn get_pointer_report(&mut self, urb_header: &UrbXractHeader, (interface_number, subclass, protocol): (u8, u8, u8), report_data: &[u8]) -> Option<String> {
+        /* Mouse, Touch and Pen Reports, their Positions feed the Pointer Pane */
+        let interface_key = (urb_header.bus_id, urb_header.device_id, interface_number);
+        let device_address = (urb_header.bus_id, urb_header.device_id);
+        let is_boot_report = self.is_boot_report(urb_header, interface_number);
+        let has_descriptor = !is_boot_report && self.get_report_descriptor(urb_header, interface_number).is_some();
+
+        let pointer_session = self.pointer_sessions.entry(interface_key).or_default();
+        let (description, samples) = match self.report_descriptors.get(&interface_key) {
+            Some((_, report_descriptor)) if has_descriptor => pointer_session.consume_report(device_address, report_descriptor, report_data)?,
+            _ if is_boot_report && subclass == SUBCLASS_BOOT && protocol == PROTOCOL_MOUSE => pointer_session.consume_boot_report(device_address, report_data)?,
+            _ => return None,
+        };
+
+        let mut pointer_trail = self.module_context.pointer_trail.write().unwrap();
+        samples.into_iter().for_each(|sample| pointer_trail.push(sample));
+        Some(description)
+    }
+
+    async fn flush_idle_sessions(&mut self, timestamp: u64) {
+        /* Typed Text ends with a Pause, or with the Capture */
+        let keyboard_layout = self.module_context.keyboard_layout;
+        let typed_rows: Vec<ReconstructedTransmission> = self.keyboard_sessions
+            .values_mut()
+            .filter_map(|keyboard_session| keyboard_session.flush_idle(keyboard_layout, timestamp))
+            .collect();
+
+        for typed_row in typed_rows {
+            self.module_tx.send(typed_row).await.unwrap();
+        }
+    }
+
+    async fn consume_control(&mut self, mut urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let Some((setup_packet, data)) = self.pending_requests.pair(&mut urb_packet) else { return };
+        let mut description = self.describe_request(&urb_header, &setup_packet, data);
+        if setup_packet.request == REQUEST_SET_PROTOCOL && urb_header.status == 0 {
+            let interface_key = (urb_header.bus_id, urb_header.device_id, setup_packet.index as u8);
+            match setup_packet.value {
+                0 => self.boot_interfaces.insert(interface_key),
+                _ => self.boot_interfaces.remove(&interface_key),
+            };
+        }
+
+        if urb_header.status != 0 {
+            description += &format!(" -> {}", protocol_control::describe_status(&urb_header));
+        }
+
+        self.dispatch_description(urb_header, vec![urb_packet], description, urb_header.status != 0).await;
+    }
+}
+
+impl ReconstructionModule for Reconstructor {
+    fn new(module_tx: Sender<ReconstructedTransmission>, module_context: &ModuleContext) -> Self {
+        Self {
+            module_tx,
+            module_context: module_context.clone(),
+            report_descriptors: HashMap::new(),
+            pending_requests: PendingRequests::default(),
+            boot_interfaces: HashSet::new(),
+            keyboard_sessions: HashMap::new(),
+            pointer_sessions: HashMap::new(),
+            fido_sessions: HashMap::new(),
+        }
+    }
+
+    async fn consume_packet(&mut self, urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        if urb_header.transfer_type == UrbTransferType::Control {
+            return self.consume_control(urb_packet).await;
+        }
+
+        /* Interrupt IN Data arrives on Completion, OUT Data on Submission */
+        let Some(report_data) = urb_packet.data.as_deref() else { return };
+        let kind = if urb_header.endpoint_info & 0x80 != 0 { ReportKind::Input } else { ReportKind::Output };
+        let interface = self.get_endpoint_interface(&urb_header);
+        if let Some((interface_number, _, _)) = interface && kind == ReportKind::Output {
+            self.update_caps_lock(&urb_header, interface_number, report_data);
+        }
+
+        /* CTAPHID Messages span several Reports and pair into Transactions */
+        if let Some((interface_number, _, _)) = interface && self.is_fido_interface(&urb_header, interface_number, kind, report_data) {
+            let interface_key = (urb_header.bus_id, urb_header.device_id, interface_number);
+            let fido_session = self.fido_sessions.entry(interface_key).or_default();
+            for transaction_row in fido_session.consume_report(urb_packet, kind == ReportKind::Output) {
+                self.module_tx.send(transaction_row).await.unwrap();
+            }
+
+            return;
+        }
+
+        /* Keyboard Reports become typed Text instead of one Row per Report */
+        let keyboard_usages = interface
+            .filter(|_| kind == ReportKind::Input)
+            .and_then(|interface| self.get_keyboard_usages(&urb_header, interface, report_data));
+
+        if let Some((interface_number, _, _)) = interface && let Some(keyboard_usages) = keyboard_usages {
+            let keyboard_layout = self.module_context.keyboard_layout;
+            let interface_key = (urb_header.bus_id, urb_header.device_id, interface_number);
+            let keyboard_session = self.keyboard_sessions.entry(interface_key).or_default();
+            for typed_row in keyboard_session.consume_report(keyboard_layout, urb_packet, keyboard_usages) {
+                self.module_tx.send(typed_row).await.unwrap();
+            }
+
+            return;
+        }
+
+        let pointer_description = interface
+            .filter(|_| kind == ReportKind::Input)
+            .and_then(|interface| self.get_pointer_report(&urb_header, interface, report_data));
+
+        let description = match pointer_description {
+            Some(pointer_description) => pointer_description,
+            None => format!("[HID] {}", self.describe_report(&urb_header, interface.map(|(interface_number, _, _)| interface_number), kind, report_data)),
+        };
+        self.dispatch_description(urb_header, vec![urb_packet], description, urb_header.status != 0).await;
+    }
+
+    async fn flush_idle(&mut self, timestamp: u64) {
+        self.flush_idle_sessions(timestamp).await;
+    }
+}
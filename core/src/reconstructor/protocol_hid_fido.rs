@@ -0,0 +1,464 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use crate::sniffer::UrbXractPacket;
+
+use super::fido_cbor;
+use super::hid_descriptor::ReportDescriptor;
+use super::protocol_control::format_hex;
+use super::ReconstructedTransmission;
+
+/*
+    CTAPHID (CTAP 2.1, Section 11.2). Messages are split across 64 Byte Reports:
+    an Initialization Packet (Channel ID, Command with Bit 7 set, Byte Count)
+    followed by Continuation Packets (Channel ID, Sequence 0..0x7F). One
+    Transaction per Channel, Requests are Output and Responses Input Reports
+*/
+const USAGE_FIDO_AUTHENTICATOR: u32 = 0xF1D0_0001;
+const CHANNEL_BROADCAST: u32 = 0xFFFF_FFFF;
+const INIT_HEADER_LENGTH: usize = 7;
+const CONT_HEADER_LENGTH: usize = 5;
+
+const COMMAND_PING: u8 = 0x81;
+const COMMAND_MSG: u8 = 0x83;
+const COMMAND_LOCK: u8 = 0x84;
+const COMMAND_INIT: u8 = 0x86;
+const COMMAND_WINK: u8 = 0x88;
+const COMMAND_CBOR: u8 = 0x90;
+const COMMAND_CANCEL: u8 = 0x91;
+const COMMAND_KEEPALIVE: u8 = 0xBB;
+const COMMAND_ERROR: u8 = 0xBF;
+
+const KEEPALIVE_PROCESSING: u8 = 0x01;
+const KEEPALIVE_UP_NEEDED: u8 = 0x02;
+const CAPABILITIES: [(u8, &str); 3] = [(0x01, "WINK"), (0x04, "CBOR"), (0x08, "NMSG")];
+
+/* U2F Raw Messages (FIDO U2F Raw Message Formats v1.2) carried by CTAPHID_MSG */
+const U2F_REGISTER: u8 = 0x01;
+const U2F_AUTHENTICATE: u8 = 0x02;
+const U2F_VERSION: u8 = 0x03;
+const SW_NO_ERROR: u16 = 0x9000;
+const SW_CONDITIONS_NOT_SATISFIED: u16 = 0x6985;
+
+struct CtapMessage {
+    command: u8,
+    length: usize,
+    data: Vec<u8>,
+    next_sequence: u8,
+    transmission: ReconstructedTransmission,
+}
+
+struct CtapTransaction {
+    command: u8,
+    request: Vec<u8>,
+    description: String,
+    keepalives: Vec<u8>,
+    is_cancelled: bool,
+    transmission: ReconstructedTransmission,
+}
+
+#[derive(Default)]
+pub struct FidoSession {
+    messages: HashMap<(bool, u32), CtapMessage>, /* (Is Request, Channel), Message being reassembled */
+    transactions: HashMap<u32, CtapTransaction>, /* Channel, Request awaiting its Response */
+}
+
+pub fn is_fido_descriptor(report_descriptor: &ReportDescriptor) -> bool {
+    report_descriptor.fields.iter().any(|field| field.application == USAGE_FIDO_AUTHENTICATOR)
+}
+
+pub fn is_init_request(report_data: &[u8]) -> bool {
+    /* Every Client opens with INIT on the Broadcast Channel and an 8 Byte Nonce */
+    report_data.len() == 64 && report_data[..5] == [0xFF, 0xFF, 0xFF, 0xFF, COMMAND_INIT] && report_data[5..7] == [0x00, 0x08]
+}
+
+fn format_channel(channel_id: u32) -> String {
+    match channel_id {
+        CHANNEL_BROADCAST => String::from("Broadcast"),
+        channel_id => format!("0x{:08X}", channel_id),
+    }
+}
+
+fn get_command_name(command: u8) -> String {
+    match command {
+        COMMAND_PING => String::from("PING"),
+        COMMAND_MSG => String::from("MSG"),
+        COMMAND_LOCK => String::from("LOCK"),
+        COMMAND_INIT => String::from("INIT"),
+        COMMAND_WINK => String::from("WINK"),
+        COMMAND_CBOR => String::from("CBOR"),
+        COMMAND_CANCEL => String::from("CANCEL"),
+        COMMAND_KEEPALIVE => String::from("KEEPALIVE"),
+        COMMAND_ERROR => String::from("ERROR"),
+        0xC0..=0xFF => format!("Vendor Command 0x{:02X}", command),
+        command => format!("Unknown Command 0x{:02X}", command),
+    }
+}
+
+fn get_error_name(error_code: u8) -> &'static str {
+    match error_code {
+        0x01 => "ERR_INVALID_CMD",
+        0x02 => "ERR_INVALID_PAR",
+        0x03 => "ERR_INVALID_LEN",
+        0x04 => "ERR_INVALID_SEQ",
+        0x05 => "ERR_MSG_TIMEOUT",
+        0x06 => "ERR_CHANNEL_BUSY",
+        0x0A => "ERR_LOCK_REQUIRED",
+        0x0B => "ERR_INVALID_CHANNEL",
+        0x7F => "ERR_OTHER",
+        _ => "ERR_UNKNOWN"
+    }
+}
+
+fn get_status_word_name(status_word: u16) -> &'static str {
+    match status_word {
+        SW_NO_ERROR => "SW_NO_ERROR",
+        SW_CONDITIONS_NOT_SATISFIED => "SW_CONDITIONS_NOT_SATISFIED (User Presence needed)",
+        0x6A80 => "SW_WRONG_DATA",
+        0x6700 => "SW_WRONG_LENGTH",
+        0x6D00 => "SW_INS_NOT_SUPPORTED",
+        0x6E00 => "SW_CLA_NOT_SUPPORTED",
+        _ => "SW_UNKNOWN"
+    }
+}
+
+fn parse_u2f_request(apdu: &[u8]) -> Option<(u8, u8, &[u8])> {
+    /* Instruction, P1 and Command Data. Extended Length (00 Lc1 Lc2) or Short (Lc) */
+    let (header, body) = (apdu.get(..4)?, &apdu[4..]);
+    let data = match body {
+        [] => body,
+        [0x00, length_high, length_low, data @ ..] => data.get(..u16::from_be_bytes([*length_high, *length_low]) as usize).unwrap_or(data),
+        [length, data @ ..] => data.get(..*length as usize).unwrap_or(data),
+    };
+
+    Some((header[1], header[2], data))
+}
+
+fn describe_u2f_request(apdu: &[u8]) -> String {
+    let Some((instruction, parameter, data)) = parse_u2f_request(apdu) else { return format!("U2F (Truncated APDU) {}", format_hex(apdu)) };
+    match instruction {
+        U2F_REGISTER if data.len() >= 64 => format!(
+            "U2F REGISTER Challenge {} AppId {}",
+            fido_cbor::format_bytes(&data[..32]),
+            fido_cbor::format_bytes(&data[32..64])
+        ),
+
+        U2F_AUTHENTICATE if data.len() >= 65 => {
+            let control = match parameter {
+                0x03 => "Enforce User Presence",
+                0x07 => "Check Only",
+                0x08 => "Don't Enforce User Presence",
+                _ => "Unknown Control",
+            };
+
+            let key_handle = data[65..].get(..data[64] as usize).unwrap_or(&data[65..]);
+            format!(
+                "U2F AUTHENTICATE ({}) Challenge {} AppId {} KeyHandle {}",
+                control,
+                fido_cbor::format_bytes(&data[..32]),
+                fido_cbor::format_bytes(&data[32..64]),
+                fido_cbor::format_bytes(key_handle)
+            )
+        },
+
+        U2F_VERSION => String::from("U2F VERSION"),
+        instruction => format!("U2F INS 0x{:02X} P1 0x{:02X} {}", instruction, parameter, fido_cbor::format_bytes(data)),
+    }
+}
+
+fn describe_u2f_response(request: &[u8], response: &[u8]) -> (String, bool) {
+    /* Response Data followed by the Status Word */
+    if response.len() < 2 {
+        return (format!("(Truncated APDU) {}", format_hex(response)), true);
+    }
+
+    let (data, status_bytes) = response.split_at(response.len() - 2);
+    let status_word = u16::from_be_bytes([status_bytes[0], status_bytes[1]]);
+    let status = format!("{:04X} {}", status_word, get_status_word_name(status_word));
+    if status_word != SW_NO_ERROR || data.is_empty() {
+        /* Conditions not satisfied is how U2F asks for a Touch, not a Failure */
+        return (status, !matches!(status_word, SW_NO_ERROR | SW_CONDITIONS_NOT_SATISFIED));
+    }
+
+    let instruction = parse_u2f_request(request).map(|(instruction, _, _)| instruction);
+    let description = match instruction {
+        /* Reserved 0x05, Public Key (65), Key Handle Length and Handle, Certificate and Signature */
+        Some(U2F_REGISTER) if data.len() > 67 && data[0] == 0x05 => {
+            let key_handle_end = 67 + data[66] as usize;
+            format!(
+                "PublicKey {} KeyHandle {} Attestation and Signature {} bytes",
+                fido_cbor::format_bytes(&data[1..66]),
+                fido_cbor::format_bytes(data.get(67..key_handle_end).unwrap_or(&data[67..])),
+                data.len().saturating_sub(key_handle_end)
+            )
+        },
+
+        /* User Presence, Counter and Signature */
+        Some(U2F_AUTHENTICATE) if data.len() >= 5 => format!(
+            "User Presence {} Counter {} Signature {}",
+            if data[0] & 0x01 != 0 { "Verified" } else { "Not Verified" },
+            u32::from_be_bytes([data[1], data[2], data[3], data[4]]),
+            fido_cbor::format_bytes(&data[5..])
+        ),
+
+        Some(U2F_VERSION) => format!("{:?}", String::from_utf8_lossy(data)),
+        _ => fido_cbor::format_bytes(data),
+    };
+
+    (format!("{} {}", status, description), false)
+}
+
+fn describe_request(command: u8, data: &[u8]) -> String {
+    match command {
+        COMMAND_PING => format!("PING {} bytes", data.len()),
+        COMMAND_MSG => format!("MSG {}", describe_u2f_request(data)),
+        COMMAND_LOCK => format!("LOCK {} s", data.first().copied().unwrap_or_default()),
+        COMMAND_INIT => format!("INIT Nonce {}", format_hex(data)),
+        COMMAND_CBOR => match data.split_first() {
+            Some((ctap_command, parameters)) => format!(
+                "CBOR {} {}",
+                fido_cbor::get_command_name(*ctap_command),
+                fido_cbor::describe_parameters(*ctap_command, false, parameters)
+            ).trim_end().to_string(),
+            None => String::from("CBOR (Empty)"),
+        },
+
+        COMMAND_WINK | COMMAND_CANCEL => get_command_name(command),
+        command => format!("{} {}", get_command_name(command), format_hex(data)).trim_end().to_string(),
+    }
+}
+
+fn describe_response(request_command: u8, request: &[u8], command: u8, data: &[u8]) -> (String, bool) {
+    /* Response Text and whether it failed */
+    match command {
+        COMMAND_ERROR => (format!("ERROR {}", get_error_name(data.first().copied().unwrap_or_default())), true),
+        command if command != request_command => (format!("Unexpected {} {}", get_command_name(command), format_hex(data)), true),
+
+        /* Nonce, assigned Channel, Protocol Version, Device Version and Capabilities */
+        COMMAND_INIT if data.len() >= 17 => {
+            let capabilities: Vec<&str> = CAPABILITIES.iter().filter(|(bit, _)| data[16] & bit != 0).map(|(_, name)| *name).collect();
+            let is_nonce_mismatch = request != &data[..8];
+            let description = format!(
+                "Channel {} Protocol {} Device {}.{}.{} Capabilities [{}]{}",
+                format_channel(u32::from_be_bytes([data[8], data[9], data[10], data[11]])),
+                data[12],
+                data[13],
+                data[14],
+                data[15],
+                capabilities.join(", "),
+                if is_nonce_mismatch { " (Nonce Mismatch)" } else { "" }
+            );
+
+            (description, is_nonce_mismatch)
+        },
+
+        COMMAND_PING if data == request => (String::from("Echo OK"), false),
+        COMMAND_PING => (format!("Echo Mismatch ({} of {} bytes)", data.len(), request.len()), true),
+        COMMAND_MSG => describe_u2f_response(request, data),
+        COMMAND_CBOR => match (request.first(), data.split_first()) {
+            (Some(ctap_command), Some((status, parameters))) => {
+                let description = format!("{} {}", fido_cbor::get_status_name(*status), fido_cbor::describe_parameters(*ctap_command, true, parameters));
+                (description.trim_end().to_string(), *status != 0x00)
+            },
+            _ => (String::from("(Empty)"), true),
+        },
+
+        _ if data.is_empty() => (String::from("OK"), false),
+        _ => (format_hex(data), false),
+    }
+}
+
+impl FidoSession {
+    fn finish_transaction(&mut self, channel_id: u32, transaction: CtapTransaction, response: Option<CtapMessage>) -> ReconstructedTransmission {
+        let mut transmission = transaction.transmission;
+        let (response_description, is_error) = match &response {
+            Some(response) => describe_response(transaction.command, &transaction.request, response.command, &response.data),
+            None => (String::from("No Response"), true),
+        };
+
+        /* Keepalives tell whether the Authenticator waited for a Touch */
+        let mut notes = vec![];
+        if let Some(response) = response {
+            transmission.sources.extend(response.transmission.sources);
+            let end_timestamp = transmission.sources.last().map(|source| source.header.timestamp).unwrap_or_default();
+            notes.push(format!("{:.3} ms", end_timestamp.saturating_sub(transmission.urbx_header.timestamp) as f64 / 1000.0));
+        }
+
+        if !transaction.keepalives.is_empty() {
+            let keepalive_reason = match transaction.keepalives.contains(&KEEPALIVE_UP_NEEDED) {
+                true => "User Presence needed",
+                false if transaction.keepalives.contains(&KEEPALIVE_PROCESSING) => "Processing",
+                false => "Unknown Status",
+            };
+
+            notes.push(format!("{} KEEPALIVE, {}", transaction.keepalives.len(), keepalive_reason));
+        }
+
+        transmission.combined_payload = format!(
+            "[CTAPHID {}] {}{} -> {}{}",
+            format_channel(channel_id),
+            transaction.description,
+            if transaction.is_cancelled { " (Cancelled)" } else { "" },
+            response_description,
+            if notes.is_empty() { String::new() } else { format!(" ({})", notes.join(", ")) }
+        );
+
+        transmission.is_error = is_error;
+        transmission
+    }
+
+    fn finish_message(&mut self, channel_id: u32, message: CtapMessage, is_request: bool) -> Vec<ReconstructedTransmission> {
+        let mut dispatch_rows = vec![];
+        if is_request {
+            /* CANCEL belongs to the Request it aborts */
+            if message.command == COMMAND_CANCEL && let Some(transaction) = self.transactions.get_mut(&channel_id) {
+                transaction.is_cancelled = true;
+                transaction.transmission.sources.extend(message.transmission.sources);
+                return dispatch_rows;
+            }
+
+            /* A new Request abandons the previous one */
+            if let Some(transaction) = self.transactions.remove(&channel_id) {
+                dispatch_rows.push(self.finish_transaction(channel_id, transaction, None));
+            }
+
+            let description = describe_request(message.command, &message.data);
+            if message.command == COMMAND_CANCEL {
+                let mut transmission = message.transmission;
+                transmission.combined_payload = format!("[CTAPHID {}] {}", format_channel(channel_id), description);
+                dispatch_rows.push(transmission);
+                return dispatch_rows;
+            }
+
+            self.transactions.insert(channel_id, CtapTransaction {
+                command: message.command,
+                request: message.data,
+                description,
+                keepalives: vec![],
+                is_cancelled: false,
+                transmission: message.transmission,
+            });
+
+            return dispatch_rows;
+        }
+
+        match self.transactions.remove(&channel_id) {
+            Some(mut transaction) if message.command == COMMAND_KEEPALIVE => {
+                transaction.keepalives.push(message.data.first().copied().unwrap_or_default());
+                transaction.transmission.sources.extend(message.transmission.sources);
+                self.transactions.insert(channel_id, transaction);
+            },
+
+            Some(transaction) => dispatch_rows.push(self.finish_transaction(channel_id, transaction, Some(message))),
+            None => {
+                let (description, is_error) = describe_response(message.command, &[], message.command, &message.data);
+                let mut transmission = message.transmission;
+                transmission.combined_payload = format!("[CTAPHID {}] Response without Request: {} {}", format_channel(channel_id), get_command_name(message.command), description);
+                transmission.is_error = is_error;
+                dispatch_rows.push(transmission);
+            }
+        }
+
+        dispatch_rows
+    }
+
+    pub fn consume_report(&mut self, urb_packet: UrbXractPacket, is_request: bool) -> Vec<ReconstructedTransmission> {
+        /* Returns the Rows to dispatch: paired Transactions and Framing Errors */
+        let report_data = urb_packet.data.clone().unwrap_or_default();
+        let mut dispatch_rows = vec![];
+        let mut transmission = ReconstructedTransmission {
+            urbx_header: urb_packet.header,
+            combined_payload: String::new(),
+            sources: vec![urb_packet],
+            is_error: false,
+        };
+
+        if report_data.len() < INIT_HEADER_LENGTH {
+            transmission.combined_payload = format!("[CTAPHID] Short Packet: {}", format_hex(&report_data));
+            transmission.is_error = true;
+            return vec![transmission];
+        }
+
+        let channel_id = u32::from_be_bytes([report_data[0], report_data[1], report_data[2], report_data[3]]);
+        let message_key = (is_request, channel_id);
+        if report_data[4] & 0x80 != 0 {
+            /* Initialization Packet: an unfinished Message on this Channel is lost */
+            if let Some(abandoned) = self.messages.remove(&message_key) {
+                let mut abandoned_transmission = abandoned.transmission;
+                abandoned_transmission.combined_payload = format!(
+                    "[CTAPHID {}] {} incomplete: {} of {} bytes",
+                    format_channel(channel_id),
+                    get_command_name(abandoned.command),
+                    abandoned.data.len(),
+                    abandoned.length
+                );
+
+                abandoned_transmission.is_error = true;
+                dispatch_rows.push(abandoned_transmission);
+            }
+
+            let length = u16::from_be_bytes([report_data[5], report_data[6]]) as usize;
+            self.messages.insert(message_key, CtapMessage {
+                command: report_data[4],
+                length,
+                data: report_data[INIT_HEADER_LENGTH..].iter().take(length).copied().collect(),
+                next_sequence: 0,
+                transmission,
+            });
+        } else {
+            let sequence = report_data[4];
+            match self.messages.remove(&message_key) {
+                Some(mut message) if message.next_sequence == sequence => {
+                    let remaining = message.length - message.data.len();
+                    message.data.extend(report_data[CONT_HEADER_LENGTH..].iter().take(remaining));
+                    message.next_sequence += 1;
+                    message.transmission.sources.extend(transmission.sources);
+                    self.messages.insert(message_key, message);
+                },
+
+                Some(mut message) => {
+                    message.transmission.sources.extend(transmission.sources);
+                    message.transmission.combined_payload = format!(
+                        "[CTAPHID {}] {} dropped: expected Sequence {}, got {}",
+                        format_channel(channel_id),
+                        get_command_name(message.command),
+                        message.next_sequence,
+                        sequence
+                    );
+
+                    message.transmission.is_error = true;
+                    dispatch_rows.push(message.transmission);
+                },
+
+                None => {
+                    transmission.combined_payload = format!("[CTAPHID {}] Continuation {} without Initialization", format_channel(channel_id), sequence);
+                    transmission.is_error = true;
+                    dispatch_rows.push(transmission);
+                }
+            }
+        }
+
+        let is_complete = self.messages.get(&message_key).is_some_and(|message| message.data.len() >= message.length);
+        if is_complete && let Some(message) = self.messages.remove(&message_key) {
+            dispatch_rows.extend(self.finish_message(channel_id, message, is_request));
+        }
+
+        dispatch_rows
+    }
+}
@@ -0,0 +1,320 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::hid_descriptor::{self, ReportDescriptor, ReportField, ReportKind};
+
+/*
+    Gamepads and Joysticks. Generic Controllers are decoded from their Report
+    Descriptor, Vendor Layouts follow the Linux xpad and hid-playstation
+    Drivers since their Descriptors only declare Vendor Usages
+*/
+
+/* Define Constants */
+const USAGE_JOYSTICK: u32 = 0x0001_0004;
+const USAGE_GAMEPAD: u32 = 0x0001_0005;
+const USAGE_MULTI_AXIS: u32 = 0x0001_0008;
+const USAGE_HAT_SWITCH: u32 = 0x0001_0039;
+const VENDOR_SONY: u16 = 0x054C;
+const HAT_DIRECTIONS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+const XBOX360_BUTTONS: [&str; 16] = [
+    "DPad Up", "DPad Down", "DPad Left", "DPad Right", "Start", "Back", "LS", "RS",
+    "LB", "RB", "Guide", "", "A", "B", "X", "Y",
+];
+const PLAYSTATION_FACE_BUTTONS: [&str; 4] = ["Square", "Cross", "Circle", "Triangle"];
+const DUALSHOCK4_BUTTONS: [&str; 10] = ["L1", "R1", "L2", "R2", "Share", "Options", "L3", "R3", "PS", "Touchpad"];
+const DUALSENSE_BUTTONS: [&str; 11] = ["L1", "R1", "L2", "R2", "Create", "Options", "L3", "R3", "PS", "Touchpad", "Mute"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GamepadLayout {
+    Xbox360,
+    DualShock4,
+    DualSense
+}
+
+impl GamepadLayout {
+    pub fn from_ids(vendor_id: u16, product_id: u16) -> Option<Self> {
+        match (vendor_id, product_id) {
+            (VENDOR_SONY, 0x05C4 | 0x09CC | 0x0BA0) => Some(GamepadLayout::DualShock4),
+            (VENDOR_SONY, 0x0CE6 | 0x0DF2) => Some(GamepadLayout::DualSense),
+            _ => None
+        }
+    }
+
+    pub fn from_interface(class: u8, subclass: u8, protocol: u8) -> Option<Self> {
+        /* XInput Controllers are Vendor Specific Interfaces, Subclass 0x5D Protocol 0x01 */
+        match (class, subclass, protocol) {
+            (0xFF, 0x5D, 0x01) => Some(GamepadLayout::Xbox360),
+            _ => None
+        }
+    }
+
+    fn get_name(&self) -> &'static str {
+        match self {
+            GamepadLayout::Xbox360 => "Xbox 360",
+            GamepadLayout::DualShock4 => "DualShock 4",
+            GamepadLayout::DualSense => "DualSense",
+        }
+    }
+}
+
+fn get_pressed<'a>(bits: u32, names: &[&'a str]) -> Vec<&'a str> {
+    names.iter().enumerate().filter(|(bit, name)| bits & (1 << bit) != 0 && !name.is_empty()).map(|(_, name)| *name).collect()
+}
+
+fn format_hat(direction: u8, positions: u8) -> &'static str {
+    /* Values past the last Position are the Null State */
+    match positions {
+        4 if direction < 4 => HAT_DIRECTIONS[direction as usize * 2],
+        8 if direction < 8 => HAT_DIRECTIONS[direction as usize],
+        _ => "Centered",
+    }
+}
+
+fn format_stick(value: i64, center: f64, half_range: f64) -> String {
+    format!("{} ({:+.2})", value, ((value as f64 - center) / half_range).clamp(-1.0, 1.0))
+}
+
+fn read_i16(data: &[u8], offset: usize) -> i16 {
+    i16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn describe_touch_point(touch_point: &[u8]) -> Option<String> {
+    /* Bit 7 set while the Finger is lifted, 12 bit Coordinates */
+    if touch_point[0] & 0x80 != 0 {
+        return None;
+    }
+
+    let x = touch_point[1] as u16 | ((touch_point[2] as u16 & 0x0F) << 8);
+    let y = (touch_point[2] as u16 >> 4) | ((touch_point[3] as u16) << 4);
+    Some(format!("#{} X={} Y={}", touch_point[0] & 0x7F, x, y))
+}
+
+fn describe_playstation_input(layout: GamepadLayout, report_data: &[u8]) -> Option<String> {
+    /* Sticks, Face Buttons and Hat share their Layout, Triggers and Status move */
+    let (buttons_offset, triggers_offset, gyro_offset, status_offset, touch_offset, button_names): (usize, usize, usize, usize, usize, &[&str]) = match layout {
+        GamepadLayout::DualShock4 if report_data.len() >= 43 => (5, 8, 13, 30, 35, &DUALSHOCK4_BUTTONS),
+        GamepadLayout::DualSense if report_data.len() >= 54 => (8, 5, 16, 53, 33, &DUALSENSE_BUTTONS),
+        _ => return None
+    };
+
+    let buttons = &report_data[buttons_offset..(buttons_offset + 3)];
+    let mut pressed = get_pressed((buttons[0] >> 4) as u32, &PLAYSTATION_FACE_BUTTONS);
+    pressed.extend(get_pressed(buttons[1] as u32 | (buttons[2] as u32) << 8, button_names));
+
+    let mut parts = vec![
+        format!("Buttons [{}]", pressed.join(", ")),
+        format!("Hat={}", format_hat(buttons[0] & 0x0F, 8)),
+    ];
+
+    for (index, axis_name) in ["LX", "LY", "RX", "RY"].iter().enumerate() {
+        parts.push(format!("{}={}", axis_name, format_stick(report_data[1 + index] as i64, 127.5, 127.5)));
+    }
+
+    parts.push(format!("L2={} R2={}", report_data[triggers_offset], report_data[triggers_offset + 1]));
+    let motion: Vec<i16> = (0..6).map(|index| read_i16(report_data, gyro_offset + index * 2)).collect();
+    parts.push(format!("Gyro=({}, {}, {}) Accel=({}, {}, {})", motion[0], motion[1], motion[2], motion[3], motion[4], motion[5]));
+
+    let status = report_data[status_offset];
+    parts.push(match layout {
+        GamepadLayout::DualShock4 => format!("Battery {}{}", (status & 0x0F).min(10) * 10, if status & 0x10 != 0 { "% (Cable)" } else { "%" }),
+        _ => format!("Battery {}% {}", (status & 0x0F).min(10) * 10, ["Discharging", "Charging", "Full"].get((status >> 4) as usize).unwrap_or(&"Charge Error")),
+    });
+
+    let touch_points: Vec<String> = report_data[touch_offset..(touch_offset + 8)].chunks_exact(4).filter_map(describe_touch_point).collect();
+    if !touch_points.is_empty() {
+        parts.push(format!("Touch [{}]", touch_points.join(", ")));
+    }
+
+    Some(parts.join(" "))
+}
+
+fn describe_playstation_output(layout: GamepadLayout, report_data: &[u8]) -> Option<String> {
+    let mut parts = vec![];
+    match layout {
+        /* Report 0x05: Flags, Weak and Strong Motor, Lightbar Color and Flash Timing */
+        GamepadLayout::DualShock4 if report_data.len() >= 11 => {
+            let flags = report_data[1];
+            if flags & 0x01 != 0 {
+                parts.push(format!("Rumble Strong={} Weak={}", report_data[5], report_data[4]));
+            }
+
+            if flags & 0x02 != 0 {
+                parts.push(format!("Lightbar #{:02X}{:02X}{:02X}", report_data[6], report_data[7], report_data[8]));
+            }
+
+            if flags & 0x04 != 0 {
+                parts.push(format!("Flash On={} Off={}", report_data[9], report_data[10]));
+            }
+        },
+
+        /* Report 0x02: Valid Flags select the Fields the Host updates */
+        GamepadLayout::DualSense if report_data.len() >= 48 => {
+            let (valid_flag0, valid_flag1) = (report_data[1], report_data[2]);
+            if valid_flag0 & 0x03 != 0 {
+                parts.push(format!("Rumble Strong={} Weak={}", report_data[4], report_data[3]));
+            }
+
+            if valid_flag0 & 0x04 != 0 {
+                parts.push(format!("Right Trigger Effect 0x{:02X}", report_data[11]));
+            }
+
+            if valid_flag0 & 0x08 != 0 {
+                parts.push(format!("Left Trigger Effect 0x{:02X}", report_data[22]));
+            }
+
+            if valid_flag1 & 0x01 != 0 {
+                parts.push(format!("Mic LED {}", ["Off", "On", "Pulse"].get(report_data[9] as usize).unwrap_or(&"Unknown")));
+            }
+
+            if valid_flag1 & 0x04 != 0 {
+                parts.push(format!("Lightbar #{:02X}{:02X}{:02X}", report_data[45], report_data[46], report_data[47]));
+            }
+
+            if valid_flag1 & 0x10 != 0 {
+                let player_leds: String = (0..5).map(|bit| if report_data[44] & (1 << bit) != 0 { '●' } else { '○' }).collect();
+                parts.push(format!("Player LEDs {}", player_leds));
+            }
+        },
+
+        _ => return None
+    }
+
+    Some(if parts.is_empty() { String::from("No Changes") } else { parts.join(" ") })
+}
+
+fn describe_xbox360_report(kind: ReportKind, report_data: &[u8]) -> Option<String> {
+    /* Messages start with a Type and their Length */
+    let (message_type, message_length) = (*report_data.first()?, *report_data.get(1)? as usize);
+    let message = report_data.get(..message_length.max(2))?;
+    match (kind, message_type) {
+        (ReportKind::Input, 0x00) if message.len() >= 14 => {
+            let buttons = u16::from_le_bytes([message[2], message[3]]);
+            let sticks: Vec<String> = ["LX", "LY", "RX", "RY"]
+                .iter()
+                .enumerate()
+                .map(|(index, axis_name)| format!("{}={}", axis_name, format_stick(read_i16(message, 6 + index * 2) as i64, 0.0, 32767.5)))
+                .collect();
+
+            Some(format!(
+                "Buttons [{}] LT={} RT={} {}",
+                get_pressed(buttons as u32, &XBOX360_BUTTONS).join(", "),
+                message[4],
+                message[5],
+                sticks.join(" ")
+            ))
+        },
+
+        (ReportKind::Input, 0x01) if message.len() >= 3 => Some(format!("LED State {}", describe_xbox360_led(message[2]))),
+        (ReportKind::Input, 0x03) if message.len() >= 3 => Some(format!("Rumble Status 0x{:02X}", message[2])),
+        (ReportKind::Input, 0x08) if message.len() >= 3 => Some(format!("Headset {}", if message[2] & 0x02 != 0 { "Connected" } else { "Disconnected" })),
+        (ReportKind::Output, 0x00) if message.len() >= 5 => Some(format!("Rumble Strong={} Weak={}", message[3], message[4])),
+        (ReportKind::Output, 0x01) if message.len() >= 3 => Some(format!("Set LED {}", describe_xbox360_led(message[2]))),
+        _ => None
+    }
+}
+
+fn describe_xbox360_led(led_pattern: u8) -> &'static str {
+    match led_pattern {
+        0x00 => "Off",
+        0x01 => "All Blinking",
+        0x02 => "Player 1 (Flash)",
+        0x03 => "Player 2 (Flash)",
+        0x04 => "Player 3 (Flash)",
+        0x05 => "Player 4 (Flash)",
+        0x06 => "Player 1",
+        0x07 => "Player 2",
+        0x08 => "Player 3",
+        0x09 => "Player 4",
+        0x0A => "Rotating",
+        0x0B => "Blinking",
+        0x0C => "Slow Blinking",
+        0x0D => "Alternating",
+        _ => "Unknown"
+    }
+}
+
+pub fn describe_vendor_report(layout: GamepadLayout, kind: ReportKind, report_data: &[u8]) -> Option<String> {
+    /* None leaves the Report to the Descriptor */
+    let report_id = *report_data.first()?;
+    let description = match (layout, kind, report_id) {
+        (GamepadLayout::Xbox360, kind, _) => return describe_xbox360_report(kind, report_data)
+            .map(|description| format!("Xbox 360 {} Message 0x{:02X}: {}", kind.get_name(), report_id, description)),
+        (GamepadLayout::DualShock4 | GamepadLayout::DualSense, ReportKind::Input, 0x01) => describe_playstation_input(layout, report_data)?,
+        (GamepadLayout::DualShock4, ReportKind::Output, 0x05) | (GamepadLayout::DualSense, ReportKind::Output, 0x02) => describe_playstation_output(layout, report_data)?,
+        (GamepadLayout::DualShock4, ReportKind::Feature, 0x02) | (GamepadLayout::DualSense, ReportKind::Feature, 0x05) => format!("Motion Calibration, {} bytes", report_data.len()),
+        (GamepadLayout::DualShock4, ReportKind::Feature, 0x12) | (GamepadLayout::DualSense, ReportKind::Feature, 0x09) if report_data.len() >= 7 => {
+            let mac_address: Vec<String> = report_data[1..7].iter().rev().map(|byte| format!("{:02X}", byte)).collect();
+            format!("Pairing Info, Controller {}", mac_address.join(":"))
+        },
+        (GamepadLayout::DualShock4, ReportKind::Feature, 0xA3) | (GamepadLayout::DualSense, ReportKind::Feature, 0x20) => format!("Firmware Info, {} bytes", report_data.len()),
+        _ => return None
+    };
+
+    Some(format!("{} {} Report 0x{:02X}: {}", layout.get_name(), kind.get_name(), report_id, description))
+}
+
+fn describe_axis(field: &ReportField, usage: u32, value: i64) -> String {
+    /* Raw Value and its Position within the Logical Range, Hats as Directions */
+    if usage == USAGE_HAT_SWITCH {
+        let positions = (field.logical_max - field.logical_min + 1).clamp(0, 8) as u8;
+        let direction = value - field.logical_min;
+        return format!("Hat={}", format_hat(if direction < 0 { u8::MAX } else { direction.min(u8::MAX as i64) as u8 }, positions));
+    }
+
+    let half_range = (field.logical_max - field.logical_min) as f64 / 2.0;
+    let name = hid_descriptor::get_usage_name(usage);
+    match half_range > 0.0 {
+        true => format!("{}={}", name, format_stick(value, field.logical_min as f64 + half_range, half_range)),
+        false => format!("{}={}", name, value),
+    }
+}
+
+pub fn describe_generic_report(report_descriptor: &ReportDescriptor, report_data: &[u8]) -> Option<String> {
+    /* Joystick, Gamepad and Multi-axis Input Reports, None for other Collections */
+    let (report_id, payload) = report_descriptor.split_report(report_data);
+    let fields: Vec<&ReportField> = report_descriptor.get_fields(ReportKind::Input, report_id).filter(|field| !field.is_constant()).collect();
+    let application = fields.first()?.application;
+    if ![USAGE_JOYSTICK, USAGE_GAMEPAD, USAGE_MULTI_AXIS].contains(&application) {
+        return None;
+    }
+
+    let mut buttons = vec![];
+    let mut axes = vec![];
+    for field in fields {
+        for index in 0..field.get_payload_count(payload) {
+            let Some(value) = field.read_value(payload, index) else { continue };
+            match (field.is_variable(), field.get_usage(index)) {
+                (false, _) => buttons.extend(field.get_array_usage(value).map(hid_descriptor::get_usage_name)),
+                (true, Some(usage)) if field.bit_size == 1 => {
+                    if value != 0 {
+                        buttons.push(hid_descriptor::get_usage_name(usage));
+                    }
+                },
+                (true, Some(usage)) => axes.push(describe_axis(field, usage, value)),
+                (true, None) => {}
+            }
+        }
+    }
+
+    let report_name = match report_descriptor.has_report_ids {
+        true => format!("Input Report {}", report_id),
+        false => String::from("Input Report"),
+    };
+
+    Some(format!("{} ({}): Buttons [{}] {}", report_name, hid_descriptor::get_usage_name(application), buttons.join(", "), axes.join(" ")))
+}
@@ -0,0 +1,453 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use crate::sniffer::UrbXractPacket;
+
+use super::hid_descriptor::{self, ReportDescriptor, ReportKind};
+use super::ReconstructedTransmission;
+
+/*
+    Typed Text from Keyboard Reports. Reports list the Keys currently held,
+    a Key is typed when it first appears. Boot Protocol Reports are a
+    Modifier Byte, a Reserved Byte and up to six Key Usages (HID 1.11 B.1)
+*/
+
+/* Define Constants */
+const USAGE_ERROR_ROLLOVER: u16 = 0x01;
+const USAGE_CAPS_LOCK: u16 = 0x39;
+const USAGE_ENTER: u16 = 0x28;
+const USAGE_KEYPAD_ENTER: u16 = 0x58;
+const USAGE_LEFT_CONTROL: u16 = 0xE0;
+const USAGE_LED_CAPS_LOCK: u32 = (hid_descriptor::USAGE_PAGE_LED as u32) << 16 | 0x02;
+const MODIFIER_CONTROL: u8 = 0x11;
+const MODIFIER_SHIFT: u8 = 0x22;
+const MODIFIER_ALT: u8 = 0x04;
+const MODIFIER_ALTGR: u8 = 0x40;
+const MODIFIER_GUI: u8 = 0x88;
+const TYPING_IDLE_TIMEOUT: u64 = 2_000_000; /* µs without a Keystroke closing a Row */
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum KeyboardLayout {
+    #[default]
+    Us,
+    Uk,
+    De,
+    Fr
+}
+
+/* Usage, Unshifted, Shifted, AltGr */
+type KeyMapping = (u16, char, char, Option<char>);
+
+const US_KEYS: &[KeyMapping] = &[
+    (0x1E, '1', '!', None), (0x1F, '2', '@', None), (0x20, '3', '#', None), (0x21, '4', '$', None), (0x22, '5', '%', None),
+    (0x23, '6', '^', None), (0x24, '7', '&', None), (0x25, '8', '*', None), (0x26, '9', '(', None), (0x27, '0', ')', None),
+    (0x2C, ' ', ' ', None), (0x2D, '-', '_', None), (0x2E, '=', '+', None), (0x2F, '[', '{', None), (0x30, ']', '}', None),
+    (0x31, '\\', '|', None), (0x32, '#', '~', None), (0x33, ';', ':', None), (0x34, '\'', '"', None), (0x35, '`', '~', None),
+    (0x36, ',', '<', None), (0x37, '.', '>', None), (0x38, '/', '?', None), (0x64, '\\', '|', None),
+    (0x54, '/', '/', None), (0x55, '*', '*', None), (0x56, '-', '-', None), (0x57, '+', '+', None), (0x59, '1', '1', None),
+    (0x5A, '2', '2', None), (0x5B, '3', '3', None), (0x5C, '4', '4', None), (0x5D, '5', '5', None), (0x5E, '6', '6', None),
+    (0x5F, '7', '7', None), (0x60, '8', '8', None), (0x61, '9', '9', None), (0x62, '0', '0', None), (0x63, '.', '.', None),
+];
+
+const UK_KEYS: &[KeyMapping] = &[
+    (0x1F, '2', '"', None), (0x20, '3', '£', None), (0x21, '4', '$', Some('€')), (0x31, '#', '~', None), (0x32, '#', '~', None),
+    (0x34, '\'', '@', None), (0x35, '`', '¬', Some('¦')), (0x64, '\\', '|', None),
+];
+
+const DE_KEYS: &[KeyMapping] = &[
+    (0x1C, 'z', 'Z', None), (0x1D, 'y', 'Y', None), (0x14, 'q', 'Q', Some('@')), (0x08, 'e', 'E', Some('€')), (0x10, 'm', 'M', Some('µ')),
+    (0x1F, '2', '"', Some('²')), (0x20, '3', '§', Some('³')), (0x23, '6', '&', None), (0x24, '7', '/', Some('{')),
+    (0x25, '8', '(', Some('[')), (0x26, '9', ')', Some(']')), (0x27, '0', '=', Some('}')), (0x2D, 'ß', '?', Some('\\')),
+    (0x2E, '´', '`', None), (0x2F, 'ü', 'Ü', None), (0x30, '+', '*', Some('~')), (0x31, '#', '\'', None), (0x32, '#', '\'', None),
+    (0x33, 'ö', 'Ö', None), (0x34, 'ä', 'Ä', None), (0x35, '^', '°', None), (0x36, ',', ';', None), (0x37, '.', ':', None),
+    (0x38, '-', '_', None), (0x64, '<', '>', Some('|')), (0x63, ',', ',', None),
+];
+
+const FR_KEYS: &[KeyMapping] = &[
+    (0x04, 'q', 'Q', None), (0x14, 'a', 'A', None), (0x1A, 'z', 'Z', None), (0x1D, 'w', 'W', None), (0x33, 'm', 'M', None),
+    (0x10, ',', '?', None), (0x08, 'e', 'E', Some('€')), (0x1E, '&', '1', None), (0x1F, 'é', '2', Some('~')), (0x20, '"', '3', Some('#')),
+    (0x21, '\'', '4', Some('{')), (0x22, '(', '5', Some('[')), (0x23, '-', '6', Some('|')), (0x24, 'è', '7', Some('`')),
+    (0x25, '_', '8', Some('\\')), (0x26, 'ç', '9', Some('^')), (0x27, 'à', '0', Some('@')), (0x2D, ')', '°', Some(']')),
+    (0x2E, '=', '+', Some('}')), (0x2F, '^', '¨', None), (0x30, '$', '£', Some('¤')), (0x31, '*', 'µ', None), (0x32, '*', 'µ', None),
+    (0x34, 'ù', '%', None), (0x35, '²', '²', None), (0x36, ';', '.', None), (0x37, ':', '/', None), (0x38, '!', '§', None),
+    (0x64, '<', '>', None),
+];
+
+struct TypedRow {
+    transmission: ReconstructedTransmission,
+    key_count: usize,
+    first_key_down: u64,
+    last_key_down: u64,
+    hold_total: u64,
+    hold_count: u64,
+    min_gap: Option<u64>,
+    is_complete: bool, /* Closed by Enter, dispatched once all Keys are released */
+}
+
+#[derive(Default)]
+pub struct KeyboardSession {
+    held_keys: Vec<u16>,
+    modifiers: u8,
+    lone_modifiers: u8, /* Modifiers pressed and released without another Key, e.g. <GUI> */
+    caps_lock: bool,
+    key_down_times: HashMap<u16, u64>,
+    typed_row: Option<TypedRow>,
+    last_activity: u64,
+}
+
+impl FromStr for KeyboardLayout {
+    type Err = String;
+
+    fn from_str(layout_name: &str) -> Result<Self, Self::Err> {
+        match layout_name.to_ascii_lowercase().as_str() {
+            "us" => Ok(KeyboardLayout::Us),
+            "uk" | "gb" => Ok(KeyboardLayout::Uk),
+            "de" => Ok(KeyboardLayout::De),
+            "fr" => Ok(KeyboardLayout::Fr),
+            _ => Err(format!("Unknown Keyboard Layout {}, expected us, uk, de or fr", layout_name))
+        }
+    }
+}
+
+impl fmt::Display for KeyboardLayout {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match self {
+            KeyboardLayout::Us => "us",
+            KeyboardLayout::Uk => "uk",
+            KeyboardLayout::De => "de",
+            KeyboardLayout::Fr => "fr",
+        })
+    }
+}
+
+impl KeyboardLayout {
+    fn get_key_mapping(&self, usage: u16) -> Option<KeyMapping> {
+        /* Layouts override the US Mapping */
+        let layout_keys: &[KeyMapping] = match self {
+            KeyboardLayout::Us => &[],
+            KeyboardLayout::Uk => UK_KEYS,
+            KeyboardLayout::De => DE_KEYS,
+            KeyboardLayout::Fr => FR_KEYS,
+        };
+
+        let find = |keys: &[KeyMapping]| keys.iter().find(|(key_usage, _, _, _)| *key_usage == usage).copied();
+        find(layout_keys).or_else(|| find(US_KEYS)).or_else(|| match usage {
+            0x04..=0x1D => {
+                let letter = (b'a' + (usage - 0x04) as u8) as char;
+                Some((usage, letter, letter.to_ascii_uppercase(), None))
+            },
+            _ => None
+        })
+    }
+
+    fn has_altgr(&self) -> bool {
+        /* Right Alt is a plain Alt on the US Layout */
+        *self != KeyboardLayout::Us
+    }
+}
+
+fn get_special_key_name(usage: u16) -> String {
+    let key_name = match usage {
+        0x28 | 0x58 => "ENTER",
+        0x29 => "ESC",
+        0x2A => "BACKSPACE",
+        0x2B => "TAB",
+        0x2C => "SPACE",
+        0x39 => "CAPSLOCK",
+        0x3A..=0x45 => return format!("F{}", usage - 0x39),
+        0x46 => "PRINTSCREEN",
+        0x47 => "SCROLLLOCK",
+        0x48 => "PAUSE",
+        0x49 => "INSERT",
+        0x4A => "HOME",
+        0x4B => "PAGEUP",
+        0x4C => "DELETE",
+        0x4D => "END",
+        0x4E => "PAGEDOWN",
+        0x4F => "RIGHT",
+        0x50 => "LEFT",
+        0x51 => "DOWN",
+        0x52 => "UP",
+        0x53 => "NUMLOCK",
+        0x65 => "MENU",
+        0x66 => "POWER",
+        0x68..=0x73 => return format!("F{}", usage - 0x68 + 13),
+        0x7F => "MUTE",
+        0x80 => "VOLUMEUP",
+        0x81 => "VOLUMEDOWN",
+        _ => return format!("KEY_0x{:02X}", usage)
+    };
+
+    String::from(key_name)
+}
+
+fn format_modifiers(modifiers: u8, has_altgr: bool) -> Vec<&'static str> {
+    let mut modifier_names = vec![];
+    if modifiers & MODIFIER_CONTROL != 0 { modifier_names.push("CTRL"); }
+    if modifiers & MODIFIER_SHIFT != 0 { modifier_names.push("SHIFT"); }
+    if modifiers & MODIFIER_ALT != 0 || (!has_altgr && modifiers & MODIFIER_ALTGR != 0) { modifier_names.push("ALT"); }
+    if has_altgr && modifiers & MODIFIER_ALTGR != 0 { modifier_names.push("ALTGR"); }
+    if modifiers & MODIFIER_GUI != 0 { modifier_names.push("GUI"); }
+    modifier_names
+}
+
+pub fn get_keyboard_usages(report_descriptor: Option<&ReportDescriptor>, report_data: &[u8]) -> Option<Vec<u16>> {
+    /* Held Keyboard Usages, Modifiers as 0xE0..0xE7. None for Reports of other Collections */
+    let Some(report_descriptor) = report_descriptor else {
+        let (modifiers, key_usages) = (report_data.first()?, report_data.get(2..)?);
+        let modifier_usages = (0..8).filter(|bit| modifiers & (1 << bit) != 0).map(|bit| USAGE_LEFT_CONTROL + bit);
+        return Some(modifier_usages.chain(key_usages.iter().filter(|usage| **usage != 0).map(|usage| *usage as u16)).collect());
+    };
+
+    let (report_id, payload) = report_descriptor.split_report(report_data);
+    let keyboard_fields: Vec<_> = report_descriptor
+        .get_fields(ReportKind::Input, report_id)
+        .filter(|field| !field.is_constant() && field.get_usage_page() == hid_descriptor::USAGE_PAGE_KEYBOARD)
+        .collect();
+
+    if keyboard_fields.is_empty() {
+        return None;
+    }
+
+    let mut usages = vec![];
+    for field in keyboard_fields {
+        match field.is_variable() {
+            /* Modifier Bits and NKRO Bitmaps */
+            true => usages.extend((0..field.get_payload_count(payload))
+                .filter(|index| field.read_value(payload, *index).is_some_and(|value| value != 0))
+                .filter_map(|index| field.get_usage(index))),
+            false => usages.extend((0..field.get_payload_count(payload))
+                .filter_map(|index| field.read_value(payload, index))
+                .filter_map(|value| field.get_array_usage(value))),
+        }
+    }
+
+    Some(usages.into_iter().map(|usage| usage as u16).collect())
+}
+
+pub fn get_caps_lock_led(report_descriptor: Option<&ReportDescriptor>, report_data: &[u8]) -> Option<bool> {
+    /* LED Output Reports, the Boot Protocol has Caps Lock at Bit 1 */
+    let Some(report_descriptor) = report_descriptor else {
+        return report_data.first().map(|leds| leds & 0x02 != 0);
+    };
+
+    let (report_id, payload) = report_descriptor.split_report(report_data);
+    report_descriptor
+        .get_fields(ReportKind::Output, report_id)
+        .filter(|field| field.is_variable())
+        .find_map(|field| {
+            let index = (0..field.get_payload_count(payload)).find(|index| field.get_usage(*index) == Some(USAGE_LED_CAPS_LOCK))?;
+            field.read_value(payload, index).map(|value| value != 0)
+        })
+}
+
+impl KeyboardSession {
+    pub fn set_caps_lock(&mut self, caps_lock: bool) {
+        self.caps_lock = caps_lock;
+    }
+
+    fn type_key(&self, layout: KeyboardLayout, usage: u16) -> String {
+        /* Printable Keys type their Character, everything else becomes a Token */
+        let has_altgr = layout.has_altgr();
+        let is_shifted = self.modifiers & MODIFIER_SHIFT != 0;
+        let is_altgr = has_altgr && self.modifiers & MODIFIER_ALTGR != 0;
+        let command_modifiers = self.modifiers & (MODIFIER_CONTROL | MODIFIER_ALT | MODIFIER_GUI | if has_altgr { 0 } else { MODIFIER_ALTGR });
+        let key_mapping = layout.get_key_mapping(usage);
+
+        if command_modifiers == 0 && let Some((_, unshifted, shifted, altgr)) = key_mapping {
+            let character = match (is_altgr, altgr) {
+                (true, Some(altgr)) => Some(altgr),
+                (true, None) => None,
+                (false, _) => Some(if is_shifted { shifted } else { unshifted }),
+            };
+
+            if let Some(character) = character {
+                /* Caps Lock inverts the Case of Letters */
+                return match self.caps_lock && !is_altgr && character.is_alphabetic() {
+                    true if character.is_uppercase() => character.to_lowercase().to_string(),
+                    true => character.to_uppercase().to_string(),
+                    false => character.to_string(),
+                };
+            }
+        }
+
+        let key_name = match key_mapping {
+            Some((_, unshifted, _, _)) if usage != 0x2C => unshifted.to_uppercase().to_string(),
+            _ => get_special_key_name(usage),
+        };
+
+        let mut token_parts = format_modifiers(self.modifiers, has_altgr);
+        token_parts.push(&key_name);
+        format!("<{}>", token_parts.join("+"))
+    }
+
+    fn get_typed_row(&mut self, urb_packet: &UrbXractPacket) -> &mut TypedRow {
+        let timestamp = urb_packet.header.timestamp;
+        self.typed_row.get_or_insert_with(|| TypedRow {
+            transmission: ReconstructedTransmission {
+                urbx_header: urb_packet.header,
+                combined_payload: String::new(),
+                sources: vec![],
+                is_error: false,
+            },
+            key_count: 0,
+            first_key_down: timestamp,
+            last_key_down: timestamp,
+            hold_total: 0,
+            hold_count: 0,
+            min_gap: None,
+            is_complete: false,
+        })
+    }
+
+    fn finish(&mut self, layout: KeyboardLayout) -> Option<ReconstructedTransmission> {
+        let mut typed_row = self.typed_row.take()?;
+        if typed_row.key_count == 0 {
+            return None;
+        }
+
+        let duration = typed_row.last_key_down - typed_row.first_key_down;
+        let mut statistics = vec![format!("{} keys in {:.3} s", typed_row.key_count, duration as f64 / 1_000_000.0)];
+        if typed_row.hold_count != 0 {
+            statistics.push(format!("hold avg {:.1} ms", typed_row.hold_total as f64 / typed_row.hold_count as f64 / 1000.0));
+        }
+
+        if let Some(min_gap) = typed_row.min_gap {
+            statistics.push(format!("min gap {:.1} ms", min_gap as f64 / 1000.0));
+        }
+
+        typed_row.transmission.combined_payload = format!(
+            "[Keyboard {}] {} ({})",
+            layout.to_string().to_uppercase(),
+            typed_row.transmission.combined_payload,
+            statistics.join(", ")
+        );
+
+        Some(typed_row.transmission)
+    }
+
+    pub fn flush_idle(&mut self, layout: KeyboardLayout, timestamp: u64) -> Option<ReconstructedTransmission> {
+        match timestamp.saturating_sub(self.last_activity) > TYPING_IDLE_TIMEOUT {
+            true => self.finish(layout),
+            false => None,
+        }
+    }
+
+    pub fn consume_report(&mut self, layout: KeyboardLayout, urb_packet: UrbXractPacket, usages: Vec<u16>) -> Vec<ReconstructedTransmission> {
+        /* Returns the Rows to dispatch: completed Lines and Rollover Errors */
+        let timestamp = urb_packet.header.timestamp;
+        let mut dispatch_rows = vec![];
+
+        /* Phantom State: too many Keys held, the previous State stays valid */
+        if usages.contains(&USAGE_ERROR_ROLLOVER) {
+            dispatch_rows.extend(self.finish(layout));
+            dispatch_rows.push(ReconstructedTransmission {
+                urbx_header: urb_packet.header,
+                combined_payload: String::from("[Keyboard] ErrorRollOver: more Keys held than the Report can carry"),
+                sources: vec![urb_packet],
+                is_error: true,
+            });
+
+            return dispatch_rows;
+        }
+
+        let modifiers = usages.iter()
+            .filter(|usage| (USAGE_LEFT_CONTROL..=USAGE_LEFT_CONTROL + 7).contains(*usage))
+            .fold(0u8, |modifiers, usage| modifiers | 1 << (usage - USAGE_LEFT_CONTROL));
+        let held_keys: Vec<u16> = usages.into_iter().filter(|usage| !(USAGE_LEFT_CONTROL..=USAGE_LEFT_CONTROL + 7).contains(usage) && *usage > 0x03).collect();
+
+        /* Key Up: Hold Time since Key Down */
+        for released_key in self.held_keys.iter().filter(|key| !held_keys.contains(key)) {
+            if let Some(key_down_time) = self.key_down_times.remove(released_key) && let Some(typed_row) = self.typed_row.as_mut() {
+                typed_row.hold_total += timestamp.saturating_sub(key_down_time);
+                typed_row.hold_count += 1;
+            }
+        }
+
+        let pressed_keys: Vec<u16> = held_keys.iter().filter(|key| !self.held_keys.contains(key)).copied().collect();
+        let is_modifier_pressed = modifiers & !self.modifiers != 0;
+        self.lone_modifiers = match (pressed_keys.is_empty(), is_modifier_pressed) {
+            (false, _) => 0,
+            (true, true) => self.lone_modifiers | modifiers,
+            (true, false) => self.lone_modifiers,
+        };
+
+        self.modifiers = modifiers;
+        if !pressed_keys.is_empty() || self.lone_modifiers != 0 || self.typed_row.is_some() {
+            /* A new Keystroke after Enter starts the next Row */
+            if !pressed_keys.is_empty() && self.typed_row.as_ref().is_some_and(|typed_row| typed_row.is_complete) {
+                dispatch_rows.extend(self.finish(layout));
+            }
+
+            self.get_typed_row(&urb_packet);
+        }
+
+        for pressed_key in pressed_keys {
+            let typed_text = self.type_key(layout, pressed_key);
+            if pressed_key == USAGE_CAPS_LOCK {
+                self.caps_lock = !self.caps_lock;
+            }
+
+            let typed_row = self.typed_row.as_mut().unwrap();
+            match typed_row.key_count {
+                0 => typed_row.first_key_down = timestamp,
+                _ => {
+                    let gap = timestamp.saturating_sub(typed_row.last_key_down);
+                    typed_row.min_gap = Some(typed_row.min_gap.map_or(gap, |min_gap| min_gap.min(gap)));
+                }
+            }
+
+            typed_row.transmission.combined_payload += &typed_text;
+            typed_row.key_count += 1;
+            typed_row.last_key_down = timestamp;
+            typed_row.is_complete |= pressed_key == USAGE_ENTER || pressed_key == USAGE_KEYPAD_ENTER;
+            self.key_down_times.insert(pressed_key, timestamp);
+        }
+
+        /* Modifiers released on their own, e.g. GUI to open the Start Menu */
+        if modifiers == 0 && self.lone_modifiers != 0 {
+            let lone_modifiers = format_modifiers(self.lone_modifiers, layout.has_altgr());
+            if lone_modifiers != ["SHIFT"] && let Some(typed_row) = self.typed_row.as_mut() {
+                typed_row.transmission.combined_payload += &format!("<{}>", lone_modifiers.join("+"));
+                if typed_row.key_count == 0 {
+                    typed_row.first_key_down = timestamp;
+                }
+
+                typed_row.key_count += 1;
+                typed_row.last_key_down = timestamp;
+            }
+
+            self.lone_modifiers = 0;
+        }
+
+        self.held_keys = held_keys;
+        self.last_activity = timestamp;
+        if let Some(typed_row) = self.typed_row.as_mut() {
+            typed_row.transmission.sources.push(urb_packet);
+        }
+
+        if self.held_keys.is_empty() && self.typed_row.as_ref().is_some_and(|typed_row| typed_row.is_complete) {
+            dispatch_rows.extend(self.finish(layout));
+        }
+
+        dispatch_rows
+    }
+}
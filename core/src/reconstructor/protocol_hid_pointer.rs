@@ -0,0 +1,333 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::VecDeque;
+use super::hid_descriptor::{self, ReportDescriptor, ReportField, ReportKind};
+
+/*
+    Mouse, Touch and Pen Reports. Every Collection carrying X and Y is one
+    Contact: Multi-touch Digitizers repeat a Finger Collection per Contact
+    (Microsoft Windows Precision Touch), Pens and Mice have a single one
+*/
+
+/* Define Constants */
+const USAGE_POINTER: u32 = 0x0001_0001;
+const USAGE_MOUSE: u32 = 0x0001_0002;
+const USAGE_X: u32 = 0x0001_0030;
+const USAGE_Y: u32 = 0x0001_0031;
+const USAGE_WHEEL: u32 = 0x0001_0038;
+const USAGE_AC_PAN: u32 = 0x000C_0238;
+const USAGE_DIGITIZER: u32 = 0x000D_0001;
+const USAGE_PEN: u32 = 0x000D_0002;
+const USAGE_TOUCH_SCREEN: u32 = 0x000D_0004;
+const USAGE_TOUCH_PAD: u32 = 0x000D_0005;
+const USAGE_TIP_PRESSURE: u32 = 0x000D_0030;
+const USAGE_IN_RANGE: u32 = 0x000D_0032;
+const USAGE_X_TILT: u32 = 0x000D_003D;
+const USAGE_Y_TILT: u32 = 0x000D_003E;
+const USAGE_TIP_SWITCH: u32 = 0x000D_0042;
+const USAGE_BARREL_SWITCH: u32 = 0x000D_0044;
+const USAGE_ERASER: u32 = 0x000D_0045;
+const USAGE_CONFIDENCE: u32 = 0x000D_0047;
+const USAGE_WIDTH: u32 = 0x000D_0048;
+const USAGE_HEIGHT: u32 = 0x000D_0049;
+const USAGE_CONTACT_IDENTIFIER: u32 = 0x000D_0051;
+const USAGE_CONTACT_COUNT: u32 = 0x000D_0054;
+const USAGE_SCAN_TIME: u32 = 0x000D_0056;
+const TRAIL_LENGTH: usize = 2048;
+const CURSOR_SCREEN: (f64, f64) = (1920.0, 1080.0); /* Relative Mice move a Cursor across a virtual Screen */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerKind {
+    Mouse,
+    Touch,
+    Pen
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PointerSample {
+    pub kind: PointerKind,
+    pub device_address: (u16, u16),
+    pub contact_id: u32,
+    pub x: f64, /* Normalized to 0..1, Origin at the Top Left */
+    pub y: f64,
+    pub is_touching: bool, /* Tip down, or Button held while the Mouse moves */
+}
+
+#[derive(Default)]
+pub struct PointerTrail {
+    samples: VecDeque<PointerSample>,
+}
+
+#[derive(Default)]
+pub struct PointerSession {
+    cursor: Option<(f64, f64)>,
+}
+
+struct UsageValue<'a> {
+    usage: u32,
+    value: i64,
+    field: &'a ReportField,
+}
+
+#[derive(Default)]
+struct PointerContact<'a> {
+    values: Vec<UsageValue<'a>>,
+}
+
+impl PointerTrail {
+    pub fn push(&mut self, sample: PointerSample) {
+        if self.samples.len() == TRAIL_LENGTH {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(sample);
+    }
+
+    pub fn get_samples(&self) -> Vec<PointerSample> {
+        self.samples.iter().copied().collect()
+    }
+}
+
+impl PointerKind {
+    fn from_application(application: u32) -> Option<Self> {
+        match application {
+            USAGE_MOUSE | USAGE_POINTER => Some(PointerKind::Mouse),
+            USAGE_TOUCH_SCREEN | USAGE_TOUCH_PAD => Some(PointerKind::Touch),
+            USAGE_PEN | USAGE_DIGITIZER => Some(PointerKind::Pen),
+            _ => None
+        }
+    }
+}
+
+impl<'a> PointerContact<'a> {
+    fn get(&self, usage: u32) -> Option<&UsageValue<'a>> {
+        self.values.iter().find(|usage_value| usage_value.usage == usage)
+    }
+
+    fn get_flag(&self, usage: u32) -> Option<bool> {
+        self.get(usage).map(|usage_value| usage_value.value != 0)
+    }
+
+    fn get_normalized(&self, usage: u32) -> Option<f64> {
+        let usage_value = self.get(usage)?;
+        let field = usage_value.field;
+        match field.logical_max > field.logical_min {
+            true => Some(((usage_value.value - field.logical_min) as f64 / (field.logical_max - field.logical_min) as f64).clamp(0.0, 1.0)),
+            false => None,
+        }
+    }
+
+    fn get_buttons(&self) -> Vec<String> {
+        self.values
+            .iter()
+            .filter(|usage_value| (usage_value.usage >> 16) as u16 == hid_descriptor::USAGE_PAGE_BUTTON && usage_value.value != 0)
+            .map(|usage_value| hid_descriptor::get_usage_name(usage_value.usage))
+            .collect()
+    }
+
+    fn describe(&self, kind: PointerKind) -> String {
+        let mut parts = vec![];
+        match kind {
+            PointerKind::Mouse => {
+                let buttons = self.get_buttons();
+                if !buttons.is_empty() {
+                    parts.push(format!("[{}]", buttons.join(", ")));
+                }
+            },
+
+            PointerKind::Touch | PointerKind::Pen => {
+                if let Some(contact_id) = self.get(USAGE_CONTACT_IDENTIFIER) {
+                    parts.push(format!("#{}", contact_id.value));
+                }
+
+                parts.push(String::from(match (self.get_flag(USAGE_TIP_SWITCH), self.get_flag(USAGE_IN_RANGE)) {
+                    (Some(true), _) => "Down",
+                    (_, Some(true)) => "Hover",
+                    (_, Some(false)) => "Out of Range",
+                    _ => "Up",
+                }));
+
+                for (usage, name) in [(USAGE_BARREL_SWITCH, "Barrel"), (USAGE_ERASER, "Eraser")] {
+                    if self.get_flag(usage) == Some(true) {
+                        parts.push(String::from(name));
+                    }
+                }
+
+                if self.get_flag(USAGE_CONFIDENCE) == Some(false) {
+                    parts.push(String::from("Palm"));
+                }
+            },
+        }
+
+        /* Coordinates and Measurements with their Physical Units */
+        let measurements = [
+            (USAGE_X, "X"), (USAGE_Y, "Y"), (USAGE_TIP_PRESSURE, "Pressure"), (USAGE_X_TILT, "Tilt X"), (USAGE_Y_TILT, "Tilt Y"),
+            (USAGE_WIDTH, "Width"), (USAGE_HEIGHT, "Height"), (USAGE_WHEEL, "Wheel"), (USAGE_AC_PAN, "Pan"),
+        ];
+
+        for (usage, name) in measurements {
+            if let Some(usage_value) = self.get(usage) {
+                parts.push(format!("{}={}", name, usage_value.field.format_value(usage_value.value)));
+            }
+        }
+
+        parts.join(" ")
+    }
+}
+
+fn get_usage_values<'a>(report_descriptor: &'a ReportDescriptor, report_id: u8, payload: &[u8]) -> Vec<(usize, UsageValue<'a>)> {
+    /* Variable Fields with their Collection, Buttons also arrive as Arrays */
+    let mut usage_values = vec![];
+    for field in report_descriptor.get_fields(ReportKind::Input, report_id).filter(|field| !field.is_constant()) {
+        for index in 0..field.get_payload_count(payload) {
+            let Some(value) = field.read_value(payload, index) else { continue };
+            let usage = match field.is_variable() {
+                true => field.get_usage(index),
+                false => field.get_array_usage(value),
+            };
+
+            if let Some(usage) = usage {
+                let value = if field.is_variable() { value } else { 1 };
+                usage_values.push((field.collection_id, UsageValue { usage, value, field }));
+            }
+        }
+    }
+
+    usage_values
+}
+
+impl PointerSession {
+    fn move_cursor(&mut self, delta_x: i64, delta_y: i64) -> (f64, f64) {
+        let (cursor_x, cursor_y) = self.cursor.unwrap_or((CURSOR_SCREEN.0 / 2.0, CURSOR_SCREEN.1 / 2.0));
+        let cursor = ((cursor_x + delta_x as f64).clamp(0.0, CURSOR_SCREEN.0), (cursor_y + delta_y as f64).clamp(0.0, CURSOR_SCREEN.1));
+        self.cursor = Some(cursor);
+        (cursor.0 / CURSOR_SCREEN.0, cursor.1 / CURSOR_SCREEN.1)
+    }
+
+    pub fn consume_boot_report(&mut self, device_address: (u16, u16), report_data: &[u8]) -> Option<(String, Vec<PointerSample>)> {
+        /* Boot Mouse: Buttons, X and Y Displacement, optional Wheel (HID 1.11 B.2) */
+        let (buttons, delta_x, delta_y) = (*report_data.first()?, *report_data.get(1)? as i8 as i64, *report_data.get(2)? as i8 as i64);
+        let button_names: Vec<String> = (0..8).filter(|bit| buttons & (1 << bit) != 0).map(|bit| format!("Button {}", bit + 1)).collect();
+
+        let mut parts = vec![];
+        if !button_names.is_empty() {
+            parts.push(format!("[{}]", button_names.join(", ")));
+        }
+
+        parts.push(format!("X={:+} Y={:+}", delta_x, delta_y));
+        if let Some(wheel) = report_data.get(3) {
+            parts.push(format!("Wheel={:+}", *wheel as i8));
+        }
+
+        let (x, y) = self.move_cursor(delta_x, delta_y);
+        let sample = PointerSample { kind: PointerKind::Mouse, device_address, contact_id: 0, x, y, is_touching: buttons != 0 };
+        Some((format!("[HID Mouse] {}", parts.join(" ")), vec![sample]))
+    }
+
+    pub fn consume_report(&mut self, device_address: (u16, u16), report_descriptor: &ReportDescriptor, report_data: &[u8]) -> Option<(String, Vec<PointerSample>)> {
+        /* Returns the Row Description and the Positions to plot, None for other Collections */
+        let (report_id, payload) = report_descriptor.split_report(report_data);
+        let application = report_descriptor.get_fields(ReportKind::Input, report_id).next()?.application;
+        let kind = PointerKind::from_application(application)?;
+        let usage_values = get_usage_values(report_descriptor, report_id, payload);
+
+        /* Collections with Coordinates are Contacts, the rest describes the whole Report */
+        let mut contacts: Vec<(usize, PointerContact)> = vec![];
+        let mut report_values = PointerContact::default();
+        for (collection_id, usage_value) in usage_values {
+            let has_coordinates = usage_value.usage == USAGE_X || usage_value.usage == USAGE_Y;
+            match contacts.iter_mut().find(|(contact_collection, _)| *contact_collection == collection_id) {
+                Some((_, contact)) => contact.values.push(usage_value),
+                None if has_coordinates => contacts.push((collection_id, PointerContact { values: vec![usage_value] })),
+                None => report_values.values.push(usage_value),
+            }
+        }
+
+        /* Values declared ahead of the Coordinates in the same Collection */
+        for (collection_id, contact) in contacts.iter_mut() {
+            let (own_values, other_values): (Vec<UsageValue>, Vec<UsageValue>) = std::mem::take(&mut report_values.values)
+                .into_iter()
+                .partition(|usage_value| usage_value.field.collection_id == *collection_id);
+
+            contact.values.splice(0..0, own_values);
+            report_values.values = other_values;
+        }
+
+        /* Hybrid Mode: Contacts beyond the Contact Count are stale */
+        let contact_count = report_values.get(USAGE_CONTACT_COUNT).map(|usage_value| usage_value.value as usize);
+        if let Some(contact_count) = contact_count.filter(|contact_count| *contact_count > 0) {
+            contacts.truncate(contact_count);
+        }
+
+        let mut samples = vec![];
+        let mut contact_descriptions = vec![];
+        for (index, (_, contact)) in contacts.iter().enumerate() {
+            contact_descriptions.push(contact.describe(kind));
+            let is_relative = contact.get(USAGE_X).is_some_and(|usage_value| usage_value.field.is_relative());
+            let contact_id = contact.get(USAGE_CONTACT_IDENTIFIER).map(|usage_value| usage_value.value as u32).unwrap_or(index as u32);
+            let (position, is_touching) = match kind {
+                PointerKind::Mouse if is_relative => {
+                    let delta_x = contact.get(USAGE_X).map(|usage_value| usage_value.value).unwrap_or(0);
+                    let delta_y = contact.get(USAGE_Y).map(|usage_value| usage_value.value).unwrap_or(0);
+                    (Some(self.move_cursor(delta_x, delta_y)), !contact.get_buttons().is_empty())
+                },
+
+                PointerKind::Mouse => (contact.get_normalized(USAGE_X).zip(contact.get_normalized(USAGE_Y)), !contact.get_buttons().is_empty()),
+
+                /* Touch Contacts are only plotted while down, Pens also while hovering */
+                PointerKind::Touch | PointerKind::Pen => {
+                    let is_touching = contact.get_flag(USAGE_TIP_SWITCH) == Some(true);
+                    let is_visible = is_touching || (kind == PointerKind::Pen && contact.get_flag(USAGE_IN_RANGE) == Some(true));
+                    (contact.get_normalized(USAGE_X).zip(contact.get_normalized(USAGE_Y)).filter(|_| is_visible), is_touching)
+                }
+            };
+
+            if let Some((x, y)) = position {
+                samples.push(PointerSample { kind, device_address, contact_id, x, y, is_touching });
+            }
+        }
+
+        let mut description = format!("[HID {}]", hid_descriptor::get_usage_name(application));
+        match kind {
+            PointerKind::Touch => {
+                let active_count = contacts.iter().filter(|(_, contact)| contact.get_flag(USAGE_TIP_SWITCH) == Some(true)).count();
+                description += &format!(" Contacts {} ({} down): {}", contacts.len(), active_count, contact_descriptions.join(" | "));
+            },
+            _ => description += &format!(" {}", contact_descriptions.join(" | ")),
+        }
+
+        /* Buttons and Wheels outside the Pointer Collection, Scan Time of Digitizers */
+        let report_description = report_values.describe(PointerKind::Mouse);
+        if !report_description.is_empty() {
+            description += &format!(", {}", report_description);
+        }
+
+        if let Some(scan_time) = report_values.get(USAGE_SCAN_TIME) {
+            /* 100 µs Units unless the Descriptor declares otherwise */
+            let scan_time = match scan_time.field.has_physical_value() {
+                true => scan_time.field.format_value(scan_time.value),
+                false => format!("{:.1} ms", scan_time.value as f64 / 10.0),
+            };
+
+            description += &format!(", Scan Time {}", scan_time);
+        }
+
+        Some((description, samples))
+    }
+}
@@ -0,0 +1,336 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/*
+    Mobile Broadband Interface Model. Control Messages travel in CDC
+    Encapsulated Commands, Commands address a CID of a Device Service by
+    UUID. Messages beyond the Control Transfer Size are fragmented
+*/
+pub const MESSAGE_OPEN: u32 = 0x00000001;
+pub const MESSAGE_CLOSE: u32 = 0x00000002;
+pub const MESSAGE_COMMAND: u32 = 0x00000003;
+pub const MESSAGE_HOST_ERROR: u32 = 0x00000004;
+pub const MESSAGE_OPEN_DONE: u32 = 0x80000001;
+pub const MESSAGE_CLOSE_DONE: u32 = 0x80000002;
+pub const MESSAGE_COMMAND_DONE: u32 = 0x80000003;
+pub const MESSAGE_FUNCTION_ERROR: u32 = 0x80000004;
+pub const MESSAGE_INDICATE_STATUS: u32 = 0x80000007;
+const HEADER_LENGTH: usize = 12;
+const FRAGMENT_HEADER_LENGTH: usize = 20;
+
+/* Device Service UUIDs as they appear on the Wire */
+const SERVICE_BASIC_CONNECT: [u8; 16] = [0xA2, 0x89, 0xCC, 0x33, 0xBC, 0xBB, 0x8B, 0x4F, 0xB6, 0xB0, 0x13, 0x3E, 0xC2, 0xAA, 0xE6, 0xDF];
+pub const SERVICE_QMI: [u8; 16] = [0xD1, 0xA3, 0x0B, 0xC2, 0xF9, 0x7A, 0x6E, 0x43, 0xBF, 0x65, 0xC7, 0xE2, 0x4F, 0xB0, 0xF0, 0xD3];
+const KNOWN_SERVICES: [([u8; 16], &str); 12] = [
+    (SERVICE_BASIC_CONNECT, "BASIC_CONNECT"),
+    ([0x53, 0x3F, 0xBE, 0xEB, 0x14, 0xFE, 0x44, 0x67, 0x9F, 0x90, 0x33, 0xA2, 0x23, 0xE5, 0x6C, 0x3F], "SMS"),
+    ([0xE5, 0x50, 0xA0, 0xC8, 0x5E, 0x82, 0x47, 0x9E, 0x82, 0xF7, 0x10, 0xAB, 0xF4, 0xC3, 0x35, 0x1F], "USSD"),
+    ([0x4B, 0xF3, 0x84, 0x76, 0x1E, 0x6A, 0x41, 0xDB, 0xB1, 0xD8, 0xBE, 0xD2, 0x89, 0xC2, 0x5B, 0xDB], "PHONEBOOK"),
+    ([0xD8, 0xF2, 0x01, 0x31, 0xFC, 0xB5, 0x4E, 0x17, 0x86, 0x02, 0xD6, 0xED, 0x38, 0x16, 0x16, 0x4C], "STK"),
+    ([0x1D, 0x2B, 0x5F, 0xF7, 0x0A, 0xA1, 0x48, 0xB2, 0xAA, 0x52, 0x50, 0xF1, 0x57, 0x67, 0x17, 0x4E], "AUTH"),
+    ([0xC0, 0x8A, 0x26, 0xDD, 0x77, 0x18, 0x43, 0x82, 0x84, 0x82, 0x6E, 0x0D, 0x58, 0x3C, 0x4D, 0x0E], "DSS"),
+    (SERVICE_QMI, "QMI"),
+    ([0xE9, 0xF7, 0xDE, 0xA2, 0xFE, 0xAF, 0x40, 0x09, 0x93, 0xCE, 0x90, 0xA3, 0x69, 0x41, 0x03, 0xB6], "MS_FIRMWARE_ID"),
+    ([0x88, 0x3B, 0x7C, 0x26, 0x98, 0x5F, 0x43, 0xFA, 0x98, 0x04, 0x27, 0xD7, 0xFB, 0x80, 0x95, 0x9C], "MS_HOST_SHUTDOWN"),
+    ([0x3D, 0x01, 0xDC, 0xC5, 0xFE, 0xF5, 0x4D, 0x05, 0x0D, 0x3A, 0xBE, 0xF7, 0x05, 0x8E, 0x9A, 0xAF], "MS_BASIC_CONNECT_EXTENSIONS"),
+    ([0x83, 0x8C, 0xF7, 0xFB, 0x8D, 0x0D, 0x4D, 0x7F, 0x87, 0x1E, 0xD7, 0x1D, 0xBE, 0xFB, 0xB3, 0x9B], "PROXY_CONTROL"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MbimMessage {
+    pub message_type: u32,
+    pub transaction_id: u32,
+    pub total_fragments: u32,
+    pub current_fragment: u32,
+    pub service: [u8; 16],
+    pub cid: u32,
+    pub value: u32, /* Command Type of Commands, Status of Completions, Error Code of Errors */
+    pub information_buffer: Vec<u8>,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    data.get(offset..offset + 4).map_or(0, |bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+pub fn parse_message(data: &[u8]) -> Result<MbimMessage, String> {
+    if data.len() < HEADER_LENGTH {
+        return Err(String::from("truncated Header"));
+    }
+
+    let message_type = read_u32(data, 0);
+    let message_length = read_u32(data, 4) as usize;
+    if message_length > data.len() || message_length < HEADER_LENGTH {
+        return Err(format!("Message Length {} with {} bytes", message_length, data.len()));
+    }
+
+    let data = &data[..message_length];
+    let mut mbim_message = MbimMessage {
+        message_type,
+        transaction_id: read_u32(data, 8),
+        total_fragments: 1,
+        current_fragment: 0,
+        service: [0; 16],
+        cid: 0,
+        value: 0,
+        information_buffer: vec![],
+    };
+
+    /* Fragmented Messages carry their Service and CID in the first Fragment only */
+    let is_fragmented = matches!(message_type, MESSAGE_COMMAND | MESSAGE_COMMAND_DONE | MESSAGE_INDICATE_STATUS);
+    if is_fragmented {
+        mbim_message.total_fragments = read_u32(data, 12);
+        mbim_message.current_fragment = read_u32(data, 16);
+    }
+
+    match message_type {
+        _ if is_fragmented && mbim_message.current_fragment > 0 => {
+            mbim_message.information_buffer = data.get(FRAGMENT_HEADER_LENGTH..).unwrap_or_default().to_vec();
+        },
+
+        MESSAGE_COMMAND | MESSAGE_COMMAND_DONE | MESSAGE_INDICATE_STATUS => {
+            let buffer_offset = if message_type == MESSAGE_INDICATE_STATUS { 44 } else { 48 };
+            mbim_message.service = data.get(20..36).ok_or("truncated Command")?.try_into().unwrap();
+            mbim_message.cid = read_u32(data, 36);
+            mbim_message.value = if message_type == MESSAGE_INDICATE_STATUS { 0 } else { read_u32(data, 40) };
+            mbim_message.information_buffer = data.get(buffer_offset..).unwrap_or_default().to_vec();
+        },
+
+        _ => mbim_message.value = read_u32(data, 12),
+    }
+
+    Ok(mbim_message)
+}
+
+pub fn get_service_name(service: &[u8; 16]) -> String {
+    match KNOWN_SERVICES.iter().find(|(uuid, _)| uuid == service) {
+        Some((_, service_name)) => String::from(*service_name),
+        None => {
+            let hex: String = service.iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+        }
+    }
+}
+
+pub fn get_cid_name(service: &[u8; 16], cid: u32) -> String {
+    let service_name = get_service_name(service);
+    let cid_name = match (service_name.as_str(), cid) {
+        ("BASIC_CONNECT", 1) => "DEVICE_CAPS",
+        ("BASIC_CONNECT", 2) => "SUBSCRIBER_READY_STATUS",
+        ("BASIC_CONNECT", 3) => "RADIO_STATE",
+        ("BASIC_CONNECT", 4) => "PIN",
+        ("BASIC_CONNECT", 5) => "PIN_LIST",
+        ("BASIC_CONNECT", 6) => "HOME_PROVIDER",
+        ("BASIC_CONNECT", 7) => "PREFERRED_PROVIDERS",
+        ("BASIC_CONNECT", 8) => "VISIBLE_PROVIDERS",
+        ("BASIC_CONNECT", 9) => "REGISTER_STATE",
+        ("BASIC_CONNECT", 10) => "PACKET_SERVICE",
+        ("BASIC_CONNECT", 11) => "SIGNAL_STATE",
+        ("BASIC_CONNECT", 12) => "CONNECT",
+        ("BASIC_CONNECT", 13) => "PROVISIONED_CONTEXTS",
+        ("BASIC_CONNECT", 14) => "SERVICE_ACTIVATION",
+        ("BASIC_CONNECT", 15) => "IP_CONFIGURATION",
+        ("BASIC_CONNECT", 16) => "DEVICE_SERVICES",
+        ("BASIC_CONNECT", 19) => "DEVICE_SERVICE_SUBSCRIBE_LIST",
+        ("BASIC_CONNECT", 20) => "PACKET_STATISTICS",
+        ("BASIC_CONNECT", 21) => "NETWORK_IDLE_HINT",
+        ("BASIC_CONNECT", 22) => "EMERGENCY_MODE",
+        ("BASIC_CONNECT", 23) => "IP_PACKET_FILTERS",
+        ("BASIC_CONNECT", 24) => "MULTICARRIER_PROVIDERS",
+        ("SMS", 1) => "CONFIGURATION",
+        ("SMS", 2) => "READ",
+        ("SMS", 3) => "SEND",
+        ("SMS", 4) => "DELETE",
+        ("SMS", 5) => "MESSAGE_STORE_STATUS",
+        ("USSD", 1) => "USSD",
+        ("PHONEBOOK", 1) => "CONFIGURATION",
+        ("PHONEBOOK", 2) => "READ",
+        ("PHONEBOOK", 3) => "DELETE",
+        ("PHONEBOOK", 4) => "WRITE",
+        ("STK", 1) => "PAC",
+        ("STK", 2) => "TERMINAL_RESPONSE",
+        ("STK", 3) => "ENVELOPE",
+        ("AUTH", 1) => "AKA",
+        ("AUTH", 2) => "AKAP",
+        ("AUTH", 3) => "SIM",
+        ("DSS", 1) => "CONNECT",
+        ("QMI", 1) => "MSG",
+        ("MS_FIRMWARE_ID", 1) => "GET",
+        ("MS_HOST_SHUTDOWN", 1) => "NOTIFY",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 1) => "PROVISIONED_CONTEXTS_V2",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 2) => "NETWORK_BLACKLIST",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 3) => "LTE_ATTACH_CONFIGURATION",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 4) => "LTE_ATTACH_INFO",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 5) => "SYS_CAPS",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 6) => "DEVICE_CAPS_V2",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 7) => "DEVICE_SLOT_MAPPINGS",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 8) => "SLOT_INFO_STATUS",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 9) => "PCO",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 10) => "DEVICE_RESET",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 11) => "BASE_STATIONS_INFO",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 12) => "LOCATION_INFO_STATUS",
+        ("MS_BASIC_CONNECT_EXTENSIONS", 15) => "VERSION",
+        ("PROXY_CONTROL", 1) => "CONFIGURATION",
+        _ => return format!("{} CID {}", service_name, cid),
+    };
+
+    format!("{} {}", service_name, cid_name)
+}
+
+pub fn get_status_name(status: u32) -> String {
+    let status_name = match status {
+        0 => "SUCCESS",
+        1 => "BUSY",
+        2 => "FAILURE",
+        3 => "SIM_NOT_INSERTED",
+        4 => "BAD_SIM",
+        5 => "PIN_REQUIRED",
+        6 => "PIN_DISABLED",
+        7 => "NOT_REGISTERED",
+        8 => "PROVIDERS_NOT_FOUND",
+        9 => "NO_DEVICE_SUPPORT",
+        10 => "PROVIDER_NOT_VISIBLE",
+        11 => "DATA_CLASS_NOT_AVAILABLE",
+        12 => "PACKET_SERVICE_DETACHED",
+        13 => "MAX_ACTIVATED_CONTEXTS",
+        14 => "NOT_INITIALIZED",
+        15 => "VOICE_CALL_IN_PROGRESS",
+        16 => "CONTEXT_NOT_ACTIVATED",
+        17 => "SERVICE_NOT_ACTIVATED",
+        18 => "INVALID_ACCESS_STRING",
+        19 => "INVALID_USER_NAME_PWD",
+        20 => "RADIO_POWER_OFF",
+        21 => "INVALID_PARAMETERS",
+        22 => "READ_FAILURE",
+        23 => "WRITE_FAILURE",
+        25 => "NO_PHONEBOOK",
+        26 => "PARAMETER_TOO_LONG",
+        27 => "STK_BUSY",
+        28 => "OPERATION_NOT_ALLOWED",
+        29 => "MEMORY_FAILURE",
+        30 => "INVALID_MEMORY_INDEX",
+        31 => "MEMORY_FULL",
+        32 => "FILTER_NOT_SUPPORTED",
+        33 => "DSS_INSTANCE_LIMIT",
+        34 => "INVALID_DEVICE_SERVICE_OPERATION",
+        35 => "AUTH_INCORRECT_AUTN",
+        36 => "AUTH_SYNC_FAILURE",
+        37 => "AUTH_AMF_NOT_SET",
+        38 => "CONTEXT_NOT_SUPPORTED",
+        _ => return format!("Status {}", status),
+    };
+
+    String::from(status_name)
+}
+
+pub fn get_protocol_error_name(error_code: u32) -> String {
+    let error_name = match error_code {
+        1 => "TIMEOUT_FRAGMENT",
+        2 => "FRAGMENT_OUT_OF_SEQUENCE",
+        3 => "LENGTH_MISMATCH",
+        4 => "DUPLICATED_TID",
+        5 => "NOT_OPENED",
+        6 => "UNKNOWN",
+        7 => "CANCEL",
+        8 => "MAX_TRANSFER",
+        _ => return format!("Error {}", error_code),
+    };
+
+    String::from(error_name)
+}
+
+pub fn describe_information(service: &[u8; 16], cid: u32, buffer: &[u8]) -> Option<String> {
+    /* Frequently watched BASIC_CONNECT States, others only by Size */
+    if *service != SERVICE_BASIC_CONNECT || buffer.is_empty() {
+        return (!buffer.is_empty()).then(|| format!("{} bytes", buffer.len()));
+    }
+
+    let description = match (cid, buffer.len()) {
+        (2, 4..) => match read_u32(buffer, 0) {
+            0 => String::from("Not Initialized"),
+            1 => String::from("Initialized"),
+            2 => String::from("SIM Not Inserted"),
+            3 => String::from("Bad SIM"),
+            4 => String::from("Failure"),
+            5 => String::from("Not Activated"),
+            6 => String::from("Device Locked"),
+            ready_state => format!("Ready State {}", ready_state),
+        },
+
+        (3, 8..) => format!(
+            "Hardware Radio {}, Software Radio {}",
+            if read_u32(buffer, 0) != 0 { "On" } else { "Off" },
+            if read_u32(buffer, 4) != 0 { "On" } else { "Off" }
+        ),
+
+        (3, 4) => format!("Radio {}", if read_u32(buffer, 0) != 0 { "On" } else { "Off" }),
+        (9, 8..) => format!("{}{}", match read_u32(buffer, 4) {
+            0 => String::from("Unknown"),
+            1 => String::from("Deregistered"),
+            2 => String::from("Searching"),
+            3 => String::from("Home"),
+            4 => String::from("Roaming"),
+            5 => String::from("Partner"),
+            6 => String::from("Denied"),
+            register_state => format!("Register State {}", register_state),
+        }, match read_u32(buffer, 0) {
+            0 => String::new(),
+            network_error => format!(", Network Error {}", network_error),
+        }),
+
+        (10, 8..) => String::from(match read_u32(buffer, 4) {
+            1 => "Attaching",
+            2 => "Attached",
+            3 => "Detaching",
+            4 => "Detached",
+            _ => "Unknown",
+        }),
+
+        /* RSSI Steps of 2 dBm from -113 dBm, 99 is unknown */
+        (11, 8..) => match read_u32(buffer, 0) {
+            99 => String::from("RSSI unknown"),
+            rssi => format!("RSSI {} dBm", -113 + 2 * rssi.min(31) as i32),
+        },
+
+        (12, 12..) => format!("Session {} {}", read_u32(buffer, 0), match read_u32(buffer, 4) {
+            1 => "Activated",
+            2 => "Activating",
+            3 => "Deactivated",
+            4 => "Deactivating",
+            _ => "Unknown",
+        }),
+
+        (_, length) => format!("{} bytes", length),
+    };
+
+    Some(description)
+}
+
+pub fn get_message_name(message_type: u32) -> String {
+    match message_type {
+        MESSAGE_OPEN => String::from("OPEN"),
+        MESSAGE_CLOSE => String::from("CLOSE"),
+        MESSAGE_COMMAND => String::from("COMMAND"),
+        MESSAGE_HOST_ERROR => String::from("HOST_ERROR"),
+        MESSAGE_OPEN_DONE => String::from("OPEN_DONE"),
+        MESSAGE_CLOSE_DONE => String::from("CLOSE_DONE"),
+        MESSAGE_COMMAND_DONE => String::from("COMMAND_DONE"),
+        MESSAGE_FUNCTION_ERROR => String::from("FUNCTION_ERROR"),
+        MESSAGE_INDICATE_STATUS => String::from("INDICATE_STATUS"),
+        message_type => format!("Message 0x{:08X}", message_type),
+    }
+}
@@ -0,0 +1,335 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::protocol_control::format_hex;
+
+/*
+    Modbus RTU over USB Serial. The Host is the Client, so OUT carries
+    Requests and IN carries Responses. Frames are delimited by their
+    Function Code instead of the 3.5 Character Silence, which USB hides.
+    Specification: https://modbus.org/docs/Modbus_Application_Protocol_V1_1b3.pdf
+*/
+
+/* Define Constants */
+const MODBUS_EXCEPTION_FLAG: u8 = 0x80;
+const MODBUS_BROADCAST_ADDRESS: u8 = 0x00;
+const MODBUS_MAX_FRAME_LENGTH: usize = 256;
+const MEI_READ_DEVICE_ID: u8 = 0x0E;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ModbusRequest {
+    pub address: u8,
+    pub function: u8,
+    pub start: u16,
+    pub quantity: u16,
+    pub timestamp: u64,
+}
+
+fn get_function_name(function: u8) -> &'static str {
+    match function & !MODBUS_EXCEPTION_FLAG {
+        0x01 => "Read Coils",
+        0x02 => "Read Discrete Inputs",
+        0x03 => "Read Holding Registers",
+        0x04 => "Read Input Registers",
+        0x05 => "Write Single Coil",
+        0x06 => "Write Single Register",
+        0x07 => "Read Exception Status",
+        0x08 => "Diagnostics",
+        0x0B => "Get Comm Event Counter",
+        0x0C => "Get Comm Event Log",
+        0x0F => "Write Multiple Coils",
+        0x10 => "Write Multiple Registers",
+        0x11 => "Report Server ID",
+        0x14 => "Read File Record",
+        0x15 => "Write File Record",
+        0x16 => "Mask Write Register",
+        0x17 => "Read/Write Multiple Registers",
+        0x18 => "Read FIFO Queue",
+        0x2B => "Encapsulated Interface Transport",
+        _ => "Unknown Function"
+    }
+}
+
+fn get_exception_name(exception_code: u8) -> &'static str {
+    match exception_code {
+        0x01 => "Illegal Function",
+        0x02 => "Illegal Data Address",
+        0x03 => "Illegal Data Value",
+        0x04 => "Server Device Failure",
+        0x05 => "Acknowledge",
+        0x06 => "Server Device Busy",
+        0x08 => "Memory Parity Error",
+        0x0A => "Gateway Path Unavailable",
+        0x0B => "Gateway Target Device Failed to Respond",
+        _ => "Unknown Exception"
+    }
+}
+
+fn get_crc16(data: &[u8]) -> u16 {
+    /* CRC-16/MODBUS, transmitted Low Byte first */
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 0x0001 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+    }
+
+    crc
+}
+
+fn read_u16(frame: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([frame[offset], frame[offset + 1]])
+}
+
+fn format_registers(start: Option<u16>, data: &[u8]) -> String {
+    /* Register Addresses come from the paired Request */
+    data.chunks_exact(2)
+        .enumerate()
+        .map(|(index, register)| match start {
+            Some(start) => format!("0x{:04X}=0x{:04X}", start.wrapping_add(index as u16), u16::from_be_bytes([register[0], register[1]])),
+            None => format!("0x{:04X}", u16::from_be_bytes([register[0], register[1]])),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn format_bits(start: Option<u16>, quantity: Option<u16>, data: &[u8]) -> String {
+    let bit_count = quantity.map(|quantity| quantity as usize).unwrap_or(data.len() * 8).min(data.len() * 8);
+    (0..bit_count)
+        .map(|index| {
+            let bit = (data[index / 8] >> (index % 8)) & 0x01;
+            match start {
+                Some(start) => format!("0x{:04X}={}", start.wrapping_add(index as u16), bit),
+                None => bit.to_string(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn get_device_id_length(buffer: &[u8]) -> Option<usize> {
+    /* Read Device Identification lists its Objects as Id, Length and Value after a 6 Byte Header */
+    let object_count = *buffer.get(7)?;
+    let mut offset = 8;
+    for _ in 0..object_count {
+        if offset > MODBUS_MAX_FRAME_LENGTH {
+            break;
+        }
+
+        offset += 2 + *buffer.get(offset + 1)? as usize;
+    }
+
+    Some(offset + 2)
+}
+
+pub fn get_frame_length(buffer: &[u8], is_request: bool) -> Result<Option<usize>, String> {
+    /* Ok(None) waits for more bytes, Err means the Function cannot be framed */
+    if buffer.len() < 2 {
+        return Ok(None);
+    }
+
+    let function = buffer[1];
+    let byte_at = |offset: usize| buffer.get(offset).map(|byte| *byte as usize);
+    let frame_length = match (is_request, function) {
+        (false, function) if function & MODBUS_EXCEPTION_FLAG != 0 => Some(5),
+        (true, 0x01..=0x06) | (true, 0x08) => Some(8),
+        (true, 0x07) | (true, 0x0B) | (true, 0x0C) | (true, 0x11) => Some(4),
+        (true, 0x0F) | (true, 0x10) => byte_at(6).map(|byte_count| 9 + byte_count),
+        (true, 0x14) | (true, 0x15) => byte_at(2).map(|byte_count| 5 + byte_count),
+        (true, 0x16) => Some(10),
+        (true, 0x17) => byte_at(10).map(|byte_count| 13 + byte_count),
+        (true, 0x18) => Some(6),
+        (true, 0x2B) => Some(7),
+        (false, 0x01..=0x04) | (false, 0x0C) | (false, 0x11) | (false, 0x14) | (false, 0x15) | (false, 0x17) => byte_at(2).map(|byte_count| 5 + byte_count),
+        (false, 0x05) | (false, 0x06) | (false, 0x08) | (false, 0x0B) | (false, 0x0F) | (false, 0x10) => Some(8),
+        (false, 0x07) => Some(5),
+        (false, 0x16) => Some(10),
+        (false, 0x18) => (buffer.len() >= 4).then(|| 6 + read_u16(buffer, 2) as usize),
+        (false, 0x2B) => match byte_at(2).map(|mei_type| mei_type as u8) {
+            Some(MEI_READ_DEVICE_ID) => get_device_id_length(buffer),
+            Some(mei_type) => return Err(format!("{} MEI Type 0x{:02X}", get_function_name(function), mei_type)),
+            None => None,
+        },
+        _ => return Err(format!("{} 0x{:02X}", get_function_name(function), function))
+    };
+
+    match frame_length {
+        Some(frame_length) if frame_length > MODBUS_MAX_FRAME_LENGTH => Err(format!("Frame Length {} exceeds the ADU Limit", frame_length)),
+        Some(frame_length) if buffer.len() >= frame_length => Ok(Some(frame_length)),
+        _ => Ok(None)
+    }
+}
+
+pub fn is_crc_valid(frame: &[u8]) -> bool {
+    let payload_length = frame.len() - 2;
+    get_crc16(&frame[..payload_length]) == u16::from_le_bytes([frame[payload_length], frame[payload_length + 1]])
+}
+
+fn get_crc_status(frame: &[u8]) -> &'static str {
+    match is_crc_valid(frame) {
+        true => "CRC OK",
+        false => "CRC ERROR",
+    }
+}
+
+pub fn is_error_frame(frame: &[u8], is_request: bool) -> bool {
+    /* Exceptions only travel in Responses */
+    !is_crc_valid(frame) || (!is_request && frame[1] & MODBUS_EXCEPTION_FLAG != 0)
+}
+
+pub fn describe_request(frame: &[u8], timestamp: u64) -> (String, Option<ModbusRequest>) {
+    /* frame holds a complete ADU as sized by get_frame_length */
+    let (address, function) = (frame[0], frame[1]);
+    let data = &frame[2..(frame.len() - 2)];
+    let mut modbus_request = ModbusRequest { address, function, start: 0, quantity: 0, timestamp };
+
+    let details = match function {
+        0x01..=0x04 => {
+            modbus_request.start = read_u16(data, 0);
+            modbus_request.quantity = read_u16(data, 2);
+            format!("@0x{:04X} x{}", modbus_request.start, modbus_request.quantity)
+        },
+        0x05 => {
+            modbus_request.start = read_u16(data, 0);
+            modbus_request.quantity = 1;
+            format!("@0x{:04X} = {}", modbus_request.start, match read_u16(data, 2) { 0xFF00 => "ON", 0x0000 => "OFF", _ => "INVALID" })
+        },
+        0x06 => {
+            modbus_request.start = read_u16(data, 0);
+            modbus_request.quantity = 1;
+            format!("@0x{:04X} = 0x{:04X}", modbus_request.start, read_u16(data, 2))
+        },
+        0x0F => {
+            modbus_request.start = read_u16(data, 0);
+            modbus_request.quantity = read_u16(data, 2);
+            format!("@0x{:04X} x{} = {}", modbus_request.start, modbus_request.quantity, format_bits(None, Some(modbus_request.quantity), &data[5..]))
+        },
+        0x10 => {
+            modbus_request.start = read_u16(data, 0);
+            modbus_request.quantity = read_u16(data, 2);
+            format!("@0x{:04X} x{} = {}", modbus_request.start, modbus_request.quantity, format_registers(None, &data[5..]))
+        },
+        0x16 => format!("@0x{:04X} AND 0x{:04X} OR 0x{:04X}", read_u16(data, 0), read_u16(data, 2), read_u16(data, 4)),
+        0x17 => {
+            modbus_request.start = read_u16(data, 0);
+            modbus_request.quantity = read_u16(data, 2);
+            format!(
+                "Read @0x{:04X} x{}, Write @0x{:04X} x{} = {}",
+                modbus_request.start, modbus_request.quantity, read_u16(data, 4), read_u16(data, 6), format_registers(None, &data[9..])
+            )
+        },
+        0x08 => format!("Sub-function 0x{:04X} Data 0x{:04X}", read_u16(data, 0), read_u16(data, 2)),
+        0x18 => format!("@0x{:04X}", read_u16(data, 0)),
+        _ if data.is_empty() => String::new(),
+        _ => format_hex(data),
+    };
+
+    let description = format!(
+        "{} {}{}{}, {}",
+        if address == MODBUS_BROADCAST_ADDRESS { String::from("Broadcast") } else { format!("Server {}", address) },
+        get_function_name(function),
+        if details.is_empty() { "" } else { " " },
+        details,
+        get_crc_status(frame)
+    );
+
+    (description, (address != MODBUS_BROADCAST_ADDRESS).then_some(modbus_request))
+}
+
+pub fn describe_response(frame: &[u8], timestamp: u64, modbus_request: Option<&ModbusRequest>) -> String {
+    let (address, function) = (frame[0], frame[1]);
+    let data = &frame[2..(frame.len() - 2)];
+
+    /* Responses echo the Server Address and Function of their Request */
+    let modbus_request = modbus_request.filter(|request| request.address == address && request.function == function & !MODBUS_EXCEPTION_FLAG);
+    let start = modbus_request.map(|request| request.start);
+    let details = match function {
+        _ if function & MODBUS_EXCEPTION_FLAG != 0 => {
+            format!("Exception 0x{:02X} {}", data[0], get_exception_name(data[0]))
+        },
+        0x01 | 0x02 => format_bits(start, modbus_request.map(|request| request.quantity), &data[1..]),
+        0x03 | 0x04 | 0x17 => format_registers(start, &data[1..]),
+        0x05 => format!("@0x{:04X} = {}", read_u16(data, 0), match read_u16(data, 2) { 0xFF00 => "ON", _ => "OFF" }),
+        0x06 => format!("@0x{:04X} = 0x{:04X}", read_u16(data, 0), read_u16(data, 2)),
+        0x0F | 0x10 => format!("@0x{:04X} x{} written", read_u16(data, 0), read_u16(data, 2)),
+        0x07 => format!("Status 0x{:02X}", data[0]),
+        0x0B => format!("Status 0x{:04X} Events {}", read_u16(data, 0), read_u16(data, 2)),
+        0x18 => format_registers(None, data.get(4..).unwrap_or_default()),
+        _ if data.is_empty() => String::new(),
+        _ => format_hex(data),
+    };
+
+    let response_time = match modbus_request {
+        Some(request) => format!(" (+{:.3} ms)", timestamp.saturating_sub(request.timestamp) as f64 / 1000.0),
+        None => String::from(" (Unsolicited)"),
+    };
+
+    format!(
+        "Server {} {}{}{}, {}{}",
+        address,
+        get_function_name(function),
+        if details.is_empty() { "" } else { ": " },
+        details,
+        get_crc_status(frame),
+        response_time
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_read_holding_registers() {
+        let request = [0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCD];
+        let response = [0x01, 0x03, 0x04, 0x00, 0x0A, 0x00, 0x0B, 0x9B, 0xF6];
+        assert_eq!(get_crc16(&request[..6]), 0xCDC5);
+        assert_eq!(get_frame_length(&request, true), Ok(Some(8)));
+        assert_eq!(get_frame_length(&response, false), Ok(Some(9)));
+
+        let (description, modbus_request) = describe_request(&request, 1_000);
+        assert_eq!(description, "Server 1 Read Holding Registers @0x0000 x10, CRC OK");
+        let description = describe_response(&response, 3_500, modbus_request.as_ref());
+        assert_eq!(description, "Server 1 Read Holding Registers: 0x0000=0x000A 0x0001=0x000B, CRC OK (+2.500 ms)");
+        assert!(!is_error_frame(&response, false));
+    }
+
+    #[test]
+    fn frames_read_device_identification() {
+        let response = [0x01, 0x2B, 0x0E, 0x01, 0x01, 0x00, 0x00, 0x01, 0x00, 0x03, b'A', b'B', b'C', 0x2D, 0x63];
+        assert_eq!(get_frame_length(&response, false), Ok(Some(15)));
+        assert!(is_crc_valid(&response));
+        assert!(get_frame_length(&[0x01, 0x2B, 0x0D, 0x00], false).is_err());
+    }
+
+    #[test]
+    fn waits_for_truncated_frames() {
+        let response = [0x01, 0x03, 0x04, 0x00, 0x0A, 0x00, 0x0B, 0x9B, 0xF6];
+        for length in 0..response.len() {
+            assert_eq!(get_frame_length(&response[..length], false), Ok(None));
+        }
+
+        let response = [0x01, 0x2B, 0x0E, 0x01, 0x01, 0x00, 0x00, 0x01, 0x00, 0x03, b'A', b'B', b'C', 0x2D, 0x63];
+        for length in 0..response.len() {
+            assert_eq!(get_frame_length(&response[..length], false), Ok(None));
+        }
+
+        assert!(!is_crc_valid(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCE]));
+    }
+}
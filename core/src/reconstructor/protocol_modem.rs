@@ -0,0 +1,320 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use tokio::sync::mpsc::Sender;
+use crate::sniffer::{UrbTransferType, UrbXractHeader, UrbXractPacket};
+
+use super::device_model::{self, InterfaceModel, SetupPacket};
+use super::protocol_cdc::{self, LineState};
+use super::protocol_mbim::{self, MbimMessage};
+use super::protocol_qmi::{self, QmiKind, QmuxMessage};
+use super::protocol_control::{self, PendingRequests};
+use super::protocol_ncm;
+use super::{ModuleContext, ReconstructedTransmission, ReconstructionModule};
+
+/* Define Constants */
+const CLASS_CDC: u8 = 0x02;
+const CLASS_VENDOR: u8 = 0xFF;
+const SUBCLASS_MBIM: u8 = 0x0E;
+const REQUEST_SEND_ENCAPSULATED_COMMAND: u8 = 0x00;
+const REQUEST_GET_ENCAPSULATED_RESPONSE: u8 = 0x01;
+const MBIM_CID_QMI_MSG: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModemKind {
+    Mbim,
+    Qmi
+}
+
+pub struct Reconstructor {
+    module_tx: Sender<ReconstructedTransmission>,
+    module_context: ModuleContext,
+    pending_requests: PendingRequests, /* Control Requests awaiting Completion */
+    line_states: HashMap<String, LineState>, /* Bus:Device, Notification State */
+    mbim_fragments: HashMap<(String, u32, u32), MbimMessage>, /* (Bus:Device, Message Type, Transaction ID) */
+    mbim_requests: HashMap<(String, u32), (MbimMessage, u64)>, /* (Bus:Device, Transaction ID), Request and Timestamp */
+    qmi_requests: HashMap<(String, u8, u8, u16), (QmuxMessage, u64)>, /* (Bus:Device, Service, Client, Transaction ID) */
+}
+
+pub fn get_modem_kind(interface: &InterfaceModel) -> Option<ModemKind> {
+    /* QMI Modems expose Vendor Interfaces with CDC Encapsulated Commands */
+    match (interface.class, interface.subclass) {
+        (CLASS_CDC, SUBCLASS_MBIM) => Some(ModemKind::Mbim),
+        (CLASS_VENDOR, _) => Some(ModemKind::Qmi),
+        _ => None,
+    }
+}
+
+pub fn is_modem_request(interface: &InterfaceModel, setup_packet: &SetupPacket) -> bool {
+    match get_modem_kind(interface) {
+        Some(ModemKind::Mbim) => true,
+        Some(ModemKind::Qmi) => matches!(setup_packet.request, REQUEST_SEND_ENCAPSULATED_COMMAND | REQUEST_GET_ENCAPSULATED_RESPONSE),
+        None => false,
+    }
+}
+
+fn format_elapsed(request_timestamp: u64, timestamp: u64) -> String {
+    format!(" ({:.3} ms)", timestamp.saturating_sub(request_timestamp) as f64 / 1000.0)
+}
+
+fn describe_qmux(qmux_message: &QmuxMessage) -> String {
+    let message_name = protocol_qmi::get_message_name(qmux_message.service, qmux_message.message_id);
+    match qmux_message.kind {
+        QmiKind::Request => format!("{} Tx {} {}{}", qmux_message.get_label(), qmux_message.transaction_id, message_name, qmux_message.describe_tlvs()),
+        QmiKind::Response => format!("{} Tx {} {} -> {}{}", qmux_message.get_label(), qmux_message.transaction_id, message_name, qmux_message.describe_result(), qmux_message.describe_tlvs()),
+        QmiKind::Indication => format!("{} Indication {}{}", qmux_message.get_label(), message_name, qmux_message.describe_tlvs()),
+    }
+}
+
+fn describe_mbim_information(mbim_message: &MbimMessage) -> Option<String> {
+    /* QMI Messages tunnelled through the MBIM QMI Service */
+    if mbim_message.service == protocol_mbim::SERVICE_QMI && mbim_message.cid == MBIM_CID_QMI_MSG && let Ok(qmux_message) = protocol_qmi::parse_qmux(&mbim_message.information_buffer) {
+        return Some(describe_qmux(&qmux_message));
+    }
+
+    protocol_mbim::describe_information(&mbim_message.service, mbim_message.cid, &mbim_message.information_buffer)
+}
+
+fn describe_mbim_request(mbim_message: &MbimMessage) -> String {
+    match mbim_message.message_type {
+        protocol_mbim::MESSAGE_OPEN => format!("OPEN Max Control Transfer {}", mbim_message.value),
+        protocol_mbim::MESSAGE_COMMAND => format!(
+            "{} {}{}",
+            if mbim_message.value == 1 { "SET" } else { "QUERY" },
+            protocol_mbim::get_cid_name(&mbim_message.service, mbim_message.cid),
+            describe_mbim_information(mbim_message).map_or(String::new(), |information| format!(": {}", information))
+        ),
+        message_type => protocol_mbim::get_message_name(message_type),
+    }
+}
+
+impl Reconstructor {
+    fn get_kind(&self, urb_header: &UrbXractHeader, interface_number: Option<u8>) -> Option<ModemKind> {
+        let device_registry = self.module_context.device_registry.read().unwrap();
+        let interface = match interface_number {
+            Some(interface_number) => device_registry.get_device(urb_header)?.get_interface_by_number(interface_number)?,
+            None => device_registry.get_interface(urb_header)?,
+        };
+
+        get_modem_kind(interface)
+    }
+
+    async fn dispatch_row(&mut self, urb_packet: UrbXractPacket, combined_payload: String, is_error: bool) {
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_packet.header,
+            combined_payload,
+            sources: vec![urb_packet],
+            is_error,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    fn collect_fragments(&mut self, device_key: &str, mbim_message: MbimMessage) -> Result<Option<MbimMessage>, String> {
+        /* The first Fragment holds the Header, later Fragments extend its Information Buffer */
+        if mbim_message.total_fragments <= 1 {
+            return Ok(Some(mbim_message));
+        }
+
+        let fragment_key = (String::from(device_key), mbim_message.message_type, mbim_message.transaction_id);
+        if mbim_message.current_fragment == 0 {
+            self.mbim_fragments.insert(fragment_key, mbim_message);
+            return Ok(None);
+        }
+
+        let Some(first_fragment) = self.mbim_fragments.get_mut(&fragment_key) else {
+            return Err(format!("Fragment {} of {} without its first Fragment", mbim_message.current_fragment + 1, mbim_message.total_fragments));
+        };
+
+        first_fragment.information_buffer.extend_from_slice(&mbim_message.information_buffer);
+        if mbim_message.current_fragment + 1 < mbim_message.total_fragments {
+            return Ok(None);
+        }
+
+        Ok(self.mbim_fragments.remove(&fragment_key))
+    }
+
+    fn describe_mbim(&mut self, urb_header: &UrbXractHeader, data: &[u8]) -> Option<(String, bool)> {
+        let device_key = device_model::get_device_key(urb_header);
+        let mbim_message = match protocol_mbim::parse_message(data) {
+            Ok(mbim_message) => mbim_message,
+            Err(error) => return Some((format!("[MBIM] Invalid Message: {}", error), true)),
+        };
+
+        let transaction_id = mbim_message.transaction_id;
+        let mbim_message = match self.collect_fragments(&device_key, mbim_message) {
+            Ok(Some(mbim_message)) => mbim_message,
+            Ok(None) => return None,
+            Err(error) => return Some((format!("[MBIM Tx {}] {}", transaction_id, error), true)),
+        };
+
+        /* Requests wait for the Completion with their Transaction ID */
+        match mbim_message.message_type {
+            protocol_mbim::MESSAGE_OPEN | protocol_mbim::MESSAGE_CLOSE | protocol_mbim::MESSAGE_COMMAND => {
+                self.mbim_requests.insert((device_key, transaction_id), (mbim_message, urb_header.timestamp));
+                None
+            },
+
+            protocol_mbim::MESSAGE_OPEN_DONE | protocol_mbim::MESSAGE_CLOSE_DONE | protocol_mbim::MESSAGE_COMMAND_DONE => {
+                let information = match mbim_message.message_type {
+                    protocol_mbim::MESSAGE_COMMAND_DONE => describe_mbim_information(&mbim_message).map_or(String::new(), |information| format!(": {}", information)),
+                    _ => String::new(),
+                };
+
+                let request = match self.mbim_requests.remove(&(device_key, transaction_id)) {
+                    Some((request, request_timestamp)) => format!("{}{}", describe_mbim_request(&request), format_elapsed(request_timestamp, urb_header.timestamp)),
+                    None => protocol_mbim::get_message_name(mbim_message.message_type),
+                };
+
+                let status = protocol_mbim::get_status_name(mbim_message.value);
+                Some((format!("[MBIM Tx {}] {} -> {}{}", transaction_id, request, status, information), mbim_message.value != 0))
+            },
+
+            protocol_mbim::MESSAGE_INDICATE_STATUS => Some((format!(
+                "[MBIM] INDICATE_STATUS {}{}",
+                protocol_mbim::get_cid_name(&mbim_message.service, mbim_message.cid),
+                describe_mbim_information(&mbim_message).map_or(String::new(), |information| format!(": {}", information))
+            ), false)),
+
+            /* Errors end the Transaction they name */
+            protocol_mbim::MESSAGE_HOST_ERROR | protocol_mbim::MESSAGE_FUNCTION_ERROR => {
+                let request = self.mbim_requests
+                    .remove(&(device_key, transaction_id))
+                    .map_or(String::new(), |(request, _)| format!(" for {}", describe_mbim_request(&request)));
+
+                Some((format!(
+                    "[MBIM Tx {}] {} {}{}",
+                    transaction_id,
+                    protocol_mbim::get_message_name(mbim_message.message_type),
+                    protocol_mbim::get_protocol_error_name(mbim_message.value),
+                    request
+                ), true))
+            },
+
+            message_type => Some((format!("[MBIM Tx {}] {}", transaction_id, protocol_mbim::get_message_name(message_type)), false)),
+        }
+    }
+
+    fn describe_qmi(&mut self, urb_header: &UrbXractHeader, data: &[u8]) -> Option<(String, bool)> {
+        let qmux_message = match protocol_qmi::parse_qmux(data) {
+            Ok(qmux_message) => qmux_message,
+            Err(error) => return Some((format!("[QMI] Invalid QMUX: {}", error), true)),
+        };
+
+        /* Requests wait for the Response from the same Client with their Transaction ID */
+        let device_key = device_model::get_device_key(urb_header);
+        let request_key = (device_key, qmux_message.service, qmux_message.client_id, qmux_message.transaction_id);
+        let message_name = protocol_qmi::get_message_name(qmux_message.service, qmux_message.message_id);
+        match qmux_message.kind {
+            QmiKind::Request => {
+                self.qmi_requests.insert(request_key, (qmux_message, urb_header.timestamp));
+                None
+            },
+
+            QmiKind::Response => {
+                let request = match self.qmi_requests.remove(&request_key) {
+                    Some((request, request_timestamp)) => format!("{}{}{}", message_name, request.describe_tlvs(), format_elapsed(request_timestamp, urb_header.timestamp)),
+                    None => message_name,
+                };
+
+                Some((format!(
+                    "[{} Tx {}] {} -> {}{}",
+                    qmux_message.get_label(),
+                    qmux_message.transaction_id,
+                    request,
+                    qmux_message.describe_result(),
+                    qmux_message.describe_tlvs()
+                ), qmux_message.is_failure()))
+            },
+
+            QmiKind::Indication => Some((format!("[{}] Indication {}{}", qmux_message.get_label(), message_name, qmux_message.describe_tlvs()), false)),
+        }
+    }
+
+    async fn consume_control(&mut self, mut urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let Some((setup_packet, data)) = self.pending_requests.pair(&mut urb_packet) else { return };
+        let modem_kind = self.get_kind(&urb_header, Some(setup_packet.index as u8));
+        let label = match modem_kind {
+            Some(ModemKind::Mbim) => "MBIM",
+            _ => "QMI",
+        };
+
+        if urb_header.status != 0 {
+            let description = format!("[{}] {} -> {}", label, protocol_control::describe_setup(&setup_packet, Some(CLASS_CDC)), protocol_control::describe_status(&urb_header));
+            return self.dispatch_row(urb_packet, description, true).await;
+        }
+
+        /* Encapsulated Commands carry the Messages, other MBIM Requests are NCM Requests */
+        let is_encapsulated = matches!(setup_packet.request, REQUEST_SEND_ENCAPSULATED_COMMAND | REQUEST_GET_ENCAPSULATED_RESPONSE);
+        let described = match (modem_kind, data.filter(|data| !data.is_empty())) {
+            (Some(ModemKind::Mbim), Some(data)) if is_encapsulated => match self.describe_mbim(&urb_header, data) {
+                Some(described) => Some(described),
+                None => return,
+            },
+
+            (Some(ModemKind::Qmi), Some(data)) if is_encapsulated => match self.describe_qmi(&urb_header, data) {
+                Some(described) => Some(described),
+                None => return,
+            },
+
+            (Some(ModemKind::Mbim), _) => protocol_ncm::describe_request(&setup_packet, data).map(|description| (format!("[MBIM] {}", description), false)),
+            _ => None,
+        };
+
+        let (description, is_error) = described.unwrap_or_else(|| (protocol_control::describe_setup(&setup_packet, Some(CLASS_CDC)), false));
+        self.dispatch_row(urb_packet, description, is_error).await;
+    }
+
+    async fn consume_notification(&mut self, urb_packet: UrbXractPacket) {
+        /* RESPONSE_AVAILABLE announces the next Encapsulated Response */
+        let urb_data = urb_packet.data.as_deref().unwrap_or_default();
+        let label = match self.get_kind(&urb_packet.header, None) {
+            Some(ModemKind::Mbim) => "MBIM",
+            _ => "QMI",
+        };
+
+        let line_state = self.line_states.entry(device_model::get_device_key(&urb_packet.header)).or_default();
+        if let Some(description) = protocol_cdc::describe_notification(line_state, urb_data) {
+            self.dispatch_row(urb_packet, format!("[{}] {}", label, description), false).await;
+        }
+    }
+}
+
+impl ReconstructionModule for Reconstructor {
+    fn new(module_tx: Sender<ReconstructedTransmission>, module_context: &ModuleContext) -> Self {
+        Self {
+            module_tx,
+            module_context: module_context.clone(),
+            pending_requests: PendingRequests::default(),
+            line_states: HashMap::new(),
+            mbim_fragments: HashMap::new(),
+            mbim_requests: HashMap::new(),
+            qmi_requests: HashMap::new(),
+        }
+    }
+
+    async fn consume_packet(&mut self, urb_packet: UrbXractPacket) {
+        match urb_packet.header.transfer_type {
+            UrbTransferType::Control => self.consume_control(urb_packet).await,
+            UrbTransferType::Interrupt if urb_packet.data.is_some() => self.consume_notification(urb_packet).await,
+            _ => {}
+        }
+    }
+}
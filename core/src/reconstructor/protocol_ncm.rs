@@ -0,0 +1,239 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::device_model::SetupPacket;
+
+/*
+    CDC NCM Transfer Blocks. The NTB Header points to a chain of Datagram
+    Pointer Tables, each listing Index and Length of the Ethernet Frames in
+    the Block. NTB-16 and NTB-32 differ only in their Field Widths
+*/
+const SIGNATURE_NTH16: &[u8; 4] = b"NCMH";
+const SIGNATURE_NTH32: &[u8; 4] = b"ncmh";
+const MAX_DATAGRAM_POINTERS: usize = 64; /* NDP Chains are walked at most this far */
+
+pub struct TransferBlock<'a> {
+    pub sequence: u16,
+    pub datagrams: Vec<&'a [u8]>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<usize> {
+    Some(u16::from_le_bytes([*data.get(offset)?, *data.get(offset + 1)?]) as usize)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as usize)
+}
+
+pub fn parse_transfer_block(data: &[u8]) -> Result<TransferBlock<'_>, String> {
+    let is_ntb32 = match data.get(0..4) {
+        Some(signature) if signature == SIGNATURE_NTH16 => false,
+        Some(signature) if signature == SIGNATURE_NTH32 => true,
+        _ => return Err(String::from("missing NTH Signature")),
+    };
+
+    /* NTH: Signature, Header Length, Sequence, Block Length, first NDP Index */
+    let read_field = if is_ntb32 { read_u32 } else { read_u16 };
+    let sequence = read_u16(data, 6).ok_or("truncated NTH")? as u16;
+    let header_fields = match is_ntb32 {
+        true => read_u32(data, 8).zip(read_u32(data, 12)),
+        false => read_u16(data, 8).zip(read_u16(data, 10)),
+    };
+
+    let (block_length, mut ndp_index) = header_fields.ok_or("truncated NTH")?;
+    if block_length > data.len() {
+        return Err(format!("Block Length {} exceeds Transfer of {} bytes", block_length, data.len()));
+    }
+
+    let block = &data[..block_length];
+    let mut datagrams = vec![];
+    for _ in 0..MAX_DATAGRAM_POINTERS {
+        if ndp_index == 0 {
+            break;
+        }
+
+        /* NDP: Signature, Length, then the next NDP Index and the Datagram Pointer Entries */
+        let ndp_length = read_u16(block, ndp_index + 4).ok_or("NDP outside the Block")?;
+        let (next_index, entries_offset) = match is_ntb32 {
+            true => (read_u32(block, ndp_index + 8), ndp_index + 16),
+            false => (read_u16(block, ndp_index + 6), ndp_index + 8),
+        };
+
+        let entry_size = if is_ntb32 { 8 } else { 4 };
+        let entries_end = (ndp_index + ndp_length).min(block.len());
+        let mut entry_offset = entries_offset;
+        while entry_offset + entry_size <= entries_end {
+            let datagram_index = read_field(block, entry_offset).unwrap_or_default();
+            let datagram_length = read_field(block, entry_offset + entry_size / 2).unwrap_or_default();
+            if datagram_index == 0 || datagram_length == 0 {
+                break;
+            }
+
+            match block.get(datagram_index..datagram_index + datagram_length) {
+                Some(datagram) => datagrams.push(datagram),
+                None => return Err(format!("Datagram at {} with {} bytes outside the Block", datagram_index, datagram_length)),
+            }
+
+            entry_offset += entry_size;
+        }
+
+        ndp_index = next_index.unwrap_or_default();
+    }
+
+    Ok(TransferBlock { sequence, datagrams })
+}
+
+pub fn describe_request(setup_packet: &SetupPacket, data: Option<&[u8]>) -> Option<String> {
+    /* ECM Filters and Statistics, NCM Transfer Block Parameters */
+    let data = data.unwrap_or_default();
+    let value = setup_packet.value;
+    match setup_packet.request {
+        0x40 => Some(format!("SET_ETHERNET_MULTICAST_FILTERS: {} Addresses", value)),
+        0x41 => Some(format!("SET_ETHERNET_POWER_MANAGEMENT_PATTERN_FILTER: Filter {}", value)),
+        0x42 => Some(format!("GET_ETHERNET_POWER_MANAGEMENT_PATTERN_FILTER: Filter {}", value)),
+        0x43 => {
+            let filter_names: Vec<&str> = [(0x01, "Promiscuous"), (0x02, "All Multicast"), (0x04, "Directed"), (0x08, "Broadcast"), (0x10, "Multicast")]
+                .iter()
+                .filter(|(filter_bit, _)| value & filter_bit != 0)
+                .map(|(_, filter_name)| *filter_name)
+                .collect();
+
+            Some(format!("SET_ETHERNET_PACKET_FILTER: {}", if filter_names.is_empty() { String::from("None") } else { filter_names.join(", ") }))
+        },
+
+        0x44 => Some(format!("GET_ETHERNET_STATISTIC: Feature {}{}", value, match read_u32(data, 0) {
+            Some(statistic) => format!(" = {}", statistic),
+            None => String::new(),
+        })),
+
+        /* NTB Parameters: Formats, In Max Size, Divisor, Remainder, Alignment, Out Max Size, ... */
+        0x80 if data.len() >= 28 => Some(format!(
+            "GET_NTB_PARAMETERS: {}, IN Max {} bytes, OUT Max {} bytes, OUT Max {} Datagrams",
+            if read_u16(data, 2).unwrap_or_default() & 0x02 != 0 { "NTB-16 and NTB-32" } else { "NTB-16" },
+            read_u32(data, 4).unwrap_or_default(),
+            read_u32(data, 16).unwrap_or_default(),
+            read_u16(data, 26).unwrap_or_default()
+        )),
+
+        0x80 => Some(String::from("GET_NTB_PARAMETERS")),
+        0x81 | 0x82 => Some(format!(
+            "{}_NET_ADDRESS{}",
+            if setup_packet.request == 0x81 { "GET" } else { "SET" },
+            match data.get(0..6) {
+                Some(address) => format!(": {}", address.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(":")),
+                None => String::new(),
+            }
+        )),
+
+        0x83 => Some(format!("GET_NTB_FORMAT{}", match read_u16(data, 0) {
+            Some(format) => format!(": NTB-{}", if format == 1 { 32 } else { 16 }),
+            None => String::new(),
+        })),
+
+        0x84 => Some(format!("SET_NTB_FORMAT: NTB-{}", if value == 1 { 32 } else { 16 })),
+        0x85 | 0x86 => Some(format!(
+            "{}_NTB_INPUT_SIZE{}",
+            if setup_packet.request == 0x85 { "GET" } else { "SET" },
+            match read_u32(data, 0) {
+                Some(input_size) => format!(": {} bytes", input_size),
+                None => String::new(),
+            }
+        )),
+
+        0x87 => Some(format!("GET_MAX_DATAGRAM_SIZE{}", match read_u16(data, 0) {
+            Some(datagram_size) => format!(": {} bytes", datagram_size),
+            None => String::new(),
+        })),
+
+        0x88 => Some(format!("SET_MAX_DATAGRAM_SIZE: {} bytes", read_u16(data, 0).unwrap_or_default())),
+        0x89 => Some(format!("GET_CRC_MODE{}", match read_u16(data, 0) {
+            Some(crc_mode) => format!(": {}", if crc_mode != 0 { "CRC appended" } else { "No CRC" }),
+            None => String::new(),
+        })),
+
+        0x8A => Some(format!("SET_CRC_MODE: {}", if value != 0 { "CRC appended" } else { "No CRC" })),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put_u16(block: &mut Vec<u8>, value: usize) {
+        block.extend_from_slice(&(value as u16).to_le_bytes());
+    }
+
+    fn put_u32(block: &mut Vec<u8>, value: usize) {
+        block.extend_from_slice(&(value as u32).to_le_bytes());
+    }
+
+    #[test]
+    fn parses_ntb16_with_chained_ndps() {
+        /* NTH16 at 0, NDPs at 12 and 28, Datagrams at 44 and 48 */
+        let mut block = b"NCMH".to_vec();
+        for value in [12, 7, 51, 12] {
+            put_u16(&mut block, value);
+        }
+        block.extend_from_slice(b"NCM0");
+        for value in [16, 28, 44, 4, 0, 0] {
+            put_u16(&mut block, value);
+        }
+        block.extend_from_slice(b"NCM0");
+        for value in [16, 0, 48, 3, 0, 0] {
+            put_u16(&mut block, value);
+        }
+        block.extend_from_slice(b"ABCDxyz");
+
+        let transfer_block = parse_transfer_block(&block).unwrap();
+        assert_eq!(transfer_block.sequence, 7);
+        assert_eq!(transfer_block.datagrams, [b"ABCD".as_slice(), b"xyz".as_slice()]);
+
+        /* Block Length beyond the Transfer, Datagram beyond the Block */
+        assert!(parse_transfer_block(&block[..50]).is_err());
+        block[8] = 50;
+        assert!(parse_transfer_block(&block).is_err());
+    }
+
+    #[test]
+    fn parses_ntb32_with_chained_ndps() {
+        /* NTH32 at 0, NDPs at 16 and 48, Datagrams at 80 and 84 */
+        let mut block = b"ncmh".to_vec();
+        put_u16(&mut block, 16);
+        put_u16(&mut block, 0x1234);
+        put_u32(&mut block, 87);
+        put_u32(&mut block, 16);
+        for (next_index, datagram_index, datagram_length) in [(48, 80, 4), (0, 84, 3)] {
+            block.extend_from_slice(b"ncm0");
+            put_u16(&mut block, 32);
+            put_u16(&mut block, 0);
+            for value in [next_index, 0, datagram_index, datagram_length, 0, 0] {
+                put_u32(&mut block, value);
+            }
+        }
+        block.extend_from_slice(b"ABCDxyz");
+
+        let transfer_block = parse_transfer_block(&block).unwrap();
+        assert_eq!(transfer_block.sequence, 0x1234);
+        assert_eq!(transfer_block.datagrams, [b"ABCD".as_slice(), b"xyz".as_slice()]);
+
+        /* A 16 Bit Reading of the same Block finds no Signature */
+        block[0..4].copy_from_slice(b"NCMX");
+        assert!(parse_transfer_block(&block).is_err());
+    }
+}
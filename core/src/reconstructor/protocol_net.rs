@@ -0,0 +1,293 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use tokio::sync::mpsc::Sender;
+use crate::sniffer::{UrbEventType, UrbTransferType, UrbXractHeader, UrbXractPacket};
+
+use super::device_model::{self, DeviceModel, InterfaceModel, SetupPacket};
+use super::ethernet_frame::{self, PcapWriter};
+use super::protocol_cdc::{self, LineState};
+use super::protocol_control::{self, PendingRequests};
+use super::{protocol_ncm, protocol_rndis};
+use super::{ModuleContext, ReconstructedTransmission, ReconstructionModule};
+
+/* Define Constants */
+const CLASS_CDC: u8 = 0x02;
+const CLASS_CDC_DATA: u8 = 0x0A;
+const DESCRIPTOR_CS_INTERFACE: u8 = 0x24;
+const SUBTYPE_UNION: u8 = 0x06;
+const REQUEST_SEND_ENCAPSULATED_COMMAND: u8 = 0x00;
+const REQUEST_GET_ENCAPSULATED_RESPONSE: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NetworkKind {
+    Ecm,
+    Ncm,
+    Rndis
+}
+
+pub struct Reconstructor {
+    module_tx: Sender<ReconstructedTransmission>,
+    module_context: ModuleContext,
+    pending_requests: PendingRequests, /* Control Requests awaiting Completion */
+    line_states: HashMap<String, LineState>, /* Bus:Device, Notification State */
+    rndis_requests: HashMap<(String, u32), protocol_rndis::ControlMessage>, /* (Bus:Device, Request ID) */
+    ntb_sequences: HashMap<(String, u8), u16>, /* (Bus:Device, Endpoint), last NTB Sequence */
+    pcap_writer: Option<PcapWriter>,
+    is_pcap_failed: bool,
+}
+
+impl NetworkKind {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            NetworkKind::Ecm => "ECM",
+            NetworkKind::Ncm => "NCM",
+            NetworkKind::Rndis => "RNDIS",
+        }
+    }
+}
+
+fn get_control_interface(device: &DeviceModel, data_interface: u8) -> Option<&InterfaceModel> {
+    /* The Union Functional Descriptor names the Data Interface, otherwise it follows its Control Interface */
+    let configuration = device.get_active_configuration()?;
+    configuration.interfaces
+        .iter()
+        .find(|interface| interface.extra_descriptors.iter().any(|descriptor| {
+            descriptor.len() >= 5 && descriptor[1] == DESCRIPTOR_CS_INTERFACE && descriptor[2] == SUBTYPE_UNION && descriptor[4..].contains(&data_interface)
+        }))
+        .or_else(|| device.get_interface_by_number(data_interface.checked_sub(1)?))
+}
+
+pub fn get_network_kind(device: &DeviceModel, interface: &InterfaceModel) -> Option<NetworkKind> {
+    /* Data Interfaces take the Networking Model of their Control Interface */
+    let control_interface = match interface.class {
+        CLASS_CDC_DATA => get_control_interface(device, interface.number)?,
+        _ => interface,
+    };
+
+    match (control_interface.class, control_interface.subclass, control_interface.protocol) {
+        (CLASS_CDC, 0x06, _) => Some(NetworkKind::Ecm),
+        (CLASS_CDC, 0x0D, _) => Some(NetworkKind::Ncm),
+        (CLASS_CDC, 0x02, 0xFF) | (0xE0, 0x01, 0x03) | (0xEF, 0x04, 0x01) => Some(NetworkKind::Rndis),
+        _ => None,
+    }
+}
+
+impl Reconstructor {
+    fn get_kind(&self, urb_header: &UrbXractHeader, interface_number: Option<u8>) -> Option<NetworkKind> {
+        let device_registry = self.module_context.device_registry.read().unwrap();
+        let device = device_registry.get_device(urb_header)?;
+        let interface = match interface_number {
+            Some(interface_number) => device.get_interface_by_number(interface_number)?,
+            None => device_registry.get_interface(urb_header)?,
+        };
+
+        get_network_kind(device, interface)
+    }
+
+    async fn dispatch_row(&mut self, urb_packet: UrbXractPacket, combined_payload: String, is_error: bool) {
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_packet.header,
+            combined_payload,
+            sources: vec![urb_packet],
+            is_error,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    fn write_pcap(&mut self, timestamp: u64, frame: &[u8]) -> Option<String> {
+        /* Opened with the first Frame, a failed Write stops the Export */
+        let pcap_path = self.module_context.ethernet_pcap.clone().filter(|_| !self.is_pcap_failed)?;
+        let result = match self.pcap_writer.as_mut() {
+            Some(pcap_writer) => pcap_writer.write_frame(timestamp, frame),
+            None => PcapWriter::create(&pcap_path, ethernet_frame::LINKTYPE_ETHERNET).and_then(|mut pcap_writer| {
+                pcap_writer.write_frame(timestamp, frame)?;
+                self.pcap_writer = Some(pcap_writer);
+                Ok(())
+            }),
+        };
+
+        match result {
+            Ok(_) => None,
+            Err(error) => {
+                self.is_pcap_failed = true;
+                self.pcap_writer = None;
+                Some(format!("[Ethernet] Export to {} stopped: {}", pcap_path.display(), error))
+            }
+        }
+    }
+
+    async fn consume_frames(&mut self, urb_packet: UrbXractPacket, network_kind: NetworkKind) {
+        let urb_header = urb_packet.header;
+        let urb_data = urb_packet.data.as_deref().unwrap_or_default();
+        let device_key = device_model::get_device_key(&urb_header);
+        let mut notes: Vec<(String, bool)> = vec![];
+
+        /* Unwrap the Transfer into Ethernet Frames */
+        let frames: Vec<&[u8]> = match network_kind {
+            NetworkKind::Ecm => vec![urb_data],
+            NetworkKind::Ncm => match protocol_ncm::parse_transfer_block(urb_data) {
+                Ok(transfer_block) => {
+                    let last_sequence = self.ntb_sequences.insert((device_key, urb_header.endpoint_info), transfer_block.sequence);
+                    if let Some(last_sequence) = last_sequence && transfer_block.sequence != last_sequence.wrapping_add(1) {
+                        notes.push((format!("[NCM] NTB Sequence {} after {}, {} Blocks missing", transfer_block.sequence, last_sequence, transfer_block.sequence.wrapping_sub(last_sequence).wrapping_sub(1)), true));
+                    }
+
+                    transfer_block.datagrams
+                },
+
+                Err(error) => {
+                    notes.push((format!("[NCM] Invalid NTB: {}", error), true));
+                    vec![]
+                }
+            },
+
+            NetworkKind::Rndis => protocol_rndis::parse_packets(urb_data).unwrap_or_else(|error| {
+                notes.push((format!("[RNDIS] Invalid PACKET_MSG: {}", error), true));
+                vec![]
+            }),
+        };
+
+        let mut rows: Vec<(UrbXractPacket, String, bool)> = vec![];
+        for frame in frames {
+            if let Some(export_error) = self.write_pcap(urb_header.timestamp, frame) {
+                notes.push((export_error, true));
+            }
+
+            /* Rows show the Frame, the Wrapper is in the URB */
+            let frame_packet = UrbXractPacket { header: urb_header, data: Some(frame.to_vec()), iso_descriptors: vec![] };
+            rows.push((frame_packet, format!("[{}] {}", network_kind.get_name(), ethernet_frame::describe_frame(frame)), false));
+        }
+
+        for (description, is_error) in notes {
+            rows.push((UrbXractPacket { header: urb_header, data: None, iso_descriptors: vec![] }, description, is_error));
+        }
+
+        for (source, description, is_error) in rows {
+            self.dispatch_row(source, description, is_error).await;
+        }
+    }
+
+    fn describe_encapsulated(&mut self, urb_header: &UrbXractHeader, setup_packet: &SetupPacket, data: &[u8]) -> Option<(String, bool)> {
+        /* RNDIS Messages: Commands wait for the Completion with their Request ID */
+        let control_message = protocol_rndis::parse_control_message(data)?;
+        let device_key = device_model::get_device_key(urb_header);
+        match (setup_packet.request, control_message.request_id) {
+            (REQUEST_SEND_ENCAPSULATED_COMMAND, Some(request_id)) if protocol_rndis::expects_completion(control_message.message_type) => {
+                self.rndis_requests.insert((device_key, request_id), control_message);
+                None
+            },
+
+            (REQUEST_GET_ENCAPSULATED_RESPONSE, Some(request_id)) if protocol_rndis::is_completion(control_message.message_type) => {
+                let is_error = control_message.status.is_some_and(|status| status != 0);
+                match self.rndis_requests.remove(&(device_key, request_id)) {
+                    Some(request) => Some((format!("[RNDIS] {}", protocol_rndis::describe_completion(&request, &control_message, data)), is_error)),
+                    None => Some((format!("[RNDIS] Request {} -> {}", request_id, control_message.summary), is_error)),
+                }
+            },
+
+            _ => Some((format!("[RNDIS] {}", control_message.summary), false)),
+        }
+    }
+
+    async fn consume_control(&mut self, mut urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let Some((setup_packet, data)) = self.pending_requests.pair(&mut urb_packet) else { return };
+        let network_kind = self.get_kind(&urb_header, Some(setup_packet.index as u8));
+        if urb_header.status != 0 {
+            let description = format!(
+                "[{}] {} -> {}",
+                network_kind.map_or("CDC", |network_kind| network_kind.get_name()),
+                protocol_control::describe_setup(&setup_packet, Some(CLASS_CDC)),
+                protocol_control::describe_status(&urb_header)
+            );
+
+            return self.dispatch_row(urb_packet, description, true).await;
+        }
+
+        let described = match (network_kind, setup_packet.request) {
+            (Some(NetworkKind::Rndis), REQUEST_SEND_ENCAPSULATED_COMMAND | REQUEST_GET_ENCAPSULATED_RESPONSE) => {
+                match data.filter(|data| !data.is_empty()) {
+                    Some(data) => match self.describe_encapsulated(&urb_header, &setup_packet, data) {
+                        Some(described) => Some(described),
+                        None => return,
+                    },
+                    None => None,
+                }
+            },
+
+            (Some(network_kind), _) => protocol_ncm::describe_request(&setup_packet, data)
+                .map(|description| (format!("[{}] {}", network_kind.get_name(), description), false)),
+
+            (None, _) => None,
+        };
+
+        let (description, is_error) = described.unwrap_or_else(|| (protocol_control::describe_setup(&setup_packet, Some(CLASS_CDC)), false));
+        self.dispatch_row(urb_packet, description, is_error).await;
+    }
+
+    async fn consume_notification(&mut self, urb_packet: UrbXractPacket) {
+        /* CDC Notifications, RNDIS signals RESPONSE_AVAILABLE with its own 8 Bytes */
+        let urb_data = urb_packet.data.as_deref().unwrap_or_default();
+        let line_state = self.line_states.entry(device_model::get_device_key(&urb_packet.header)).or_default();
+        let description = match urb_data {
+            [0x01, 0x00, 0x00, 0x00, ..] if urb_data.len() == 8 => Some(String::from("[RNDIS] RESPONSE_AVAILABLE")),
+            _ => protocol_cdc::describe_notification(line_state, urb_data).map(|description| format!("[CDC] {}", description)),
+        };
+
+        if let Some(description) = description {
+            self.dispatch_row(urb_packet, description, false).await;
+        }
+    }
+}
+
+impl ReconstructionModule for Reconstructor {
+    fn new(module_tx: Sender<ReconstructedTransmission>, module_context: &ModuleContext) -> Self {
+        Self {
+            module_tx,
+            module_context: module_context.clone(),
+            pending_requests: PendingRequests::default(),
+            line_states: HashMap::new(),
+            rndis_requests: HashMap::new(),
+            ntb_sequences: HashMap::new(),
+            pcap_writer: None,
+            is_pcap_failed: false,
+        }
+    }
+
+    async fn consume_packet(&mut self, urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        match urb_header.transfer_type {
+            UrbTransferType::Control => self.consume_control(urb_packet).await,
+            UrbTransferType::Interrupt if urb_packet.data.is_some() => self.consume_notification(urb_packet).await,
+            UrbTransferType::Bulk => {
+                /* OUT Frames travel with the Submission, IN Frames with the Completion, Zero Length Packets end Transfers */
+                if urb_packet.data.as_deref().is_none_or(|urb_data| urb_data.is_empty()) || (urb_header.event_type != UrbEventType::Submit && urb_header.status != 0) {
+                    return;
+                }
+
+                if let Some(network_kind) = self.get_kind(&urb_header, None) {
+                    self.consume_frames(urb_packet, network_kind).await;
+                }
+            },
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,213 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/*
+    NMEA-0183 Sentences from GPS Receivers: $TTSSS,field,...*HH
+    TT is the Talker (GP, GN, GL...), SSS the Sentence and HH the XOR of
+    everything between $ and *
+*/
+
+fn get_field<'a>(fields: &[&'a str], index: usize) -> &'a str {
+    fields.get(index).copied().unwrap_or_default()
+}
+
+fn format_time(time: &str) -> String {
+    /* hhmmss.ss */
+    match time.len() >= 6 && time.is_ascii() {
+        true => format!("{}:{}:{} UTC", &time[0..2], &time[2..4], &time[4..]),
+        false => String::from("--:--:--")
+    }
+}
+
+fn format_date(date: &str) -> String {
+    /* ddmmyy */
+    match date.len() == 6 && date.is_ascii() {
+        true => format!("{}/{}/{}", &date[0..2], &date[2..4], &date[4..6]),
+        false => String::from("--/--/--")
+    }
+}
+
+fn format_coordinate(value: &str, hemisphere: &str, degree_digits: usize) -> Option<String> {
+    /* ddmm.mmmm or dddmm.mmmm to Decimal Degrees */
+    if value.len() <= degree_digits || !value.is_char_boundary(degree_digits) {
+        return None;
+    }
+
+    let degrees = value[..degree_digits].parse::<f64>().ok()?;
+    let minutes = value[degree_digits..].parse::<f64>().ok()?;
+    Some(format!("{:.6}°{}", degrees + minutes / 60.0, hemisphere))
+}
+
+fn format_position(fields: &[&str], latitude_index: usize) -> String {
+    let latitude = format_coordinate(get_field(fields, latitude_index), get_field(fields, latitude_index + 1), 2);
+    let longitude = format_coordinate(get_field(fields, latitude_index + 2), get_field(fields, latitude_index + 3), 3);
+    match (latitude, longitude) {
+        (Some(latitude), Some(longitude)) => format!("{} {}", latitude, longitude),
+        _ => String::from("No Position")
+    }
+}
+
+fn get_fix_quality(quality: &str) -> &'static str {
+    match quality {
+        "0" => "No Fix",
+        "1" => "GPS Fix",
+        "2" => "DGPS Fix",
+        "3" => "PPS Fix",
+        "4" => "RTK Fixed",
+        "5" => "RTK Float",
+        "6" => "Estimated",
+        "7" => "Manual",
+        "8" => "Simulation",
+        _ => "Unknown Fix"
+    }
+}
+
+fn describe_fields(sentence: &str, fields: &[&str]) -> String {
+    /* fields[0] is the Address, e.g. GPGGA */
+    match sentence {
+        "GGA" => format!(
+            "Fix {} {} {}, {} Satellites, HDOP {}, Altitude {} m",
+            format_time(get_field(fields, 1)),
+            format_position(fields, 2),
+            get_fix_quality(get_field(fields, 6)),
+            get_field(fields, 7),
+            get_field(fields, 8),
+            get_field(fields, 9)
+        ),
+
+        "RMC" => format!(
+            "{} {} {} {}, {} kn, Course {}°",
+            if get_field(fields, 2) == "A" { "Valid" } else { "Void" },
+            format_date(get_field(fields, 9)),
+            format_time(get_field(fields, 1)),
+            format_position(fields, 3),
+            get_field(fields, 7),
+            get_field(fields, 8)
+        ),
+
+        "GLL" => format!(
+            "{} {} {}",
+            if get_field(fields, 6) == "A" { "Valid" } else { "Void" },
+            format_position(fields, 1),
+            format_time(get_field(fields, 5))
+        ),
+
+        "GSA" => {
+            let satellites: Vec<&str> = (3..15).map(|index| get_field(fields, index)).filter(|prn| !prn.is_empty()).collect();
+            format!(
+                "{} Fix, Satellites [{}], PDOP {} HDOP {} VDOP {}",
+                match get_field(fields, 2) { "2" => "2D", "3" => "3D", _ => "No" },
+                satellites.join(" "),
+                get_field(fields, 15),
+                get_field(fields, 16),
+                get_field(fields, 17)
+            )
+        },
+
+        "GSV" => {
+            /* Groups of PRN, Elevation, Azimuth and SNR */
+            let satellites: Vec<String> = fields.get(4..).unwrap_or_default()
+                .chunks(4)
+                .filter(|satellite| satellite.len() == 4 && !satellite[0].is_empty())
+                .map(|satellite| format!("{} El {}° Az {}° SNR {}", satellite[0], satellite[1], satellite[2], if satellite[3].is_empty() { "-" } else { satellite[3] }))
+                .collect();
+
+            format!(
+                "Message {}/{}, {} in View: {}",
+                get_field(fields, 2),
+                get_field(fields, 1),
+                get_field(fields, 3),
+                satellites.join(", ")
+            )
+        },
+
+        "VTG" => format!(
+            "Course {}° True, {} kn, {} km/h",
+            get_field(fields, 1),
+            get_field(fields, 5),
+            get_field(fields, 7)
+        ),
+
+        "ZDA" => format!(
+            "{} {}-{}-{}",
+            format_time(get_field(fields, 1)),
+            get_field(fields, 4),
+            get_field(fields, 3),
+            get_field(fields, 2)
+        ),
+
+        _ => fields.get(1..).unwrap_or_default().join(", ")
+    }
+}
+
+pub fn describe_sentence(row: &str, is_checksum_required: bool) -> Option<String> {
+    /* Rows that are not Sentences stay untouched */
+    let sentence = row.trim_end_matches(['\r', '\n']);
+    let body = sentence.strip_prefix('$').or_else(|| sentence.strip_prefix('!'))?;
+    let (body, checksum) = match body.split_once('*') {
+        Some((body, checksum)) => (body, Some(u8::from_str_radix(checksum.trim(), 16).ok()?)),
+        None if is_checksum_required => return None,
+        None => (body, None),
+    };
+
+    let fields: Vec<&str> = body.split(',').collect();
+    let address = fields[0];
+    if address.len() < 3 || !address.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    /* Proprietary Sentences have no Talker */
+    let (talker, sentence_type) = match address.starts_with('P') {
+        true => ("P", &address[1..]),
+        false => address.split_at(address.len().min(2)),
+    };
+
+    let checksum_status = match checksum {
+        Some(checksum) if body.bytes().fold(0u8, |value, byte| value ^ byte) == checksum => "Checksum OK",
+        Some(_) => "Checksum ERROR",
+        None => "No Checksum",
+    };
+
+    Some(format!(
+        "[NMEA {}{}] {}, {}",
+        talker,
+        sentence_type,
+        describe_fields(sentence_type, &fields),
+        checksum_status
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_gga_sentence() {
+        let description = describe_sentence("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47", true).unwrap();
+        assert_eq!(description, "[NMEA GPGGA] Fix 12:35:19 UTC 48.117300°N 11.516667°E GPS Fix, 08 Satellites, HDOP 0.9, Altitude 545.4 m, Checksum OK");
+    }
+
+    #[test]
+    fn rejects_non_ascii_time() {
+        /* Garbled Receiver Output at the wrong Baud Rate */
+        for sentence in ["$GPGGA,1é3456,,,,,0,,,,,,,,*00", "$GPRMC,12é456,V,,,,,,,,,,*00", "$GPGLL,,,,,1é3456,V*00", "$GPZDA,é23456,01,01,2024,,*00"] {
+            let description = describe_sentence(sentence, true).unwrap();
+            assert!(description.contains("--:--:--"), "{}", description);
+        }
+    }
+}
@@ -0,0 +1,381 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use tokio::sync::mpsc::Sender;
+use crate::sniffer::{UrbEventType, UrbTransferType, UrbXractHeader, UrbXractPacket};
+
+use super::device_model::{self, DeviceModel, InterfaceModel};
+use super::ptp_container::{self, ContainerHeader};
+use super::protocol_control::{self, PendingRequests};
+use super::{ModuleContext, ReconstructedTransmission, ReconstructionModule};
+
+/*
+    MTP and PTP Sessions run one Transaction at a time: a Command Container
+    on Bulk OUT, an optional Data Phase in either Direction spanning many
+    Transfers, then a Response Container on Bulk IN. Events arrive on
+    Interrupt IN
+*/
+const CLASS_STILL_IMAGE: u8 = 0x06;
+const CLASS_VENDOR: u8 = 0xFF;
+const MAX_DATASET_LENGTH: usize = 1 << 20; /* Data Phases kept for Decoding, Objects go to Files */
+const UNKNOWN_DATA_LENGTH: u32 = 0xFFFFFFFF; /* Objects beyond 4 GiB end with their Response */
+
+struct Transaction {
+    operation: u16,
+    transaction_id: u32,
+    parameters: Vec<u32>,
+    timestamp: u64,
+    dataset: Vec<u8>,
+    data_length: u64,
+    object_file: Option<(PathBuf, File)>,
+    export_error: Option<String>,
+}
+
+pub struct Reconstructor {
+    module_tx: Sender<ReconstructedTransmission>,
+    module_context: ModuleContext,
+    pending_requests: PendingRequests, /* Control Requests awaiting Completion */
+    transactions: HashMap<String, Transaction>, /* Bus:Device, open Transaction */
+    data_phases: HashMap<(String, u8), u64>, /* (Bus:Device, Endpoint), remaining Data Bytes */
+    object_names: HashMap<(String, u32), String>, /* (Bus:Device, Object Handle), Filename */
+    sent_object_names: HashMap<String, String>, /* Bus:Device, Filename announced by SendObjectInfo */
+}
+
+pub fn is_mtp_interface(device: &DeviceModel, interface: &InterfaceModel) -> bool {
+    /* Android announces MTP on a Vendor Interface named by its String Descriptor */
+    match interface.class {
+        CLASS_STILL_IMAGE => interface.subclass == 0x01 && interface.protocol == 0x01,
+        CLASS_VENDOR => device.get_string(interface.string_index) == "MTP",
+        _ => false,
+    }
+}
+
+fn get_safe_filename(filename: &str) -> String {
+    /* Filenames come from the Device, keep them inside the Directory */
+    let safe_filename: String = filename
+        .chars()
+        .map(|character| if matches!(character, '/' | '\\' | ':') || character.is_control() { '_' } else { character })
+        .collect();
+
+    /* Anything but a single plain Component (Root, Prefix, "..") could escape the Join */
+    let mut components = Path::new(&safe_filename).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if !safe_filename.trim_matches('.').is_empty() => safe_filename,
+        _ => String::from("object"),
+    }
+}
+
+fn get_object_path(object_directory: &Path, filename: &str) -> PathBuf {
+    /* Repeated Names are numbered instead of overwritten */
+    let file_path = object_directory.join(filename);
+    if !file_path.exists() {
+        return file_path;
+    }
+
+    let (stem, extension) = match filename.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (filename, String::new()),
+    };
+
+    (1..)
+        .map(|index| object_directory.join(format!("{} ({}){}", stem, index, extension)))
+        .find(|file_path| !file_path.exists())
+        .unwrap()
+}
+
+impl Transaction {
+    fn is_object_transfer(&self) -> bool {
+        matches!(self.operation, ptp_container::OPERATION_GET_OBJECT | ptp_container::OPERATION_SEND_OBJECT)
+    }
+
+    fn describe(&self) -> String {
+        format!("{}{}", ptp_container::get_operation_name(self.operation), ptp_container::format_parameters(&self.parameters))
+    }
+}
+
+impl Reconstructor {
+    async fn dispatch_row(&mut self, urb_packet: UrbXractPacket, combined_payload: String, is_error: bool) {
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_packet.header,
+            combined_payload,
+            sources: vec![urb_packet],
+            is_error,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    fn get_object_name(&self, device_key: &str, transaction: &Transaction) -> String {
+        /* GetObjectInfo or SendObjectInfo named the Object earlier in the Session */
+        let object_name = match transaction.operation {
+            ptp_container::OPERATION_SEND_OBJECT => self.sent_object_names.get(device_key).cloned(),
+            _ => transaction.parameters.first().and_then(|object_handle| self.object_names.get(&(String::from(device_key), *object_handle)).cloned()),
+        };
+
+        match object_name {
+            Some(object_name) => get_safe_filename(&object_name),
+            None => format!("object_{:08X}", transaction.parameters.first().copied().unwrap_or_default()),
+        }
+    }
+
+    fn append_data(&mut self, device_key: &str, data: &[u8]) {
+        let object_directory = self.module_context.object_directory.clone();
+        let object_name = match self.transactions.get(device_key) {
+            Some(transaction) if transaction.is_object_transfer() && transaction.object_file.is_none() && transaction.export_error.is_none() => Some(self.get_object_name(device_key, transaction)),
+            _ => None,
+        };
+
+        let Some(transaction) = self.transactions.get_mut(device_key) else { return };
+        transaction.data_length += data.len() as u64;
+        if !transaction.is_object_transfer() {
+            let kept_length = MAX_DATASET_LENGTH.saturating_sub(transaction.dataset.len()).min(data.len());
+            transaction.dataset.extend_from_slice(&data[..kept_length]);
+            return;
+        }
+
+        /* Objects are written while they arrive, the first Chunk creates the File */
+        let Some(object_directory) = object_directory.filter(|_| transaction.export_error.is_none()) else { return };
+        if let Some(object_name) = object_name {
+            let file_path = get_object_path(&object_directory, &object_name);
+            match fs::create_dir_all(&object_directory).and_then(|_| File::create(&file_path)) {
+                Ok(object_file) => transaction.object_file = Some((file_path, object_file)),
+                Err(error) => transaction.export_error = Some(format!("Unable to create {}: {}", file_path.display(), error)),
+            }
+        }
+
+        if let Some((file_path, object_file)) = transaction.object_file.as_mut() && let Err(error) = object_file.write_all(data) {
+            transaction.export_error = Some(format!("Unable to write {}: {}", file_path.display(), error));
+            transaction.object_file = None;
+        }
+    }
+
+    fn finish_transaction(&mut self, device_key: &str, urb_header: &UrbXractHeader, container_header: &ContainerHeader, response_parameters: &[u32]) -> (String, bool) {
+        let response_name = ptp_container::get_response_name(container_header.code);
+        let is_ok = container_header.code == ptp_container::RESPONSE_OK;
+        let Some(transaction) = self.transactions.remove(device_key).filter(|transaction| transaction.transaction_id == container_header.transaction_id) else {
+            return (format!("[PTP Tx {}] Response {}{}", container_header.transaction_id, response_name, ptp_container::format_parameters(response_parameters)), !is_ok);
+        };
+
+        /* Remember Object Names for the following Object Transfers */
+        let object_info = ptp_container::parse_object_info(&transaction.dataset).filter(|_| is_ok);
+        match (transaction.operation, object_info) {
+            (ptp_container::OPERATION_GET_OBJECT_INFO, Some(object_info)) if !transaction.parameters.is_empty() => {
+                self.object_names.insert((String::from(device_key), transaction.parameters[0]), object_info.filename);
+            },
+
+            /* Response Parameters: Storage, Parent and the new Object Handle */
+            (ptp_container::OPERATION_SEND_OBJECT_INFO, Some(object_info)) => {
+                if let Some(object_handle) = response_parameters.get(2) {
+                    self.object_names.insert((String::from(device_key), *object_handle), object_info.filename.clone());
+                }
+
+                self.sent_object_names.insert(String::from(device_key), object_info.filename);
+            },
+
+            (ptp_container::OPERATION_SEND_OBJECT, _) => {
+                self.sent_object_names.remove(device_key);
+            },
+
+            _ => {}
+        }
+
+        let mut notes = vec![];
+        if let Some(description) = ptp_container::describe_dataset(transaction.operation, &transaction.dataset) {
+            notes.push(description);
+        } else if transaction.data_length > 0 {
+            notes.push(format!("{} bytes", transaction.data_length));
+        }
+
+        match (&transaction.object_file, &transaction.export_error) {
+            (Some((file_path, _)), None) if is_ok => notes.push(format!("Saved {}", file_path.display())),
+            (Some((file_path, _)), None) => notes.push(format!("Incomplete {}", file_path.display())),
+            (_, Some(export_error)) => notes.push(format!("Export failed: {}", export_error)),
+            _ => {}
+        }
+
+        let notes = if notes.is_empty() { String::new() } else { format!(", {}", notes.join(", ")) };
+        (format!(
+            "[PTP Tx {}] {} -> {}{}{} ({:.3} ms)",
+            transaction.transaction_id,
+            transaction.describe(),
+            response_name,
+            ptp_container::format_parameters(response_parameters),
+            notes,
+            urb_header.timestamp.saturating_sub(transaction.timestamp) as f64 / 1000.0
+        ), !is_ok || transaction.export_error.is_some())
+    }
+
+    async fn consume_bulk(&mut self, urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let urb_data = urb_packet.data.as_deref().unwrap_or_default();
+        let device_key = device_model::get_device_key(&urb_header);
+        let phase_key = (device_key.clone(), urb_header.endpoint_info);
+
+        /* Transfers continuing a Data Container carry no Header, unless an unbounded one meets its Response */
+        let is_response = ptp_container::parse_header(urb_data).is_some_and(|container_header| {
+            container_header.container_type == ptp_container::CONTAINER_RESPONSE
+                && container_header.length as usize == urb_data.len()
+                && self.transactions.get(&device_key).is_some_and(|transaction| transaction.transaction_id == container_header.transaction_id)
+        });
+
+        if let Some(remaining_length) = self.data_phases.get(&phase_key).copied() && !(remaining_length == u64::MAX && is_response) {
+            let chunk_length = (remaining_length.min(urb_data.len() as u64)) as usize;
+            self.append_data(&device_key, &urb_data[..chunk_length]);
+            match remaining_length - chunk_length as u64 {
+                0 => self.data_phases.remove(&phase_key),
+                remaining_length => self.data_phases.insert(phase_key, remaining_length),
+            };
+
+            return;
+        }
+
+        let Some(container_header) = ptp_container::parse_header(urb_data) else {
+            let description = format!("[PTP] Invalid Container of {} bytes", urb_data.len());
+            return self.dispatch_row(urb_packet, description, true).await;
+        };
+
+        let parameters = ptp_container::get_parameters(urb_data, &container_header);
+        let row = match container_header.container_type {
+            ptp_container::CONTAINER_COMMAND => {
+                let transaction = Transaction {
+                    operation: container_header.code,
+                    transaction_id: container_header.transaction_id,
+                    parameters,
+                    timestamp: urb_header.timestamp,
+                    dataset: vec![],
+                    data_length: 0,
+                    object_file: None,
+                    export_error: None,
+                };
+
+                /* A new Command abandons a Transaction still waiting for its Response */
+                self.transactions
+                    .insert(device_key.clone(), transaction)
+                    .map(|abandoned| (format!("[PTP Tx {}] {} without Response", abandoned.transaction_id, abandoned.describe()), true))
+            },
+
+            ptp_container::CONTAINER_DATA => {
+                let container_length = container_header.length as usize;
+                let chunk_end = container_length.min(urb_data.len());
+                self.append_data(&device_key, &urb_data[ptp_container::CONTAINER_HEADER_LENGTH..chunk_end]);
+
+                let remaining_length = match container_header.length {
+                    UNKNOWN_DATA_LENGTH => u64::MAX,
+                    _ => (container_length - chunk_end) as u64,
+                };
+
+                if remaining_length > 0 {
+                    self.data_phases.insert(phase_key, remaining_length);
+                }
+
+                None
+            },
+
+            ptp_container::CONTAINER_RESPONSE => {
+                self.data_phases.retain(|(phase_device, _), _| *phase_device != device_key);
+                Some(self.finish_transaction(&device_key, &urb_header, &container_header, &parameters))
+            },
+
+            _ => Some((format!("[PTP] Event {}{}", ptp_container::get_event_name(container_header.code), ptp_container::format_parameters(&parameters)), false)),
+        };
+
+        if let Some((description, is_error)) = row {
+            self.dispatch_row(urb_packet, description, is_error).await;
+        }
+    }
+
+    async fn consume_event(&mut self, urb_packet: UrbXractPacket) {
+        let urb_data = urb_packet.data.as_deref().unwrap_or_default();
+        let Some(container_header) = ptp_container::parse_header(urb_data).filter(|container_header| container_header.container_type == ptp_container::CONTAINER_EVENT) else { return };
+        let parameters = ptp_container::get_parameters(urb_data, &container_header);
+        let description = format!("[PTP] Event {}{}", ptp_container::get_event_name(container_header.code), ptp_container::format_parameters(&parameters));
+        self.dispatch_row(urb_packet, description, false).await;
+    }
+
+    async fn consume_control(&mut self, mut urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let Some((setup_packet, data)) = self.pending_requests.pair(&mut urb_packet) else { return };
+        let description = match ptp_container::describe_class_request(setup_packet.request, data.unwrap_or_default()) {
+            Some(description) => format!("[PTP] {}", description),
+            None => protocol_control::describe_setup(&setup_packet, Some(CLASS_STILL_IMAGE)),
+        };
+
+        if urb_header.status != 0 {
+            let description = format!("{} -> {}", description, protocol_control::describe_status(&urb_header));
+            return self.dispatch_row(urb_packet, description, true).await;
+        }
+
+        /* Cancel and Reset abort the running Data Phase */
+        if matches!(setup_packet.request, 0x64 | 0x66) {
+            let device_key = device_model::get_device_key(&urb_header);
+            self.data_phases.retain(|(phase_device, _), _| *phase_device != device_key);
+        }
+
+        self.dispatch_row(urb_packet, description, false).await;
+    }
+}
+
+impl ReconstructionModule for Reconstructor {
+    fn new(module_tx: Sender<ReconstructedTransmission>, module_context: &ModuleContext) -> Self {
+        Self {
+            module_tx,
+            module_context: module_context.clone(),
+            pending_requests: PendingRequests::default(),
+            transactions: HashMap::new(),
+            data_phases: HashMap::new(),
+            object_names: HashMap::new(),
+            sent_object_names: HashMap::new(),
+        }
+    }
+
+    async fn consume_packet(&mut self, urb_packet: UrbXractPacket) {
+        /* OUT Containers travel with the Submission, IN Containers with the Completion, Zero Length Packets end Transfers */
+        let urb_header = urb_packet.header;
+        let is_transfer_data = (urb_header.endpoint_info & 0x80 != 0) == (urb_header.event_type != UrbEventType::Submit)
+            && (urb_header.event_type == UrbEventType::Submit || urb_header.status == 0)
+            && urb_packet.data.as_deref().is_some_and(|urb_data| !urb_data.is_empty());
+
+        match urb_header.transfer_type {
+            UrbTransferType::Control => self.consume_control(urb_packet).await,
+            UrbTransferType::Bulk if is_transfer_data => self.consume_bulk(urb_packet).await,
+            UrbTransferType::Interrupt if is_transfer_data => self.consume_event(urb_packet).await,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_filenames_inside_the_directory() {
+        assert_eq!(get_safe_filename("IMG_0001.JPG"), "IMG_0001.JPG");
+        assert_eq!(get_safe_filename("../../etc/passwd"), ".._.._etc_passwd");
+        assert_eq!(get_safe_filename("C:\\Windows\\win.ini"), "C__Windows_win.ini");
+        assert_eq!(get_safe_filename("C:"), "C_");
+        assert_eq!(get_safe_filename("line\nbreak"), "line_break");
+        for filename in ["", ".", "..", "..."] {
+            assert_eq!(get_safe_filename(filename), "object");
+        }
+    }
+}
@@ -0,0 +1,303 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::protocol_control::format_hex;
+
+/*
+    Qualcomm MSM Interface over QMUX. A QMUX Header addresses a Client of a
+    Service, the Message inside carries a Transaction ID, a Message ID and
+    TLVs. Responses repeat the Transaction ID and start with a Result TLV
+*/
+const QMUX_INTERFACE_TYPE: u8 = 0x01;
+const QMUX_HEADER_LENGTH: usize = 6;
+const SERVICE_CTL: u8 = 0x00;
+const TLV_RESULT: u8 = 0x02;
+const TLV_PREVIEW_LENGTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QmiKind {
+    Request,
+    Response,
+    Indication
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QmuxMessage {
+    pub service: u8,
+    pub client_id: u8,
+    pub kind: QmiKind,
+    pub transaction_id: u16,
+    pub message_id: u16,
+    pub tlvs: Vec<(u8, Vec<u8>)>,
+}
+
+pub fn is_qmux(data: &[u8]) -> bool {
+    /* QMUX Length excludes the Interface Type Byte */
+    data.len() > QMUX_HEADER_LENGTH && data[0] == QMUX_INTERFACE_TYPE && u16::from_le_bytes([data[1], data[2]]) as usize + 1 == data.len()
+}
+
+pub fn parse_qmux(data: &[u8]) -> Result<QmuxMessage, String> {
+    if !is_qmux(data) {
+        return Err(String::from("not a QMUX Message"));
+    }
+
+    /* CTL uses one Byte Transaction IDs and its own Flag Values */
+    let (service, client_id) = (data[4], data[5]);
+    let sdu = &data[QMUX_HEADER_LENGTH..];
+    let (kind, transaction_id, header_length) = match service {
+        SERVICE_CTL if sdu.len() >= 6 => (match sdu[0] & 0x03 {
+            0x01 => QmiKind::Response,
+            0x02 => QmiKind::Indication,
+            _ => QmiKind::Request,
+        }, sdu[1] as u16, 2),
+
+        _ if sdu.len() >= 7 => (match sdu[0] & 0x06 {
+            0x02 => QmiKind::Response,
+            0x04 => QmiKind::Indication,
+            _ => QmiKind::Request,
+        }, u16::from_le_bytes([sdu[1], sdu[2]]), 3),
+
+        _ => return Err(String::from("truncated QMI Header")),
+    };
+
+    let message_id = u16::from_le_bytes([sdu[header_length], sdu[header_length + 1]]);
+    let tlv_length = u16::from_le_bytes([sdu[header_length + 2], sdu[header_length + 3]]) as usize;
+    let tlv_data = &sdu[header_length + 4..];
+    if tlv_length > tlv_data.len() {
+        return Err(format!("TLV Length {} exceeds {} bytes", tlv_length, tlv_data.len()));
+    }
+
+    /* TLV: Type, two Byte Length, Value */
+    let mut tlvs = vec![];
+    let mut offset = 0;
+    while offset + 3 <= tlv_length {
+        let value_length = u16::from_le_bytes([tlv_data[offset + 1], tlv_data[offset + 2]]) as usize;
+        let Some(value) = tlv_data.get(offset + 3..offset + 3 + value_length) else {
+            return Err(format!("TLV 0x{:02X} exceeds the Message", tlv_data[offset]));
+        };
+
+        tlvs.push((tlv_data[offset], value.to_vec()));
+        offset += 3 + value_length;
+    }
+
+    Ok(QmuxMessage { service, client_id, kind, transaction_id, message_id, tlvs })
+}
+
+pub fn get_service_name(service: u8) -> String {
+    let service_name = match service {
+        SERVICE_CTL => "CTL",
+        0x01 => "WDS",
+        0x02 => "DMS",
+        0x03 => "NAS",
+        0x04 => "QOS",
+        0x05 => "WMS",
+        0x06 => "PDS",
+        0x07 => "AUTH",
+        0x08 => "AT",
+        0x09 => "VOICE",
+        0x0A => "CAT2",
+        0x0B => "UIM",
+        0x0C => "PBM",
+        0x10 => "LOC",
+        0x11 => "SAR",
+        0x1A => "WDA",
+        0x24 => "PDC",
+        0xE0 => "CAT",
+        0xE1 => "RMS",
+        0xE2 => "OMA",
+        _ => return format!("Service 0x{:02X}", service),
+    };
+
+    String::from(service_name)
+}
+
+pub fn get_message_name(service: u8, message_id: u16) -> String {
+    let message_name = match (service, message_id) {
+        (SERVICE_CTL, 0x0020) => "Set Instance ID",
+        (SERVICE_CTL, 0x0021) => "Get Version Info",
+        (SERVICE_CTL, 0x0022) => "Get Client ID",
+        (SERVICE_CTL, 0x0023) => "Release Client ID",
+        (SERVICE_CTL, 0x0024) => "Revoke Client ID",
+        (SERVICE_CTL, 0x0025) => "Invalid Client ID",
+        (SERVICE_CTL, 0x0026) => "Set Data Format",
+        (SERVICE_CTL, 0x0027) => "Sync",
+        (_, 0x0000) => "Reset",
+        (0x01, 0x0001) | (0x02, 0x0001) => "Event Report",
+        (0x01, 0x0002) => "Abort",
+        (0x01, 0x0020) => "Start Network",
+        (0x01, 0x0021) => "Stop Network",
+        (0x01, 0x0022) => "Get Packet Service Status",
+        (0x01, 0x0023) => "Get Channel Rates",
+        (0x01, 0x0024) => "Get Packet Statistics",
+        (0x01, 0x0027) => "Create Profile",
+        (0x01, 0x0028) => "Modify Profile",
+        (0x01, 0x0029) => "Delete Profile",
+        (0x01, 0x002A) => "Get Profile List",
+        (0x01, 0x002B) => "Get Profile Settings",
+        (0x01, 0x002C) => "Get Default Settings",
+        (0x01, 0x002D) => "Get Current Settings",
+        (0x01, 0x004D) => "Set IP Family",
+        (0x01, 0x00A2) => "Bind Mux Data Port",
+        (0x02, 0x0020) => "Get Capabilities",
+        (0x02, 0x0021) => "Get Manufacturer",
+        (0x02, 0x0022) => "Get Model",
+        (0x02, 0x0023) => "Get Revision",
+        (0x02, 0x0024) => "Get MSISDN",
+        (0x02, 0x0025) => "Get IDs",
+        (0x02, 0x0026) => "Get Power State",
+        (0x02, 0x002D) => "Get Operating Mode",
+        (0x02, 0x002E) => "Set Operating Mode",
+        (0x02, 0x002F) => "Get Time",
+        (0x03, 0x0002) => "Set Event Report",
+        (0x03, 0x0003) => "Register Indications",
+        (0x03, 0x0020) => "Get Signal Strength",
+        (0x03, 0x0021) => "Network Scan",
+        (0x03, 0x0022) => "Initiate Network Register",
+        (0x03, 0x0024) => "Get Serving System",
+        (0x03, 0x0025) => "Get Home Network",
+        (0x03, 0x0033) => "Set System Selection Preference",
+        (0x03, 0x0034) => "Get System Selection Preference",
+        (0x03, 0x0043) => "Get Operator Name",
+        (0x03, 0x004D) => "Get System Info",
+        (0x03, 0x004E) => "System Info",
+        (0x03, 0x004F) => "Get Signal Info",
+        (0x03, 0x0051) => "Signal Info",
+        (0x0B, 0x0020) => "Read Transparent",
+        (0x0B, 0x0021) => "Read Record",
+        (0x0B, 0x0022) => "Write Transparent",
+        (0x0B, 0x0023) => "Write Record",
+        (0x0B, 0x0024) => "Get File Attributes",
+        (0x0B, 0x0025) => "Set PIN Protection",
+        (0x0B, 0x0026) => "Verify PIN",
+        (0x0B, 0x0027) => "Unblock PIN",
+        (0x0B, 0x0028) => "Change PIN",
+        (0x0B, 0x002E) => "Register Events",
+        (0x0B, 0x002F) => "Get Card Status",
+        (0x0B, 0x0032) => "Card Status",
+        (0x1A, 0x0020) => "Set Data Format",
+        (0x1A, 0x0021) => "Get Data Format",
+        _ => return format!("Message 0x{:04X}", message_id),
+    };
+
+    String::from(message_name)
+}
+
+pub fn get_error_name(error_code: u16) -> String {
+    let error_name = match error_code {
+        0 => "NONE",
+        1 => "MALFORMED_MSG",
+        2 => "NO_MEMORY",
+        3 => "INTERNAL",
+        4 => "ABORTED",
+        5 => "CLIENT_IDS_EXHAUSTED",
+        6 => "UNABORTABLE_TRANSACTION",
+        7 => "INVALID_CLIENT_ID",
+        8 => "NO_THRESHOLDS",
+        9 => "INVALID_HANDLE",
+        10 => "INVALID_PROFILE",
+        11 => "INVALID_PINID",
+        12 => "INCORRECT_PIN",
+        13 => "NO_NETWORK_FOUND",
+        14 => "CALL_FAILED",
+        15 => "OUT_OF_CALL",
+        16 => "NOT_PROVISIONED",
+        17 => "MISSING_ARG",
+        19 => "ARG_TOO_LONG",
+        22 => "INVALID_TX_ID",
+        23 => "DEVICE_IN_USE",
+        24 => "OP_NETWORK_UNSUPPORTED",
+        25 => "OP_DEVICE_UNSUPPORTED",
+        26 => "NO_EFFECT",
+        27 => "NO_FREE_PROFILE",
+        28 => "INVALID_PDP_TYPE",
+        34 => "AUTHENTICATION_FAILED",
+        35 => "PIN_BLOCKED",
+        36 => "PIN_PERM_BLOCKED",
+        37 => "SIM_NOT_INITIALIZED",
+        41 => "INVALID_ID",
+        43 => "INTERFACE_NOT_FOUND",
+        45 => "INVALID_DATA_FORMAT",
+        46 => "GENERAL",
+        47 => "UNKNOWN",
+        48 => "INVALID_ARG",
+        49 => "INVALID_INDEX",
+        50 => "NO_ENTRY",
+        51 => "DEVICE_STORAGE_FULL",
+        52 => "DEVICE_NOT_READY",
+        53 => "NETWORK_NOT_READY",
+        54 => "CAUSE_CODE",
+        94 => "NOT_SUPPORTED",
+        _ => return format!("Error {}", error_code),
+    };
+
+    String::from(error_name)
+}
+
+fn format_tlv_value(value: &[u8]) -> String {
+    /* Printable Strings quoted, short Values as Integers, the rest as Hex */
+    match value.len() {
+        0 => String::from("(empty)"),
+        1 => format!("{}", value[0]),
+        2 => format!("{}", u16::from_le_bytes([value[0], value[1]])),
+        4 => format!("{}", u32::from_le_bytes(value[0..4].try_into().unwrap())),
+        length if length >= 2 && value.iter().all(|byte| byte.is_ascii_graphic() || *byte == b' ') => format!("\"{}\"", String::from_utf8_lossy(value)),
+        length => {
+            let preview = format_hex(&value[..value.len().min(TLV_PREVIEW_LENGTH)]);
+            if length > TLV_PREVIEW_LENGTH { format!("{} ...", preview) } else { preview }
+        }
+    }
+}
+
+impl QmuxMessage {
+    pub fn get_result(&self) -> Option<(u16, u16)> {
+        /* Result TLV: 0 Success or 1 Failure, then the Error Code */
+        self.tlvs
+            .iter()
+            .find(|(tlv_type, value)| *tlv_type == TLV_RESULT && value.len() >= 4)
+            .map(|(_, value)| (u16::from_le_bytes([value[0], value[1]]), u16::from_le_bytes([value[2], value[3]])))
+    }
+
+    pub fn describe_result(&self) -> String {
+        match self.get_result() {
+            Some((0, _)) => String::from("SUCCESS"),
+            Some((_, error_code)) => format!("FAILURE {}", get_error_name(error_code)),
+            None => String::from("No Result"),
+        }
+    }
+
+    pub fn is_failure(&self) -> bool {
+        self.get_result().is_some_and(|(result, _)| result != 0)
+    }
+
+    pub fn describe_tlvs(&self) -> String {
+        let tlvs: Vec<String> = self.tlvs
+            .iter()
+            .filter(|(tlv_type, _)| self.kind != QmiKind::Response || *tlv_type != TLV_RESULT)
+            .map(|(tlv_type, value)| format!("0x{:02X}={}", tlv_type, format_tlv_value(value)))
+            .collect();
+
+        match tlvs.is_empty() {
+            true => String::new(),
+            false => format!(" {{{}}}", tlvs.join(", ")),
+        }
+    }
+
+    pub fn get_label(&self) -> String {
+        format!("QMI {} Client {}", get_service_name(self.service), self.client_id)
+    }
+}
@@ -0,0 +1,297 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::protocol_control::format_hex;
+
+/*
+    Remote NDIS. Control Messages travel in SEND_ENCAPSULATED_COMMAND and
+    GET_ENCAPSULATED_RESPONSE, Completions carry the Request ID of their
+    Message. Bulk Transfers hold one or more PACKET_MSGs, each wrapping a Frame
+*/
+pub const MESSAGE_PACKET: u32 = 0x00000001;
+const MESSAGE_INITIALIZE: u32 = 0x00000002;
+const MESSAGE_HALT: u32 = 0x00000003;
+const MESSAGE_QUERY: u32 = 0x00000004;
+const MESSAGE_SET: u32 = 0x00000005;
+const MESSAGE_RESET: u32 = 0x00000006;
+const MESSAGE_INDICATE_STATUS: u32 = 0x00000007;
+const MESSAGE_KEEPALIVE: u32 = 0x00000008;
+const MESSAGE_COMPLETION: u32 = 0x80000000;
+const PACKET_HEADER_LENGTH: usize = 44;
+
+const OID_GEN_SUPPORTED_LIST: u32 = 0x00010101;
+const OID_GEN_MEDIA_CONNECT_STATUS: u32 = 0x00010114;
+const OID_GEN_LINK_SPEED: u32 = 0x00010107;
+const OID_GEN_CURRENT_PACKET_FILTER: u32 = 0x0001010E;
+const OID_GEN_VENDOR_DESCRIPTION: u32 = 0x0001010D;
+const OID_802_3_PERMANENT_ADDRESS: u32 = 0x01010101;
+const OID_802_3_CURRENT_ADDRESS: u32 = 0x01010102;
+const OID_802_3_MULTICAST_LIST: u32 = 0x01010103;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlMessage {
+    pub message_type: u32,
+    pub request_id: Option<u32>, /* INDICATE_STATUS has none */
+    pub oid: Option<u32>, /* QUERY and SET */
+    pub summary: String,
+    pub status: Option<u32>,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn get_message_name(message_type: u32) -> String {
+    let message_name = match message_type & !MESSAGE_COMPLETION {
+        MESSAGE_PACKET => "PACKET",
+        MESSAGE_INITIALIZE => "INITIALIZE",
+        MESSAGE_HALT => "HALT",
+        MESSAGE_QUERY => "QUERY",
+        MESSAGE_SET => "SET",
+        MESSAGE_RESET => "RESET",
+        MESSAGE_INDICATE_STATUS => "INDICATE_STATUS",
+        MESSAGE_KEEPALIVE => "KEEPALIVE",
+        _ => return format!("Message 0x{:08X}", message_type),
+    };
+
+    match message_type & MESSAGE_COMPLETION != 0 {
+        true => format!("{}_CMPLT", message_name),
+        false => String::from(message_name),
+    }
+}
+
+pub fn get_status_name(status: u32) -> String {
+    match status {
+        0x00000000 => String::from("SUCCESS"),
+        0xC0000001 => String::from("FAILURE"),
+        0xC0010015 => String::from("INVALID_DATA"),
+        0xC00000BB => String::from("NOT_SUPPORTED"),
+        0x4001000B => String::from("MEDIA_CONNECT"),
+        0x4001000C => String::from("MEDIA_DISCONNECT"),
+        0x40010012 => String::from("LINK_SPEED_CHANGE"),
+        status => format!("Status 0x{:08X}", status),
+    }
+}
+
+fn get_oid_name(oid: u32) -> String {
+    let oid_name = match oid {
+        OID_GEN_SUPPORTED_LIST => "OID_GEN_SUPPORTED_LIST",
+        0x00010102 => "OID_GEN_HARDWARE_STATUS",
+        0x00010103 => "OID_GEN_MEDIA_SUPPORTED",
+        0x00010104 => "OID_GEN_MEDIA_IN_USE",
+        0x00010106 => "OID_GEN_MAXIMUM_FRAME_SIZE",
+        OID_GEN_LINK_SPEED => "OID_GEN_LINK_SPEED",
+        OID_GEN_VENDOR_DESCRIPTION => "OID_GEN_VENDOR_DESCRIPTION",
+        OID_GEN_CURRENT_PACKET_FILTER => "OID_GEN_CURRENT_PACKET_FILTER",
+        0x00010111 => "OID_GEN_MAXIMUM_TOTAL_SIZE",
+        OID_GEN_MEDIA_CONNECT_STATUS => "OID_GEN_MEDIA_CONNECT_STATUS",
+        0x00010116 => "OID_GEN_VENDOR_DRIVER_VERSION",
+        0x00010202 => "OID_GEN_PHYSICAL_MEDIUM",
+        0x00020101 => "OID_GEN_XMIT_OK",
+        0x00020102 => "OID_GEN_RCV_OK",
+        0x00020103 => "OID_GEN_XMIT_ERROR",
+        0x00020104 => "OID_GEN_RCV_ERROR",
+        0x00020105 => "OID_GEN_RCV_NO_BUFFER",
+        OID_802_3_PERMANENT_ADDRESS => "OID_802_3_PERMANENT_ADDRESS",
+        OID_802_3_CURRENT_ADDRESS => "OID_802_3_CURRENT_ADDRESS",
+        OID_802_3_MULTICAST_LIST => "OID_802_3_MULTICAST_LIST",
+        0x01010104 => "OID_802_3_MAXIMUM_LIST_SIZE",
+        _ => return format!("OID 0x{:08X}", oid),
+    };
+
+    String::from(oid_name)
+}
+
+fn format_oid_value(oid: u32, buffer: &[u8]) -> String {
+    /* Known OIDs decoded, the rest as Integers or Hex */
+    match (oid, buffer.len()) {
+        (_, 0) => String::from("(empty)"),
+        (OID_802_3_PERMANENT_ADDRESS | OID_802_3_CURRENT_ADDRESS, 6) => buffer.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(":"),
+        (OID_802_3_MULTICAST_LIST, length) => format!("{} Addresses", length / 6),
+        (OID_GEN_SUPPORTED_LIST, length) => format!("{} OIDs", length / 4),
+        (OID_GEN_VENDOR_DESCRIPTION, _) => format!("\"{}\"", String::from_utf8_lossy(buffer).trim_end_matches('\0')),
+        (OID_GEN_MEDIA_CONNECT_STATUS, 4) => String::from(if buffer[0] == 0 { "Connected" } else { "Disconnected" }),
+        (OID_GEN_LINK_SPEED, 4) => format!("{} Mbit/s", read_u32(buffer, 0).unwrap_or_default() as f64 / 10_000.0),
+        (OID_GEN_CURRENT_PACKET_FILTER, 4) => format!("0x{:08X}", read_u32(buffer, 0).unwrap_or_default()),
+        (_, 4) => format!("{}", read_u32(buffer, 0).unwrap_or_default()),
+        _ => format_hex(&buffer[..buffer.len().min(32)]),
+    }
+}
+
+fn get_information_buffer(message: &[u8], length_offset: usize) -> &[u8] {
+    /* Buffer Offsets count from the Request ID, 8 Bytes into the Message */
+    let length = read_u32(message, length_offset).unwrap_or_default() as usize;
+    let offset = read_u32(message, length_offset + 4).unwrap_or_default() as usize + 8;
+    message.get(offset..offset + length).unwrap_or_default()
+}
+
+pub fn parse_control_message(message: &[u8]) -> Option<ControlMessage> {
+    let message_type = read_u32(message, 0)?;
+    let message_name = get_message_name(message_type);
+    let request_id = read_u32(message, 8);
+    let oid = read_u32(message, 12).filter(|_| matches!(message_type, MESSAGE_QUERY | MESSAGE_SET));
+    let (request_id, summary, status) = match message_type {
+        MESSAGE_INITIALIZE => (request_id, format!(
+            "{} Version {}.{}, Max Transfer {} bytes",
+            message_name,
+            read_u32(message, 12).unwrap_or_default(),
+            read_u32(message, 16).unwrap_or_default(),
+            read_u32(message, 20).unwrap_or_default()
+        ), None),
+
+        MESSAGE_QUERY => (request_id, format!("{} {}", message_name, get_oid_name(oid.unwrap_or_default())), None),
+        MESSAGE_SET => {
+            let buffer = get_information_buffer(message, 16);
+            (request_id, format!("{} {} = {}", message_name, get_oid_name(oid.unwrap_or_default()), format_oid_value(oid.unwrap_or_default(), buffer)), None)
+        },
+
+        MESSAGE_HALT | MESSAGE_KEEPALIVE => (request_id, message_name, None),
+        MESSAGE_RESET => (None, message_name, None),
+        MESSAGE_INDICATE_STATUS => {
+            let status = read_u32(message, 8).unwrap_or_default();
+            (None, format!("{}: {}", message_name, get_status_name(status)), Some(status))
+        },
+
+        /* Completions: Request ID then Status */
+        message_type if message_type & MESSAGE_COMPLETION != 0 => {
+            let status = read_u32(message, 12);
+            let details = match message_type & !MESSAGE_COMPLETION {
+                MESSAGE_INITIALIZE if message.len() >= 44 => format!(
+                    ", Max {} Packets per Transfer, Max Transfer {} bytes, Alignment {}",
+                    read_u32(message, 32).unwrap_or_default(),
+                    read_u32(message, 36).unwrap_or_default(),
+                    1u64 << read_u32(message, 40).unwrap_or_default().min(31)
+                ),
+
+                MESSAGE_RESET => return Some(ControlMessage {
+                    message_type,
+                    request_id: None,
+                    oid: None,
+                    summary: format!("{}: {}", message_name, get_status_name(request_id.unwrap_or_default())),
+                    status: request_id,
+                }),
+
+                _ => String::new(),
+            };
+
+            (request_id, format!("{}{}", get_status_name(status.unwrap_or_default()), details), status)
+        },
+
+        _ => (request_id, message_name, None),
+    };
+
+    Some(ControlMessage { message_type, request_id, oid, summary, status })
+}
+
+pub fn describe_completion(request: &ControlMessage, completion: &ControlMessage, message: &[u8]) -> String {
+    /* Query Completions carry the requested Value */
+    let value = match (request.oid, request.message_type, completion.status) {
+        (Some(oid), MESSAGE_QUERY, Some(0)) => format!(": {}", format_oid_value(oid, get_information_buffer(message, 16))),
+        _ => String::new(),
+    };
+
+    format!("{} -> {}{}", request.summary, completion.summary, value)
+}
+
+pub fn is_completion(message_type: u32) -> bool {
+    message_type & MESSAGE_COMPLETION != 0
+}
+
+pub fn expects_completion(message_type: u32) -> bool {
+    matches!(message_type, MESSAGE_INITIALIZE | MESSAGE_QUERY | MESSAGE_SET | MESSAGE_RESET | MESSAGE_KEEPALIVE)
+}
+
+pub fn parse_packets(data: &[u8]) -> Result<Vec<&[u8]>, String> {
+    /* PACKET_MSG: Type, Length, Data Offset (from Byte 8), Data Length, OOB and Per-Packet Info */
+    let mut frames = vec![];
+    let mut offset = 0;
+    while offset + PACKET_HEADER_LENGTH <= data.len() {
+        let message = &data[offset..];
+        let message_type = read_u32(message, 0).unwrap_or_default();
+        let message_length = read_u32(message, 4).unwrap_or_default() as usize;
+        if message_type != MESSAGE_PACKET {
+            return Err(format!("{} in the Data Stream", get_message_name(message_type)));
+        }
+
+        if message_length < PACKET_HEADER_LENGTH || message_length > message.len() {
+            return Err(format!("PACKET Length {} with {} bytes left", message_length, message.len()));
+        }
+
+        let data_offset = read_u32(message, 8).unwrap_or_default() as usize + 8;
+        let data_length = read_u32(message, 12).unwrap_or_default() as usize;
+        match message[..message_length].get(data_offset..data_offset + data_length) {
+            Some(frame) => frames.push(frame),
+            None => return Err(format!("Frame of {} bytes at {} outside its PACKET", data_length, data_offset)),
+        }
+
+        offset += message_length;
+    }
+
+    /* Transfers may be padded with a single zero Byte to avoid Zero Length Packets */
+    if data[offset..].iter().any(|byte| *byte != 0) {
+        return Err(format!("{} trailing bytes", data.len() - offset));
+    }
+
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet_message(frame: &[u8]) -> Vec<u8> {
+        /* Data Offset counts from Byte 8, so the Frame follows the 44 Byte Header at 36 */
+        let mut message = vec![];
+        for value in [MESSAGE_PACKET, (PACKET_HEADER_LENGTH + frame.len()) as u32, 36, frame.len() as u32] {
+            message.extend_from_slice(&value.to_le_bytes());
+        }
+        message.resize(PACKET_HEADER_LENGTH, 0x00);
+        message.extend_from_slice(frame);
+        message
+    }
+
+    #[test]
+    fn parses_multiple_packets() {
+        let first_frame = [0xAA; 14];
+        let second_frame = [0xBB; 60];
+        let mut transfer = packet_message(&first_frame);
+        transfer.extend(packet_message(&second_frame));
+        assert_eq!(parse_packets(&transfer), Ok(vec![first_frame.as_slice(), second_frame.as_slice()]));
+
+        /* A single zero Byte pads away the Zero Length Packet */
+        transfer.push(0x00);
+        assert_eq!(parse_packets(&transfer).map(|frames| frames.len()), Ok(2));
+        transfer.push(0x01);
+        assert_eq!(parse_packets(&transfer), Err(String::from("2 trailing bytes")));
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        let mut transfer = packet_message(&[0xAA; 14]);
+        transfer[0] = MESSAGE_INITIALIZE as u8;
+        assert_eq!(parse_packets(&transfer), Err(String::from("INITIALIZE in the Data Stream")));
+
+        let mut transfer = packet_message(&[0xAA; 14]);
+        transfer[4] = 80;
+        assert_eq!(parse_packets(&transfer), Err(String::from("PACKET Length 80 with 58 bytes left")));
+
+        let mut transfer = packet_message(&[0xAA; 14]);
+        transfer[12] = 15;
+        assert_eq!(parse_packets(&transfer), Err(String::from("Frame of 15 bytes at 44 outside its PACKET")));
+    }
+}
@@ -13,31 +13,436 @@
     GNU General Public License for more details.
 
     You should have received a copy of the GNU General Public License
-    along with this program.  If not, see <https://www.gnu.org/licenses/>. 
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use std::collections::HashMap;
+use std::ptr;
 use tokio::sync::mpsc::Sender;
-use super::{ReconstructedTransmission, ReconstructionModule};
+use crate::sniffer::{UrbXractHeader, UrbXractPacket};
+
+use super::protocol_ata::{self, BridgeCommand, JMicronNvmePhase, NvmeCommand};
+use super::{CommandBlockWrapper, CommandStatusWrapper, ModuleContext, ReconstructedTransmission, ReconstructionModule};
+use super::{COMMAND_BLK_WRAP_SIGNATURE, COMMAND_STS_WRAP_SIGNATURE};
+
+/* Define Constants */
+const MAX_DECODE_LENGTH: usize = 4096; /* Largest Payload kept for Decoding (NVMe Identify) */
+const CSW_STATUS_PASSED: u8 = 0x00;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScsiDataDirection {
+    None,
+    In,
+    Out
+}
+
+struct PendingCommand {
+    urbx_header: UrbXractHeader,
+    tag: u32,
+    description: String,
+    cdb: Vec<u8>,
+    is_device_to_host: bool,
+    data: Vec<u8>,
+    data_length: usize,
+    sources: Vec<UrbXractPacket>,
+}
 
 pub struct Reconstructor {
-    module_tx: Sender<ReconstructedTransmission>
+    module_tx: Sender<ReconstructedTransmission>,
+    pending: HashMap<String, PendingCommand>, /* Bus:Device, Command awaiting CSW */
+    nvme_commands: HashMap<String, NvmeCommand>, /* Bus:Device, Last JMicron NVMe Command */
 }
 
-impl ReconstructionModule for Reconstructor {    
-    fn new(module_tx: Sender<ReconstructedTransmission>) -> Self {
-        Self {
-            module_tx
+fn read_be16(data: &[u8], offset: usize) -> u64 {
+    u16::from_be_bytes([data[offset], data[offset + 1]]) as u64
+}
+
+fn read_be32(data: &[u8], offset: usize) -> u64 {
+    u32::from_be_bytes(data[offset..(offset + 4)].try_into().unwrap()) as u64
+}
+
+fn read_be64(data: &[u8], offset: usize) -> u64 {
+    u64::from_be_bytes(data[offset..(offset + 8)].try_into().unwrap())
+}
+
+pub fn get_opcode_name(opcode: u8) -> &'static str {
+    /*
+        Opcode Info:
+        https://www.t10.org/lists/op-num.htm
+    */
+    match opcode {
+        0x00 => "TEST UNIT READY",
+        0x03 => "REQUEST SENSE",
+        0x04 => "FORMAT UNIT",
+        0x08 => "READ(6)",
+        0x0A => "WRITE(6)",
+        0x12 => "INQUIRY",
+        0x15 => "MODE SELECT(6)",
+        0x1A => "MODE SENSE(6)",
+        0x1B => "START STOP UNIT",
+        0x1D => "SEND DIAGNOSTIC",
+        0x1E => "PREVENT ALLOW MEDIUM REMOVAL",
+        0x23 => "READ FORMAT CAPACITIES",
+        0x25 => "READ CAPACITY(10)",
+        0x28 => "READ(10)",
+        0x2A => "WRITE(10)",
+        0x2F => "VERIFY(10)",
+        0x35 => "SYNCHRONIZE CACHE(10)",
+        0x3B => "WRITE BUFFER",
+        0x3C => "READ BUFFER",
+        0x42 => "UNMAP",
+        0x43 => "READ TOC/PMA/ATIP",
+        0x46 => "GET CONFIGURATION",
+        0x4A => "GET EVENT STATUS NOTIFICATION",
+        0x4D => "LOG SENSE",
+        0x55 => "MODE SELECT(10)",
+        0x5A => "MODE SENSE(10)",
+        0x85 => "ATA PASS-THROUGH(16)",
+        0x88 => "READ(16)",
+        0x8A => "WRITE(16)",
+        0x8F => "VERIFY(16)",
+        0x91 => "SYNCHRONIZE CACHE(16)",
+        0x9E => "SERVICE ACTION IN(16)",
+        0xA0 => "REPORT LUNS",
+        0xA1 => "ATA PASS-THROUGH(12)",
+        0xA3 => "MAINTENANCE IN",
+        0xA8 => "READ(12)",
+        0xAA => "WRITE(12)",
+        _ => "UNKNOWN"
+    }
+}
+
+pub fn get_data_direction(cdb: &[u8]) -> ScsiDataDirection {
+    /* Bridge Commands carry the Direction in their own Flags */
+    let bridge_direction = protocol_ata::decode_bridge_cdb(cdb).and_then(|bridge_command| bridge_command.get_data_direction());
+    if let Some(direction) = bridge_direction {
+        return direction;
+    }
+
+    match cdb.first() {
+        None => ScsiDataDirection::None,
+        Some(opcode) => match opcode {
+            0x03 | 0x08 | 0x12 | 0x1A | 0x23 | 0x25 | 0x28 | 0x3C |
+            0x43 | 0x46 | 0x4A | 0x4D | 0x5A | 0x88 | 0x9E | 0xA0 |
+            0xA3 | 0xA8 => ScsiDataDirection::In,
+
+            0x04 | 0x0A | 0x15 | 0x1D | 0x2A | 0x3B | 0x42 | 0x55 |
+            0x8A | 0xAA => ScsiDataDirection::Out,
+
+            _ => ScsiDataDirection::None
         }
     }
+}
+
+pub fn get_status_name(status: u8) -> &'static str {
+    match status {
+        0x00 => "GOOD",
+        0x02 => "CHECK CONDITION",
+        0x04 => "CONDITION MET",
+        0x08 => "BUSY",
+        0x18 => "RESERVATION CONFLICT",
+        0x28 => "TASK SET FULL",
+        0x30 => "ACA ACTIVE",
+        0x40 => "TASK ABORTED",
+        _ => "UNKNOWN STATUS"
+    }
+}
+
+fn get_sense_key_name(sense_key: u8) -> &'static str {
+    match sense_key {
+        0x0 => "NO SENSE",
+        0x1 => "RECOVERED ERROR",
+        0x2 => "NOT READY",
+        0x3 => "MEDIUM ERROR",
+        0x4 => "HARDWARE ERROR",
+        0x5 => "ILLEGAL REQUEST",
+        0x6 => "UNIT ATTENTION",
+        0x7 => "DATA PROTECT",
+        0x8 => "BLANK CHECK",
+        0x9 => "VENDOR SPECIFIC",
+        0xA => "COPY ABORTED",
+        0xB => "ABORTED COMMAND",
+        0xD => "VOLUME OVERFLOW",
+        0xE => "MISCOMPARE",
+        _ => "RESERVED"
+    }
+}
+
+fn get_additional_sense_name(asc: u8, ascq: u8) -> Option<&'static str> {
+    /* Only the Codes commonly seen on USB Storage */
+    match (asc, ascq) {
+        (0x04, 0x01) => Some("LOGICAL UNIT IS IN PROCESS OF BECOMING READY"),
+        (0x04, _) => Some("LOGICAL UNIT NOT READY"),
+        (0x11, 0x00) => Some("UNRECOVERED READ ERROR"),
+        (0x1A, 0x00) => Some("PARAMETER LIST LENGTH ERROR"),
+        (0x20, 0x00) => Some("INVALID COMMAND OPERATION CODE"),
+        (0x21, 0x00) => Some("LOGICAL BLOCK ADDRESS OUT OF RANGE"),
+        (0x24, 0x00) => Some("INVALID FIELD IN CDB"),
+        (0x25, 0x00) => Some("LOGICAL UNIT NOT SUPPORTED"),
+        (0x26, 0x00) => Some("INVALID FIELD IN PARAMETER LIST"),
+        (0x27, 0x00) => Some("WRITE PROTECTED"),
+        (0x28, 0x00) => Some("NOT READY TO READY CHANGE, MEDIUM MAY HAVE CHANGED"),
+        (0x29, _) => Some("POWER ON, RESET, OR BUS DEVICE RESET OCCURRED"),
+        (0x3A, _) => Some("MEDIUM NOT PRESENT"),
+        _ => None
+    }
+}
+
+pub fn describe_sense(sense_data: &[u8]) -> String {
+    /*
+        Sense Data Formats (Fixed and Descriptor):
+        SPC-4, Section 4.5
+    */
+    if sense_data.is_empty() {
+        return String::from("No Sense Data");
+    }
+
+    let (sense_key, asc, ascq) = match sense_data[0] & 0x7F {
+        0x70 | 0x71 if sense_data.len() >= 14 => (sense_data[2] & 0x0F, sense_data[12], sense_data[13]),
+        0x72 | 0x73 if sense_data.len() >= 4 => (sense_data[1] & 0x0F, sense_data[2], sense_data[3]),
+        _ => return format!("Malformed Sense Data ({} bytes)", sense_data.len())
+    };
+
+    match get_additional_sense_name(asc, ascq) {
+        Some(asc_name) => format!("{}: {}", get_sense_key_name(sense_key), asc_name),
+        None => format!("{}: ASC=0x{:02X} ASCQ=0x{:02X}", get_sense_key_name(sense_key), asc, ascq)
+    }
+}
+
+pub fn describe_command(cdb: &[u8]) -> String {
+    if cdb.is_empty() {
+        return String::from("(Empty CDB)");
+    }
+
+    /* ATA and NVMe Commands tunnelled by SATA/NVMe Bridges */
+    if let Some(bridge_command) = protocol_ata::decode_bridge_cdb(cdb) {
+        return bridge_command.describe();
+    }
+
+    /* Decode Parameters for Common Commands */
+    let opcode = cdb[0];
+    let opcode_name = get_opcode_name(opcode);
+    let parameters = match opcode {
+        0x03 | 0x12 if cdb.len() >= 6 => format!("AllocLen={}", read_be16(cdb, 3)),
+        0x08 | 0x0A if cdb.len() >= 6 => format!(
+            "LBA=0x{:X} Blocks={}",
+            ((cdb[1] as u64 & 0x1F) << 16) | read_be16(cdb, 2),
+            if cdb[4] == 0 { 256 } else { cdb[4] as u64 }
+        ),
+        0x1A if cdb.len() >= 6 => format!("Page=0x{:02X} AllocLen={}", cdb[2] & 0x3F, cdb[4]),
+        0x5A if cdb.len() >= 10 => format!("Page=0x{:02X} AllocLen={}", cdb[2] & 0x3F, read_be16(cdb, 7)),
+        0x1B if cdb.len() >= 6 => format!(
+            "{}{}",
+            if cdb[4] & 0x01 != 0 { "Start" } else { "Stop" },
+            if cdb[4] & 0x02 != 0 { ", Eject/Load" } else { "" }
+        ),
+        0x1E if cdb.len() >= 6 => format!("Prevent={}", cdb[4] & 0x03),
+        0x28 | 0x2A | 0x2F if cdb.len() >= 10 => format!("LBA=0x{:X} Blocks={}", read_be32(cdb, 2), read_be16(cdb, 7)),
+        0xA8 | 0xAA if cdb.len() >= 12 => format!("LBA=0x{:X} Blocks={}", read_be32(cdb, 2), read_be32(cdb, 6)),
+        0x88 | 0x8A | 0x8F if cdb.len() >= 16 => format!("LBA=0x{:X} Blocks={}", read_be64(cdb, 2), read_be32(cdb, 10)),
+        0x35 if cdb.len() >= 10 => format!("LBA=0x{:X} Blocks={}", read_be32(cdb, 2), read_be16(cdb, 7)),
+        0x9E if cdb.len() >= 16 && (cdb[1] & 0x1F) == 0x10 => {
+            return format!("READ CAPACITY(16) AllocLen={}", read_be32(cdb, 10));
+        },
+        0xA0 if cdb.len() >= 12 => format!("AllocLen={}", read_be32(cdb, 6)),
+        _ => String::new()
+    };
+
+    if opcode_name == "UNKNOWN" {
+        format!("Opcode 0x{:02X}", opcode)
+    } else if parameters.is_empty() {
+        String::from(opcode_name)
+    } else {
+        format!("{} {}", opcode_name, parameters)
+    }
+}
 
-    async fn consume_packet(&mut self, urb_packet: crate::sniffer::UrbXractPacket) {
+pub fn describe_data(cdb: &[u8], data: &[u8], nvme_command: Option<&NvmeCommand>) -> Option<String> {
+    if let Some(bridge_command) = protocol_ata::decode_bridge_cdb(cdb) {
+        return match bridge_command {
+            BridgeCommand::SatPassThrough(taskfile, _) => taskfile.describe_data(data),
+            BridgeCommand::CypressAtacb(taskfile) => taskfile.describe_data(data),
+            BridgeCommand::JMicronAta(taskfile, _) => taskfile.describe_data(data),
+            BridgeCommand::ASMediaNvme(nvme_command) => nvme_command.describe_data(data),
+            BridgeCommand::RealtekNvme(nvme_command) => nvme_command.describe_data(data),
+            BridgeCommand::JMicronNvme(JMicronNvmePhase::Command) => {
+                protocol_ata::decode_jmicron_nvme_command(data).map(|nvme_command| nvme_command.describe())
+            },
+            BridgeCommand::JMicronNvme(_) => nvme_command.map(|nvme_command| {
+                match nvme_command.describe_data(data) {
+                    Some(decoded_data) => format!("{}: {}", nvme_command.describe(), decoded_data),
+                    None => nvme_command.describe()
+                }
+            }),
+        };
+    }
+
+    match cdb.first()? {
+        0x12 if data.len() >= 36 => Some(format!(
+            "Type=0x{:02X} Vendor=\"{}\" Product=\"{}\" Revision=\"{}\"",
+            data[0] & 0x1F,
+            String::from_utf8_lossy(&data[8..16]).trim(),
+            String::from_utf8_lossy(&data[16..32]).trim(),
+            String::from_utf8_lossy(&data[32..36]).trim()
+        )),
+        0x25 if data.len() >= 8 => Some(format!("Last LBA=0x{:X} Block Size={}", read_be32(data, 0), read_be32(data, 4))),
+        0x9E if data.len() >= 12 => Some(format!("Last LBA=0x{:X} Block Size={}", read_be64(data, 0), read_be32(data, 8))),
+        0x03 if data.len() >= 14 => Some(describe_sense(data)),
+        _ => None
+    }
+}
+
+pub fn format_completion(description: &str, completion: &str, data_length: usize, direction: ScsiDataDirection, decoded_data: Option<String>) -> String {
+    let direction = match direction {
+        ScsiDataDirection::In => " IN",
+        ScsiDataDirection::Out => " OUT",
+        ScsiDataDirection::None => "",
+    };
+
+    let completion = format!("{} -> {} (Data: {} bytes{})", description, completion, data_length, direction);
+    match decoded_data {
+        Some(decoded_data) => format!("{} | {}", completion, decoded_data),
+        None => completion
+    }
+}
+
+fn get_csw_status_name(status: u8) -> &'static str {
+    match status {
+        0x00 => "PASSED",
+        0x01 => "FAILED",
+        0x02 => "PHASE ERROR",
+        _ => "RESERVED STATUS"
+    }
+}
+
+impl Reconstructor {
+    async fn dispatch_command(&mut self, device_key: &str, pending_command: PendingCommand, completion: String, is_error: bool) {
+        let nvme_command = self.nvme_commands.get(device_key);
+        let decoded_data = describe_data(&pending_command.cdb, &pending_command.data, nvme_command);
+
+        /* Remember the JMicron NVMe Command for the following Data and Response Phases */
+        if let Some(BridgeCommand::JMicronNvme(JMicronNvmePhase::Command)) = protocol_ata::decode_bridge_cdb(&pending_command.cdb)
+            && let Some(nvme_command) = protocol_ata::decode_jmicron_nvme_command(&pending_command.data) {
+            self.nvme_commands.insert(String::from(device_key), nvme_command);
+        }
+
+        let transmission = ReconstructedTransmission {
+            urbx_header: pending_command.urbx_header,
+            combined_payload: format_completion(
+                &pending_command.description,
+                &completion,
+                pending_command.data_length,
+                if pending_command.data_length == 0 { ScsiDataDirection::None }
+                else if pending_command.is_device_to_host { ScsiDataDirection::In }
+                else { ScsiDataDirection::Out },
+                decoded_data
+            ),
+            sources: pending_command.sources,
+            is_error,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    async fn dispatch_notice(&mut self, urb_packet: UrbXractPacket, notice: String, is_error: bool) {
         let transmission = ReconstructedTransmission {
             urbx_header: urb_packet.header,
-            combined_payload: String::from("(Indentified SCSI Packet: Parsing Not Implemented)"),
-            sources: vec![],
+            combined_payload: notice,
+            sources: vec![urb_packet],
+            is_error,
         };
 
-        /* Transmit Packet */
         self.module_tx.send(transmission).await.unwrap();
     }
-}
\ No newline at end of file
+}
+
+impl ReconstructionModule for Reconstructor {
+    fn new(module_tx: Sender<ReconstructedTransmission>, _module_context: &ModuleContext) -> Self {
+        Self {
+            module_tx,
+            pending: HashMap::new(),
+            nvme_commands: HashMap::new(),
+        }
+    }
+
+    async fn consume_packet(&mut self, urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let urb_data = urb_packet.data.as_ref().unwrap();
+        let device_key = format!("{}:{}", urb_header.bus_id, urb_header.device_id);
+
+        /* Command Transport: Command Block Wrapper */
+        if urb_data.len() >= size_of::<CommandBlockWrapper>() {
+            let cbw_packet = unsafe { ptr::read_unaligned(urb_data.as_ptr() as *const CommandBlockWrapper) };
+            if cbw_packet.signature == COMMAND_BLK_WRAP_SIGNATURE {
+                /* The previous Command never got its Status */
+                if let Some(stale_command) = self.pending.remove(&device_key) {
+                    self.dispatch_command(&device_key, stale_command, String::from("(Status Not Captured)"), false).await;
+                }
+
+                let command_length = std::cmp::min(cbw_packet.command_length as usize, cbw_packet.command_data.len());
+                let command_data = cbw_packet.command_data;
+                let cdb = command_data[0..command_length].to_vec();
+                self.pending.insert(device_key, PendingCommand {
+                    urbx_header: urb_header,
+                    tag: cbw_packet.tag,
+                    description: format!("CBW Tag=0x{:08X} LUN={}: {}", { cbw_packet.tag }, cbw_packet.logical_unitnumber & 0x0F, describe_command(&cdb)),
+                    cdb,
+                    is_device_to_host: cbw_packet.direction & 0x80 != 0,
+                    data: vec![],
+                    data_length: 0,
+                    sources: vec![UrbXractPacket { header: urb_header, data: None, iso_descriptors: vec![] }],
+                });
+
+                return;
+            }
+        }
+
+        /* Status Transport: Command Status Wrapper */
+        if urb_data.len() == size_of::<CommandStatusWrapper>() {
+            let csw_packet = unsafe { ptr::read_unaligned(urb_data.as_ptr() as *const CommandStatusWrapper) };
+            if csw_packet.signature == COMMAND_STS_WRAP_SIGNATURE {
+                /* FAILED carries a CHECK CONDITION, PHASE ERROR needs a Reset Recovery */
+                let residue = csw_packet.residue;
+                let is_error = csw_packet.status != CSW_STATUS_PASSED;
+                let completion = if residue > 0 {
+                    format!("{} (Residue: {})", get_csw_status_name(csw_packet.status), residue)
+                } else {
+                    String::from(get_csw_status_name(csw_packet.status))
+                };
+
+                match self.pending.remove(&device_key) {
+                    Some(mut pending_command) if pending_command.tag == csw_packet.tag => {
+                        pending_command.sources.push(UrbXractPacket { header: urb_header, data: None, iso_descriptors: vec![] });
+                        self.dispatch_command(&device_key, pending_command, completion, is_error).await;
+                    },
+
+                    stale_command => {
+                        if let Some(stale_command) = stale_command {
+                            self.dispatch_command(&device_key, stale_command, String::from("(Status Not Captured)"), false).await;
+                        }
+
+                        let notice = format!("CSW Tag=0x{:08X}: (Command Not Captured) -> {}", { csw_packet.tag }, completion);
+                        self.dispatch_notice(urb_packet, notice, is_error).await;
+                    }
+                }
+
+                return;
+            }
+        }
+
+        /* Data Transport: Belongs to the Outstanding Command */
+        match self.pending.get_mut(&device_key) {
+            Some(pending_command) => {
+                let keep_length = std::cmp::min(urb_data.len(), MAX_DECODE_LENGTH.saturating_sub(pending_command.data.len()));
+                pending_command.data.extend_from_slice(&urb_data[0..keep_length]);
+                pending_command.data_length += urb_data.len();
+                pending_command.sources.push(UrbXractPacket { header: urb_header, data: None, iso_descriptors: vec![] });
+            },
+
+            None => {
+                let notice = format!("BOT Data: {} bytes without Outstanding Command", urb_data.len());
+                self.dispatch_notice(urb_packet, notice, false).await;
+            }
+        }
+    }
+}
@@ -18,97 +18,470 @@
 
 use std::collections::HashMap;
 use tokio::sync::mpsc::Sender;
-use crate::sniffer::UrbXractPacket;
+use crate::sniffer::{UrbTransferType, UrbXractPacket};
 
-use super::{ReconstructedTransmission, ReconstructionModule};
+use super::device_model;
+use super::protocol_cdc::{self, LineState};
+use super::protocol_at::AtSession;
+use super::protocol_control::{self, PendingRequests};
+use super::protocol_modbus::{self, ModbusRequest};
+use super::protocol_nmea;
+use super::protocol_serial_vendor::{self, SerialVendor};
+use super::serial_codec::{self, DecodedFrame, FrameCodec, FrameDecoder};
+use super::serial_encoding;
+use super::serial_framing::{FramingRule, SerialProtocol};
+use super::{ModuleContext, ReconstructedTransmission, ReconstructionModule};
+use crate::sniffer::UrbXractHeader;
+
+#[derive(Default)]
+struct SerialStream {
+    pending_bytes: Vec<u8>, /* Incomplete Character */
+    frame_length: usize,
+    frame_tail: Vec<u8>, /* Last bytes of the Frame for Delimiter matching */
+    last_timestamp: u64,
+    idle_timeout: Option<u64>,
+    frame_decoder: Option<FrameDecoder>, /* Binary Framing, see serial_codec */
+    protocol: Option<SerialProtocol>,
+    protocol_buffer: Vec<u8>, /* Modbus ADU awaiting its remaining bytes */
+    discarded_bytes: Vec<u8>, /* Bytes skipped while resynchronizing on Modbus Frames */
+    discard_reason: String,
+}
+
+impl SerialStream {
+    fn is_binary(&self) -> bool {
+        self.frame_decoder.is_some() || self.protocol == Some(SerialProtocol::Modbus)
+    }
+
+    fn is_in_frame(&self) -> bool {
+        self.frame_length > 0
+            || !self.protocol_buffer.is_empty()
+            || !self.discarded_bytes.is_empty()
+            || self.frame_decoder.as_ref().is_some_and(|frame_decoder| frame_decoder.is_in_frame())
+    }
+}
 
 pub struct Reconstructor {
     module_tx: Sender<ReconstructedTransmission>,
-    datastore: HashMap<String, ReconstructedTransmission>, /* DeviceId, DataStore */
+    module_context: ModuleContext,
+    datastore: HashMap<String, ReconstructedTransmission>, /* Bus:Device:Endpoint, DataStore */
+    streams: HashMap<String, SerialStream>, /* Bus:Device:Endpoint, Framing State */
+    line_states: HashMap<String, LineState>, /* Bus:Device, CDC-ACM or Vendor Line State */
+    pending_requests: PendingRequests, /* Control Requests awaiting Completion */
+    modbus_requests: HashMap<String, ModbusRequest>, /* Bus:Device, Outstanding Modbus Request */
+    at_sessions: HashMap<String, AtSession>, /* Bus:Device, Open AT Transaction */
 }
 
 impl Reconstructor {
-    async fn dispatch_packet(&mut self, device_id: String) {
+    async fn dispatch_packet(&mut self, stream_key: &str) {
         /* Dispatch the Constructed Datastore */
-        match self.datastore.remove(&device_id) {
-            None => return,
-            Some(dispatch_data) => {
-                /* Transmit the Data */
-                self.module_tx.send(dispatch_data).await.unwrap();
+        if let Some(dispatch_data) = self.datastore.remove(stream_key) {
+            self.module_tx.send(dispatch_data).await.unwrap();
+        }
+    }
+
+    fn append_text(&mut self, stream_key: &str, urb_header: UrbXractHeader, text: &str) {
+        /* Rows start with the Header of their first URB */
+        let datastore = self.datastore
+            .entry(stream_key.to_string())
+            .or_insert_with(|| ReconstructedTransmission {
+                urbx_header: urb_header,
+                combined_payload: String::new(),
+                sources: vec![],
+                is_error: false,
+            });
+
+        datastore.combined_payload += text;
+        datastore.sources.push(UrbXractPacket { header: urb_header, data: None, iso_descriptors: vec![] });
+    }
+
+    fn append_bytes(&mut self, stream_key: &str, urb_header: UrbXractHeader, bytes: &[u8], is_frame_end: bool) {
+        /* A Frame End also ends a partial Character */
+        let serial_stream = self.streams.entry(stream_key.to_string()).or_default();
+        serial_stream.pending_bytes.extend_from_slice(bytes);
+        let decoded_text = serial_encoding::decode(self.module_context.serial_encoding, &mut serial_stream.pending_bytes, is_frame_end);
+        self.append_text(stream_key, urb_header, &decoded_text);
+    }
+
+    async fn dispatch_description(&mut self, stream_key: &str, urb_header: UrbXractHeader, description: String, is_error: bool) {
+        /* Frames started in an earlier URB already hold its Header */
+        if !self.datastore.contains_key(stream_key) {
+            self.append_text(stream_key, urb_header, "");
+        }
+
+        if let Some(datastore) = self.datastore.get_mut(stream_key) {
+            datastore.combined_payload = description;
+            datastore.is_error = is_error;
+        }
+
+        self.dispatch_packet(stream_key).await;
+    }
+
+    async fn dispatch_frame(&mut self, stream_key: &str, urb_header: UrbXractHeader, codec: FrameCodec, frame: DecodedFrame) {
+        let is_error = frame.error.is_some() || frame.crc_valid == Some(false);
+        self.dispatch_description(stream_key, urb_header, serial_codec::describe_frame(codec, &frame), is_error).await;
+    }
+
+    async fn consume_modbus_stream(&mut self, stream_key: &str, urb_header: UrbXractHeader, urb_data: &[u8]) {
+        let device_key = device_model::get_device_key(&urb_header);
+        let is_request = urb_header.endpoint_info & 0x80 == 0;
+        self.append_text(stream_key, urb_header, "");
+
+        let serial_stream = self.streams.entry(stream_key.to_string()).or_default();
+        serial_stream.protocol_buffer.extend_from_slice(urb_data);
+        loop {
+            let serial_stream = self.streams.entry(stream_key.to_string()).or_default();
+            let frame_length = match protocol_modbus::get_frame_length(&serial_stream.protocol_buffer, is_request) {
+                Ok(None) => break,
+                Ok(Some(frame_length)) if protocol_modbus::is_crc_valid(&serial_stream.protocol_buffer[..frame_length]) => frame_length,
+
+                /* A lost or corrupted Byte misaligns the Stream, slide by one until a Frame checks out */
+                framing_result => {
+                    if serial_stream.discarded_bytes.is_empty() {
+                        serial_stream.discard_reason = framing_result.err().unwrap_or_else(|| String::from("CRC ERROR"));
+                    }
+
+                    let discarded_byte = serial_stream.protocol_buffer.remove(0);
+                    serial_stream.discarded_bytes.push(discarded_byte);
+                    continue;
+                }
+            };
+
+            let frame: Vec<u8> = serial_stream.protocol_buffer.drain(..frame_length).collect();
+            let discarded_bytes = std::mem::take(&mut serial_stream.discarded_bytes);
+            if !discarded_bytes.is_empty() {
+                let description = format!(
+                    "[Modbus] {}, Discarded {} Bytes: {}",
+                    serial_stream.discard_reason,
+                    discarded_bytes.len(),
+                    protocol_control::format_hex(&discarded_bytes)
+                );
+
+                self.dispatch_description(stream_key, urb_header, description, true).await;
             }
+
+            let description = match is_request {
+                true => {
+                    let (description, modbus_request) = protocol_modbus::describe_request(&frame, urb_header.timestamp);
+                    match modbus_request {
+                        Some(modbus_request) => self.modbus_requests.insert(device_key.clone(), modbus_request),
+                        None => self.modbus_requests.remove(&device_key),
+                    };
+
+                    description
+                },
+                false => protocol_modbus::describe_response(&frame, urb_header.timestamp, self.modbus_requests.remove(&device_key).as_ref())
+            };
+
+            let is_error = protocol_modbus::is_error_frame(&frame, is_request);
+            self.dispatch_description(stream_key, urb_header, format!("[Modbus] {}", description), is_error).await;
+        }
+
+        /* Leftover bytes of the next ADU start a Row with this URB */
+        let has_partial_adu = self.streams.get(stream_key).is_some_and(|serial_stream| !serial_stream.protocol_buffer.is_empty() || !serial_stream.discarded_bytes.is_empty());
+        if has_partial_adu && !self.datastore.contains_key(stream_key) {
+            self.append_text(stream_key, urb_header, "");
+        }
+    }
+
+    async fn consume_codec_stream(&mut self, stream_key: &str, urb_header: UrbXractHeader, urb_data: &[u8], codec: FrameCodec, max_length: Option<usize>) {
+        let mut is_sourced = false;
+        for byte in urb_data {
+            let frame_decoder = self.streams
+                .entry(stream_key.to_string())
+                .or_default()
+                .frame_decoder
+                .get_or_insert_with(|| FrameDecoder::new(codec, max_length));
+
+            if let Some(frame) = frame_decoder.push(*byte) {
+                self.dispatch_frame(stream_key, urb_header, codec, frame).await;
+                is_sourced = false;
+            } else if frame_decoder.is_in_frame() && !is_sourced {
+                self.append_text(stream_key, urb_header, "");
+                is_sourced = true;
+            }
+        }
+    }
+
+    async fn close_frame(&mut self, stream_key: &str) {
+        /* Binary Frames cut short are still shown, marked incomplete */
+        let partial_frame = self.streams
+            .get_mut(stream_key)
+            .and_then(|serial_stream| serial_stream.frame_decoder.as_mut())
+            .and_then(|frame_decoder| frame_decoder.take_partial().map(|frame| (frame_decoder.get_codec(), frame)));
+
+        if let Some((codec, frame)) = partial_frame
+            && let Some(urb_header) = self.datastore.get(stream_key).map(|datastore| datastore.urbx_header) {
+            return self.dispatch_frame(stream_key, urb_header, codec, frame).await;
+        }
+
+        let partial_adu = self.streams
+            .get_mut(stream_key)
+            .map(|serial_stream| [std::mem::take(&mut serial_stream.discarded_bytes), std::mem::take(&mut serial_stream.protocol_buffer)].concat())
+            .unwrap_or_default();
+
+        if !partial_adu.is_empty()
+            && let Some(urb_header) = self.datastore.get(stream_key).map(|datastore| datastore.urbx_header) {
+            let description = format!(
+                "[Modbus] Incomplete Frame: {}",
+                protocol_control::format_hex(&partial_adu)
+            );
+
+            return self.dispatch_description(stream_key, urb_header, description, true).await;
+        }
+
+        if let Some(serial_stream) = self.streams.get_mut(stream_key) {
+            let pending_text = serial_encoding::decode(self.module_context.serial_encoding, &mut serial_stream.pending_bytes, true);
+            if let Some(datastore) = self.datastore.get_mut(stream_key) {
+                datastore.combined_payload += &pending_text;
+            }
+
+            serial_stream.frame_length = 0;
+            serial_stream.frame_tail.clear();
+
+            /* Sentences with a Checksum are recognized without Configuration */
+            let is_checksum_required = serial_stream.protocol != Some(SerialProtocol::Nmea);
+            if let Some(datastore) = self.datastore.get_mut(stream_key)
+                && let Some(description) = protocol_nmea::describe_sentence(&datastore.combined_payload, is_checksum_required) {
+                datastore.combined_payload = description;
+            }
+        }
+
+        /* AT Rows are held until their Transaction completes */
+        if self.streams.get(stream_key).is_some_and(|serial_stream| serial_stream.protocol == Some(SerialProtocol::At))
+            && let Some(row) = self.datastore.remove(stream_key) {
+            let is_out = row.urbx_header.endpoint_info & 0x80 == 0;
+            let at_session = self.at_sessions.entry(device_model::get_device_key(&row.urbx_header)).or_default();
+            for dispatch_row in at_session.consume_row(row, is_out) {
+                self.module_tx.send(dispatch_row).await.unwrap();
+            }
+
+            return;
+        }
+
+        self.dispatch_packet(stream_key).await;
+    }
+
+    async fn flush_streams(&mut self, device_key: &str) {
+        /* Binary Frames are self-delimiting and stay open across Line Events */
+        let stream_prefix = format!("{}:", device_key);
+        let mut stream_keys: Vec<String> = self.datastore
+            .keys()
+            .filter(|stream_key| stream_key.starts_with(&stream_prefix))
+            .filter(|stream_key| self.streams.get(*stream_key).is_none_or(|serial_stream| !serial_stream.is_binary()))
+            .cloned()
+            .collect();
+
+        stream_keys.sort();
+        for stream_key in stream_keys {
+            self.close_frame(&stream_key).await;
+        }
+    }
+
+    async fn flush_idle_streams(&mut self, timestamp: u64) {
+        /* Streams without an Idle Timeout stay open until the Capture ends */
+        let mut stream_keys: Vec<String> = self.streams
+            .iter()
+            .filter(|(_, serial_stream)| serial_stream.is_in_frame())
+            .filter(|(_, serial_stream)| match serial_stream.idle_timeout {
+                Some(idle_timeout) => timestamp.saturating_sub(serial_stream.last_timestamp) > idle_timeout,
+                None => timestamp == u64::MAX,
+            })
+            .map(|(stream_key, _)| stream_key.clone())
+            .collect();
+
+        stream_keys.sort();
+        for stream_key in stream_keys {
+            self.close_frame(&stream_key).await;
+        }
+    }
+
+    fn get_device_ids(&self, urb_header: &UrbXractHeader) -> Option<(u16, u16)> {
+        let device_registry = self.module_context.device_registry.read().unwrap();
+        let descriptor = device_registry.get_device(urb_header)?.descriptor.as_ref()?;
+        Some((descriptor.vendor_id, descriptor.product_id))
+    }
+
+    async fn consume_text_stream(&mut self, stream_key: &str, urb_header: UrbXractHeader, urb_data: &[u8], framing_rule: &FramingRule) {
+        /* Split the URB wherever the Rule ends a Frame */
+        let tail_length = framing_rule.get_max_delimiter_length();
+        let mut frame_start = 0;
+        for (offset, byte) in urb_data.iter().enumerate() {
+            let serial_stream = self.streams.entry(stream_key.to_string()).or_default();
+            serial_stream.frame_length += 1;
+            serial_stream.frame_tail.push(*byte);
+            if serial_stream.frame_tail.len() > tail_length {
+                serial_stream.frame_tail.remove(0);
+            }
+
+            if framing_rule.is_frame_end(serial_stream.frame_length, &serial_stream.frame_tail) {
+                self.append_bytes(stream_key, urb_header, &urb_data[frame_start..=offset], true);
+                self.close_frame(stream_key).await;
+                frame_start = offset + 1;
+            }
+        }
+
+        if frame_start < urb_data.len() {
+            self.append_bytes(stream_key, urb_header, &urb_data[frame_start..], false);
+        }
+
+        /* Prompts without a Delimiter are matched on the decoded Row */
+        if let Some(pattern) = &framing_rule.pattern
+            && self.datastore.get(stream_key).is_some_and(|datastore| pattern.is_match(&datastore.combined_payload)) {
+            self.close_frame(stream_key).await;
+        }
+    }
+
+    async fn consume_stream(&mut self, urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let Some(urb_data) = urb_packet.data else { return };
+        let device_key = device_model::get_device_key(&urb_header);
+        let stream_key = format!("{}:{:02x}", device_key, urb_header.endpoint_info);
+        let serial_framing = self.module_context.serial_framing.clone();
+        let framing_rule = serial_framing.get_rule(&device_key, self.get_device_ids(&urb_header));
+
+        /* Modbus brings its own Framing, Codecs replace Text Framing */
+        self.streams.entry(stream_key.clone()).or_default().protocol = framing_rule.protocol;
+        match (framing_rule.protocol, framing_rule.codec) {
+            (Some(SerialProtocol::Modbus), _) => self.consume_modbus_stream(&stream_key, urb_header, &urb_data).await,
+            (_, Some(codec)) => self.consume_codec_stream(&stream_key, urb_header, &urb_data, codec, framing_rule.max_length).await,
+            _ => self.consume_text_stream(&stream_key, urb_header, &urb_data, framing_rule).await,
+        }
+
+        if let Some(serial_stream) = self.streams.get_mut(&stream_key) {
+            serial_stream.last_timestamp = urb_header.timestamp;
+            serial_stream.idle_timeout = framing_rule.idle_timeout;
+        }
+    }
+
+    fn get_serial_vendor(&self, urb_header: &UrbXractHeader) -> Option<(SerialVendor, bool)> {
+        /* Vendor Adapters are identified by the snooped Device Descriptor */
+        let device_registry = self.module_context.device_registry.read().unwrap();
+        let device = device_registry.get_device(urb_header)?;
+        let descriptor = device.descriptor.as_ref()?;
+        let is_multi_port = device.get_active_configuration().is_some_and(|configuration| configuration.num_interfaces > 1);
+
+        SerialVendor::from_ids(descriptor.vendor_id, descriptor.product_id).map(|vendor| (vendor, is_multi_port))
+    }
+
+    fn get_max_packet_size(&self, urb_header: &UrbXractHeader) -> Option<usize> {
+        let device_registry = self.module_context.device_registry.read().unwrap();
+        let endpoint = device_registry.get_device(urb_header)?.get_endpoint(urb_header.endpoint_info)?;
+        Some((endpoint.max_packet_size & 0x07FF) as usize)
+    }
+
+    async fn dispatch_line_event(&mut self, urb_packet: UrbXractPacket, description: String) {
+        /* Flush pending Text so the Event appears in order with the Stream */
+        self.flush_streams(&device_model::get_device_key(&urb_packet.header)).await;
+
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_packet.header,
+            combined_payload: format!(
+                "[{}] {}",
+                self.get_serial_vendor(&urb_packet.header).map(|(vendor, _)| vendor.get_name()).unwrap_or("CDC-ACM"),
+                description
+            ),
+            sources: vec![urb_packet],
+            is_error: false,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    async fn consume_control(&mut self, mut urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let Some((setup_packet, data)) = self.pending_requests.pair(&mut urb_packet) else { return };
+        if urb_header.status != 0 {
+            let description = format!(
+                "{} -> {}",
+                protocol_control::describe_setup(&setup_packet, Some(0x02)),
+                protocol_control::describe_status(&urb_header)
+            );
+
+            return self.dispatch_line_event(urb_packet, description).await;
+        }
+
+        let serial_vendor = self.get_serial_vendor(&urb_header);
+        let line_state = self.line_states.entry(device_model::get_device_key(&urb_header)).or_default();
+        let description = match serial_vendor {
+            Some((vendor, is_multi_port)) => protocol_serial_vendor::describe_vendor_request(vendor, line_state, &setup_packet, data, is_multi_port),
+            None => protocol_cdc::describe_acm_request(line_state, &setup_packet, data)
+        }
+        .unwrap_or_else(|| protocol_control::describe_setup(&setup_packet, Some(0x02)));
+
+        self.dispatch_line_event(urb_packet, description).await;
+    }
+
+    async fn consume_notification(&mut self, urb_packet: UrbXractPacket) {
+        let urb_data = urb_packet.data.as_deref().unwrap_or_default();
+        let serial_vendor = self.get_serial_vendor(&urb_packet.header);
+        let line_state = self.line_states.entry(device_model::get_device_key(&urb_packet.header)).or_default();
+        let description = match serial_vendor {
+            Some((vendor, _)) => protocol_serial_vendor::describe_vendor_notification(vendor, line_state, urb_data),
+            None => protocol_cdc::describe_notification(line_state, urb_data)
+        };
+
+        if let Some(description) = description {
+            self.dispatch_line_event(urb_packet, description).await;
         }
     }
 }
 
 impl ReconstructionModule for Reconstructor {
-    fn new(module_tx: Sender<ReconstructedTransmission>) -> Self {
+    fn new(module_tx: Sender<ReconstructedTransmission>, module_context: &ModuleContext) -> Self {
         Self {
             module_tx,
+            module_context: module_context.clone(),
             datastore: HashMap::new(),
+            streams: HashMap::new(),
+            line_states: HashMap::new(),
+            pending_requests: PendingRequests::default(),
+            modbus_requests: HashMap::new(),
+            at_sessions: HashMap::new(),
         }
     }
 
-    async fn consume_packet(&mut self, urb_packet: crate::sniffer::UrbXractPacket) {
-        let urb_header = &urb_packet.header;
-        let urb_data = urb_packet.data.as_ref().unwrap();
-        let strbuild_result = String::from_utf8(urb_data.to_vec());
-
-        /* Construct Datastore */
-        let device_id = &format!("{}:{}:{}", urb_header.bus_id, urb_header.device_id, (urb_header.endpoint_info & 0b10000000 == 0));
-        let datastore = self.datastore.get(device_id);
-        
-        if strbuild_result.is_err() {
-            if datastore.is_some() {
-                /* We have data from previous packets, dispatch it */
-                self.dispatch_packet(String::from(device_id)).await;
-            } else {
-                /* Build a new Packet */
-                self.datastore.insert(
-                    String::from(device_id),
-                    ReconstructedTransmission {
-                        urbx_header: urb_packet.header,
-                        combined_payload: String::from("(Non-UTF8 Binary Data)"),
-                        sources: vec![urb_packet], /* Should have URB Packet? */
-                    }
-                );
+    async fn consume_packet(&mut self, mut urb_packet: crate::sniffer::UrbXractPacket) {
+        /* CDC-ACM and Vendor Requests and Notifications are shown inline with the Stream */
+        let serial_vendor = self.get_serial_vendor(&urb_packet.header).map(|(vendor, _)| vendor);
+        match urb_packet.header.transfer_type {
+            UrbTransferType::Control => return self.consume_control(urb_packet).await,
+            UrbTransferType::Interrupt if serial_vendor.is_some() => return self.consume_notification(urb_packet).await,
+            UrbTransferType::Interrupt if protocol_cdc::is_notification(urb_packet.data.as_deref().unwrap_or_default()) => {
+                return self.consume_notification(urb_packet).await;
+            },
+            _ => {}
+        }
+
+        /* FTDI prefixes every Bulk IN Packet with its Modem and Line Status */
+        if serial_vendor == Some(SerialVendor::Ftdi) && urb_packet.header.endpoint_info & 0x80 != 0 {
+            let max_packet_size = self.get_max_packet_size(&urb_packet.header);
+            let line_state = self.line_states.entry(device_model::get_device_key(&urb_packet.header)).or_default();
+            let (serial_data, status_change) = protocol_serial_vendor::strip_ftdi_status(line_state, urb_packet.data.as_deref().unwrap_or_default(), max_packet_size);
 
-                /* Dispatch the Packet */
-                self.dispatch_packet(String::from(device_id)).await;
+            if let Some(status_change) = status_change {
+                let status_packet = UrbXractPacket { header: urb_packet.header, data: None, iso_descriptors: vec![] };
+                self.dispatch_line_event(status_packet, status_change).await;
             }
-        } else {
-            /* We have a valid UTF8 Serial String */
-            let parsed_strdata = strbuild_result.unwrap();
-
-            /* Check Datastore, Create one if it doesn't exist */
-            if datastore.is_none() {
-                self.datastore.insert(
-                    String::from(device_id), 
-                    ReconstructedTransmission {
-                        urbx_header: urb_packet.header,
-                        combined_payload: parsed_strdata.clone(),
-                        sources: vec![
-                            UrbXractPacket { 
-                                header: urb_packet.header, 
-                                data: None 
-                            }
-                        ],
-                    }
-                );
-            } else {
-                /* Expand the Previous String */
-                let datastore_mut = self.datastore.get_mut(device_id).unwrap();
-                datastore_mut.combined_payload += &parsed_strdata;
-                datastore_mut.sources.push(
-                    UrbXractPacket {
-                        header: urb_packet.header,
-                        data: None
-                    }
-                );
+
+            if serial_data.is_empty() {
+                return;
             }
 
-            /* If \r\n or \n, dispatch the packet */
-            if parsed_strdata.ends_with("\n") {
-                self.dispatch_packet(String::from(device_id)).await;
+            urb_packet.data = Some(serial_data);
+        }
+
+        self.consume_stream(urb_packet).await;
+    }
+
+    async fn flush_idle(&mut self, timestamp: u64) {
+        self.flush_idle_streams(timestamp).await;
+
+        /* Closed Streams may have completed the Transaction first */
+        let mut device_keys: Vec<String> = self.at_sessions.keys().cloned().collect();
+        device_keys.sort();
+        for device_key in device_keys {
+            if let Some(transaction_row) = self.at_sessions.get_mut(&device_key).and_then(|at_session| at_session.flush_idle(timestamp)) {
+                self.module_tx.send(transaction_row).await.unwrap();
             }
         }
     }
@@ -0,0 +1,380 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>. 
+*/
+
+use super::device_model::SetupPacket;
+use super::protocol_cdc::{self, LineCoding, LineState};
+
+/*
+    Vendor USB-Serial Adapters, Register layouts follow the Linux drivers:
+    drivers/usb/serial/{ftdi_sio, cp210x, ch341, pl2303}.c
+*/
+const FTDI_STATUS_LENGTH: usize = 2;
+const FTDI_DEFAULT_PACKET_SIZE: usize = 64;
+const CP210X_BAUD_CLOCK: u32 = 3_686_400;
+const CH341_BAUD_CLOCK: u32 = 48_000_000;
+const CH341_REGISTER_LCR: u16 = 0x2518;
+const CH341_REGISTER_BAUD: u16 = 0x1312;
+const PL2303_STATUS_OFFSET: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SerialVendor {
+    Ftdi,
+    Cp210x,
+    Ch34x,
+    Pl2303
+}
+
+impl SerialVendor {
+    pub fn from_ids(vendor_id: u16, product_id: u16) -> Option<Self> {
+        match (vendor_id, product_id) {
+            (0x0403, _) => Some(SerialVendor::Ftdi),
+            (0x10C4, 0xEA60 | 0xEA61 | 0xEA63 | 0xEA70 | 0xEA71 | 0xEA80) => Some(SerialVendor::Cp210x),
+            (0x1A86, 0x5523 | 0x7522 | 0x7523 | 0x55D2 | 0x55D3 | 0x55D4) => Some(SerialVendor::Ch34x),
+            (0x067B, 0x2303 | 0x23A3 | 0x23B3 | 0x23C3 | 0x23D3 | 0x23E3 | 0x23F3) => Some(SerialVendor::Pl2303),
+            _ => None
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            SerialVendor::Ftdi => "FTDI",
+            SerialVendor::Cp210x => "CP210x",
+            SerialVendor::Ch34x => "CH34x",
+            SerialVendor::Pl2303 => "PL2303",
+        }
+    }
+}
+
+fn describe_data_format(data_bits: u8, parity: u8, stop_bits: u8) -> LineCoding {
+    /* Reuse the CDC Line Coding notation without a Baud Rate */
+    LineCoding { baud_rate: 0, stop_bits, parity, data_bits }
+}
+
+fn update_data_format(line_state: &mut LineState, data_format: LineCoding) -> String {
+    /* Vendor Adapters set Baud Rate and Data Format separately */
+    let baud_rate = line_state.line_coding.map(|line_coding| line_coding.baud_rate).unwrap_or(0);
+    line_state.update_line_coding(LineCoding { baud_rate, ..data_format })
+}
+
+fn update_baud_rate(line_state: &mut LineState, baud_rate: u32) -> String {
+    let data_format = line_state.line_coding.unwrap_or(describe_data_format(8, 0, 0));
+    line_state.update_line_coding(LineCoding { baud_rate, ..data_format })
+}
+
+fn get_ftdi_baud_rate(setup_packet: &SetupPacket, is_multi_port: bool) -> u32 {
+    /* Multi-Port Chips carry the Port in the low Byte of wIndex */
+    let divisor_high = if is_multi_port { setup_packet.index >> 8 } else { setup_packet.index };
+    let base_clock: f64 = if divisor_high & 0x02 != 0 { 12_000_000.0 } else { 3_000_000.0 };
+
+    /* Sub-Integer Divisors in eighths, selected by Bits 15..14 and the next Bit */
+    const FRACTION_EIGHTHS: [u32; 8] = [0, 4, 2, 1, 3, 5, 6, 7];
+    let integer_divisor = (setup_packet.value & 0x3FFF) as u32;
+    let fraction_code = ((setup_packet.value >> 14) | ((divisor_high & 0x01) << 2)) as usize;
+    let divisor_eighths = integer_divisor * 8 + FRACTION_EIGHTHS[fraction_code];
+
+    match (integer_divisor, fraction_code) {
+        (0, 0) => base_clock as u32,
+        (1, 0) if base_clock < 12_000_000.0 => 2_000_000,
+        _ => (base_clock * 8.0 / divisor_eighths as f64).round() as u32
+    }
+}
+
+fn describe_ftdi_request(line_state: &mut LineState, setup_packet: &SetupPacket, data: Option<&[u8]>, is_multi_port: bool) -> Option<String> {
+    let description = match setup_packet.request {
+        0x00 => format!("RESET: {}", match setup_packet.value { 0 => "SIO", 1 => "Purge RX", 2 => "Purge TX", _ => "Unknown" }),
+        0x01 => {
+            /* Bits 9..8 select which of DTR (Bit 0) and RTS (Bit 1) are written */
+            let dtr = (setup_packet.value & 0x0100 != 0).then_some(setup_packet.value & 0x01 != 0);
+            let rts = (setup_packet.value & 0x0200 != 0).then_some(setup_packet.value & 0x02 != 0);
+            format!("SET_MODEM_CTRL: {}", line_state.update_control_lines(dtr, rts))
+        },
+        0x02 => format!("SET_FLOW_CTRL: {}", match setup_packet.index >> 8 {
+            0x00 => String::from("None"),
+            0x01 => String::from("RTS/CTS"),
+            0x02 => String::from("DTR/DSR"),
+            0x04 => format!("XON/XOFF (XON=0x{:02X} XOFF=0x{:02X})", setup_packet.value & 0xFF, setup_packet.value >> 8),
+            flow_mode => format!("Unknown 0x{:02X}", flow_mode)
+        }),
+        0x03 => format!("SET_BAUD_RATE: {}", update_baud_rate(line_state, get_ftdi_baud_rate(setup_packet, is_multi_port))),
+        0x04 => {
+            let data_format = describe_data_format(
+                (setup_packet.value & 0xFF) as u8,
+                ((setup_packet.value >> 8) & 0x07) as u8,
+                ((setup_packet.value >> 11) & 0x03) as u8
+            );
+
+            format!(
+                "SET_DATA: {}{}",
+                update_data_format(line_state, data_format),
+                if setup_packet.value & 0x4000 != 0 { ", Break On" } else { "" }
+            )
+        },
+        0x05 => match data {
+            Some(data) if !data.is_empty() => {
+                let modem_status = get_ftdi_modem_status(data);
+                format!("GET_MODEM_STATUS: {}", line_state.update_modem_status(modem_status).unwrap_or(String::from("Unchanged")))
+            },
+            _ => String::from("GET_MODEM_STATUS")
+        },
+        0x06 => format!("SET_EVENT_CHAR: 0x{:02X} {}", setup_packet.value & 0xFF, if setup_packet.value & 0x100 != 0 { "Enabled" } else { "Disabled" }),
+        0x07 => format!("SET_ERROR_CHAR: 0x{:02X} {}", setup_packet.value & 0xFF, if setup_packet.value & 0x100 != 0 { "Enabled" } else { "Disabled" }),
+        0x09 => format!("SET_LATENCY_TIMER: {} ms", setup_packet.value & 0xFF),
+        0x0A => match data.and_then(|data| data.first()) {
+            Some(latency) => format!("GET_LATENCY_TIMER: {} ms", latency),
+            None => String::from("GET_LATENCY_TIMER")
+        },
+        0x0B => format!("SET_BITMODE: Mode 0x{:02X}, Mask 0x{:02X}", setup_packet.value >> 8, setup_packet.value & 0xFF),
+        0x0C => String::from("READ_PINS"),
+        0x90 => format!("READ_EEPROM: Word 0x{:02X}", setup_packet.index),
+        0x91 => format!("WRITE_EEPROM: Word 0x{:02X} = 0x{:04X}", setup_packet.index, setup_packet.value),
+        0x92 => String::from("ERASE_EEPROM"),
+        request => format!("Vendor Request 0x{:02X}", request)
+    };
+
+    Some(description)
+}
+
+fn get_ftdi_modem_status(status_data: &[u8]) -> u8 {
+    /* Byte 0: CTS, DSR, RI, DCD in Bits 7..4, Byte 1: OE, PE, FE, BI in Bits 4..1 */
+    let modem_byte = status_data[0];
+    let line_byte = *status_data.get(1).unwrap_or(&0);
+
+    ((modem_byte >> 4) & 0x0F) | ((line_byte << 3) & 0xF0)
+}
+
+pub fn strip_ftdi_status(line_state: &mut LineState, data: &[u8], max_packet_size: Option<usize>) -> (Vec<u8>, Option<String>) {
+    /* Every Packet of a Bulk IN Transfer starts with two Status Bytes */
+    let packet_size = max_packet_size.unwrap_or_else(|| {
+        /* High-Speed Chips use 512 byte Packets, the Status Byte has its low Nibble set to 1 */
+        let is_highspeed = data.len() > FTDI_DEFAULT_PACKET_SIZE && data[FTDI_DEFAULT_PACKET_SIZE] & 0x0F != 0x01;
+        if is_highspeed { 512 } else { FTDI_DEFAULT_PACKET_SIZE }
+    });
+
+    let mut serial_data = Vec::with_capacity(data.len());
+    let mut status_changes = vec![];
+    for packet in data.chunks(packet_size.max(FTDI_STATUS_LENGTH + 1)) {
+        if packet.len() < FTDI_STATUS_LENGTH {
+            break;
+        }
+
+        if let Some(status_change) = line_state.update_modem_status(get_ftdi_modem_status(packet)) {
+            status_changes.push(status_change);
+        }
+
+        serial_data.extend_from_slice(&packet[FTDI_STATUS_LENGTH..]);
+    }
+
+    (serial_data, status_changes.pop())
+}
+
+fn describe_cp210x_request(line_state: &mut LineState, setup_packet: &SetupPacket, data: Option<&[u8]>) -> Option<String> {
+    let description = match setup_packet.request {
+        0x00 => format!("IFC_ENABLE: {}", if setup_packet.value != 0 { "Enabled" } else { "Disabled" }),
+        0x01 if setup_packet.value != 0 => format!("SET_BAUDDIV: {}", update_baud_rate(line_state, CP210X_BAUD_CLOCK / setup_packet.value as u32)),
+        0x02 => String::from("GET_BAUDDIV"),
+        0x03 => {
+            /* Stop Bits in Bits 3..0, Parity in Bits 7..4, Data Bits in Bits 15..8 */
+            let data_format = describe_data_format(
+                (setup_packet.value >> 8) as u8,
+                ((setup_packet.value >> 4) & 0x0F) as u8,
+                (setup_packet.value & 0x0F) as u8
+            );
+
+            format!("SET_LINE_CTL: {}", update_data_format(line_state, data_format))
+        },
+        0x04 => String::from("GET_LINE_CTL"),
+        0x05 => format!("SET_BREAK: {}", if setup_packet.value != 0 { "On" } else { "Off" }),
+        0x07 => {
+            let dtr = (setup_packet.value & 0x0100 != 0).then_some(setup_packet.value & 0x01 != 0);
+            let rts = (setup_packet.value & 0x0200 != 0).then_some(setup_packet.value & 0x02 != 0);
+            format!("SET_MHS: {}", line_state.update_control_lines(dtr, rts))
+        },
+        0x08 => match data.and_then(|data| data.first()) {
+            Some(modem_byte) => {
+                /* CTS, DSR, RI, DCD in Bits 7..4 */
+                let modem_status = (modem_byte >> 4) & 0x0F;
+                format!("GET_MDMSTS: {}", line_state.update_modem_status(modem_status).unwrap_or(String::from("Unchanged")))
+            },
+            None => String::from("GET_MDMSTS")
+        },
+        0x0F => String::from("GET_PROPS"),
+        0x10 => match data {
+            Some(data) if data.len() >= 4 => {
+                let errors = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+                let error_names: Vec<&str> = [(0x01, "BREAK"), (0x02, "FRAMING_ERROR"), (0x04, "HW_OVERRUN"), (0x08, "QUEUE_OVERRUN"), (0x10, "PARITY_ERROR")]
+                    .iter()
+                    .filter(|(error_bit, _)| errors & error_bit != 0)
+                    .map(|(_, error_name)| *error_name)
+                    .collect();
+
+                format!("GET_COMM_STATUS: {}", if error_names.is_empty() { String::from("No Errors") } else { error_names.join(" ") })
+            },
+            _ => String::from("GET_COMM_STATUS")
+        },
+        0x11 => String::from("RESET"),
+        0x12 => format!("PURGE: 0x{:04X}", setup_packet.value),
+        0x13 => match data {
+            Some(data) if data.len() >= 8 => {
+                /* ulControlHandshake Bit 3 enables CTS, ulFlowReplace Bits 1..0 enable XON/XOFF */
+                let control_handshake = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+                let flow_replace = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+                let mut flow_modes = vec![];
+                if control_handshake & 0x08 != 0 { flow_modes.push("RTS/CTS"); }
+                if control_handshake & 0x10 != 0 { flow_modes.push("DSR/DTR"); }
+                if flow_replace & 0x03 != 0 { flow_modes.push("XON/XOFF"); }
+
+                format!("SET_FLOW: {}", if flow_modes.is_empty() { String::from("None") } else { flow_modes.join(", ") })
+            },
+            _ => String::from("SET_FLOW")
+        },
+        0x14 => String::from("GET_FLOW"),
+        0x19 => String::from("SET_CHARS"),
+        0x1D => match data {
+            Some(data) if data.len() >= 4 => format!("GET_BAUDRATE: {}", u32::from_le_bytes([data[0], data[1], data[2], data[3]])),
+            _ => String::from("GET_BAUDRATE")
+        },
+        0x1E => match data {
+            Some(data) if data.len() >= 4 => format!("SET_BAUDRATE: {}", update_baud_rate(line_state, u32::from_le_bytes([data[0], data[1], data[2], data[3]]))),
+            _ => String::from("SET_BAUDRATE")
+        },
+        0xFF => format!("VENDOR_SPECIFIC: 0x{:04X}", setup_packet.value),
+        request => format!("Vendor Request 0x{:02X}", request)
+    };
+
+    Some(description)
+}
+
+fn describe_ch34x_request(line_state: &mut LineState, setup_packet: &SetupPacket, data: Option<&[u8]>) -> Option<String> {
+    let description = match (setup_packet.request, setup_packet.value) {
+        (0x5F, _) => match data {
+            Some(data) if data.len() >= 2 => format!("READ_VERSION: 0x{:02X}{:02X}", data[0], data[1]),
+            _ => String::from("READ_VERSION")
+        },
+        (0xA1, _) => String::from("SERIAL_INIT"),
+        (0x9A, CH341_REGISTER_BAUD) => {
+            /* Baud = 48 MHz / (2^(12 - 3 * Prescaler - Factor) * (256 - Divisor)) */
+            let prescaler = (setup_packet.index & 0x03) as u32;
+            let factor = ((setup_packet.index >> 2) & 0x01) as u32;
+            let divisor = 256 - (setup_packet.index >> 8) as u32;
+            let clock_shift = 12u32.saturating_sub(3 * prescaler + factor);
+            let baud_rate = CH341_BAUD_CLOCK / ((1 << clock_shift) * divisor.max(1));
+
+            format!("WRITE_REG Baud Rate: {}", update_baud_rate(line_state, baud_rate))
+        },
+        (0x9A, CH341_REGISTER_LCR) => {
+            /* Data Bits 5..8 in Bits 1..0, Bit 2 two Stop Bits, Bit 3 Parity Enable, Bits 5..4 Parity */
+            let lcr = setup_packet.index & 0xFF;
+            let parity = match (lcr & 0x08 != 0, (lcr >> 4) & 0x03) {
+                (false, _) => 0,
+                (true, 0) => 1,
+                (true, 1) => 2,
+                (true, 2) => 3,
+                (true, _) => 4
+            };
+
+            let data_format = describe_data_format(5 + (lcr & 0x03) as u8, parity, if lcr & 0x04 != 0 { 2 } else { 0 });
+            format!("WRITE_REG Line Control: {}", update_data_format(line_state, data_format))
+        },
+        (0x9A, register) => format!("WRITE_REG: 0x{:04X} = 0x{:04X}", register, setup_packet.index),
+        (0x95, 0x0706) => match data.and_then(|data| data.first()) {
+            Some(modem_byte) => {
+                /* Status Register is active low: CTS, DSR, RI, DCD in Bits 3..0 */
+                let modem_status = !modem_byte & 0x0F;
+                format!("READ_REG Modem Status: {}", line_state.update_modem_status(modem_status).unwrap_or(String::from("Unchanged")))
+            },
+            None => String::from("READ_REG Modem Status")
+        },
+        (0x95, register) => format!("READ_REG: 0x{:04X}", register),
+        (0xA4, _) => {
+            /* Modem Control is active low: DTR in Bit 5, RTS in Bit 6 */
+            let dtr = setup_packet.value & 0x20 == 0;
+            let rts = setup_packet.value & 0x40 == 0;
+            format!("MODEM_CTRL: {}", line_state.update_control_lines(Some(dtr), Some(rts)))
+        },
+        (request, _) => format!("Vendor Request 0x{:02X}", request)
+    };
+
+    Some(description)
+}
+
+fn describe_pl2303_request(line_state: &mut LineState, setup_packet: &SetupPacket, data: Option<&[u8]>) -> Option<String> {
+    /* Line Coding and Control Lines use the CDC-ACM Requests */
+    if (setup_packet.request_type >> 5) & 0x03 == 1 {
+        return protocol_cdc::describe_acm_request(line_state, setup_packet, data);
+    }
+
+    let description = match (setup_packet.request_type & 0x80 != 0, setup_packet.request) {
+        (false, 0x01) if setup_packet.value == 0 && (setup_packet.index == 0x41 || setup_packet.index == 0x61) => String::from("Flow Control: RTS/CTS"),
+        (false, 0x01) if setup_packet.value == 0 && setup_packet.index == 0x00 => String::from("Flow Control: None"),
+        (false, 0x01) => format!("VENDOR_WRITE: 0x{:04X} = 0x{:04X}", setup_packet.value, setup_packet.index),
+        (true, 0x01) => match data.and_then(|data| data.first()) {
+            Some(register_value) => format!("VENDOR_READ: 0x{:04X} -> 0x{:02X}", setup_packet.value, register_value),
+            None => format!("VENDOR_READ: 0x{:04X}", setup_packet.value)
+        },
+        (false, 0x80) if setup_packet.value == 0x0A => format!("Flow Control: {}", match setup_packet.index {
+            0xFA => "RTS/CTS",
+            0xEE => "XON/XOFF",
+            _ => "None"
+        }),
+        (false, 0x80) => format!("HXN_WRITE: 0x{:04X} = 0x{:04X}", setup_packet.value, setup_packet.index),
+        (true, 0x81) => format!("HXN_READ: 0x{:04X}", setup_packet.value),
+        (_, request) => format!("Vendor Request 0x{:02X}", request)
+    };
+
+    Some(description)
+}
+
+pub fn describe_vendor_request(vendor: SerialVendor, line_state: &mut LineState, setup_packet: &SetupPacket, data: Option<&[u8]>, is_multi_port: bool) -> Option<String> {
+    match vendor {
+        SerialVendor::Ftdi => describe_ftdi_request(line_state, setup_packet, data, is_multi_port),
+        SerialVendor::Cp210x => describe_cp210x_request(line_state, setup_packet, data),
+        SerialVendor::Ch34x => describe_ch34x_request(line_state, setup_packet, data),
+        SerialVendor::Pl2303 => describe_pl2303_request(line_state, setup_packet, data),
+    }
+}
+
+pub fn describe_vendor_notification(vendor: SerialVendor, line_state: &mut LineState, data: &[u8]) -> Option<String> {
+    match vendor {
+        SerialVendor::Ch34x if data.len() >= 4 => {
+            /* Byte 2 holds the active low Modem Status, Byte 1 the Line Errors */
+            let line_errors = if data[1] & 0x04 != 0 { protocol_cdc::LINE_STATUS_OVERRUN } else { 0 }
+                | if data[1] & 0x08 != 0 { protocol_cdc::LINE_STATUS_PARITY } else { 0 }
+                | if data[1] & 0x40 != 0 { protocol_cdc::LINE_STATUS_FRAMING } else { 0 };
+
+            line_state.update_modem_status((!data[2] & 0x0F) | line_errors)
+        },
+
+        SerialVendor::Pl2303 if data.len() > PL2303_STATUS_OFFSET => {
+            /* UART State: DCD, DSR, BREAK, RING, FRAMING, PARITY, OVERRUN, CTS */
+            let uart_state = data[PL2303_STATUS_OFFSET];
+            let bit = |mask: u8, status: u8| if uart_state & mask != 0 { status } else { 0 };
+            let modem_status = bit(0x80, protocol_cdc::MODEM_STATUS_CTS)
+                | bit(0x02, protocol_cdc::MODEM_STATUS_DSR)
+                | bit(0x08, protocol_cdc::MODEM_STATUS_RI)
+                | bit(0x01, protocol_cdc::MODEM_STATUS_DCD)
+                | bit(0x40, protocol_cdc::LINE_STATUS_OVERRUN)
+                | bit(0x20, protocol_cdc::LINE_STATUS_PARITY)
+                | bit(0x10, protocol_cdc::LINE_STATUS_FRAMING)
+                | bit(0x04, protocol_cdc::LINE_STATUS_BREAK);
+
+            line_state.update_modem_status(modem_status)
+        },
+
+        _ => None
+    }
+}
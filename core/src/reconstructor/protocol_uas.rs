@@ -0,0 +1,493 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use tokio::sync::mpsc::Sender;
+use crate::sniffer::{UrbXractHeader, UrbXractPacket};
+
+use super::protocol_scsi::{self, ScsiDataDirection};
+use super::{ModuleContext, ReconstructedTransmission, ReconstructionModule};
+
+/*
+    Information Unit Info:
+    https://www.usb.org/sites/default/files/uasp_1_0.zip
+    T10 UAS-2, Section 6.2
+*/
+const IU_ID_COMMAND: u8 = 0x01;
+const IU_ID_SENSE: u8 = 0x03;
+const IU_ID_RESPONSE: u8 = 0x04;
+const IU_ID_TASK_MGMT: u8 = 0x05;
+const IU_ID_READ_READY: u8 = 0x06;
+const IU_ID_WRITE_READY: u8 = 0x07;
+const COMMAND_IU_HDRLEN: usize = 16;
+const COMMAND_IU_MINLEN: usize = 32;
+const SENSE_IU_HDRLEN: usize = 16;
+const STATUS_GOOD: u8 = 0x00;
+const RESPONSE_CODE_COMPLETE: u8 = 0x00;
+const RESPONSE_CODE_SUCCEEDED: u8 = 0x08;
+const MAX_DECODE_LENGTH: usize = 4096;
+
+struct PendingCommand {
+    urbx_header: UrbXractHeader,
+    description: String,
+    cdb: Vec<u8>,
+    direction: ScsiDataDirection,
+    data: Vec<u8>,
+    data_length: usize,
+    sources: Vec<UrbXractPacket>,
+}
+
+#[derive(Default)]
+struct UasDevice {
+    command_pipe: Option<u8>,
+    status_pipe: Option<u8>,
+    read_ready: Option<u16>,
+    write_ready: Option<u16>,
+    pending: Vec<(u16, PendingCommand)>, /* Tag, Command (Oldest First) */
+}
+
+pub struct Reconstructor {
+    module_tx: Sender<ReconstructedTransmission>,
+    devices: HashMap<String, UasDevice>, /* Bus:Device, Pipe State */
+}
+
+fn get_tag(data: &[u8]) -> u16 {
+    u16::from_be_bytes([data[2], data[3]])
+}
+
+fn get_task_mgmt_name(function: u8) -> &'static str {
+    match function {
+        0x01 => "ABORT TASK",
+        0x02 => "ABORT TASK SET",
+        0x04 => "CLEAR TASK SET",
+        0x08 => "LOGICAL UNIT RESET",
+        0x10 => "I_T NEXUS RESET",
+        0x40 => "CLEAR ACA",
+        0x80 => "QUERY TASK",
+        0x81 => "QUERY TASK SET",
+        0x82 => "QUERY ASYNCHRONOUS EVENT",
+        _ => "UNKNOWN FUNCTION"
+    }
+}
+
+fn get_response_code_name(response_code: u8) -> &'static str {
+    match response_code {
+        0x00 => "TASK MANAGEMENT FUNCTION COMPLETE",
+        0x02 => "INVALID INFORMATION UNIT",
+        0x04 => "TASK MANAGEMENT FUNCTION NOT SUPPORTED",
+        0x05 => "TASK MANAGEMENT FUNCTION FAILED",
+        0x08 => "TASK MANAGEMENT FUNCTION SUCCEEDED",
+        0x09 => "INCORRECT LOGICAL UNIT NUMBER",
+        0x0A => "OVERLAPPED TAG ATTEMPTED",
+        _ => "UNKNOWN RESPONSE"
+    }
+}
+
+pub fn is_command_iu(data: &[u8]) -> bool {
+    /* Command IUs are 32 bytes plus the Additional CDB (in DWORDs) */
+    data.len() >= COMMAND_IU_MINLEN
+        && data[0] == IU_ID_COMMAND
+        && data[1] == 0
+        && data[5] == 0
+        && data[7] == 0
+        && data.len() == COMMAND_IU_MINLEN + ((data[6] >> 2) as usize * 4)
+        && protocol_scsi::get_opcode_name(data[COMMAND_IU_HDRLEN]) != "UNKNOWN"
+}
+
+fn is_status_iu(data: &[u8]) -> bool {
+    data.len() >= 4
+        && data[1] == 0
+        && matches!(data[0], IU_ID_SENSE | IU_ID_RESPONSE | IU_ID_READ_READY | IU_ID_WRITE_READY)
+}
+
+fn source_of(urb_packet: &UrbXractPacket) -> UrbXractPacket {
+    UrbXractPacket {
+        header: urb_packet.header,
+        data: None,
+        iso_descriptors: vec![]
+    }
+}
+
+impl Reconstructor {
+    async fn dispatch_command(&mut self, pending_command: PendingCommand, completion: String, is_error: bool) {
+        let decoded_data = protocol_scsi::describe_data(&pending_command.cdb, &pending_command.data, None);
+        let transmission = ReconstructedTransmission {
+            urbx_header: pending_command.urbx_header,
+            combined_payload: protocol_scsi::format_completion(
+                &pending_command.description,
+                &completion,
+                pending_command.data_length,
+                pending_command.direction,
+                decoded_data
+            ),
+            sources: pending_command.sources,
+            is_error,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    async fn dispatch_notice(&mut self, urb_packet: UrbXractPacket, notice: String, is_error: bool) {
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_packet.header,
+            combined_payload: notice,
+            sources: vec![urb_packet],
+            is_error,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    async fn consume_command_pipe(&mut self, device_key: &str, urb_packet: UrbXractPacket) {
+        let urb_data = urb_packet.data.as_ref().unwrap();
+        let device = self.devices.get_mut(device_key).unwrap();
+
+        match urb_data[0] {
+            IU_ID_COMMAND if urb_data.len() >= COMMAND_IU_MINLEN => {
+                /* Decode CDB through the SCSI Module, including the Additional CDB */
+                let tag = get_tag(urb_data);
+                let cdb = &urb_data[COMMAND_IU_HDRLEN..];
+                let pending_command = PendingCommand {
+                    urbx_header: urb_packet.header,
+                    description: format!("UAS Tag=0x{:04X} LUN={}: {}", tag, urb_data[9], protocol_scsi::describe_command(cdb)),
+                    cdb: cdb.to_vec(),
+                    direction: protocol_scsi::get_data_direction(cdb),
+                    data: vec![],
+                    data_length: 0,
+                    sources: vec![source_of(&urb_packet)],
+                };
+
+                device.pending.retain(|(pending_tag, _)| *pending_tag != tag);
+                device.pending.push((tag, pending_command));
+            },
+
+            IU_ID_TASK_MGMT if urb_data.len() >= 16 => {
+                let tag = get_tag(urb_data);
+                let pending_command = PendingCommand {
+                    urbx_header: urb_packet.header,
+                    description: format!(
+                        "UAS Tag=0x{:04X} LUN={}: {} (Managed Tag=0x{:04X})",
+                        tag,
+                        urb_data[9],
+                        get_task_mgmt_name(urb_data[4]),
+                        u16::from_be_bytes([urb_data[6], urb_data[7]])
+                    ),
+                    cdb: vec![],
+                    direction: ScsiDataDirection::None,
+                    data: vec![],
+                    data_length: 0,
+                    sources: vec![source_of(&urb_packet)],
+                };
+
+                device.pending.retain(|(pending_tag, _)| *pending_tag != tag);
+                device.pending.push((tag, pending_command));
+            },
+
+            iu_id => {
+                let notice = format!("UAS Command Pipe: Unknown IU 0x{:02X} ({} bytes)", iu_id, urb_data.len());
+                self.dispatch_notice(urb_packet, notice, true).await;
+            }
+        }
+    }
+
+    async fn consume_status_pipe(&mut self, device_key: &str, urb_packet: UrbXractPacket) {
+        let urb_data = urb_packet.data.as_ref().unwrap();
+        if urb_data.len() < 4 {
+            let notice = format!("UAS Status Pipe: Truncated IU ({} bytes)", urb_data.len());
+            self.dispatch_notice(urb_packet, notice, true).await;
+            return;
+        }
+
+        let device = self.devices.get_mut(device_key).unwrap();
+        let tag = get_tag(urb_data);
+
+        /* Ready IUs announce which Tag the Data Pipes are serving (USB 2.0 without Streams) */
+        match urb_data[0] {
+            IU_ID_READ_READY => { device.read_ready = Some(tag); return; },
+            IU_ID_WRITE_READY => { device.write_ready = Some(tag); return; },
+            _ => {}
+        }
+
+        /* Build the Completion Description, anything but GOOD or a successful Task Management Function fails */
+        let (completion, is_error) = match urb_data[0] {
+            IU_ID_SENSE if urb_data.len() >= SENSE_IU_HDRLEN => {
+                let status = urb_data[6];
+                let sense_length = u16::from_be_bytes([urb_data[14], urb_data[15]]) as usize;
+                let sense_end = std::cmp::min(urb_data.len(), SENSE_IU_HDRLEN + sense_length);
+
+                let completion = if sense_length > 0 {
+                    format!(
+                        "{}, {}",
+                        protocol_scsi::get_status_name(status),
+                        protocol_scsi::describe_sense(&urb_data[SENSE_IU_HDRLEN..sense_end])
+                    )
+                } else {
+                    String::from(protocol_scsi::get_status_name(status))
+                };
+
+                (completion, status != STATUS_GOOD)
+            },
+
+            IU_ID_RESPONSE if urb_data.len() >= 8 => (
+                String::from(get_response_code_name(urb_data[7])),
+                !matches!(urb_data[7], RESPONSE_CODE_COMPLETE | RESPONSE_CODE_SUCCEEDED)
+            ),
+            iu_id => (format!("Malformed Status IU 0x{:02X} ({} bytes)", iu_id, urb_data.len()), true)
+        };
+
+        /* Complete the Pending Command */
+        if device.read_ready == Some(tag) { device.read_ready = None; }
+        if device.write_ready == Some(tag) { device.write_ready = None; }
+
+        match device.pending.iter().position(|(pending_tag, _)| *pending_tag == tag) {
+            None => {
+                let notice = format!("UAS Tag=0x{:04X}: (Command Not Captured) -> {}", tag, completion);
+                self.dispatch_notice(urb_packet, notice, is_error).await;
+            },
+
+            Some(pending_index) => {
+                let (_, mut pending_command) = device.pending.remove(pending_index);
+                pending_command.sources.push(source_of(&urb_packet));
+                self.dispatch_command(pending_command, completion, is_error).await;
+            }
+        }
+    }
+
+    async fn consume_data_pipe(&mut self, device_key: &str, urb_packet: UrbXractPacket) {
+        let urb_data = urb_packet.data.as_ref().unwrap();
+        let urb_data_length = urb_data.len();
+        let device = self.devices.get_mut(device_key).unwrap();
+        let is_device_to_host = (urb_packet.header.endpoint_info & 0b10000000) != 0;
+
+        /*
+            Stream IDs are not exposed by the capture, so correlate using
+            the Tag from the last Ready IU or the oldest Command in the direction
+        */
+        let (ready_tag, data_direction) = if is_device_to_host {
+            (device.read_ready, ScsiDataDirection::In)
+        } else {
+            (device.write_ready, ScsiDataDirection::Out)
+        };
+
+        let pending_index = match ready_tag {
+            Some(tag) => device.pending.iter().position(|(pending_tag, _)| *pending_tag == tag),
+            None => device.pending.iter().position(|(_, command)| command.direction == data_direction),
+        };
+
+        match pending_index {
+            Some(pending_index) => {
+                let (_, pending_command) = &mut device.pending[pending_index];
+                let keep_length = std::cmp::min(urb_data_length, MAX_DECODE_LENGTH.saturating_sub(pending_command.data.len()));
+                pending_command.data.extend_from_slice(&urb_data[0..keep_length]);
+                pending_command.data_length += urb_data_length;
+                pending_command.sources.push(source_of(&urb_packet));
+            },
+
+            None => {
+                let notice = format!("UAS Data Pipe: {} bytes without Outstanding Command", urb_data_length);
+                self.dispatch_notice(urb_packet, notice, false).await;
+            }
+        }
+    }
+}
+
+impl ReconstructionModule for Reconstructor {
+    fn new(module_tx: Sender<ReconstructedTransmission>, _module_context: &ModuleContext) -> Self {
+        Self {
+            module_tx,
+            devices: HashMap::new(),
+        }
+    }
+
+    async fn consume_packet(&mut self, urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let urb_data = urb_packet.data.as_ref().unwrap();
+        let is_device_to_host = (urb_header.endpoint_info & 0b10000000) != 0;
+
+        /* Get the Device, Pipes are learnt from the first IU seen on them */
+        let device_key = format!("{}:{}", urb_header.bus_id, urb_header.device_id);
+        let device = self.devices.entry(device_key.clone()).or_default();
+
+        if !is_device_to_host {
+            let is_command_pipe = match device.command_pipe {
+                Some(command_pipe) => command_pipe == urb_header.endpoint_info,
+                None => is_command_iu(urb_data),
+            };
+
+            if is_command_pipe {
+                device.command_pipe = Some(urb_header.endpoint_info);
+                self.consume_command_pipe(&device_key, urb_packet).await;
+                return;
+            }
+        } else {
+            let is_status_pipe = match device.status_pipe {
+                Some(status_pipe) => status_pipe == urb_header.endpoint_info,
+                None => is_status_iu(urb_data) && device.pending.iter().any(|(tag, _)| *tag == get_tag(urb_data)),
+            };
+
+            if is_status_pipe {
+                device.status_pipe = Some(urb_header.endpoint_info);
+                self.consume_status_pipe(&device_key, urb_packet).await;
+                return;
+            }
+        }
+
+        /* Anything else belongs to the Data-In or Data-Out Pipe */
+        self.consume_data_pipe(&device_key, urb_packet).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::{self, Receiver};
+    use crate::sniffer::{UrbEventType, UrbTransferType};
+
+    const COMMAND_PIPE: u8 = 0x04;
+    const STATUS_PIPE: u8 = 0x83;
+    const DATA_IN_PIPE: u8 = 0x81;
+    const DATA_OUT_PIPE: u8 = 0x02;
+
+    fn bulk_packet(endpoint_info: u8, data: &[u8]) -> UrbXractPacket {
+        UrbXractPacket {
+            header: UrbXractHeader {
+                urb_id: 0,
+                bus_id: 1,
+                device_id: 2,
+                endpoint_info,
+                transfer_type: UrbTransferType::Bulk,
+                event_type: UrbEventType::Complete,
+                status: 0,
+                timestamp: 0,
+                setup_packet: None
+            },
+            data: Some(data.to_vec()),
+            iso_descriptors: vec![]
+        }
+    }
+
+    fn command_iu(tag: u16, lun: u8, cdb: &[u8]) -> Vec<u8> {
+        /* IU ID, Reserved, Tag, Attributes, Reserved, Additional CDB Length, Reserved, LUN, CDB */
+        let mut command_iu = vec![IU_ID_COMMAND, 0x00, (tag >> 8) as u8, tag as u8, 0x00, 0x00, 0x00, 0x00];
+        command_iu.extend_from_slice(&[0x00, lun, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        command_iu.extend_from_slice(cdb);
+        command_iu.resize(COMMAND_IU_MINLEN, 0x00);
+        command_iu
+    }
+
+    fn sense_iu(tag: u16, status: u8, sense_data: &[u8]) -> Vec<u8> {
+        /* IU ID, Reserved, Tag, Status Qualifier, Status, Reserved, Sense Length, Sense Data */
+        let mut sense_iu = vec![IU_ID_SENSE, 0x00, (tag >> 8) as u8, tag as u8, 0x00, 0x00, status, 0x00];
+        sense_iu.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, sense_data.len() as u8]);
+        sense_iu.extend_from_slice(sense_data);
+        sense_iu
+    }
+
+    async fn consume(reconstructor: &mut Reconstructor, module_rx: &mut Receiver<ReconstructedTransmission>, endpoint_info: u8, data: &[u8]) -> Option<(String, bool)> {
+        reconstructor.consume_packet(bulk_packet(endpoint_info, data)).await;
+        module_rx.try_recv().ok().map(|transmission| (transmission.combined_payload, transmission.is_error))
+    }
+
+    fn new_reconstructor() -> (Reconstructor, Receiver<ReconstructedTransmission>) {
+        let (module_tx, module_rx) = mpsc::channel(16);
+        (Reconstructor::new(module_tx, &ModuleContext::default()), module_rx)
+    }
+
+    #[tokio::test]
+    async fn decodes_command_and_sense_ius() {
+        let (mut reconstructor, mut module_rx) = new_reconstructor();
+        assert_eq!(consume(&mut reconstructor, &mut module_rx, COMMAND_PIPE, &command_iu(0x0005, 1, &[0x00; 6])).await, None);
+
+        /* Fixed Format Sense: NOT READY, MEDIUM NOT PRESENT */
+        let sense_data = [0x70, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x3A, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let (payload, is_error) = consume(&mut reconstructor, &mut module_rx, STATUS_PIPE, &sense_iu(0x0005, 0x02, &sense_data)).await.unwrap();
+        assert_eq!(payload, format!(
+            "UAS Tag=0x0005 LUN=1: {} -> CHECK CONDITION, {} (Data: 0 bytes)",
+            protocol_scsi::describe_command(&[0x00; 16]),
+            protocol_scsi::describe_sense(&sense_data)
+        ));
+        assert!(is_error);
+
+        consume(&mut reconstructor, &mut module_rx, COMMAND_PIPE, &command_iu(0x0006, 0, &[0x00; 6])).await;
+        let (payload, is_error) = consume(&mut reconstructor, &mut module_rx, STATUS_PIPE, &sense_iu(0x0006, 0x00, &[])).await.unwrap();
+        assert!(payload.ends_with("-> GOOD (Data: 0 bytes)"));
+        assert!(!is_error);
+    }
+
+    #[tokio::test]
+    async fn decodes_task_management_response_ius() {
+        let (mut reconstructor, mut module_rx) = new_reconstructor();
+        consume(&mut reconstructor, &mut module_rx, COMMAND_PIPE, &command_iu(0x0001, 0, &[0x00; 6])).await;
+
+        /* ABORT TASK for Tag 1, answered with TASK MANAGEMENT FUNCTION FAILED */
+        let task_mgmt_iu = [IU_ID_TASK_MGMT, 0x00, 0x00, 0x02, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert_eq!(consume(&mut reconstructor, &mut module_rx, COMMAND_PIPE, &task_mgmt_iu).await, None);
+        let response_iu = [IU_ID_RESPONSE, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x05];
+        assert_eq!(
+            consume(&mut reconstructor, &mut module_rx, STATUS_PIPE, &response_iu).await,
+            Some((String::from("UAS Tag=0x0002 LUN=0: ABORT TASK (Managed Tag=0x0001) -> TASK MANAGEMENT FUNCTION FAILED (Data: 0 bytes)"), true))
+        );
+
+        consume(&mut reconstructor, &mut module_rx, COMMAND_PIPE, &task_mgmt_iu).await;
+        let response_iu = [IU_ID_RESPONSE, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00];
+        let (payload, is_error) = consume(&mut reconstructor, &mut module_rx, STATUS_PIPE, &response_iu).await.unwrap();
+        assert!(payload.ends_with("-> TASK MANAGEMENT FUNCTION COMPLETE (Data: 0 bytes)"));
+        assert!(!is_error);
+    }
+
+    #[tokio::test]
+    async fn routes_data_to_the_read_ready_tag() {
+        let (mut reconstructor, mut module_rx) = new_reconstructor();
+        let read_10 = [0x28, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x01, 0x00];
+        consume(&mut reconstructor, &mut module_rx, COMMAND_PIPE, &command_iu(0x0001, 0, &read_10)).await;
+        consume(&mut reconstructor, &mut module_rx, COMMAND_PIPE, &command_iu(0x0002, 0, &read_10)).await;
+
+        /* The Device serves Tag 2 first, Tag 1 completes without Data */
+        assert_eq!(consume(&mut reconstructor, &mut module_rx, STATUS_PIPE, &[IU_ID_READ_READY, 0x00, 0x00, 0x02]).await, None);
+        assert_eq!(consume(&mut reconstructor, &mut module_rx, DATA_IN_PIPE, &[0x55; 512]).await, None);
+
+        let (payload, _) = consume(&mut reconstructor, &mut module_rx, STATUS_PIPE, &sense_iu(0x0002, 0x00, &[])).await.unwrap();
+        assert!(payload.starts_with("UAS Tag=0x0002 "));
+        assert!(payload.ends_with("-> GOOD (Data: 512 bytes IN)"));
+
+        let (payload, _) = consume(&mut reconstructor, &mut module_rx, STATUS_PIPE, &sense_iu(0x0001, 0x00, &[])).await.unwrap();
+        assert!(payload.starts_with("UAS Tag=0x0001 "));
+        assert!(payload.ends_with("-> GOOD (Data: 0 bytes IN)"));
+    }
+
+    #[tokio::test]
+    async fn routes_data_to_the_oldest_command_without_ready_iu() {
+        let (mut reconstructor, mut module_rx) = new_reconstructor();
+        let write_10 = [0x2A, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x01, 0x00];
+        let read_10 = [0x28, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x01, 0x00];
+        consume(&mut reconstructor, &mut module_rx, COMMAND_PIPE, &command_iu(0x0001, 0, &write_10)).await;
+        consume(&mut reconstructor, &mut module_rx, COMMAND_PIPE, &command_iu(0x0002, 0, &read_10)).await;
+        consume(&mut reconstructor, &mut module_rx, COMMAND_PIPE, &command_iu(0x0003, 0, &read_10)).await;
+
+        /* Streams hide the Tag, so Data goes to the oldest Command of its Direction */
+        assert_eq!(consume(&mut reconstructor, &mut module_rx, DATA_IN_PIPE, &[0xAA; 512]).await, None);
+        assert_eq!(consume(&mut reconstructor, &mut module_rx, DATA_OUT_PIPE, &[0xBB; 512]).await, None);
+
+        let (payload, _) = consume(&mut reconstructor, &mut module_rx, STATUS_PIPE, &sense_iu(0x0001, 0x00, &[])).await.unwrap();
+        assert!(payload.ends_with("-> GOOD (Data: 512 bytes OUT)"));
+        let (payload, _) = consume(&mut reconstructor, &mut module_rx, STATUS_PIPE, &sense_iu(0x0002, 0x00, &[])).await.unwrap();
+        assert!(payload.ends_with("-> GOOD (Data: 512 bytes IN)"));
+        let (payload, _) = consume(&mut reconstructor, &mut module_rx, STATUS_PIPE, &sense_iu(0x0003, 0x00, &[])).await.unwrap();
+        assert!(payload.ends_with("-> GOOD (Data: 0 bytes IN)"));
+    }
+}
@@ -0,0 +1,621 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::fs;
+use tokio::sync::mpsc::Sender;
+use crate::sniffer::{UrbEventType, UrbTransferType, UrbXractHeader, UrbXractPacket};
+
+use super::device_model::SetupPacket;
+use super::video_descriptor::{self, StreamingControl, VideoEncoding, VideoFormat};
+use super::protocol_control::{self, PendingRequests};
+use super::{ModuleContext, ReconstructedTransmission, ReconstructionModule};
+
+/*
+    USB Video Streaming. Every Payload starts with a Header whose Frame ID
+    toggles between Frames and whose EOF Bit ends one. Bulk Streams send a
+    Payload per Transfer, Isochronous Streams one per Packet
+*/
+const HEADER_FRAME_ID: u8 = 0x01;
+const HEADER_END_OF_FRAME: u8 = 0x02;
+const HEADER_PRESENTATION_TIME: u8 = 0x04;
+const HEADER_SOURCE_CLOCK: u8 = 0x08;
+const HEADER_STILL_IMAGE: u8 = 0x20;
+const HEADER_ERROR: u8 = 0x40;
+const MAX_FRAME_LENGTH: usize = 64 * 1024 * 1024; /* Flushed as oversized beyond this */
+
+const SUBCLASS_VIDEO_STREAMING: u8 = 0x02;
+const CONTROL_PROBE: u8 = 0x01;
+const CONTROL_COMMIT: u8 = 0x02;
+const CONTROL_REQUEST_ERROR_CODE: u8 = 0x02;
+const REQUEST_SET_CUR: u8 = 0x01;
+const REQUEST_GET_CUR: u8 = 0x81;
+const REQUEST_GET_LEN: u8 = 0x85;
+
+#[derive(Default)]
+struct VideoStream {
+    interface_number: u8,
+    video_formats: Vec<VideoFormat>,
+    frame_buffer: Vec<u8>,
+    frame_id: Option<bool>,
+    is_open: bool,
+    uses_end_of_frame: bool,
+    frame_start: u64,
+    presentation_time: Option<u32>,
+    source_clock: Option<(u32, u16)>, /* Source Time Clock, USB Start of Frame */
+    is_still_image: bool,
+    has_error: bool,
+    lost_packets: u64,
+    invalid_headers: u64,
+    frame_count: u64,
+    last_frame_start: Option<u64>,
+    is_export_disabled: bool,
+}
+
+pub struct Reconstructor {
+    module_tx: Sender<ReconstructedTransmission>,
+    module_context: ModuleContext,
+    pending_requests: PendingRequests, /* Control Requests awaiting Completion */
+    commits: HashMap<(u16, u16, u8), StreamingControl>, /* (Bus, Device, Interface), committed Format */
+    streams: HashMap<(u16, u16, u8), VideoStream>, /* (Bus, Device, Endpoint) */
+}
+
+fn get_request_name(request: u8) -> String {
+    match request {
+        REQUEST_SET_CUR => String::from("SET_CUR"),
+        REQUEST_GET_CUR => String::from("GET_CUR"),
+        0x82 => String::from("GET_MIN"),
+        0x83 => String::from("GET_MAX"),
+        0x84 => String::from("GET_RES"),
+        REQUEST_GET_LEN => String::from("GET_LEN"),
+        0x86 => String::from("GET_INFO"),
+        0x87 => String::from("GET_DEF"),
+        request => format!("Request 0x{:02X}", request),
+    }
+}
+
+fn get_control_name(subtype: Option<u8>, control_selector: u8) -> String {
+    /* Subtype of the addressed Entity, None for the Interface itself */
+    let control_name = match (subtype, control_selector) {
+        (None, 0x01) => "Power Mode",
+        (None, CONTROL_REQUEST_ERROR_CODE) => "Request Error Code",
+        (Some(video_descriptor::SUBTYPE_INPUT_TERMINAL), selector) => match selector {
+            0x01 => "Scanning Mode",
+            0x02 => "Auto-Exposure Mode",
+            0x03 => "Auto-Exposure Priority",
+            0x04 => "Exposure Time (Absolute)",
+            0x05 => "Exposure Time (Relative)",
+            0x06 => "Focus (Absolute)",
+            0x07 => "Focus (Relative)",
+            0x08 => "Focus, Auto",
+            0x09 => "Iris (Absolute)",
+            0x0A => "Iris (Relative)",
+            0x0B => "Zoom (Absolute)",
+            0x0C => "Zoom (Relative)",
+            0x0D => "PanTilt (Absolute)",
+            0x0E => "PanTilt (Relative)",
+            0x0F => "Roll (Absolute)",
+            0x10 => "Roll (Relative)",
+            0x11 => "Privacy",
+            _ => "",
+        },
+        (Some(video_descriptor::SUBTYPE_PROCESSING_UNIT), selector) => match selector {
+            0x01 => "Backlight Compensation",
+            0x02 => "Brightness",
+            0x03 => "Contrast",
+            0x04 => "Gain",
+            0x05 => "Power Line Frequency",
+            0x06 => "Hue",
+            0x07 => "Saturation",
+            0x08 => "Sharpness",
+            0x09 => "Gamma",
+            0x0A => "White Balance Temperature",
+            0x0B => "White Balance Temperature, Auto",
+            0x0C => "White Balance Component",
+            0x0D => "White Balance Component, Auto",
+            0x0E => "Digital Multiplier",
+            0x0F => "Digital Multiplier Limit",
+            0x10 => "Hue, Auto",
+            0x11 => "Analog Video Standard",
+            0x12 => "Analog Lock Status",
+            0x13 => "Contrast, Auto",
+            _ => "",
+        },
+        _ => "",
+    };
+
+    match control_name {
+        "" => format!("Control 0x{:02X}", control_selector),
+        control_name => String::from(control_name),
+    }
+}
+
+fn get_streaming_control_name(control_selector: u8) -> String {
+    match control_selector {
+        CONTROL_PROBE => String::from("Probe"),
+        CONTROL_COMMIT => String::from("Commit"),
+        0x03 => String::from("Still Probe"),
+        0x04 => String::from("Still Commit"),
+        0x05 => String::from("Still Image Trigger"),
+        0x06 => String::from("Stream Error Code"),
+        0x07 => String::from("Generate Key Frame"),
+        0x08 => String::from("Update Frame Segment"),
+        0x09 => String::from("Synch Delay"),
+        control_selector => format!("Control 0x{:02X}", control_selector),
+    }
+}
+
+fn get_request_error_name(error_code: u8) -> String {
+    match error_code {
+        0x00 => String::from("No Error"),
+        0x01 => String::from("Not Ready"),
+        0x02 => String::from("Wrong State"),
+        0x03 => String::from("Power"),
+        0x04 => String::from("Out of Range"),
+        0x05 => String::from("Invalid Unit"),
+        0x06 => String::from("Invalid Control"),
+        0x07 => String::from("Invalid Request"),
+        0x08 => String::from("Invalid Value within Range"),
+        error_code => format!("Error 0x{:02X}", error_code),
+    }
+}
+
+fn format_value(control_name: &str, data: &[u8]) -> String {
+    /* Controls up to 4 Bytes are Integers, Brightness and Hue are signed */
+    let is_signed = matches!(control_name, "Brightness" | "Hue");
+    match data.len() {
+        1 => format!("{}", data[0]),
+        2 if is_signed => format!("{}", i16::from_le_bytes([data[0], data[1]])),
+        2 => format!("{}", u16::from_le_bytes([data[0], data[1]])),
+        4 => format!("{}", u32::from_le_bytes(data[0..4].try_into().unwrap())),
+        _ => protocol_control::format_hex(data),
+    }
+}
+
+fn get_endpoint_name(endpoint_address: u8) -> String {
+    format!("EP 0x{:02X} {}", endpoint_address, if endpoint_address & 0x80 != 0 { "IN" } else { "OUT" })
+}
+
+impl VideoStream {
+    fn get_active_format(&self, commit: Option<&StreamingControl>) -> Option<(&VideoFormat, Option<&video_descriptor::VideoFrame>)> {
+        /* Without a captured Commit, a single Format and an Uncompressed Frame of matching Size still tell */
+        match commit {
+            Some(commit) => {
+                let video_format = self.video_formats.iter().find(|video_format| video_format.index == commit.format_index)?;
+                Some((video_format, video_format.get_frame(commit.frame_index)))
+            },
+
+            None if self.video_formats.len() == 1 => {
+                let video_format = &self.video_formats[0];
+                let video_frame = video_format.frames
+                    .iter()
+                    .find(|video_frame| video_format.get_frame_size(video_frame) == Some(self.frame_buffer.len()));
+
+                Some((video_format, video_frame))
+            },
+
+            None => None,
+        }
+    }
+
+    fn get_problems(&self, video_format: Option<(&VideoFormat, Option<&video_descriptor::VideoFrame>)>, has_end_of_frame: bool) -> Vec<String> {
+        let mut problems = vec![];
+        if !has_end_of_frame && self.uses_end_of_frame {
+            problems.push(String::from("no EOF before Frame ID toggled"));
+        }
+
+        if self.has_error {
+            problems.push(String::from("Error Bit set"));
+        }
+
+        if self.lost_packets > 0 {
+            problems.push(format!("{} lost Packets", self.lost_packets));
+        }
+
+        if self.invalid_headers > 0 {
+            problems.push(format!("{} Payloads with invalid Header", self.invalid_headers));
+        }
+
+        match video_format {
+            Some((video_format, _)) if video_format.encoding == VideoEncoding::Mjpeg => {
+                /* Devices may pad the Frame after the End of Image Marker */
+                let trimmed_length = self.frame_buffer.iter().rposition(|byte| *byte != 0).map_or(0, |position| position + 1);
+                if !self.frame_buffer.starts_with(&[0xFF, 0xD8]) {
+                    problems.push(String::from("no JPEG SOI"));
+                }
+
+                if !self.frame_buffer[..trimmed_length].ends_with(&[0xFF, 0xD9]) {
+                    problems.push(String::from("truncated, no JPEG EOI"));
+                }
+            },
+
+            Some((video_format, Some(video_frame))) => {
+                if let Some(frame_size) = video_format.get_frame_size(video_frame) && self.frame_buffer.len() != frame_size {
+                    let problem = if self.frame_buffer.len() < frame_size { "truncated" } else { "oversized" };
+                    problems.push(format!("{}, {} of {} bytes", problem, self.frame_buffer.len(), frame_size));
+                }
+            },
+
+            _ => {}
+        }
+
+        problems
+    }
+}
+
+impl Reconstructor {
+    async fn dispatch_rows(&mut self, urb_header: UrbXractHeader, rows: Vec<(String, bool)>) {
+        for (combined_payload, is_error) in rows {
+            let transmission = ReconstructedTransmission {
+                urbx_header: urb_header,
+                combined_payload,
+                sources: vec![UrbXractPacket { header: urb_header, data: None, iso_descriptors: vec![] }],
+                is_error,
+            };
+
+            self.module_tx.send(transmission).await.unwrap();
+        }
+    }
+
+    fn finish_frame(&mut self, urb_header: &UrbXractHeader, has_end_of_frame: bool) -> Vec<(String, bool)> {
+        let stream_key = (urb_header.bus_id, urb_header.device_id, urb_header.endpoint_info);
+        let endpoint_name = get_endpoint_name(urb_header.endpoint_info);
+        let video_directory = self.module_context.video_directory.clone();
+        let Some(video_stream) = self.streams.get_mut(&stream_key) else { return vec![] };
+        let commit = self.commits.get(&(urb_header.bus_id, urb_header.device_id, video_stream.interface_number)).copied();
+        let mut rows = vec![];
+
+        /* Header-only Payloads between Frames carry no Image */
+        if video_stream.frame_buffer.is_empty() && video_stream.lost_packets == 0 {
+            video_stream.is_open = false;
+            return rows;
+        }
+
+        let video_format = video_stream.get_active_format(commit.as_ref());
+        let problems = video_stream.get_problems(video_format, has_end_of_frame);
+        let frame_interval = commit
+            .map(|commit| commit.frame_interval)
+            .or(video_format.and_then(|(_, video_frame)| video_frame).map(|video_frame| video_frame.default_interval))
+            .unwrap_or(0) as u64 / 10;
+
+        /* Gaps of more than one and a half Frame Intervals between Frames lost Frames */
+        if let Some(last_frame_start) = video_stream.last_frame_start && frame_interval > 0 && !video_stream.is_still_image {
+            let frame_gap = video_stream.frame_start.saturating_sub(last_frame_start);
+            if frame_gap * 2 > frame_interval * 3 {
+                let dropped_frames = ((frame_gap as f64 / frame_interval as f64).round() as u64).saturating_sub(1).max(1);
+                rows.push((format!(
+                    "[UVC] {} {} Frames dropped before Frame {} ({:.1} ms Gap, {:.1} ms Interval)",
+                    endpoint_name,
+                    dropped_frames,
+                    video_stream.frame_count,
+                    frame_gap as f64 / 1000.0,
+                    frame_interval as f64 / 1000.0
+                ), true));
+            }
+        }
+
+        let format_name = match video_format {
+            Some((video_format, video_frame)) => video_format.describe(video_frame),
+            None => String::from("Unknown Format"),
+        };
+
+        let mut description = format!(
+            "[UVC] {} {} {}: {}, {} bytes",
+            endpoint_name,
+            if video_stream.is_still_image { "Still Image" } else { "Frame" },
+            video_stream.frame_count,
+            format_name,
+            video_stream.frame_buffer.len()
+        );
+
+        if let Some(presentation_time) = video_stream.presentation_time {
+            description += &format!(", PTS {}", presentation_time);
+        }
+
+        if let Some((source_time, start_of_frame)) = video_stream.source_clock {
+            description += &format!(", SCR {} SOF {}", source_time, start_of_frame & 0x07FF);
+        }
+
+        /* Frames are written as captured, damaged ones included */
+        if let Some(video_directory) = video_directory && !video_stream.is_export_disabled && let Some((video_format, video_frame)) = video_format {
+            let suffix = match (video_format.encoding, video_frame) {
+                (VideoEncoding::Uncompressed, Some(video_frame)) => format!("_{}x{}_{}", video_frame.width, video_frame.height, video_format.fourcc.to_lowercase()),
+                (VideoEncoding::Uncompressed, None) => format!("_{}", video_format.fourcc.to_lowercase()),
+                _ => String::new(),
+            };
+
+            let file_path = video_directory.join(format!(
+                "video_{:03}_{:03}_if{}_{:05}{}.{}",
+                urb_header.bus_id,
+                urb_header.device_id,
+                video_stream.interface_number,
+                video_stream.frame_count,
+                suffix,
+                video_format.get_extension()
+            ));
+
+            match fs::create_dir_all(&video_directory).and_then(|_| fs::write(&file_path, &video_stream.frame_buffer)) {
+                Ok(_) => description += &format!(" -> {}", file_path.display()),
+                Err(error) => {
+                    video_stream.is_export_disabled = true;
+                    rows.push((format!("[UVC] {} Export stopped: Unable to write {}: {}", endpoint_name, file_path.display(), error), true));
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            description += &format!(" ({})", problems.join(", "));
+        }
+
+        rows.push((description, !problems.is_empty()));
+        video_stream.last_frame_start = Some(video_stream.frame_start);
+        video_stream.frame_count += 1;
+        video_stream.frame_buffer.clear();
+        video_stream.presentation_time = None;
+        video_stream.source_clock = None;
+        video_stream.is_still_image = false;
+        video_stream.has_error = false;
+        video_stream.lost_packets = 0;
+        video_stream.invalid_headers = 0;
+        video_stream.is_open = false;
+        rows
+    }
+
+    fn consume_payload(&mut self, urb_header: &UrbXractHeader, payload: Option<&[u8]>) -> Vec<(String, bool)> {
+        /* None for Isochronous Packets the Host Controller failed to receive */
+        let stream_key = (urb_header.bus_id, urb_header.device_id, urb_header.endpoint_info);
+        let mut rows = vec![];
+        let Some(video_stream) = self.streams.get_mut(&stream_key) else { return rows };
+        let Some(payload) = payload else {
+            video_stream.lost_packets += 1;
+            return rows;
+        };
+
+        if payload.is_empty() {
+            return rows;
+        }
+
+        let header_length = payload[0] as usize;
+        if payload.len() < 2 || header_length < 2 || header_length > payload.len() {
+            video_stream.invalid_headers += 1;
+            return rows;
+        }
+
+        /* A toggled Frame ID ends a Frame whose EOF was lost or never sent */
+        let header_info = payload[1];
+        let frame_id = header_info & HEADER_FRAME_ID != 0;
+        if video_stream.frame_id.is_some_and(|last_frame_id| last_frame_id != frame_id) && video_stream.is_open {
+            rows.extend(self.finish_frame(urb_header, false));
+        }
+
+        let Some(video_stream) = self.streams.get_mut(&stream_key) else { return rows };
+        video_stream.frame_id = Some(frame_id);
+        if !video_stream.is_open {
+            video_stream.is_open = true;
+            video_stream.frame_start = urb_header.timestamp;
+        }
+
+        let mut field_offset = 2;
+        if header_info & HEADER_PRESENTATION_TIME != 0 && header_length >= field_offset + 4 {
+            video_stream.presentation_time = Some(u32::from_le_bytes(payload[field_offset..field_offset + 4].try_into().unwrap()));
+            field_offset += 4;
+        }
+
+        if header_info & HEADER_SOURCE_CLOCK != 0 && header_length >= field_offset + 6 {
+            let source_time = u32::from_le_bytes(payload[field_offset..field_offset + 4].try_into().unwrap());
+            let start_of_frame = u16::from_le_bytes([payload[field_offset + 4], payload[field_offset + 5]]);
+            video_stream.source_clock = Some((source_time, start_of_frame));
+        }
+
+        video_stream.is_still_image |= header_info & HEADER_STILL_IMAGE != 0;
+        video_stream.has_error |= header_info & HEADER_ERROR != 0;
+        video_stream.frame_buffer.extend_from_slice(&payload[header_length..]);
+        if header_info & HEADER_END_OF_FRAME != 0 {
+            video_stream.uses_end_of_frame = true;
+            rows.extend(self.finish_frame(urb_header, true));
+        } else if video_stream.frame_buffer.len() > MAX_FRAME_LENGTH {
+            rows.extend(self.finish_frame(urb_header, false));
+        }
+
+        rows
+    }
+
+    async fn consume_stream(&mut self, urb_packet: UrbXractPacket) {
+        /* Video arrives on Completion of IN Transfers */
+        let urb_header = urb_packet.header;
+        if urb_header.event_type == UrbEventType::Submit || urb_header.endpoint_info & 0x80 == 0 {
+            return;
+        }
+
+        let stream_key = (urb_header.bus_id, urb_header.device_id, urb_header.endpoint_info);
+        if !self.streams.contains_key(&stream_key) {
+            let device_registry = self.module_context.device_registry.read().unwrap();
+            let Some(device) = device_registry.get_device(&urb_header) else { return };
+            let Some(interface) = device_registry.get_interface(&urb_header) else { return };
+            let video_stream = VideoStream {
+                interface_number: interface.number,
+                video_formats: video_descriptor::get_streaming_formats(device, interface.number),
+                ..VideoStream::default()
+            };
+
+            drop(device_registry);
+            self.streams.insert(stream_key, video_stream);
+        }
+
+        let urb_data = urb_packet.data.as_deref().unwrap_or_default();
+        let mut rows = vec![];
+        match urb_packet.iso_descriptors.is_empty() {
+            true if urb_header.status != 0 && urb_header.transfer_type == UrbTransferType::Isochronous => rows.extend(self.consume_payload(&urb_header, None)),
+            true if urb_header.status != 0 => return,
+            true => rows.extend(self.consume_payload(&urb_header, Some(urb_data))),
+            false => {
+                for iso_descriptor in &urb_packet.iso_descriptors {
+                    let start = (iso_descriptor.offset as usize).min(urb_data.len());
+                    let end = (start + iso_descriptor.length as usize).min(urb_data.len());
+                    let payload = (iso_descriptor.status == 0).then_some(&urb_data[start..end]);
+                    rows.extend(self.consume_payload(&urb_header, payload));
+                }
+            }
+        }
+
+        self.dispatch_rows(urb_header, rows).await;
+    }
+
+    fn describe_request(&mut self, urb_header: &UrbXractHeader, setup_packet: &SetupPacket, data: Option<&[u8]>) -> String {
+        /* wIndex: Entity ID in the high Byte, Interface in the low Byte */
+        let (interface_subclass, entity_subtype, video_formats) = {
+            let device_registry = self.module_context.device_registry.read().unwrap();
+            let device = device_registry.get_device(urb_header);
+            let interface = device.and_then(|device| device.get_interface_by_number(setup_packet.index as u8));
+            let Some((device, interface)) = device.zip(interface) else {
+                return format!("[UVC] {}", protocol_control::describe_setup(setup_packet, Some(video_descriptor::CLASS_VIDEO)));
+            };
+
+            let entity_subtype = match (setup_packet.index >> 8) as u8 {
+                0 => None,
+                entity_id => Some(video_descriptor::get_entities(device).get(&entity_id).copied().unwrap_or_default()),
+            };
+
+            (interface.subclass, entity_subtype, video_descriptor::get_streaming_formats(device, interface.number))
+        };
+
+        let control_selector = (setup_packet.value >> 8) as u8;
+        let request_name = get_request_name(setup_packet.request);
+        let (target_name, control_name) = match (interface_subclass, entity_subtype) {
+            (SUBCLASS_VIDEO_STREAMING, _) => (format!("Interface {}", setup_packet.index as u8), get_streaming_control_name(control_selector)),
+            (_, Some(subtype)) => (
+                format!("{} {}", video_descriptor::get_entity_name(subtype), setup_packet.index >> 8),
+                get_control_name(Some(subtype), control_selector),
+            ),
+            (_, None) => (format!("Interface {}", setup_packet.index as u8), get_control_name(None, control_selector)),
+        };
+
+        let is_streaming_control = interface_subclass == SUBCLASS_VIDEO_STREAMING && matches!(control_selector, CONTROL_PROBE | CONTROL_COMMIT);
+        let value = data.filter(|data| !data.is_empty()).map(|data| match setup_packet.request {
+            REQUEST_GET_LEN if data.len() >= 2 => format!("{} bytes", u16::from_le_bytes([data[0], data[1]])),
+            0x86 => format!("Capabilities 0x{:02X}", data[0]),
+            _ if is_streaming_control => match StreamingControl::parse(data) {
+                Some(streaming_control) => streaming_control.describe(&video_formats),
+                None => format_value("", data),
+            },
+            _ if entity_subtype.is_none() && control_selector == CONTROL_REQUEST_ERROR_CODE && interface_subclass != SUBCLASS_VIDEO_STREAMING => get_request_error_name(data[0]),
+            _ => format_value(&control_name, data),
+        });
+
+        match value {
+            Some(value) => format!("[UVC] {} {} {}: {}", request_name, target_name, control_name, value),
+            None => format!("[UVC] {} {} {}", request_name, target_name, control_name),
+        }
+    }
+
+    async fn consume_control(&mut self, mut urb_packet: UrbXractPacket) {
+        let urb_header = urb_packet.header;
+        let Some((setup_packet, data)) = self.pending_requests.pair(&mut urb_packet) else { return };
+        let mut description = self.describe_request(&urb_header, &setup_packet, data);
+
+        /* The committed Format decides how Frames are checked and written */
+        let is_commit = (setup_packet.value >> 8) as u8 == CONTROL_COMMIT && matches!(setup_packet.request, REQUEST_SET_CUR | REQUEST_GET_CUR);
+        if urb_header.status == 0 && is_commit && setup_packet.index >> 8 == 0 && let Some(commit) = data.and_then(StreamingControl::parse) {
+            self.commits.insert((urb_header.bus_id, urb_header.device_id, setup_packet.index as u8), commit);
+        }
+
+        if urb_header.status != 0 {
+            description += &format!(" -> {}", protocol_control::describe_status(&urb_header));
+        }
+
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_header,
+            combined_payload: description,
+            sources: vec![urb_packet],
+            is_error: urb_header.status != 0,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+
+    async fn consume_interrupt(&mut self, urb_packet: UrbXractPacket) {
+        /* Status Packets: Control Changes of Video Control Entities, Buttons of Streaming Interfaces */
+        let urb_header = urb_packet.header;
+        let Some(data) = urb_packet.data.as_deref() else { return };
+        let description = match data {
+            [status_type, originator, 0x00, control_selector, attribute, value @ ..] if status_type & 0x0F == 0x01 => {
+                let attribute_name = match attribute {
+                    0x00 => "Value",
+                    0x01 => "Info",
+                    0x02 => "Failure",
+                    0x03 => "Minimum",
+                    0x04 => "Maximum",
+                    _ => "Attribute",
+                };
+
+                let entity_subtype = {
+                    let device_registry = self.module_context.device_registry.read().unwrap();
+                    device_registry.get_device(&urb_header).and_then(|device| video_descriptor::get_entities(device).get(originator).copied())
+                };
+
+                let entity_name = entity_subtype.map_or("Entity", video_descriptor::get_entity_name);
+                let control_name = get_control_name(entity_subtype, *control_selector);
+                format!("[UVC] Status: {} {} {} {} changed to {}", entity_name, originator, control_name, attribute_name, format_value(&control_name, value))
+            },
+
+            [status_type, originator, 0x00, button_state, ..] if status_type & 0x0F == 0x02 => {
+                format!("[UVC] Status: Interface {} Button {}", originator, if *button_state != 0 { "pressed" } else { "released" })
+            },
+
+            _ => format!("[UVC] Status: {}", protocol_control::format_hex(data)),
+        };
+
+        let transmission = ReconstructedTransmission {
+            urbx_header: urb_header,
+            combined_payload: description,
+            sources: vec![urb_packet],
+            is_error: false,
+        };
+
+        self.module_tx.send(transmission).await.unwrap();
+    }
+}
+
+impl ReconstructionModule for Reconstructor {
+    fn new(module_tx: Sender<ReconstructedTransmission>, module_context: &ModuleContext) -> Self {
+        Self {
+            module_tx,
+            module_context: module_context.clone(),
+            pending_requests: PendingRequests::default(),
+            commits: HashMap::new(),
+            streams: HashMap::new(),
+        }
+    }
+
+    async fn consume_packet(&mut self, urb_packet: UrbXractPacket) {
+        /* Bulk Video Streaming Endpoints share the Interrupt Path only on Video Control */
+        let is_control_interface = {
+            let device_registry = self.module_context.device_registry.read().unwrap();
+            device_registry
+                .get_interface(&urb_packet.header)
+                .is_some_and(|interface| interface.subclass == video_descriptor::SUBCLASS_VIDEO_CONTROL)
+        };
+
+        match urb_packet.header.transfer_type {
+            UrbTransferType::Control => self.consume_control(urb_packet).await,
+            UrbTransferType::Interrupt if is_control_interface => self.consume_interrupt(urb_packet).await,
+            UrbTransferType::Interrupt => {}
+            UrbTransferType::Bulk | UrbTransferType::Isochronous => self.consume_stream(urb_packet).await,
+        }
+    }
+}
@@ -0,0 +1,401 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/*
+    Picture Transfer Protocol and its Media Transfer Protocol Extensions.
+    Every Phase starts with a Container Header: Length, Type, Operation,
+    Response or Event Code and Transaction ID. Datasets use little endian
+    Integers, counted Arrays and Strings of UTF-16 Characters
+*/
+pub const CONTAINER_HEADER_LENGTH: usize = 12;
+pub const CONTAINER_COMMAND: u16 = 1;
+pub const CONTAINER_DATA: u16 = 2;
+pub const CONTAINER_RESPONSE: u16 = 3;
+pub const CONTAINER_EVENT: u16 = 4;
+pub const OPERATION_GET_DEVICE_INFO: u16 = 0x1001;
+pub const OPERATION_GET_STORAGE_IDS: u16 = 0x1004;
+pub const OPERATION_GET_STORAGE_INFO: u16 = 0x1005;
+pub const OPERATION_GET_OBJECT_HANDLES: u16 = 0x1007;
+pub const OPERATION_GET_OBJECT_INFO: u16 = 0x1008;
+pub const OPERATION_GET_OBJECT: u16 = 0x1009;
+pub const OPERATION_SEND_OBJECT_INFO: u16 = 0x100C;
+pub const OPERATION_SEND_OBJECT: u16 = 0x100D;
+pub const OPERATION_GET_OBJECT_REFERENCES: u16 = 0x9810;
+pub const RESPONSE_OK: u16 = 0x2001;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ContainerHeader {
+    pub length: u32,
+    pub container_type: u16,
+    pub code: u16,
+    pub transaction_id: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectInfo {
+    pub object_format: u16,
+    pub compressed_size: u32,
+    pub parent_object: u32,
+    pub filename: String,
+}
+
+struct DatasetReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> DatasetReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        DatasetReader { data, offset: 0 }
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.offset..self.offset + length)?;
+        self.offset += length;
+        Some(bytes)
+    }
+
+    fn read_u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.read_bytes(2)?.try_into().ok()?))
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.read_bytes(4)?.try_into().ok()?))
+    }
+
+    fn read_u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.read_bytes(8)?.try_into().ok()?))
+    }
+
+    fn read_string(&mut self) -> Option<String> {
+        /* Character Count includes the terminating NUL */
+        let character_count = *self.read_bytes(1)?.first()? as usize;
+        let characters: Vec<u16> = self.read_bytes(character_count * 2)?
+            .chunks_exact(2)
+            .map(|character| u16::from_le_bytes([character[0], character[1]]))
+            .take_while(|character| *character != 0)
+            .collect();
+
+        Some(String::from_utf16_lossy(&characters))
+    }
+
+    fn skip_array(&mut self, element_size: usize) -> Option<()> {
+        let element_count = self.read_u32()? as usize;
+        self.read_bytes(element_count.checked_mul(element_size)?)?;
+        Some(())
+    }
+}
+
+pub fn parse_header(data: &[u8]) -> Option<ContainerHeader> {
+    let header = data.get(0..CONTAINER_HEADER_LENGTH)?;
+    let container_header = ContainerHeader {
+        length: u32::from_le_bytes(header[0..4].try_into().unwrap()),
+        container_type: u16::from_le_bytes([header[4], header[5]]),
+        code: u16::from_le_bytes([header[6], header[7]]),
+        transaction_id: u32::from_le_bytes(header[8..12].try_into().unwrap()),
+    };
+
+    let is_valid = (CONTAINER_COMMAND..=CONTAINER_EVENT).contains(&container_header.container_type) && container_header.length as usize >= CONTAINER_HEADER_LENGTH;
+    is_valid.then_some(container_header)
+}
+
+pub fn get_parameters(data: &[u8], container_header: &ContainerHeader) -> Vec<u32> {
+    /* Up to five Parameters follow the Header of Commands, Responses and Events */
+    let end = (container_header.length as usize).min(data.len());
+    data.get(CONTAINER_HEADER_LENGTH..end)
+        .unwrap_or_default()
+        .chunks_exact(4)
+        .take(5)
+        .map(|parameter| u32::from_le_bytes(parameter.try_into().unwrap()))
+        .collect()
+}
+
+pub fn format_parameters(parameters: &[u32]) -> String {
+    match parameters.is_empty() {
+        true => String::new(),
+        false => format!(" {}", parameters.iter().map(|parameter| format!("0x{:08X}", parameter)).collect::<Vec<String>>().join(", ")),
+    }
+}
+
+pub fn get_operation_name(operation: u16) -> String {
+    let operation_name = match operation {
+        OPERATION_GET_DEVICE_INFO => "GetDeviceInfo",
+        0x1002 => "OpenSession",
+        0x1003 => "CloseSession",
+        OPERATION_GET_STORAGE_IDS => "GetStorageIDs",
+        OPERATION_GET_STORAGE_INFO => "GetStorageInfo",
+        0x1006 => "GetNumObjects",
+        OPERATION_GET_OBJECT_HANDLES => "GetObjectHandles",
+        OPERATION_GET_OBJECT_INFO => "GetObjectInfo",
+        OPERATION_GET_OBJECT => "GetObject",
+        0x100A => "GetThumb",
+        0x100B => "DeleteObject",
+        OPERATION_SEND_OBJECT_INFO => "SendObjectInfo",
+        OPERATION_SEND_OBJECT => "SendObject",
+        0x100E => "InitiateCapture",
+        0x100F => "FormatStore",
+        0x1010 => "ResetDevice",
+        0x1011 => "SelfTest",
+        0x1012 => "SetObjectProtection",
+        0x1013 => "PowerDown",
+        0x1014 => "GetDevicePropDesc",
+        0x1015 => "GetDevicePropValue",
+        0x1016 => "SetDevicePropValue",
+        0x1017 => "ResetDevicePropValue",
+        0x1018 => "TerminateOpenCapture",
+        0x1019 => "MoveObject",
+        0x101A => "CopyObject",
+        0x101B => "GetPartialObject",
+        0x101C => "InitiateOpenCapture",
+        0x95C1 => "GetPartialObject64",
+        0x95C2 => "SendPartialObject",
+        0x95C3 => "TruncateObject",
+        0x95C4 => "BeginEditObject",
+        0x95C5 => "EndEditObject",
+        0x9801 => "GetObjectPropsSupported",
+        0x9802 => "GetObjectPropDesc",
+        0x9803 => "GetObjectPropValue",
+        0x9804 => "SetObjectPropValue",
+        0x9805 => "GetObjectPropList",
+        0x9806 => "SetObjectPropList",
+        0x9807 => "GetInterdependentPropDesc",
+        0x9808 => "SendObjectPropList",
+        OPERATION_GET_OBJECT_REFERENCES => "GetObjectReferences",
+        0x9811 => "SetObjectReferences",
+        _ => return format!("Operation 0x{:04X}", operation),
+    };
+
+    String::from(operation_name)
+}
+
+pub fn get_response_name(response: u16) -> String {
+    let response_name = match response {
+        RESPONSE_OK => "OK",
+        0x2002 => "General Error",
+        0x2003 => "Session Not Open",
+        0x2004 => "Invalid TransactionID",
+        0x2005 => "Operation Not Supported",
+        0x2006 => "Parameter Not Supported",
+        0x2007 => "Incomplete Transfer",
+        0x2008 => "Invalid StorageID",
+        0x2009 => "Invalid ObjectHandle",
+        0x200A => "DeviceProp Not Supported",
+        0x200B => "Invalid ObjectFormatCode",
+        0x200C => "Store Full",
+        0x200D => "Object WriteProtected",
+        0x200E => "Store Read-Only",
+        0x200F => "Access Denied",
+        0x2010 => "No Thumbnail Present",
+        0x2011 => "SelfTest Failed",
+        0x2012 => "Partial Deletion",
+        0x2013 => "Store Not Available",
+        0x2014 => "Specification By Format Unsupported",
+        0x2015 => "No Valid ObjectInfo",
+        0x2016 => "Invalid Code Format",
+        0x2017 => "Unknown Vendor Code",
+        0x2018 => "Capture Already Terminated",
+        0x2019 => "Device Busy",
+        0x201A => "Invalid ParentObject",
+        0x201B => "Invalid DeviceProp Format",
+        0x201C => "Invalid DeviceProp Value",
+        0x201D => "Invalid Parameter",
+        0x201E => "Session Already Open",
+        0x201F => "Transaction Cancelled",
+        0x2020 => "Specification of Destination Unsupported",
+        0xA801 => "Invalid ObjectPropCode",
+        0xA802 => "Invalid ObjectProp Format",
+        0xA803 => "Invalid ObjectProp Value",
+        0xA804 => "Invalid ObjectReference",
+        0xA806 => "Invalid Dataset",
+        0xA807 => "Specification By Group Unsupported",
+        0xA80A => "Object Too Large",
+        _ => return format!("Response 0x{:04X}", response),
+    };
+
+    String::from(response_name)
+}
+
+pub fn get_event_name(event: u16) -> String {
+    let event_name = match event {
+        0x4001 => "CancelTransaction",
+        0x4002 => "ObjectAdded",
+        0x4003 => "ObjectRemoved",
+        0x4004 => "StoreAdded",
+        0x4005 => "StoreRemoved",
+        0x4006 => "DevicePropChanged",
+        0x4007 => "ObjectInfoChanged",
+        0x4008 => "DeviceInfoChanged",
+        0x4009 => "RequestObjectTransfer",
+        0x400A => "StoreFull",
+        0x400B => "DeviceReset",
+        0x400C => "StorageInfoChanged",
+        0x400D => "CaptureComplete",
+        0x400E => "UnreportedStatus",
+        0xC801 => "ObjectPropChanged",
+        0xC802 => "ObjectPropDescChanged",
+        0xC803 => "ObjectReferencesChanged",
+        _ => return format!("Event 0x{:04X}", event),
+    };
+
+    String::from(event_name)
+}
+
+fn get_format_name(object_format: u16) -> String {
+    let format_name = match object_format {
+        0x3000 => "Undefined",
+        0x3001 => "Association",
+        0x3004 => "Text",
+        0x3005 => "HTML",
+        0x3008 => "WAV",
+        0x3009 => "MP3",
+        0x300A => "AVI",
+        0x300B => "MPEG",
+        0x3801 => "EXIF/JPEG",
+        0x3804 => "BMP",
+        0x3807 => "GIF",
+        0x3808 => "JFIF",
+        0x380B => "PNG",
+        0x380D => "TIFF",
+        0xB901 => "WMA",
+        0xB902 => "OGG",
+        0xB903 => "AAC",
+        0xB982 => "MP4",
+        0xB984 => "3GP",
+        _ => return format!("Format 0x{:04X}", object_format),
+    };
+
+    String::from(format_name)
+}
+
+pub fn parse_object_info(data: &[u8]) -> Option<ObjectInfo> {
+    /* StorageID, Format, Protection, Size, Thumb and Image Fields, Parent, Association, Sequence, Filename */
+    let mut dataset_reader = DatasetReader::new(data);
+    dataset_reader.read_u32()?;
+    let object_format = dataset_reader.read_u16()?;
+    dataset_reader.read_u16()?;
+    let compressed_size = dataset_reader.read_u32()?;
+    dataset_reader.read_bytes(26)?;
+    let parent_object = dataset_reader.read_u32()?;
+    dataset_reader.read_bytes(10)?;
+    let filename = dataset_reader.read_string()?;
+
+    Some(ObjectInfo { object_format, compressed_size, parent_object, filename })
+}
+
+impl ObjectInfo {
+    pub fn describe(&self) -> String {
+        format!("\"{}\", {}, {} bytes", self.filename, get_format_name(self.object_format), self.compressed_size)
+    }
+}
+
+fn describe_device_info(data: &[u8]) -> Option<String> {
+    /* Versions, Extension Description, Functional Mode and five supported Code Arrays precede the Strings */
+    let mut dataset_reader = DatasetReader::new(data);
+    dataset_reader.read_bytes(8)?;
+    let extension = dataset_reader.read_string()?;
+    dataset_reader.read_u16()?;
+    for _ in 0..5 {
+        dataset_reader.skip_array(2)?;
+    }
+
+    let manufacturer = dataset_reader.read_string()?;
+    let model = dataset_reader.read_string()?;
+    let device_version = dataset_reader.read_string()?;
+    let serial_number = dataset_reader.read_string()?;
+    let extension = if extension.is_empty() { String::new() } else { format!(", Extension \"{}\"", extension) };
+    Some(format!("{} {}, Version {}, Serial {}{}", manufacturer, model, device_version, serial_number, extension))
+}
+
+fn describe_storage_info(data: &[u8]) -> Option<String> {
+    /* Storage Type, Filesystem Type, Access Capability, Capacity, Free Space, Free Objects, Description */
+    let mut dataset_reader = DatasetReader::new(data);
+    dataset_reader.read_bytes(6)?;
+    let max_capacity = dataset_reader.read_u64()?;
+    let free_space = dataset_reader.read_u64()?;
+    dataset_reader.read_u32()?;
+    let storage_description = dataset_reader.read_string()?;
+    Some(format!("\"{}\", {} of {} bytes free", storage_description, free_space, max_capacity))
+}
+
+pub fn describe_dataset(operation: u16, data: &[u8]) -> Option<String> {
+    match operation {
+        OPERATION_GET_DEVICE_INFO => describe_device_info(data),
+        OPERATION_GET_STORAGE_INFO => describe_storage_info(data),
+        OPERATION_GET_OBJECT_INFO | OPERATION_SEND_OBJECT_INFO => parse_object_info(data).map(|object_info| object_info.describe()),
+        OPERATION_GET_STORAGE_IDS => DatasetReader::new(data).read_u32().map(|storage_count| format!("{} Storages", storage_count)),
+        OPERATION_GET_OBJECT_HANDLES | OPERATION_GET_OBJECT_REFERENCES => DatasetReader::new(data).read_u32().map(|handle_count| format!("{} Objects", handle_count)),
+        _ => None,
+    }
+}
+
+pub fn describe_class_request(request: u8, data: &[u8]) -> Option<String> {
+    /* Still Image Class Requests: Cancel carries the Transaction, Device Status a Response Code */
+    let description = match request {
+        0x64 if data.len() >= 6 => format!("Cancel Request Tx {}", u32::from_le_bytes(data[2..6].try_into().unwrap())),
+        0x64 => String::from("Cancel Request"),
+        0x65 => String::from("Get Extended Event Data"),
+        0x66 => String::from("Device Reset Request"),
+        0x67 if data.len() >= 4 => format!("Get Device Status: {}", get_response_name(u16::from_le_bytes([data[2], data[3]]))),
+        0x67 => String::from("Get Device Status"),
+        _ => return None,
+    };
+
+    Some(description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put_string(dataset: &mut Vec<u8>, text: &str) {
+        let characters: Vec<u16> = text.encode_utf16().chain([0]).collect();
+        dataset.push(characters.len() as u8);
+        characters.iter().for_each(|character| dataset.extend_from_slice(&character.to_le_bytes()));
+    }
+
+    #[test]
+    fn parses_object_info_offsets() {
+        /* StorageID, Format, Protection, Size at 0..12, Thumb and Image Fields at 12..38 */
+        let mut dataset = vec![];
+        dataset.extend_from_slice(&0x0001_0001u32.to_le_bytes());
+        dataset.extend_from_slice(&0x3801u16.to_le_bytes());
+        dataset.extend_from_slice(&0x0000u16.to_le_bytes());
+        dataset.extend_from_slice(&123_456u32.to_le_bytes());
+        dataset.extend_from_slice(&[0xEE; 26]);
+
+        /* Parent at 38, Association and Sequence at 42..52, Filename at 52 */
+        dataset.extend_from_slice(&0x0000_0042u32.to_le_bytes());
+        dataset.extend_from_slice(&[0xEE; 10]);
+        assert_eq!(dataset.len(), 52);
+        put_string(&mut dataset, "IMG_0001.JPG");
+        put_string(&mut dataset, "20240101T120000");
+
+        let object_info = parse_object_info(&dataset).unwrap();
+        assert_eq!(object_info, ObjectInfo {
+            object_format: 0x3801,
+            compressed_size: 123_456,
+            parent_object: 0x42,
+            filename: String::from("IMG_0001.JPG"),
+        });
+        assert_eq!(object_info.describe(), "\"IMG_0001.JPG\", EXIF/JPEG, 123456 bytes");
+
+        /* The Filename must be complete */
+        assert_eq!(parse_object_info(&dataset[..52 + 1 + 12 * 2]), None);
+        assert_eq!(parse_object_info(&dataset[..52]), None);
+    }
+}
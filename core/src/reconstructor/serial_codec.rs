@@ -0,0 +1,375 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::str::FromStr;
+
+use super::protocol_control::format_hex;
+
+/* Define Constants */
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+const HDLC_FLAG: u8 = 0x7E;
+const HDLC_ESCAPE: u8 = 0x7D;
+const HDLC_ESCAPE_XOR: u8 = 0x20;
+const HDLC_FCS_GOOD: u16 = 0xF0B8; /* FCS-16 Residue over Data and FCS, RFC 1662 */
+
+/*
+    Binary Framing on top of the Serial Byte Stream. Length Prefix is
+    written as length:OFFSET:SIZE:ENDIAN:ADJUST, the Field counts the
+    bytes after it and ADJUST corrects Fields that include Headers or CRCs
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameCodec {
+    Slip,
+    Cobs,
+    Hdlc,
+    LengthPrefix { offset: usize, size: usize, is_big_endian: bool, adjust: i64 }
+}
+
+#[derive(Debug)]
+pub struct DecodedFrame {
+    pub payload: Vec<u8>,
+    pub crc_valid: Option<bool>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct FrameDecoder {
+    codec: FrameCodec,
+    buffer: Vec<u8>,
+    is_escaped: bool,
+    max_length: Option<usize>,
+}
+
+impl FromStr for FrameCodec {
+    type Err = String;
+
+    fn from_str(codec_name: &str) -> Result<Self, Self::Err> {
+        let mut parameters = codec_name.split(':');
+        match parameters.next().unwrap_or_default().to_ascii_lowercase().as_str() {
+            "slip" => Ok(FrameCodec::Slip),
+            "cobs" => Ok(FrameCodec::Cobs),
+            "hdlc" => Ok(FrameCodec::Hdlc),
+            "length" => {
+                let offset = parameters.next().unwrap_or("0").parse::<usize>().map_err(|_| format!("Invalid Length Offset in {}", codec_name))?;
+                let size = parameters.next().unwrap_or("1").parse::<usize>().map_err(|_| format!("Invalid Length Size in {}", codec_name))?;
+                let is_big_endian = match parameters.next().unwrap_or("le") {
+                    "le" => false,
+                    "be" => true,
+                    endian => return Err(format!("Invalid Length Endian {}, expected le or be", endian))
+                };
+
+                let adjust = parameters.next().unwrap_or("0").parse::<i64>().map_err(|_| format!("Invalid Length Adjust in {}", codec_name))?;
+                if ![1, 2, 4].contains(&size) {
+                    return Err(format!("Invalid Length Size {}, expected 1, 2 or 4", size));
+                }
+
+                Ok(FrameCodec::LengthPrefix { offset, size, is_big_endian, adjust })
+            },
+            _ => Err(format!("Unknown Codec {}, expected slip, cobs, hdlc or length", codec_name))
+        }
+    }
+}
+
+impl FrameCodec {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            FrameCodec::Slip => "SLIP",
+            FrameCodec::Cobs => "COBS",
+            FrameCodec::Hdlc => "HDLC",
+            FrameCodec::LengthPrefix { .. } => "LENGTH",
+        }
+    }
+}
+
+fn get_fcs16(data: &[u8]) -> u16 {
+    /* CRC-16/X-25 as used by HDLC and PPP */
+    let mut fcs: u16 = 0xFFFF;
+    for byte in data {
+        fcs ^= *byte as u16;
+        for _ in 0..8 {
+            fcs = if fcs & 0x0001 != 0 { (fcs >> 1) ^ 0x8408 } else { fcs >> 1 };
+        }
+    }
+
+    fcs
+}
+
+fn decode_cobs(encoded: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut offset = 0;
+    while offset < encoded.len() {
+        let code = encoded[offset] as usize;
+        if code == 0 || offset + code > encoded.len() {
+            return Err(format!("Code 0x{:02X} at Offset {} overruns the Frame", code, offset));
+        }
+
+        decoded.extend_from_slice(&encoded[(offset + 1)..(offset + code)]);
+        offset += code;
+
+        /* Codes below 0xFF stand for a Zero, except at the end of the Frame */
+        if code < 0xFF && offset < encoded.len() {
+            decoded.push(0);
+        }
+    }
+
+    Ok(decoded)
+}
+
+fn decode_hdlc(unescaped: Vec<u8>) -> DecodedFrame {
+    if unescaped.len() < 3 {
+        return DecodedFrame { payload: unescaped, crc_valid: None, error: Some(String::from("Short Frame")) };
+    }
+
+    let crc_valid = get_fcs16(&unescaped) == HDLC_FCS_GOOD;
+    let payload_length = unescaped.len() - 2;
+    let mut payload = unescaped;
+    payload.truncate(payload_length);
+
+    DecodedFrame { payload, crc_valid: Some(crc_valid), error: None }
+}
+
+impl FrameDecoder {
+    pub fn new(codec: FrameCodec, max_length: Option<usize>) -> Self {
+        Self { codec, buffer: vec![], is_escaped: false, max_length }
+    }
+
+    pub fn get_codec(&self) -> FrameCodec {
+        self.codec
+    }
+
+    pub fn is_in_frame(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    fn finish(&mut self) -> Option<DecodedFrame> {
+        /* Back-to-back Delimiters carry no Frame */
+        self.is_escaped = false;
+        if self.buffer.is_empty() {
+            return None;
+        }
+
+        let buffer = std::mem::take(&mut self.buffer);
+        Some(match self.codec {
+            FrameCodec::Cobs => match decode_cobs(&buffer) {
+                Ok(payload) => DecodedFrame { payload, crc_valid: None, error: None },
+                Err(error) => DecodedFrame { payload: buffer, crc_valid: None, error: Some(error) },
+            },
+            FrameCodec::Hdlc => decode_hdlc(buffer),
+            _ => DecodedFrame { payload: buffer, crc_valid: None, error: None },
+        })
+    }
+
+    fn push_limited(&mut self, byte: u8) -> Option<DecodedFrame> {
+        /* Garbage without Delimiters must not grow forever */
+        self.buffer.push(byte);
+        if self.max_length.is_some_and(|max_length| self.buffer.len() > max_length) {
+            let mut frame = self.finish()?;
+            frame.error = Some(String::from("Frame exceeds Maximum Length"));
+            return Some(frame);
+        }
+
+        None
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<DecodedFrame> {
+        match self.codec {
+            FrameCodec::Slip => {
+                if self.is_escaped {
+                    self.is_escaped = false;
+                    return match byte {
+                        SLIP_ESC_END => self.push_limited(SLIP_END),
+                        SLIP_ESC_ESC => self.push_limited(SLIP_ESC),
+                        _ => {
+                            let mut frame = self.finish().unwrap_or(DecodedFrame { payload: vec![], crc_valid: None, error: None });
+                            frame.payload.push(byte);
+                            frame.error = Some(format!("Invalid Escape 0x{:02X}", byte));
+                            Some(frame)
+                        }
+                    };
+                }
+
+                match byte {
+                    SLIP_END => self.finish(),
+                    SLIP_ESC => {
+                        self.is_escaped = true;
+                        None
+                    },
+                    _ => self.push_limited(byte)
+                }
+            },
+
+            FrameCodec::Cobs => match byte {
+                0x00 => self.finish(),
+                _ => self.push_limited(byte)
+            },
+
+            FrameCodec::Hdlc => match byte {
+                /* An Escape before the Flag aborts the Frame */
+                HDLC_FLAG if self.is_escaped => {
+                    let mut frame = self.finish()?;
+                    frame.error = Some(String::from("Aborted Frame"));
+                    Some(frame)
+                },
+                HDLC_FLAG => self.finish(),
+                HDLC_ESCAPE => {
+                    self.is_escaped = true;
+                    None
+                },
+                _ if self.is_escaped => {
+                    self.is_escaped = false;
+                    self.push_limited(byte ^ HDLC_ESCAPE_XOR)
+                },
+                _ => self.push_limited(byte)
+            },
+
+            FrameCodec::LengthPrefix { offset, size, is_big_endian, adjust } => {
+                if let Some(frame) = self.push_limited(byte) {
+                    return Some(frame);
+                }
+
+                if self.buffer.len() < offset + size {
+                    return None;
+                }
+
+                let length_field = &self.buffer[offset..(offset + size)];
+                let length_value = match is_big_endian {
+                    true => length_field.iter().fold(0u64, |value, byte| (value << 8) | *byte as u64),
+                    false => length_field.iter().rev().fold(0u64, |value, byte| (value << 8) | *byte as u64),
+                };
+
+                let frame_length = (offset + size) as i64 + length_value as i64 + adjust;
+                if frame_length < (offset + size) as i64 {
+                    let mut frame = self.finish()?;
+                    frame.error = Some(format!("Invalid Length {}", length_value));
+                    return Some(frame);
+                }
+
+                match self.buffer.len() as i64 >= frame_length {
+                    true => self.finish(),
+                    false => None
+                }
+            }
+        }
+    }
+
+    pub fn take_partial(&mut self) -> Option<DecodedFrame> {
+        /* Idle Timeouts end Frames whose Delimiter never arrived */
+        let mut frame = DecodedFrame { payload: std::mem::take(&mut self.buffer), crc_valid: None, error: None };
+        self.is_escaped = false;
+        if frame.payload.is_empty() {
+            return None;
+        }
+
+        frame.error = Some(String::from("Incomplete Frame"));
+        Some(frame)
+    }
+}
+
+pub fn describe_frame(codec: FrameCodec, frame: &DecodedFrame) -> String {
+    let frame_status = match (&frame.error, frame.crc_valid) {
+        (Some(error), _) => format!(", {}", error),
+        (None, Some(true)) => String::from(", CRC OK"),
+        (None, Some(false)) => String::from(", CRC ERROR"),
+        (None, None) => String::new(),
+    };
+
+    format!(
+        "[{}] {} bytes{}: {}",
+        codec.get_name(),
+        frame.payload.len(),
+        frame_status,
+        format_hex(&frame.payload)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(codec: FrameCodec, stream: &[u8]) -> Vec<DecodedFrame> {
+        let mut frame_decoder = FrameDecoder::new(codec, None);
+        stream.iter().filter_map(|byte| frame_decoder.push(*byte)).collect()
+    }
+
+    #[test]
+    fn decodes_slip_escapes() {
+        let frames = decode_all(FrameCodec::Slip, &[0xC0, 0x01, 0xDB, 0xDC, 0xDB, 0xDD, 0x02, 0xC0]);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].payload, [0x01, 0xC0, 0xDB, 0x02]);
+        assert!(frames[0].error.is_none());
+    }
+
+    #[test]
+    fn decodes_cobs_zero_run() {
+        /* 11 22 00 33 and 00 00, one Frame each */
+        let frames = decode_all(FrameCodec::Cobs, &[0x03, 0x11, 0x22, 0x02, 0x33, 0x00, 0x01, 0x01, 0x01, 0x00]);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].payload, [0x11, 0x22, 0x00, 0x33]);
+        assert_eq!(frames[1].payload, [0x00, 0x00]);
+    }
+
+    #[test]
+    fn checks_hdlc_fcs() {
+        /* CRC-16/X-25 Check Value, and the Residue once the FCS is appended */
+        assert_eq!(get_fcs16(b"123456789") ^ 0xFFFF, 0x906E);
+        assert_eq!(get_fcs16(b"123456789\x6E\x90"), HDLC_FCS_GOOD);
+
+        /* FF 03 7E 7D with FCS 0x14DE, both Payload Flag and Escape escaped */
+        let frames = decode_all(FrameCodec::Hdlc, &[0x7E, 0xFF, 0x03, 0x7D, 0x5E, 0x7D, 0x5D, 0xDE, 0x14, 0x7E]);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].payload, [0xFF, 0x03, 0x7E, 0x7D]);
+        assert_eq!(frames[0].crc_valid, Some(true));
+
+        let frames = decode_all(FrameCodec::Hdlc, &[0x7E, 0xFF, 0x03, 0x7D, 0x5E, 0x7D, 0x5D, 0xDE, 0x15, 0x7E]);
+        assert_eq!(frames[0].crc_valid, Some(false));
+    }
+
+    #[test]
+    fn frames_length_prefix() {
+        let codec: FrameCodec = "length:1:2:be:0".parse().unwrap();
+        let frames = decode_all(codec, &[0xAA, 0x00, 0x02, 0x10, 0x20, 0xBB, 0x00, 0x00]);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].payload, [0xAA, 0x00, 0x02, 0x10, 0x20]);
+        assert_eq!(frames[1].payload, [0xBB, 0x00, 0x00]);
+        assert!("length:0:3".parse::<FrameCodec>().is_err());
+    }
+
+    #[test]
+    fn reports_truncated_frames() {
+        /* COBS Code runs past the Delimiter */
+        let frames = decode_all(FrameCodec::Cobs, &[0x05, 0x11, 0x00]);
+        assert!(frames[0].error.as_deref().is_some_and(|error| error.contains("overruns")));
+
+        /* HDLC Frames need at least one byte besides the FCS */
+        let frames = decode_all(FrameCodec::Hdlc, &[0x7E, 0x01, 0x02, 0x7E]);
+        assert_eq!(frames[0].error.as_deref(), Some("Short Frame"));
+
+        /* Negative Adjust shorter than the Header */
+        let frames = decode_all("length:0:1:le:-4".parse().unwrap(), &[0x01]);
+        assert_eq!(frames[0].error.as_deref(), Some("Invalid Length 1"));
+
+        /* Bytes left without a Delimiter */
+        let mut frame_decoder = FrameDecoder::new(FrameCodec::Slip, None);
+        assert!([0x01, 0xDB].iter().all(|byte| frame_decoder.push(*byte).is_none()));
+        assert_eq!(frame_decoder.take_partial().unwrap().error.as_deref(), Some("Incomplete Frame"));
+        assert!(!frame_decoder.is_in_frame());
+    }
+}
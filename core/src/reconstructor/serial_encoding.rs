@@ -0,0 +1,179 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>. 
+*/
+
+use std::fmt;
+use std::str::FromStr;
+
+/*
+    Incremental Decoding for Serial Streams: bytes of a character split
+    across URBs stay buffered, undecodable bytes are rendered as \xNN
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SerialEncoding {
+    #[default]
+    Utf8,
+    Latin1,
+    Cp437,
+    ShiftJis
+}
+
+/* Code Page 437 upper half, 0x80 to 0xFF */
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}',
+];
+
+impl FromStr for SerialEncoding {
+    type Err = String;
+
+    fn from_str(encoding_name: &str) -> Result<Self, Self::Err> {
+        match encoding_name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Ok(SerialEncoding::Utf8),
+            "latin-1" | "latin1" | "iso-8859-1" => Ok(SerialEncoding::Latin1),
+            "cp437" | "ibm437" => Ok(SerialEncoding::Cp437),
+            "shift-jis" | "shiftjis" | "sjis" => Ok(SerialEncoding::ShiftJis),
+            _ => Err(format!("Unknown Encoding {}, expected utf-8, latin-1, cp437 or shift-jis", encoding_name))
+        }
+    }
+}
+
+impl fmt::Display for SerialEncoding {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match self {
+            SerialEncoding::Utf8 => "utf-8",
+            SerialEncoding::Latin1 => "latin-1",
+            SerialEncoding::Cp437 => "cp437",
+            SerialEncoding::ShiftJis => "shift-jis",
+        })
+    }
+}
+
+fn push_hex(text: &mut String, bytes: &[u8]) {
+    bytes.iter().for_each(|byte| *text += &format!("\\x{:02X}", byte));
+}
+
+fn decode_utf8(text: &mut String, buffer: &[u8], is_final: bool) -> usize {
+    let mut offset = 0;
+    while offset < buffer.len() {
+        match std::str::from_utf8(&buffer[offset..]) {
+            Ok(valid_text) => {
+                *text += valid_text;
+                return buffer.len();
+            },
+
+            Err(utf8_error) => {
+                let valid_length = utf8_error.valid_up_to();
+                *text += std::str::from_utf8(&buffer[offset..(offset + valid_length)]).unwrap();
+                offset += valid_length;
+
+                match utf8_error.error_len() {
+                    Some(invalid_length) => {
+                        push_hex(text, &buffer[offset..(offset + invalid_length)]);
+                        offset += invalid_length;
+                    },
+
+                    /* The Sequence continues in the next URB */
+                    None if !is_final => return offset,
+                    None => {
+                        push_hex(text, &buffer[offset..]);
+                        return buffer.len();
+                    }
+                }
+            }
+        }
+    }
+
+    offset
+}
+
+fn decode_shift_jis(text: &mut String, buffer: &[u8], is_final: bool) -> usize {
+    let mut offset = 0;
+    while offset < buffer.len() {
+        let lead_byte = buffer[offset];
+        match lead_byte {
+            0x00..=0x7F => {
+                text.push(lead_byte as char);
+                offset += 1;
+            },
+
+            /* Half-width Katakana map linearly */
+            0xA1..=0xDF => {
+                text.push(char::from_u32(0xFF61 + (lead_byte - 0xA1) as u32).unwrap());
+                offset += 1;
+            },
+
+            0x81..=0x9F | 0xE0..=0xFC => {
+                let Some(&trail_byte) = buffer.get(offset + 1) else {
+                    if !is_final {
+                        return offset;
+                    }
+
+                    push_hex(text, &buffer[offset..]);
+                    return buffer.len();
+                };
+
+                let byte_pair = [lead_byte, trail_byte];
+                let (decoded, _, had_errors) = encoding_rs::SHIFT_JIS.decode(&byte_pair);
+                if had_errors {
+                    push_hex(text, &[lead_byte]);
+                    offset += 1;
+                } else {
+                    *text += &decoded;
+                    offset += 2;
+                }
+            },
+
+            _ => {
+                push_hex(text, &[lead_byte]);
+                offset += 1;
+            }
+        }
+    }
+
+    offset
+}
+
+pub fn decode(encoding: SerialEncoding, buffer: &mut Vec<u8>, is_final: bool) -> String {
+    /* Decoded bytes are drained, an incomplete trailing Character stays in the Buffer */
+    let mut text = String::with_capacity(buffer.len());
+    let consumed_length = match encoding {
+        SerialEncoding::Utf8 => decode_utf8(&mut text, buffer, is_final),
+        SerialEncoding::ShiftJis => decode_shift_jis(&mut text, buffer, is_final),
+        SerialEncoding::Latin1 => {
+            /* C1 Controls have no Glyph */
+            buffer.iter().for_each(|byte| match byte {
+                0x80..=0x9F => push_hex(&mut text, &[*byte]),
+                _ => text.push(*byte as char)
+            });
+            buffer.len()
+        },
+        SerialEncoding::Cp437 => {
+            text.extend(buffer.iter().map(|byte| if *byte < 0x80 { *byte as char } else { CP437_HIGH[(*byte - 0x80) as usize] }));
+            buffer.len()
+        },
+    };
+
+    buffer.drain(..consumed_length);
+    text
+}
@@ -0,0 +1,284 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use regex::Regex;
+use super::serial_codec::FrameCodec;
+
+/*
+    Framing Rules decide where a Serial Row ends. A Config File holds a
+    [default] Section and [device VID:PID] or [device BUS:DEV] Sections:
+
+        [default]
+        delimiter = \r\n \0
+        idle_timeout = 50ms
+
+        [device 0403:6001]
+        fixed_length = 8
+        regex = ^\S*> $
+
+        [device 1:4]
+        codec = hdlc
+
+        [device 067b:2303]
+        protocol = modbus
+
+    Device Sections inherit the Default Rule, CLI Options override it.
+*/
+#[derive(Debug, Clone)]
+pub struct FramingRule {
+    pub delimiters: Vec<Vec<u8>>,
+    pub pattern: Option<Regex>, /* Matched against the decoded Row */
+    pub idle_timeout: Option<u64>, /* Microseconds of Silence between URBs */
+    pub max_length: Option<usize>,
+    pub fixed_length: Option<usize>,
+    pub codec: Option<FrameCodec>, /* Binary Framing replaces Delimiters, Regex and Fixed Length */
+    pub protocol: Option<SerialProtocol>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SerialProtocol {
+    Modbus, /* Framed by Function Code, replaces all other Framing */
+    Nmea, /* Decodes Rows without Checksum too, Checksummed Sentences are always detected */
+    At /* Pairs Commands with their Responses, Rows also end at \r */
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FramingConfig {
+    pub default_rule: FramingRule,
+    pub device_rules: HashMap<String, FramingRule>, /* VID:PID or Bus:Device */
+}
+
+impl Default for FramingRule {
+    fn default() -> Self {
+        Self {
+            delimiters: vec![b"\n".to_vec()],
+            pattern: None,
+            idle_timeout: None,
+            max_length: None,
+            fixed_length: None,
+            codec: None,
+            protocol: None,
+        }
+    }
+}
+
+fn parse_escaped(value: &str) -> Result<Vec<u8>, String> {
+    /* Supports \r \n \t \0 \\ and \xNN, everything else is taken literally */
+    let mut bytes = vec![];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut utf8_buffer = [0u8; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut utf8_buffer).as_bytes());
+            continue;
+        }
+
+        match chars.next() {
+            Some('r') => bytes.push(b'\r'),
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex_digits: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex_digits, 16).map_err(|_| format!("Invalid Escape \\x{} in {}", hex_digits, value))?;
+                bytes.push(byte);
+            },
+            _ => return Err(format!("Invalid Escape Sequence in {}", value))
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn parse_duration(value: &str) -> Result<u64, String> {
+    /* Bare Numbers are Milliseconds */
+    let (number, multiplier) = if let Some(number) = value.strip_suffix("us") {
+        (number, 1)
+    } else if let Some(number) = value.strip_suffix("ms") {
+        (number, 1_000)
+    } else if let Some(number) = value.strip_suffix('s') {
+        (number, 1_000_000)
+    } else {
+        (value, 1_000)
+    };
+
+    number.trim().parse::<u64>()
+        .map(|number| number * multiplier)
+        .map_err(|_| format!("Invalid Duration {}", value))
+}
+
+fn parse_length(value: &str) -> Result<Option<usize>, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Ok(None),
+        Ok(length) => Ok(Some(length)),
+        Err(_) => Err(format!("Invalid Length {}", value))
+    }
+}
+
+fn parse_device_selector(selector: &str) -> Result<String, String> {
+    /* VID:PID is always four Hex Digits each, Bus:Device is Decimal */
+    let Some((first, second)) = selector.split_once(':') else {
+        return Err(format!("Invalid Device {}, expected VID:PID or BUS:DEV", selector));
+    };
+
+    if first.len() == 4 && second.len() == 4 {
+        let vendor_id = u16::from_str_radix(first, 16).map_err(|_| format!("Invalid Vendor ID {}", first))?;
+        let product_id = u16::from_str_radix(second, 16).map_err(|_| format!("Invalid Product ID {}", second))?;
+        return Ok(format!("{:04x}:{:04x}", vendor_id, product_id));
+    }
+
+    let bus_id = first.parse::<u16>().map_err(|_| format!("Invalid Bus {}", first))?;
+    let device_id = second.parse::<u16>().map_err(|_| format!("Invalid Device {}", second))?;
+    Ok(format!("{}:{}", bus_id, device_id))
+}
+
+impl FramingRule {
+    pub fn set_option(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            /* Space separated, "none" disables Delimiters for Binary Protocols */
+            "delimiter" => {
+                self.delimiters = match value {
+                    "none" => vec![],
+                    _ => value.split_whitespace().map(parse_escaped).collect::<Result<_, _>>()?
+                };
+
+                self.delimiters.retain(|delimiter| !delimiter.is_empty());
+            },
+
+            "regex" => {
+                self.pattern = match value {
+                    "" | "none" => None,
+                    _ => Some(Regex::new(value).map_err(|error| format!("Invalid Regex {}: {}", value, error))?)
+                };
+            },
+
+            "idle_timeout" => {
+                self.idle_timeout = match value {
+                    "none" => None,
+                    _ => Some(parse_duration(value)?).filter(|timeout| *timeout > 0)
+                };
+            },
+
+            "max_length" => self.max_length = parse_length(value)?,
+            "fixed_length" => self.fixed_length = parse_length(value)?,
+            "codec" => {
+                self.codec = match value {
+                    "none" => None,
+                    _ => Some(value.parse::<FrameCodec>()?)
+                };
+            },
+            "protocol" => {
+                self.protocol = match value {
+                    "none" => None,
+                    "modbus" => Some(SerialProtocol::Modbus),
+                    "nmea" => Some(SerialProtocol::Nmea),
+                    "at" => Some(SerialProtocol::At),
+                    _ => return Err(format!("Unknown Protocol {}, expected modbus, nmea or at", value))
+                };
+
+                /* Commands are terminated by \r alone */
+                if self.protocol == Some(SerialProtocol::At) && self.delimiters == [b"\n".to_vec()] {
+                    self.delimiters = vec![b"\r".to_vec(), b"\n".to_vec()];
+                }
+            },
+            _ => return Err(format!("Unknown Framing Option {}", key))
+        }
+
+        Ok(())
+    }
+
+    pub fn get_max_delimiter_length(&self) -> usize {
+        self.delimiters.iter().map(|delimiter| delimiter.len()).max().unwrap_or(0)
+    }
+
+    pub fn is_frame_end(&self, frame_length: usize, frame_tail: &[u8]) -> bool {
+        /* frame_tail holds the last bytes of the Frame, enough for the longest Delimiter */
+        self.fixed_length.is_some_and(|fixed_length| frame_length >= fixed_length)
+            || self.max_length.is_some_and(|max_length| frame_length >= max_length)
+            || self.delimiters.iter().any(|delimiter| frame_tail.ends_with(delimiter))
+    }
+}
+
+impl FramingConfig {
+    pub fn get_rule(&self, device_key: &str, device_ids: Option<(u16, u16)>) -> &FramingRule {
+        device_ids
+            .and_then(|(vendor_id, product_id)| self.device_rules.get(&format!("{:04x}:{:04x}", vendor_id, product_id)))
+            .or_else(|| self.device_rules.get(device_key))
+            .unwrap_or(&self.default_rule)
+    }
+}
+
+pub fn parse_framing_config(config_text: &str, overrides: &[(&str, String)]) -> Result<FramingConfig, String> {
+    /* Sections are collected first, Device Rules are built once the Default is final */
+    let mut default_options: Vec<(String, String)> = vec![];
+    let mut device_sections: Vec<(String, Vec<(String, String)>)> = vec![];
+    let mut in_default = true;
+
+    for (line_index, line) in config_text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(section) = line.strip_prefix('[').and_then(|section| section.strip_suffix(']')) {
+            let section = section.trim();
+            if section == "default" {
+                in_default = true;
+            } else if let Some(selector) = section.strip_prefix("device") {
+                in_default = false;
+                device_sections.push((parse_device_selector(selector.trim())?, vec![]));
+            } else {
+                return Err(format!("Line {}: Unknown Section [{}]", line_index + 1, section));
+            }
+
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("Line {}: Expected key = value", line_index + 1));
+        };
+
+        let option = (key.trim().to_string(), value.trim().to_string());
+        match device_sections.last_mut() {
+            Some((_, options)) if !in_default => options.push(option),
+            _ => default_options.push(option),
+        }
+    }
+
+    let mut framing_config = FramingConfig::default();
+    for (key, value) in default_options.iter() {
+        framing_config.default_rule.set_option(key, value)?;
+    }
+
+    for (key, value) in overrides {
+        framing_config.default_rule.set_option(key, value)?;
+    }
+
+    for (selector, options) in device_sections {
+        let mut device_rule = framing_config.default_rule.clone();
+        for (key, value) in options.iter() {
+            device_rule.set_option(key, value)?;
+        }
+
+        framing_config.device_rules.insert(selector, device_rule);
+    }
+
+    Ok(framing_config)
+}
@@ -0,0 +1,470 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::protocol_control::format_hex;
+
+/*
+    ISO/IEC 7816-4 Command and Response APDUs, and the ISO/IEC 7816-3
+    Answer To Reset. Command Data is decoded where the Instruction defines it,
+    anything else is shown as Hex
+*/
+
+/* Define Constants */
+const DATA_PREVIEW_LENGTH: usize = 32;
+const CLOCK_RATE_FACTORS: [Option<u32>; 16] = [
+    Some(372), Some(372), Some(558), Some(744), Some(1116), Some(1488), Some(1860), None,
+    None, Some(512), Some(768), Some(1024), Some(1536), Some(2048), None, None,
+];
+const BAUD_RATE_FACTORS: [Option<u32>; 16] = [
+    None, Some(1), Some(2), Some(4), Some(8), Some(16), Some(32), Some(64),
+    Some(12), Some(20), None, None, None, None, None, None,
+];
+
+/* Registered Application Identifiers, matched by Prefix */
+const KNOWN_AIDS: &[(&[u8], &str)] = &[
+    (b"1PAY.SYS.DDF01", "Payment System Environment"),
+    (b"2PAY.SYS.DDF01", "Proximity Payment System Environment"),
+    (&[0xA0, 0x00, 0x00, 0x00, 0x03], "Visa"),
+    (&[0xA0, 0x00, 0x00, 0x00, 0x04], "Mastercard"),
+    (&[0xA0, 0x00, 0x00, 0x00, 0x25], "American Express"),
+    (&[0xA0, 0x00, 0x00, 0x00, 0x87, 0x10, 0x02], "3GPP USIM"),
+    (&[0xA0, 0x00, 0x00, 0x01, 0x51, 0x00, 0x00], "GlobalPlatform Card Manager"),
+    (&[0xA0, 0x00, 0x00, 0x02, 0x47, 0x10, 0x01], "ICAO eMRTD"),
+    (&[0xA0, 0x00, 0x00, 0x03, 0x08], "PIV"),
+    (&[0xA0, 0x00, 0x00, 0x05, 0x27, 0x21, 0x01], "YubiKey OATH"),
+    (&[0xA0, 0x00, 0x00, 0x06, 0x47, 0x2F, 0x00, 0x01], "FIDO U2F"),
+    (&[0xD2, 0x76, 0x00, 0x01, 0x24, 0x01], "OpenPGP"),
+    (&[0xE8, 0x28, 0xBD, 0x08, 0x0F], "PKCS#15"),
+];
+
+pub struct CommandApdu<'a> {
+    pub class: u8,
+    pub instruction: u8,
+    pub parameter_1: u8,
+    pub parameter_2: u8,
+    pub data: &'a [u8],
+    pub expected_length: Option<usize>,
+}
+
+impl<'a> CommandApdu<'a> {
+    pub fn parse(apdu: &'a [u8]) -> Option<Self> {
+        /* Cases 1 to 4, Short (Lc, Le one Byte) or Extended (Lc, Le after a Zero Byte) */
+        let (header, body) = (apdu.get(..4)?, &apdu[4..]);
+        let (data, expected_length) = match body {
+            [] => (body, None),
+            [expected_length] => (&body[..0], Some(if *expected_length == 0 { 256 } else { *expected_length as usize })),
+            [0x00, length_high, length_low] => {
+                let expected_length = u16::from_be_bytes([*length_high, *length_low]) as usize;
+                (&body[..0], Some(if expected_length == 0 { 65536 } else { expected_length }))
+            },
+            [0x00, length_high, length_low, rest @ ..] => {
+                let data_length = u16::from_be_bytes([*length_high, *length_low]) as usize;
+                let expected_length = match rest.get(data_length..)? {
+                    [] => None,
+                    [length_high, length_low] => Some(u16::from_be_bytes([*length_high, *length_low]) as usize).map(|length| if length == 0 { 65536 } else { length }),
+                    _ => return None,
+                };
+
+                (&rest[..data_length], expected_length)
+            },
+            [data_length, rest @ ..] => {
+                let expected_length = match rest.get(*data_length as usize..)? {
+                    [] => None,
+                    [expected_length] => Some(if *expected_length == 0 { 256 } else { *expected_length as usize }),
+                    _ => return None,
+                };
+
+                (&rest[..*data_length as usize], expected_length)
+            },
+        };
+
+        Some(CommandApdu {
+            class: header[0],
+            instruction: header[1],
+            parameter_1: header[2],
+            parameter_2: header[3],
+            data,
+            expected_length,
+        })
+    }
+
+    pub fn is_proprietary(&self) -> bool {
+        self.class & 0x80 != 0 && self.class != 0xFF
+    }
+}
+
+pub fn format_data(data: &[u8]) -> String {
+    let hex_bytes = format_hex(&data[..data.len().min(DATA_PREVIEW_LENGTH)]);
+    match data.len() > DATA_PREVIEW_LENGTH {
+        true => format!("{} … ({} bytes)", hex_bytes, data.len()),
+        false => hex_bytes,
+    }
+}
+
+fn format_printable(data: &[u8]) -> Option<String> {
+    data.iter().all(|byte| byte.is_ascii_graphic() || *byte == b' ').then(|| format!("{:?}", String::from_utf8_lossy(data)))
+}
+
+pub fn get_instruction_name(instruction: u8) -> &'static str {
+    match instruction {
+        0x04 => "DEACTIVATE FILE",
+        0x0C => "ERASE RECORD",
+        0x0E | 0x0F => "ERASE BINARY",
+        0x10 => "PERFORM SCQL OPERATION",
+        0x20 | 0x21 => "VERIFY",
+        0x22 => "MANAGE SECURITY ENVIRONMENT",
+        0x24 => "CHANGE REFERENCE DATA",
+        0x26 => "DISABLE VERIFICATION REQUIREMENT",
+        0x28 => "ENABLE VERIFICATION REQUIREMENT",
+        0x2A => "PERFORM SECURITY OPERATION",
+        0x2C => "RESET RETRY COUNTER",
+        0x44 => "ACTIVATE FILE",
+        0x46 | 0x47 => "GENERATE ASYMMETRIC KEY PAIR",
+        0x70 => "MANAGE CHANNEL",
+        0x82 => "EXTERNAL AUTHENTICATE",
+        0x84 => "GET CHALLENGE",
+        0x86 | 0x87 => "GENERAL AUTHENTICATE",
+        0x88 => "INTERNAL AUTHENTICATE",
+        0xA0 | 0xA1 => "SEARCH BINARY",
+        0xA2 => "SEARCH RECORD",
+        0xA4 => "SELECT",
+        0xA8 => "GET PROCESSING OPTIONS",
+        0xAE => "GENERATE APPLICATION CRYPTOGRAM",
+        0xB0 | 0xB1 => "READ BINARY",
+        0xB2 | 0xB3 => "READ RECORD",
+        0xC0 => "GET RESPONSE",
+        0xC2 | 0xC3 => "ENVELOPE",
+        0xCA | 0xCB => "GET DATA",
+        0xD0 | 0xD1 => "WRITE BINARY",
+        0xD2 => "WRITE RECORD",
+        0xD6 | 0xD7 => "UPDATE BINARY",
+        0xDA | 0xDB => "PUT DATA",
+        0xDC | 0xDD => "UPDATE RECORD",
+        0xE0 => "CREATE FILE",
+        0xE2 => "APPEND RECORD",
+        0xE4 => "DELETE FILE",
+        0xE6 => "TERMINATE DF",
+        0xE8 => "TERMINATE EF",
+        0xFE => "TERMINATE CARD USAGE",
+        _ => "UNKNOWN INSTRUCTION"
+    }
+}
+
+fn describe_aid(aid: &[u8]) -> String {
+    let application_name = KNOWN_AIDS.iter().find(|(prefix, _)| aid.starts_with(prefix)).map(|(_, name)| *name);
+    let aid_text = format_printable(aid).unwrap_or_else(|| aid.iter().map(|byte| format!("{:02X}", byte)).collect());
+    match application_name {
+        Some(application_name) => format!("AID {} ({})", aid_text, application_name),
+        None => format!("AID {}", aid_text),
+    }
+}
+
+fn describe_parameters(command: &CommandApdu) -> String {
+    /* What the Parameters and Data mean for the Interindustry Instructions */
+    let offset = u16::from_be_bytes([command.parameter_1, command.parameter_2]);
+    match command.instruction {
+        0xA4 => match command.parameter_1 {
+            0x04 => describe_aid(command.data),
+            0x00..=0x02 if command.data.len() == 2 => format!("FID {}", format_data(command.data).replace(' ', "")),
+            0x08 | 0x09 => format!("Path {}", format_data(command.data).replace(' ', "")),
+            _ => format!("P1=0x{:02X} P2=0x{:02X} {}", command.parameter_1, command.parameter_2, format_data(command.data)),
+        },
+
+        /* Bit 8 of P1 selects a Short EF Identifier instead of the Offset */
+        0xB0 | 0xD0 | 0xD6 if command.parameter_1 & 0x80 != 0 => format!("SFI {} Offset {}", command.parameter_1 & 0x1F, command.parameter_2),
+        0xB0 | 0xD0 | 0xD6 => format!("Offset {}", offset),
+        0xB2 | 0xDC | 0xD2 => match command.parameter_2 >> 3 {
+            0 => format!("Record {}", command.parameter_1),
+            short_identifier => format!("Record {} SFI {}", command.parameter_1, short_identifier),
+        },
+
+        /* Reference Data is a Secret, its Length is enough */
+        0x20 | 0x24 | 0x2C => match command.data.len() {
+            0 => format!("Reference 0x{:02X} (Retry Counter)", command.parameter_2),
+            data_length => format!("Reference 0x{:02X} ({} bytes)", command.parameter_2, data_length),
+        },
+
+        0xCA | 0xDA => format!("Tag {:04X}", offset),
+        0xC0 | 0x84 => String::new(),
+        _ if command.data.is_empty() => format!("P1=0x{:02X} P2=0x{:02X}", command.parameter_1, command.parameter_2),
+        _ => format!("P1=0x{:02X} P2=0x{:02X} {}", command.parameter_1, command.parameter_2, format_data(command.data)),
+    }
+}
+
+pub fn describe_command(apdu: &[u8]) -> String {
+    let Some(command) = CommandApdu::parse(apdu) else { return format!("APDU (Malformed) {}", format_data(apdu)) };
+    /* Proprietary Classes reuse Instruction Codes, except the EMV ones */
+    let (instruction_name, parameters) = match command.is_proprietary() && !matches!(command.instruction, 0xA8 | 0xAE | 0xCA) {
+        true => ("PROPRIETARY", format!("P1=0x{:02X} P2=0x{:02X} {}", command.parameter_1, command.parameter_2, format_data(command.data))),
+        false => (get_instruction_name(command.instruction), describe_parameters(&command)),
+    };
+
+    let mut description = format!("{} (CLA {:02X} INS {:02X})", instruction_name, command.class, command.instruction);
+
+    if !parameters.trim().is_empty() {
+        description += &format!(" {}", parameters.trim_end());
+    }
+
+    if let Some(expected_length) = command.expected_length {
+        description += &format!(" Le={}", expected_length);
+    }
+
+    /* Command Chaining: more Data follows in the next APDU */
+    if command.class & 0x10 != 0 && command.class & 0x80 == 0 {
+        description += " (Chained)";
+    }
+
+    description
+}
+
+pub fn describe_status_word(status_word: u16) -> String {
+    let (status_1, status_2) = ((status_word >> 8) as u8, status_word as u8);
+    let meaning = match (status_1, status_2) {
+        (0x90, 0x00) => String::from("Success"),
+        (0x61, available) => format!("{} bytes still available (GET RESPONSE)", available),
+        (0x9F, available) => format!("{} response bytes available", available),
+        (0x62, 0x00) => String::from("Warning: no information given"),
+        (0x62, 0x81) => String::from("Warning: returned data may be corrupted"),
+        (0x62, 0x82) => String::from("Warning: end of file reached before Le bytes"),
+        (0x62, 0x83) => String::from("Warning: selected file deactivated"),
+        (0x62, 0x84) => String::from("Warning: file control information not formatted"),
+        (0x63, 0xC0..=0xCF) => format!("Verification failed, {} retries left", status_2 & 0x0F),
+        (0x63, _) => String::from("Warning: state of non-volatile memory changed"),
+        (0x64, _) => String::from("Execution error: state unchanged"),
+        (0x65, 0x81) => String::from("Execution error: memory failure"),
+        (0x65, _) => String::from("Execution error: state changed"),
+        (0x67, 0x00) => String::from("Wrong length"),
+        (0x68, 0x81) => String::from("Logical channel not supported"),
+        (0x68, 0x82) => String::from("Secure messaging not supported"),
+        (0x68, 0x83) => String::from("Last command of the chain expected"),
+        (0x68, 0x84) => String::from("Command chaining not supported"),
+        (0x69, 0x81) => String::from("Command incompatible with file structure"),
+        (0x69, 0x82) => String::from("Security status not satisfied"),
+        (0x69, 0x83) => String::from("Authentication method blocked"),
+        (0x69, 0x84) => String::from("Reference data not usable"),
+        (0x69, 0x85) => String::from("Conditions of use not satisfied"),
+        (0x69, 0x86) => String::from("Command not allowed (no current EF)"),
+        (0x69, 0x87) => String::from("Expected secure messaging data objects missing"),
+        (0x69, 0x88) => String::from("Incorrect secure messaging data objects"),
+        (0x6A, 0x80) => String::from("Incorrect parameters in the data field"),
+        (0x6A, 0x81) => String::from("Function not supported"),
+        (0x6A, 0x82) => String::from("File or application not found"),
+        (0x6A, 0x83) => String::from("Record not found"),
+        (0x6A, 0x84) => String::from("Not enough memory space in the file"),
+        (0x6A, 0x86) => String::from("Incorrect parameters P1-P2"),
+        (0x6A, 0x88) => String::from("Referenced data not found"),
+        (0x6B, 0x00) => String::from("Wrong parameters P1-P2"),
+        (0x6C, available) => format!("Wrong Le field, {} bytes available", available),
+        (0x6D, 0x00) => String::from("Instruction not supported"),
+        (0x6E, 0x00) => String::from("Class not supported"),
+        (0x6F, 0x00) => String::from("No precise diagnosis"),
+        _ => String::from("Unknown Status"),
+    };
+
+    format!("{:04X} {}", status_word, meaning)
+}
+
+pub fn is_status_error(status_word: u16) -> bool {
+    /* Normal Processing (9000, 61XX) and Warnings (62XX) are not Failures */
+    !matches!(status_word >> 8, 0x90 | 0x61 | 0x62 | 0x9F)
+}
+
+pub fn describe_response(response: &[u8]) -> (String, bool) {
+    /* Response Data followed by SW1 SW2 */
+    if response.len() < 2 {
+        return (format!("(Truncated APDU) {}", format_data(response)), true);
+    }
+
+    let (data, status_bytes) = response.split_at(response.len() - 2);
+    let status_word = u16::from_be_bytes([status_bytes[0], status_bytes[1]]);
+    let description = match data.is_empty() {
+        true => describe_status_word(status_word),
+        false => format!("{} | {}", describe_status_word(status_word), format_data(data)),
+    };
+
+    (description, is_status_error(status_word))
+}
+
+pub fn describe_atr(atr: &[u8]) -> String {
+    /* TS, T0, Interface Bytes chained by TDi, Historical Bytes, TCK */
+    let convention = match atr.first() {
+        Some(0x3B) => "Direct Convention",
+        Some(0x3F) => "Inverse Convention",
+        _ => return format!("ATR (Invalid TS) {}", format_data(atr)),
+    };
+
+    let Some(format_byte) = atr.get(1) else { return format!("ATR (Truncated) {}", format_data(atr)) };
+    let historical_length = (format_byte & 0x0F) as usize;
+    let mut parts = vec![String::from(convention)];
+    let mut protocols: Vec<u8> = vec![];
+    let (mut offset, mut indicator, mut level) = (2, *format_byte, 1);
+    loop {
+        /* TAi, TBi, TCi, TDi present per the upper Nibble of the Indicator */
+        let mut interface_bytes = [None; 4];
+        for (index, interface_byte) in interface_bytes.iter_mut().enumerate() {
+            if indicator & (0x10 << index) != 0 {
+                let Some(value) = atr.get(offset) else { return format!("ATR (Truncated) {}", format_data(atr)) };
+                *interface_byte = Some(*value);
+                offset += 1;
+            }
+        }
+
+        match (level, interface_bytes) {
+            (1, [Some(clock_rates), ..]) => {
+                let clock_rate_factor = CLOCK_RATE_FACTORS[(clock_rates >> 4) as usize];
+                let baud_rate_factor = BAUD_RATE_FACTORS[(clock_rates & 0x0F) as usize];
+                match (clock_rate_factor, baud_rate_factor) {
+                    (Some(clock_rate_factor), Some(baud_rate_factor)) => parts.push(format!("Fi={} Di={}", clock_rate_factor, baud_rate_factor)),
+                    _ => parts.push(format!("TA1=0x{:02X} (Reserved Fi/Di)", clock_rates)),
+                }
+            },
+            (2, [Some(specific_mode), ..]) => parts.push(format!("Specific Mode T={}", specific_mode & 0x0F)),
+            _ => {}
+        }
+
+        if level == 1 && let Some(extra_guard_time) = interface_bytes[2] && extra_guard_time != 0 {
+            parts.push(format!("Extra Guard Time {}", extra_guard_time));
+        }
+
+        /* Level 3 onwards after T=1 carries the IFSC */
+        if level >= 3 && protocols.last() == Some(&1) && let Some(information_field_size) = interface_bytes[0] {
+            parts.push(format!("IFSC {}", information_field_size));
+        }
+
+        let Some(next_indicator) = interface_bytes[3] else { break };
+        let protocol = next_indicator & 0x0F;
+        if !protocols.contains(&protocol) {
+            protocols.push(protocol);
+        }
+
+        indicator = next_indicator;
+        level += 1;
+    }
+
+    if protocols.is_empty() {
+        protocols.push(0);
+    }
+
+    parts.insert(1, protocols.iter().map(|protocol| format!("T={}", protocol)).collect::<Vec<String>>().join(", "));
+    let Some(historical_bytes) = atr.get(offset..(offset + historical_length)) else { return format!("ATR (Truncated) {}", format_data(atr)) };
+    if !historical_bytes.is_empty() {
+        let historical_text = format_printable(historical_bytes).unwrap_or_else(|| format_data(historical_bytes));
+        parts.push(format!("Historical {}", historical_text));
+    }
+
+    /* TCK is present unless only T=0 is offered, all Bytes from T0 XOR to Zero */
+    if protocols != [0] {
+        match atr.get(offset + historical_length) {
+            Some(_) if atr[1..=(offset + historical_length)].iter().fold(0, |checksum, byte| checksum ^ byte) == 0 => parts.push(String::from("TCK OK")),
+            Some(_) => parts.push(String::from("TCK Mismatch")),
+            None => parts.push(String::from("TCK Missing")),
+        }
+    }
+
+    format!("ATR {} ({})", format_data(atr), parts.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type ApduFields = (u8, u8, u8, u8, Vec<u8>, Option<usize>); /* CLA, INS, P1, P2, Data, Le */
+
+    fn parse_fields(apdu: &[u8]) -> Option<ApduFields> {
+        CommandApdu::parse(apdu).map(|command| (
+            command.class,
+            command.instruction,
+            command.parameter_1,
+            command.parameter_2,
+            command.data.to_vec(),
+            command.expected_length
+        ))
+    }
+
+    #[test]
+    fn parses_short_apdus() {
+        /* Case 1: Header only */
+        assert_eq!(parse_fields(&[0x00, 0x70, 0x00, 0x00]), Some((0x00, 0x70, 0x00, 0x00, vec![], None)));
+
+        /* Case 2: Le, Zero means 256 */
+        assert_eq!(parse_fields(&[0x00, 0x84, 0x00, 0x00, 0x08]), Some((0x00, 0x84, 0x00, 0x00, vec![], Some(8))));
+        assert_eq!(parse_fields(&[0x00, 0xB0, 0x00, 0x00, 0x00]), Some((0x00, 0xB0, 0x00, 0x00, vec![], Some(256))));
+
+        /* Case 3: Lc and Data */
+        assert_eq!(parse_fields(&[0x00, 0x20, 0x00, 0x81, 0x02, 0x31, 0x32]), Some((0x00, 0x20, 0x00, 0x81, vec![0x31, 0x32], None)));
+
+        /* Case 4: Lc, Data and Le */
+        assert_eq!(
+            parse_fields(&[0x00, 0xA4, 0x04, 0x00, 0x05, 0xA0, 0x00, 0x00, 0x03, 0x08, 0x00]),
+            Some((0x00, 0xA4, 0x04, 0x00, vec![0xA0, 0x00, 0x00, 0x03, 0x08], Some(256)))
+        );
+
+        /* Lc beyond the APDU, or Bytes left after Le */
+        assert_eq!(parse_fields(&[0x00, 0x20, 0x00, 0x81, 0x04, 0x31, 0x32]), None);
+        assert_eq!(parse_fields(&[0x00, 0x20, 0x00, 0x81, 0x01, 0x31, 0x00, 0x00]), None);
+        assert_eq!(parse_fields(&[0x00, 0x20, 0x00]), None);
+    }
+
+    #[test]
+    fn parses_extended_apdus() {
+        /* Case 2E: Zero Byte then a two Byte Le, Zero means 65536 */
+        assert_eq!(parse_fields(&[0x00, 0xB0, 0x00, 0x00, 0x00, 0x01, 0x00]), Some((0x00, 0xB0, 0x00, 0x00, vec![], Some(256))));
+        assert_eq!(parse_fields(&[0x00, 0xB0, 0x00, 0x00, 0x00, 0x00, 0x00]), Some((0x00, 0xB0, 0x00, 0x00, vec![], Some(65536))));
+
+        /* Case 3E: Zero Byte then a two Byte Lc */
+        assert_eq!(parse_fields(&[0x00, 0xD6, 0x00, 0x00, 0x00, 0x00, 0x02, 0xAA, 0xBB]), Some((0x00, 0xD6, 0x00, 0x00, vec![0xAA, 0xBB], None)));
+
+        /* Case 4E: Lc, Data and a two Byte Le */
+        assert_eq!(
+            parse_fields(&[0x00, 0x2A, 0x9E, 0x9A, 0x00, 0x00, 0x03, 0x01, 0x02, 0x03, 0x02, 0x00]),
+            Some((0x00, 0x2A, 0x9E, 0x9A, vec![0x01, 0x02, 0x03], Some(512)))
+        );
+        assert_eq!(
+            parse_fields(&[0x00, 0x2A, 0x9E, 0x9A, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00]),
+            Some((0x00, 0x2A, 0x9E, 0x9A, vec![0x01], Some(65536)))
+        );
+
+        /* A one Byte Le after extended Data is malformed */
+        assert_eq!(parse_fields(&[0x00, 0x2A, 0x9E, 0x9A, 0x00, 0x00, 0x01, 0x01, 0x00]), None);
+        assert_eq!(parse_fields(&[0x00, 0x2A, 0x9E, 0x9A, 0x00, 0x00, 0x04, 0x01]), None);
+    }
+
+    #[test]
+    fn describes_atr_interface_bytes() {
+        /* TA1 (Fi 372, Di 12) and TD1 (T=1), TD2 (T=1) with TA3 (IFSC) and TB3, five Historical Bytes, TCK */
+        let atr = [0x3B, 0x95, 0x18, 0x81, 0x31, 0xFE, 0x45, b'H', b'e', b'l', b'l', b'o', 0xC4];
+        assert_eq!(
+            describe_atr(&atr),
+            "ATR 3B 95 18 81 31 FE 45 48 65 6C 6C 6F C4 (Direct Convention, T=1, Fi=372 Di=12, IFSC 254, Historical \"Hello\", TCK OK)"
+        );
+
+        let mut corrupted_atr = atr;
+        corrupted_atr[12] = 0x00;
+        assert!(describe_atr(&corrupted_atr).ends_with("TCK Mismatch)"));
+        assert!(describe_atr(&atr[..12]).ends_with("TCK Missing)"));
+
+        /* T=0 only: TA1 (Fi 512, Di 32) and TC1 (Extra Guard Time), no TCK */
+        assert_eq!(
+            describe_atr(&[0x3B, 0x52, 0x96, 0x02, 0x01, 0x02]),
+            "ATR 3B 52 96 02 01 02 (Direct Convention, T=0, Fi=512 Di=32, Extra Guard Time 2, Historical 01 02)"
+        );
+
+        assert!(describe_atr(&[0x3B, 0x95, 0x18]).starts_with("ATR (Truncated)"));
+        assert!(describe_atr(&[0x3A, 0x00]).starts_with("ATR (Invalid TS)"));
+    }
+}
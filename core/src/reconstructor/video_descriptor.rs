@@ -0,0 +1,253 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use super::device_model::{DeviceModel, InterfaceModel};
+
+/*
+    USB Video Class Descriptors. Alternate Setting 0 of a Streaming Interface
+    lists its Formats, each followed by the Frame Sizes it supports. The Host
+    picks one of them with the Probe and Commit Controls
+*/
+pub const CLASS_VIDEO: u8 = 0x0E;
+pub const SUBCLASS_VIDEO_CONTROL: u8 = 0x01;
+const DESCRIPTOR_CS_INTERFACE: u8 = 0x24;
+const SUBTYPE_FORMAT_UNCOMPRESSED: u8 = 0x04;
+const SUBTYPE_FRAME_UNCOMPRESSED: u8 = 0x05;
+const SUBTYPE_FORMAT_MJPEG: u8 = 0x06;
+const SUBTYPE_FRAME_MJPEG: u8 = 0x07;
+const SUBTYPE_FORMAT_FRAME_BASED: u8 = 0x10;
+const SUBTYPE_FRAME_FRAME_BASED: u8 = 0x11;
+
+pub const SUBTYPE_INPUT_TERMINAL: u8 = 0x02;
+pub const SUBTYPE_PROCESSING_UNIT: u8 = 0x05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoEncoding {
+    Uncompressed,
+    Mjpeg,
+    FrameBased
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoFrame {
+    pub index: u8,
+    pub width: u16,
+    pub height: u16,
+    pub default_interval: u32, /* 100 ns Units */
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoFormat {
+    pub index: u8,
+    pub encoding: VideoEncoding,
+    pub fourcc: String,
+    pub bits_per_pixel: u8,
+    pub frames: Vec<VideoFrame>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamingControl {
+    pub format_index: u8,
+    pub frame_index: u8,
+    pub frame_interval: u32,
+    pub max_video_frame_size: u32,
+    pub max_payload_transfer_size: u32,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn get_fourcc(guid: &[u8]) -> String {
+    /* Format GUIDs start with the FourCC, e.g. 32595559-0000-0010-8000-00AA00389B71 is YUY2 */
+    let fourcc = &guid[..4];
+    match fourcc.iter().all(|byte| byte.is_ascii_alphanumeric() || *byte == b' ') {
+        true => String::from_utf8_lossy(fourcc).trim_end().to_string(),
+        false => format!("{:02X}{:02X}{:02X}{:02X}", fourcc[0], fourcc[1], fourcc[2], fourcc[3]),
+    }
+}
+
+pub fn format_frame_rate(frame_interval: u32) -> String {
+    match frame_interval {
+        0 => String::from("? fps"),
+        frame_interval => format!("{:.2} fps", 10_000_000.0 / frame_interval as f64),
+    }
+}
+
+impl VideoFrame {
+    pub fn parse(descriptor: &[u8]) -> Option<Self> {
+        /* Frame Based Descriptors lack dwMaxVideoFrameBufferSize */
+        let interval_offset = match descriptor.get(2) {
+            Some(&SUBTYPE_FRAME_UNCOMPRESSED) | Some(&SUBTYPE_FRAME_MJPEG) => 21,
+            Some(&SUBTYPE_FRAME_FRAME_BASED) => 17,
+            _ => return None,
+        };
+
+        if descriptor.len() < interval_offset + 4 {
+            return None;
+        }
+
+        Some(VideoFrame {
+            index: descriptor[3],
+            width: read_u16(descriptor, 5),
+            height: read_u16(descriptor, 7),
+            default_interval: read_u32(descriptor, interval_offset),
+        })
+    }
+}
+
+impl VideoFormat {
+    pub fn get_frame(&self, frame_index: u8) -> Option<&VideoFrame> {
+        self.frames.iter().find(|video_frame| video_frame.index == frame_index)
+    }
+
+    pub fn get_frame_size(&self, video_frame: &VideoFrame) -> Option<usize> {
+        /* Only Uncompressed Frames have a fixed Size */
+        match self.encoding {
+            VideoEncoding::Uncompressed => Some(video_frame.width as usize * video_frame.height as usize * self.bits_per_pixel as usize / 8),
+            _ => None,
+        }
+    }
+
+    pub fn get_extension(&self) -> String {
+        match self.encoding {
+            VideoEncoding::Mjpeg => String::from("jpg"),
+            VideoEncoding::Uncompressed => String::from("yuv"),
+            VideoEncoding::FrameBased => self.fourcc.to_lowercase(),
+        }
+    }
+
+    pub fn describe(&self, video_frame: Option<&VideoFrame>) -> String {
+        match video_frame {
+            Some(video_frame) => format!("{} {}x{}", self.fourcc, video_frame.width, video_frame.height),
+            None => self.fourcc.clone(),
+        }
+    }
+}
+
+impl StreamingControl {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        /* UVC 1.0 Probe and Commit Controls are 26 Bytes, later Versions append Fields */
+        if data.len() < 26 {
+            return None;
+        }
+
+        Some(StreamingControl {
+            format_index: data[2],
+            frame_index: data[3],
+            frame_interval: read_u32(data, 4),
+            max_video_frame_size: read_u32(data, 18),
+            max_payload_transfer_size: read_u32(data, 22),
+        })
+    }
+
+    pub fn describe(&self, video_formats: &[VideoFormat]) -> String {
+        let video_format = video_formats.iter().find(|video_format| video_format.index == self.format_index);
+        let video_frame = video_format.and_then(|video_format| video_format.get_frame(self.frame_index));
+        let format_name = match video_format {
+            Some(video_format) if video_frame.is_some() => video_format.describe(video_frame),
+            Some(video_format) => format!("{} Frame {}", video_format.fourcc, self.frame_index),
+            None => format!("Format {} Frame {}", self.format_index, self.frame_index),
+        };
+
+        format!(
+            "{} {}, Max Frame {} bytes, Max Payload {} bytes",
+            format_name,
+            format_frame_rate(self.frame_interval),
+            self.max_video_frame_size,
+            self.max_payload_transfer_size
+        )
+    }
+}
+
+pub fn parse_formats(streaming_interface: &InterfaceModel) -> Vec<VideoFormat> {
+    /* Frame Descriptors follow the Format they belong to */
+    let mut video_formats: Vec<VideoFormat> = vec![];
+    let class_descriptors = streaming_interface.extra_descriptors
+        .iter()
+        .filter(|descriptor| descriptor.len() >= 5 && descriptor[1] == DESCRIPTOR_CS_INTERFACE);
+
+    for descriptor in class_descriptors {
+        match descriptor[2] {
+            SUBTYPE_FORMAT_UNCOMPRESSED | SUBTYPE_FORMAT_FRAME_BASED if descriptor.len() >= 22 => video_formats.push(VideoFormat {
+                index: descriptor[3],
+                encoding: if descriptor[2] == SUBTYPE_FORMAT_UNCOMPRESSED { VideoEncoding::Uncompressed } else { VideoEncoding::FrameBased },
+                fourcc: get_fourcc(&descriptor[5..21]),
+                bits_per_pixel: descriptor[21],
+                frames: vec![],
+            }),
+
+            SUBTYPE_FORMAT_MJPEG => video_formats.push(VideoFormat {
+                index: descriptor[3],
+                encoding: VideoEncoding::Mjpeg,
+                fourcc: String::from("MJPEG"),
+                bits_per_pixel: 0,
+                frames: vec![],
+            }),
+
+            SUBTYPE_FRAME_UNCOMPRESSED | SUBTYPE_FRAME_MJPEG | SUBTYPE_FRAME_FRAME_BASED => {
+                if let Some(video_format) = video_formats.last_mut() && let Some(video_frame) = VideoFrame::parse(descriptor) {
+                    video_format.frames.push(video_frame);
+                }
+            },
+
+            _ => {}
+        }
+    }
+
+    video_formats
+}
+
+pub fn get_streaming_formats(device: &DeviceModel, interface_number: u8) -> Vec<VideoFormat> {
+    /* The Formats live on Alternate Setting 0, Streaming happens on the others */
+    device.get_active_configuration()
+        .into_iter()
+        .flat_map(|configuration| configuration.interfaces.iter())
+        .find(|interface| interface.number == interface_number && interface.alternate_setting == 0)
+        .map(parse_formats)
+        .unwrap_or_default()
+}
+
+pub fn get_entities(device: &DeviceModel) -> HashMap<u8, u8> {
+    /* Entity ID to Descriptor Subtype of the Video Control Interfaces */
+    device.get_active_configuration()
+        .into_iter()
+        .flat_map(|configuration| configuration.interfaces.iter())
+        .filter(|interface| interface.class == CLASS_VIDEO && interface.subclass == SUBCLASS_VIDEO_CONTROL)
+        .flat_map(|interface| interface.extra_descriptors.iter())
+        .filter(|descriptor| descriptor.len() >= 4 && descriptor[1] == DESCRIPTOR_CS_INTERFACE && descriptor[2] != 0x01)
+        .map(|descriptor| (descriptor[3], descriptor[2]))
+        .collect()
+}
+
+pub fn get_entity_name(subtype: u8) -> &'static str {
+    match subtype {
+        SUBTYPE_INPUT_TERMINAL => "Input Terminal",
+        0x03 => "Output Terminal",
+        0x04 => "Selector Unit",
+        SUBTYPE_PROCESSING_UNIT => "Processing Unit",
+        0x06 => "Extension Unit",
+        0x07 => "Encoding Unit",
+        _ => "Entity"
+    }
+}
@@ -17,12 +17,16 @@
 */
 
 use std::ptr;
-use super::{PacketCaptureImpl, UrbXractHeader, UrbXractPacket};
-use pcap::{Capture, Device};
+use super::{IsoDescriptor, PacketCaptureImpl, UrbEventType, UrbTransferType, UrbXractHeader, UrbXractPacket};
+use pcap::{Capture, Device, Linktype};
 use tokio::{sync::mpsc::Sender, task::JoinHandle};
 
 /* Define Constants, etc. */
 const URB_PACKET_HDRLEN: usize = size_of::<RawUsbmonHeader>();
+const URB_PACKET_HDRLEN_LEGACY: usize = 48; /* DLT_USB_LINUX omits the ISO Fields */
+const ISO_DESCRIPTOR_LENGTH: usize = size_of::<RawIsoDescriptor>();
+const LINKTYPE_USB_LINUX: Linktype = Linktype(189);
+const URB_SETUP_PRESENT: u8 = 0; /* setup_flag is zero when setup_iso holds a Setup Packet */
 pub struct PacketCapture;
 
 #[repr(C, packed)]
@@ -48,6 +52,15 @@ pub(crate) struct RawUsbmonHeader {
     pub(crate) iso_ndesc: [u8; 4],
 }
 
+#[repr(C, packed)]
+pub(crate) struct RawIsoDescriptor {
+    /* struct mon_bin_isodesc, one per Packet between the Header and the Data */
+    pub(crate) status: [u8; 4],
+    pub(crate) offset: [u8; 4],
+    pub(crate) length: [u8; 4],
+    pub(crate) padding: [u8; 4],
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct UsbmonHeader {
@@ -103,6 +116,86 @@ fn read_urb_header(data: &[u8]) -> UsbmonHeader {
     }
 }
 
+fn read_iso_descriptors(descriptor_data: &[u8]) -> Vec<IsoDescriptor> {
+    descriptor_data
+        .chunks_exact(ISO_DESCRIPTOR_LENGTH)
+        .map(|descriptor| {
+            let raw_descriptor = unsafe { ptr::read_unaligned(descriptor.as_ptr() as *const RawIsoDescriptor) };
+            IsoDescriptor {
+                status: i32::from_ne_bytes(raw_descriptor.status),
+                offset: u32::from_ne_bytes(raw_descriptor.offset),
+                length: u32::from_ne_bytes(raw_descriptor.length),
+            }
+        })
+        .collect()
+}
+
+fn read_urb_packet(packet_data: &[u8], header_length: usize) -> Option<UrbXractPacket> {
+    /* Both Header variants share the first 40 bytes, the rest is zero-filled for DLT 189 */
+    if packet_data.len() < header_length {
+        return None;
+    }
+
+    let mut raw_header = [0u8; URB_PACKET_HDRLEN];
+    raw_header[0..header_length].copy_from_slice(&packet_data[0..header_length]);
+    let urb_packet_header = read_urb_header(&raw_header);
+    let urb_data_length = urb_packet_header.data_length as usize;
+
+    /* Construct an XtractHeader */
+    let urbx_header = UrbXractHeader {
+        urb_id: urb_packet_header.id,
+        bus_id: urb_packet_header.bus_id,
+        device_id: urb_packet_header.device_id as u16,
+        endpoint_info: urb_packet_header.endpoint,
+        transfer_type: UrbTransferType::from_raw(urb_packet_header.transfer_type),
+        event_type: match urb_packet_header.type_ {
+            b'S' => UrbEventType::Submit,
+            b'C' => UrbEventType::Complete,
+            _ => UrbEventType::Error
+        },
+        status: urb_packet_header.status,
+        timestamp: (urb_packet_header.timestamp_sec as u64) * 1_000_000 + urb_packet_header.timestamp_usec as u64,
+        setup_packet: if urb_packet_header.setup_flag == URB_SETUP_PRESENT {
+            Some(urb_packet_header.setup_iso)
+        } else {
+            None
+        }
+    };
+
+    /* Isochronous Descriptors sit between Header and Data, Packets are located by their Offset */
+    if urbx_header.transfer_type == UrbTransferType::Isochronous && header_length == URB_PACKET_HDRLEN {
+        let descriptor_end = (header_length + urb_packet_header.iso_ndesc as usize * ISO_DESCRIPTOR_LENGTH).min(packet_data.len());
+        let iso_data = &packet_data[descriptor_end..];
+        return Some(UrbXractPacket {
+            header: urbx_header,
+            data: if iso_data.is_empty() { None } else { Some(iso_data.to_vec()) },
+            iso_descriptors: read_iso_descriptors(&packet_data[header_length..descriptor_end]),
+        });
+    }
+
+    /* Construct Payload Structure for Async Transmission */
+    Some(UrbXractPacket {
+        header: urbx_header,
+        data: if urb_data_length > 0 {
+            /* Get Appropriate Data Region, Snapshot Length may truncate it */
+            let data_end = (header_length + urb_data_length).min(packet_data.len());
+            let urb_packet_data = &packet_data[header_length..data_end];
+            Some(urb_packet_data.to_vec())
+        } else {
+            /* There's no Data */
+            None
+        },
+        iso_descriptors: vec![],
+    })
+}
+
+fn get_header_length(linktype: Linktype) -> usize {
+    match linktype {
+        LINKTYPE_USB_LINUX => URB_PACKET_HDRLEN_LEGACY,
+        _ => URB_PACKET_HDRLEN
+    }
+}
+
 impl PacketCaptureImpl for PacketCapture {
     async fn capture_core(device_name: String, tx: tokio::sync::mpsc::Sender<super::UrbXractPacket>) {
          /* Get the Capture Device */
@@ -119,32 +212,25 @@ impl PacketCaptureImpl for PacketCapture {
             .open().unwrap();
 
         /* Capture the Packets and URB Data from PCAP */
+        let header_length = get_header_length(capture_stream.get_datalink());
         while let Ok(pcap_packet) = capture_stream.next_packet() {
-            let urb_packet_header = read_urb_header(&pcap_packet.data[0..URB_PACKET_HDRLEN]);
-            let urb_data_length = urb_packet_header.data_length as usize;
-            
-            /* Construct an XtractHeader */
-            let urbx_header = UrbXractHeader {
-                bus_id: urb_packet_header.bus_id,
-                device_id: urb_packet_header.device_id as u16,
-                endpoint_info: urb_packet_header.endpoint
-            };
-
-            /* Construct Payload Structure for Async Transmission */
-            let urb_payload = UrbXractPacket {
-                header: urbx_header,
-                data: if urb_data_length > 0 { 
-                    /* Get Appropriate Data Region */
-                    let urb_packet_data = &pcap_packet.data[URB_PACKET_HDRLEN..(URB_PACKET_HDRLEN + urb_data_length)];
-                    Some(urb_packet_data.to_vec()) 
-                } else {
-                    /* There's no Data */ 
-                    None 
-                },
-            };
-
             /* Transmit Packet using Tokio MPSC Channel */
-            tx.send(urb_payload).await.unwrap();
+            if let Some(urb_payload) = read_urb_packet(pcap_packet.data, header_length) {
+                tx.send(urb_payload).await.unwrap();
+            }
+        }
+    }
+
+    async fn capture_file(file_path: String, tx: tokio::sync::mpsc::Sender<super::UrbXractPacket>) {
+        /* Read a usbmon Capture saved by Wireshark, tcpdump or dumpcap */
+        let mut capture_stream = Capture::from_file(&file_path)
+            .unwrap_or_else(|_| panic!("Unable to open Capture File {}", file_path));
+
+        let header_length = get_header_length(capture_stream.get_datalink());
+        while let Ok(pcap_packet) = capture_stream.next_packet() {
+            if let Some(urb_payload) = read_urb_packet(pcap_packet.data, header_length) {
+                tx.send(urb_payload).await.unwrap();
+            }
         }
     }
     
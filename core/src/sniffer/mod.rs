@@ -28,21 +28,70 @@ cfg_if::cfg_if! {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UrbTransferType {
+    Isochronous,
+    Interrupt,
+    Control,
+    Bulk
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UrbEventType {
+    Submit,
+    Complete,
+    Error
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct UrbXractHeader {
+    pub urb_id: u64,
     pub bus_id: u16,
     pub device_id: u16,
-    pub endpoint_info: u8
+    pub endpoint_info: u8,
+    pub transfer_type: UrbTransferType,
+    pub event_type: UrbEventType,
+    pub status: i32, /* Zero on Success: errno (Linux) or USBD_STATUS (Windows) */
+    pub timestamp: u64, /* Capture Time in Microseconds since the Unix Epoch */
+    pub setup_packet: Option<[u8; 8]>
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IsoDescriptor {
+    pub status: i32, /* Per-Packet Status, Zero on Success */
+    pub offset: u32, /* Start of the Packet within the Data */
+    pub length: u32, /* Actual Length on Completion of IN, Requested Length otherwise */
 }
 
 #[derive(Debug)]
 pub struct UrbXractPacket {
     pub header: UrbXractHeader,
-    pub data: Option<Vec<u8>>
+    pub data: Option<Vec<u8>>,
+    pub iso_descriptors: Vec<IsoDescriptor> /* Empty unless Isochronous */
+}
+
+impl UrbTransferType {
+    pub fn from_raw(transfer_type: u8) -> Self {
+        /* Both usbmon and USBPcap use the Endpoint Descriptor encoding */
+        match transfer_type {
+            0 => UrbTransferType::Isochronous,
+            1 => UrbTransferType::Interrupt,
+            2 => UrbTransferType::Control,
+            _ => UrbTransferType::Bulk
+        }
+    }
+}
+
+impl UrbXractHeader {
+    pub fn is_stalled(&self) -> bool {
+        /* -EPIPE from usbmon, USBD_STATUS_STALL_PID from USBPcap */
+        self.status == -32 || self.status as u32 == 0xC0000004
+    }
 }
 
 pub(crate) trait PacketCaptureImpl {
     async fn capture_core(device_name: String, tx: Sender<UrbXractPacket>);
+    async fn capture_file(file_path: String, tx: Sender<UrbXractPacket>);
     fn get_devices_list() -> Vec<String>;
     fn get_connected_devices_list(device_name: String) -> Vec<String>;
 }
@@ -51,4 +100,10 @@ pub fn capture(device_name: String, tx: Sender<UrbXractPacket>) -> tokio::task::
     tokio::spawn(async move {
         PacketCapture::capture_core(device_name, tx).await;
     })
+}
+
+pub fn capture_file(file_path: String, tx: Sender<UrbXractPacket>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        PacketCapture::capture_file(file_path, tx).await;
+    })
 }
\ No newline at end of file
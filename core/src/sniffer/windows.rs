@@ -16,18 +16,20 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>. 
 */
 
-use std::{process::Command, ptr};
+use std::{fs::File, io::Read, process::Command, ptr};
 
-use super::{PacketCaptureImpl, UrbXractHeader, UrbXractPacket};
+use super::{PacketCaptureImpl, UrbEventType, UrbTransferType, UrbXractHeader, UrbXractPacket};
 use pcap_parser::{traits::PcapReaderIterator, LegacyPcapReader, PcapError};
 use regex::Regex;
-use tokio::net::windows::named_pipe::ServerOptions;
+use tokio::{net::windows::named_pipe::ServerOptions, sync::mpsc::Sender};
 use tokio_util::io::SyncIoBridge;
 
 /* Define Constants, etc. */
 type UsbdStatus = u32;
 pub struct PacketCapture;
 const USBPCAP_PATH: &str = r"C:\Program Files\Wireshark\extcap\USBPcapCMD.exe";
+const USBPCAP_INFO_PDO_TO_FDO: u8 = 0x01; /* Set on Completion (Device to Host Driver) */
+const USBPCAP_CONTROL_STAGE_SETUP: u8 = 0;
 
 #[allow(dead_code)]
 #[repr(C, packed)]
@@ -71,6 +73,95 @@ fn get_struct_frombytes<T>(data: &[u8]) -> T {
     }
 }
 
+fn read_pcap_stream<R: Read>(capture_reader: R, tx: Sender<UrbXractPacket>) {
+    /* Setup PCAP Stream Parser, See: https://docs.rs/pcap-parser/latest/pcap_parser/pcap/struct.LegacyPcapReader.html#example */
+    let mut pcap_stream = LegacyPcapReader::new(65536, capture_reader).unwrap();
+
+    loop {
+        match pcap_stream.next() {
+            Err(PcapError::Eof) => break,
+            Err(PcapError::Incomplete(_)) => { pcap_stream.refill().unwrap(); },
+            Err(e) => panic!("Error while reading: {:?}", e),
+
+            Ok((offset, block)) => {
+                match block {
+                    pcap_parser::PcapBlockOwned::LegacyHeader(_pcap_header) => { },
+                    pcap_parser::PcapBlockOwned::NG(_block) => { },
+
+                    pcap_parser::PcapBlockOwned::Legacy(legacy_pcap_block) => {
+                        let mut end_index = size_of::<USBPcapBufferPktHeader>();
+                        let urb_header = get_struct_frombytes::<USBPcapBufferPktHeader>(&legacy_pcap_block.data[0..end_index]);
+                        let mut control_stage = None;
+                        
+                        /* Match Transfer Types */
+                        match urb_header.xfer_type {
+                            0 => {
+                                /* ISOCHRONOUS Transfer */
+                                end_index = size_of::<USBPcapBufferIsoHeader>(); 
+                            },
+
+                            2 => {
+                                /* CONTROL Transfer */
+                                end_index = size_of::<USBPcapBufferControlHeader>();
+                                let control_header = get_struct_frombytes::<USBPcapBufferControlHeader>(&legacy_pcap_block.data[0..end_index]);
+                                control_stage = Some(control_header.stage);
+                            },
+
+                            _ => { /* Bulk, Interrupt, Invalid Transfer */ },
+                        }
+
+                        /* Get URB Payload Data */
+                        let mut urb_data = 
+                            if urb_header.data_length < 1 { None }
+                            else { 
+                                Some(legacy_pcap_block.data[
+                                    end_index..(end_index + urb_header.data_length as usize).min(legacy_pcap_block.data.len())
+                                ].to_vec()) 
+                            };
+
+                        /* USBPcap sends the Setup Packet as the Payload of the Setup Stage */
+                        let mut setup_packet = None;
+                        if control_stage == Some(USBPCAP_CONTROL_STAGE_SETUP)
+                            && let Some(setup_data) = urb_data.take_if(|setup_data| setup_data.len() >= 8) {
+                            setup_packet = Some(<[u8; 8]>::try_from(&setup_data[0..8]).unwrap());
+                            urb_data = if setup_data.len() > 8 { Some(setup_data[8..].to_vec()) } else { None };
+                        }
+
+                        /* Construct UrbXtractHeader */
+                        let urbx_header = UrbXractHeader {
+                            urb_id: urb_header.irp_id,
+                            bus_id: urb_header.bus_id,
+                            device_id: urb_header.device_id,
+                            endpoint_info: urb_header.endpoint,
+                            transfer_type: UrbTransferType::from_raw(urb_header.xfer_type),
+                            event_type: if urb_header.request_info & USBPCAP_INFO_PDO_TO_FDO != 0 {
+                                UrbEventType::Complete
+                            } else {
+                                UrbEventType::Submit
+                            },
+                            status: urb_header.status_code as i32,
+                            timestamp: (legacy_pcap_block.ts_sec as u64) * 1_000_000 + legacy_pcap_block.ts_usec as u64,
+                            setup_packet
+                        };
+
+                        /* Construct UrbXtractPacket */
+                        let urbx_packet = UrbXractPacket {
+                            header: urbx_header,
+                            data: urb_data,
+                            iso_descriptors: vec![],
+                        };
+
+                        tx.blocking_send(urbx_packet).unwrap();
+                    },
+                }                  
+                
+                /* Consume the Block */
+                pcap_stream.consume(offset);
+            },
+        }
+    }
+}
+
 impl PacketCaptureImpl for PacketCapture {
     async fn capture_core(device_name: String, tx: tokio::sync::mpsc::Sender<super::UrbXractPacket>) {        
         /* Setup a Named Pipe */
@@ -90,71 +181,19 @@ impl PacketCaptureImpl for PacketCapture {
         /* Wait for Subprocess to Connect, Spawn Tokio Task */
         capture_syspipe.connect().await.unwrap();
         tokio::task::spawn_blocking(move || {
-            /* Setup PCAP Stream Parser, See: https://docs.rs/pcap-parser/latest/pcap_parser/pcap/struct.LegacyPcapReader.html#example */
-            let capture_syncreader = SyncIoBridge::new(capture_syspipe);
-            let mut pcap_stream = LegacyPcapReader::new(65536, capture_syncreader).unwrap();
-
-            loop {
-                match pcap_stream.next() {
-                    Err(PcapError::Eof) => break,
-                    Err(PcapError::Incomplete(_)) => { pcap_stream.refill().unwrap(); },
-                    Err(e) => panic!("Error while reading: {:?}", e),
-
-                    Ok((offset, block)) => {
-                        match block {
-                            pcap_parser::PcapBlockOwned::LegacyHeader(_pcap_header) => { },
-                            pcap_parser::PcapBlockOwned::NG(_block) => { },
-
-                            pcap_parser::PcapBlockOwned::Legacy(legacy_pcap_block) => {
-                                let mut end_index = size_of::<USBPcapBufferPktHeader>();
-                                let urb_header = get_struct_frombytes::<USBPcapBufferPktHeader>(&legacy_pcap_block.data[0..end_index]);
-                                
-                                /* Match Transfer Types */
-                                match urb_header.xfer_type {
-                                    0 => {
-                                        /* ISOCHRONOUS Transfer */
-                                        end_index = size_of::<USBPcapBufferIsoHeader>(); 
-                                    },
-
-                                    2 => {
-                                        /* CONTROL Transfer */
-                                        end_index = size_of::<USBPcapBufferControlHeader>();
-                                    },
-
-                                    _ => { /* Bulk, Interrupt, Invalid Transfer */ },
-                                }
-
-                                /* Get URB Payload Data */
-                                let urb_data = 
-                                    if urb_header.data_length < 1 { None }
-                                    else { 
-                                        Some(legacy_pcap_block.data[
-                                            end_index..(end_index + urb_header.data_length as usize)
-                                        ].to_vec()) 
-                                    };
-
-                                /* Construct UrbXtractHeader */
-                                let urbx_header = UrbXractHeader {
-                                    bus_id: urb_header.bus_id,
-                                    device_id: urb_header.device_id,
-                                    endpoint_info: urb_header.endpoint
-                                };
-
-                                /* Construct UrbXtractPacket */
-                                let urbx_packet = UrbXractPacket {
-                                    header: urbx_header,
-                                    data: urb_data,
-                                };
-
-                                tx.blocking_send(urbx_packet).unwrap();
-                            },
-                        }                  
-                        
-                        /* Consume the Block */
-                        pcap_stream.consume(offset);
-                    },
-                }
-            }
+            read_pcap_stream(SyncIoBridge::new(capture_syspipe), tx);
+        })
+        .await
+        .unwrap();
+    }
+
+    async fn capture_file(file_path: String, tx: tokio::sync::mpsc::Sender<super::UrbXractPacket>) {
+        /* Read a USBPcap Capture saved by Wireshark or USBPcapCMD */
+        let capture_file = File::open(&file_path)
+            .unwrap_or_else(|_| panic!("Unable to open Capture File {}", file_path));
+
+        tokio::task::spawn_blocking(move || {
+            read_pcap_stream(capture_file, tx);
         })
         .await
         .unwrap();
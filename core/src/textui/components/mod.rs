@@ -17,4 +17,6 @@
 */
 
 pub mod tables;
-pub mod panels;
\ No newline at end of file
+pub mod panels;
+pub mod plots;
+pub mod trees;
\ No newline at end of file
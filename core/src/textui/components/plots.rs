@@ -0,0 +1,81 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use ratatui::{style::{Color, Stylize}, symbols::Marker, text::Line, widgets::{canvas::{Canvas, Points}, Block, Borders, Widget}};
+use crate::reconstructor::{PointerKind, PointerSample};
+
+pub struct PointerCanvas {
+    pub samples: Vec<PointerSample>,
+}
+
+/* Contacts cycle through these, Samples without Contact (Hover, Mouse without Button) are dimmed */
+const CONTACT_COLORS: [Color; 6] = [Color::Cyan, Color::Yellow, Color::Green, Color::Magenta, Color::LightRed, Color::LightBlue];
+
+impl Widget for PointerCanvas {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
+        /* Digitizer Coordinates grow downwards, the Canvas upwards */
+        let title = format!(" Pointer Positions ({} samples) ", self.samples.len());
+        let pointer_canvas = Canvas::default()
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .marker(Marker::Braille)
+            .x_bounds([0.0, 1.0])
+            .y_bounds([0.0, 1.0])
+            .paint(|context| {
+                if self.samples.is_empty() {
+                    context.print(0.35, 0.5, Line::from("No Pointer Reports captured").dark_gray());
+                    return;
+                }
+
+                let hover_coordinates: Vec<(f64, f64)> = self.samples
+                    .iter()
+                    .filter(|sample| !sample.is_touching)
+                    .map(|sample| (sample.x, 1.0 - sample.y))
+                    .collect();
+
+                context.draw(&Points { coords: &hover_coordinates, color: Color::DarkGray });
+                for (color_index, color) in CONTACT_COLORS.iter().enumerate() {
+                    let contact_coordinates: Vec<(f64, f64)> = self.samples
+                        .iter()
+                        .filter(|sample| sample.is_touching && sample.contact_id as usize % CONTACT_COLORS.len() == color_index)
+                        .map(|sample| (sample.x, 1.0 - sample.y))
+                        .collect();
+
+                    context.draw(&Points { coords: &contact_coordinates, color: *color });
+                }
+
+                /* Label the latest Position of every Pointer */
+                let mut labelled: Vec<((u16, u16), u32)> = vec![];
+                for sample in self.samples.iter().rev() {
+                    if labelled.contains(&(sample.device_address, sample.contact_id)) {
+                        continue;
+                    }
+
+                    labelled.push((sample.device_address, sample.contact_id));
+                    let label = match sample.kind {
+                        PointerKind::Mouse => format!("◆ {:03}:{:03}", sample.device_address.0, sample.device_address.1),
+                        PointerKind::Pen => String::from("✎"),
+                        PointerKind::Touch => format!("#{}", sample.contact_id),
+                    };
+
+                    context.print(sample.x, 1.0 - sample.y, Line::from(label).white().bold());
+                }
+            });
+
+        pointer_canvas.render(area, buf);
+    }
+}
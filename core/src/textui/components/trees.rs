@@ -0,0 +1,62 @@
+/*
+    UrbXtract
+    Copyright (C) 2025  Atheesh Thirumalairajan
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>. 
+*/
+
+use ratatui::{style::{Color, Style}, widgets::{Block, Borders, List, ListState, StatefulWidget}};
+use crate::reconstructor::DeviceTreeLine;
+
+pub struct DeviceTreeList {
+    pub lines: Vec<DeviceTreeLine>,
+}
+
+fn has_next_sibling(lines: &[DeviceTreeLine], index: usize, depth: usize) -> bool {
+    /* A Sibling follows if the same Depth appears before the Tree climbs above it */
+    lines[(index + 1)..]
+        .iter()
+        .take_while(|(line_depth, _)| *line_depth >= depth)
+        .any(|(line_depth, _)| *line_depth == depth)
+}
+
+impl StatefulWidget for DeviceTreeList {
+    type State = ListState;
+
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer, state: &mut Self::State) {
+        /* Draw the Branches in front of every Node */
+        let tree_items: Vec<String> = self.lines
+            .iter()
+            .enumerate()
+            .map(|(index, (depth, text))| {
+                let mut branches = String::new();
+                for level in 1..*depth {
+                    branches += if has_next_sibling(&self.lines, index, level) { "│  " } else { "   " };
+                }
+
+                if *depth > 0 {
+                    branches += if has_next_sibling(&self.lines, index, *depth) { "├─ " } else { "└─ " };
+                }
+
+                branches + text
+            })
+            .collect();
+
+        let tree_list = List::new(tree_items)
+            .block(Block::default().borders(Borders::ALL))
+            .highlight_style(Style::default().bg(Color::Cyan).fg(Color::Black));
+
+        StatefulWidget::render(tree_list, area, buf, state);
+    }
+}
@@ -18,16 +18,19 @@
 
 mod components;
 
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
-use components::{panels::{ShortcutsFooter, ShortcutsFooterState, TitleBar}, tables::VirtualizedTable};
+use components::{panels::{ShortcutsFooter, ShortcutsFooterState, TitleBar}, plots::PointerCanvas, tables::VirtualizedTable, trees::DeviceTreeList};
 use crossterm::event::{Event, EventStream, KeyCode, KeyModifiers};
 use futures::{FutureExt, StreamExt};
-use ratatui::{layout::{Constraint, Direction, Layout}, prelude::Backend, widgets::{Row, TableState}, Frame, Terminal};
+use ratatui::{layout::{Constraint, Direction, Layout}, prelude::Backend, style::{Color, Style}, widgets::{ListState, Row, TableState}, Frame, Terminal};
 use tokio::{sync::mpsc::Receiver, time::Instant};
-use crate::reconstructor::ReconstructedTransmission;
+use crate::reconstructor::{self, DeviceRegistry, PointerTrail, ReconstructedTransmission};
 
 enum UIPage {
-    MainTableView
+    MainTableView,
+    DeviceTreeView,
+    PointerPane
 }
 
 pub struct UserInterface<'a> {
@@ -40,9 +43,14 @@ pub struct UserInterface<'a> {
     rows: Vec<Row<'a>>,
     table_state: TableState,
     table_auto_scroll: bool,
+
+    /* Device Tree Options */
+    tree_state: ListState,
     
     /* Data consumer */
-    consume_rx: Receiver<ReconstructedTransmission>
+    consume_rx: Receiver<ReconstructedTransmission>,
+    device_registry: Arc<RwLock<DeviceRegistry>>,
+    pointer_trail: Arc<RwLock<PointerTrail>>
 }
 
 /* Define Constants */
@@ -65,6 +73,8 @@ impl UIPage {
     pub fn get_pagename(&self) -> String {
         match self {
             UIPage::MainTableView => String::from("Packet Capture"),
+            UIPage::DeviceTreeView => String::from("USB Devices"),
+            UIPage::PointerPane => String::from("Pointer Positions"),
         }
     }
     
@@ -83,6 +93,34 @@ impl<'a> UserInterface<'a> {
             .constraints([Constraint::Percentage(2), Constraint::Percentage(96), Constraint::Percentage(2)].as_ref())
             .split(rndr_area);
 
+        /* Create Title Bar */
+        let title_bar = TitleBar {
+            title: self.app_title.clone(),
+        };
+
+        frame.render_widget(title_bar, chunks[0]);
+        frame.render_stateful_widget(ShortcutsFooter {}, chunks[2], &mut (self.shortcutspnl_state));
+
+        if let UIPage::DeviceTreeView = self.active_page {
+            /* Create Device Tree from snooped Descriptors */
+            let device_tree = DeviceTreeList {
+                lines: reconstructor::get_device_tree(&self.device_registry.read().unwrap()),
+            };
+
+            frame.render_stateful_widget(device_tree, chunks[1], &mut (self.tree_state));
+            return;
+        }
+
+        if let UIPage::PointerPane = self.active_page {
+            /* Plot recent Mouse, Touch and Pen Positions */
+            let pointer_canvas = PointerCanvas {
+                samples: self.pointer_trail.read().unwrap().get_samples(),
+            };
+
+            frame.render_widget(pointer_canvas, chunks[1]);
+            return;
+        }
+
         /* Create Table */
         let table = VirtualizedTable {
             rows: self.rows.clone(),
@@ -103,21 +141,17 @@ impl<'a> UserInterface<'a> {
             ])
         };
 
-        /* Create Title Bar */
-        let title_bar = TitleBar {
-            title: self.app_title.clone(),
-        };
-
-        frame.render_widget(title_bar, chunks[0]);
         frame.render_stateful_widget(table, chunks[1], &mut (self.table_state));
-        frame.render_stateful_widget(ShortcutsFooter {}, chunks[2], &mut (self.shortcutspnl_state));
     }
     
-    pub fn new(consume_rx: Receiver<ReconstructedTransmission>) -> Self {
+    pub fn new(consume_rx: Receiver<ReconstructedTransmission>, device_registry: Arc<RwLock<DeviceRegistry>>, pointer_trail: Arc<RwLock<PointerTrail>>) -> Self {
         UserInterface { 
             app_title: UIPage::MainTableView.get_apptitle(),
             active_page: UIPage::MainTableView,
             consume_rx,
+            device_registry,
+            pointer_trail,
+            tree_state: ListState::default(),
             rows: vec![],
             table_state: TableState::default(),
             table_auto_scroll: true,
@@ -126,6 +160,7 @@ impl<'a> UserInterface<'a> {
                     String::from("More Info (↵)"),
                     String::from("To Top (Shift + Up)"),
                     String::from("To Bottom (Shift + Down)"),
+                    String::from("Devices / Pointer (Tab)"),
                     String::from("Quit (q)")
                 ]
             },
@@ -135,6 +170,30 @@ impl<'a> UserInterface<'a> {
     fn handle_terminal_event(&mut self, event: Event) {
         if let Event::Key(key_event) = event {
             if key_event.kind == crossterm::event::KeyEventKind::Press {
+                if key_event.code == KeyCode::Tab {
+                    /* Cycle through the Packet Table, the Device Tree and the Pointer Pane */
+                    self.active_page = match self.active_page {
+                        UIPage::MainTableView => UIPage::DeviceTreeView,
+                        UIPage::DeviceTreeView => UIPage::PointerPane,
+                        UIPage::PointerPane => UIPage::MainTableView,
+                    };
+
+                    self.app_title = self.active_page.get_apptitle();
+                    return;
+                }
+
+                if let UIPage::DeviceTreeView = self.active_page {
+                    match (key_event.code, key_event.modifiers) {
+                        (KeyCode::Up, KeyModifiers::SHIFT) => self.tree_state.select_first(),
+                        (KeyCode::Up, _) => self.tree_state.select_previous(),
+                        (KeyCode::Down, KeyModifiers::SHIFT) => self.tree_state.select_last(),
+                        (KeyCode::Down, _) => self.tree_state.select_next(),
+                        _ => {}
+                    }
+
+                    return;
+                }
+
                 match (key_event.code, key_event.modifiers) {
                     (KeyCode::Up, KeyModifiers::SHIFT) => {
                         self.table_state.select_first();
@@ -205,9 +264,9 @@ impl<'a> UserInterface<'a> {
                         },
 
                         /* Preview Data */
-                        sanitize_ansi_escape(&transmission.combined_payload[0..std::cmp::min(transmission.combined_payload.len(), (t_width - STATIC_ROW_WIDTH) as usize - 15)]) + 
-                        if transmission.combined_payload.len() > ((t_width - STATIC_ROW_WIDTH) as usize - 15) { "..." } else { "" },
-                    ]));
+                        sanitize_ansi_escape(&transmission.combined_payload.chars().take((t_width - STATIC_ROW_WIDTH) as usize - 15).collect::<String>()) + 
+                        if transmission.combined_payload.chars().count() > ((t_width - STATIC_ROW_WIDTH) as usize - 15) { "..." } else { "" },
+                    ]).style(if transmission.is_error { Style::default().fg(Color::Red) } else { Style::default() }));
 
                     /* Auto Scrolling */
                     if self.table_auto_scroll {